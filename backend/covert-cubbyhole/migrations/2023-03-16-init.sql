@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS SECRETS (
+    -- SHA-256 of the token that owns the secret
+    token_hash TEXT NOT NULL,
+    "key" TEXT NOT NULL,
+    "value" TEXT NOT NULL,
+    PRIMARY KEY(token_hash, "key")
+);
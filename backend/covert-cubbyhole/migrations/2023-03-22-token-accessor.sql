@@ -0,0 +1,5 @@
+-- Secrets are owned by the accessor of the token instead of a hash of the
+-- token. The tokens owning the existing secrets were invalidated when tokens
+-- started to be hashed at rest, so there is nothing left to read them.
+DELETE FROM SECRETS;
+ALTER TABLE SECRETS RENAME COLUMN token_hash TO accessor;
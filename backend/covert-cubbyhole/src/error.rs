@@ -0,0 +1,72 @@
+use std::fmt::Display;
+
+use covert_types::error::{ApiError, StatusCode};
+use thiserror::Error;
+use tracing_error::SpanTrace;
+
+#[derive(Error, Debug)]
+pub enum ErrorType {
+    #[error("Internal error")]
+    Storage(#[from] sqlx::Error),
+    #[error("Internal error")]
+    BadData(#[from] serde_json::Error),
+    #[error("Secret at path `{path}` not found")]
+    SecretNotFound { path: String },
+    #[error("`{path}` is not a valid secret path")]
+    InvalidPath { path: String },
+}
+
+#[derive(Error, Debug)]
+pub struct Error {
+    pub variant: ErrorType,
+    pub span_trace: SpanTrace,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n{}", self.variant, self.span_trace)
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        Self {
+            variant: err.into(),
+            span_trace: SpanTrace::capture(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self {
+            variant: err.into(),
+            span_trace: SpanTrace::capture(),
+        }
+    }
+}
+
+impl From<ErrorType> for Error {
+    fn from(err: ErrorType) -> Self {
+        Self {
+            variant: err,
+            span_trace: SpanTrace::capture(),
+        }
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let status_code = match err.variant {
+            ErrorType::Storage(_) | ErrorType::BadData(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorType::SecretNotFound { .. } => StatusCode::NOT_FOUND,
+            ErrorType::InvalidPath { .. } => StatusCode::BAD_REQUEST,
+        };
+
+        ApiError {
+            error: err.variant.into(),
+            status_code,
+            span_trace: Some(err.span_trace),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+#![forbid(unsafe_code)]
+#![forbid(clippy::unwrap_used)]
+#![deny(clippy::pedantic)]
+#![deny(clippy::get_unwrap)]
+#![allow(clippy::module_name_repetitions)]
+
+mod error;
+mod store;
+
+use std::sync::Arc;
+
+use covert_framework::{
+    create,
+    extract::{Extension, Json, Path},
+    Backend, Router,
+};
+use covert_storage::{
+    migrator::{migration_scripts, MigrationError},
+    BackendStoragePool,
+};
+use covert_types::{
+    backend::{BackendCategory, BackendType},
+    methods::cubbyhole::{
+        DeleteSecretResponse, ReadSecretResponse, WriteSecretParams, WriteSecretResponse,
+    },
+    response::Response,
+    token::TokenAccessor,
+};
+pub use error::Error;
+use error::ErrorType;
+use rust_embed::RustEmbed;
+use store::secrets::Repo as SecretsRepo;
+
+pub struct Context {
+    secrets_repo: SecretsRepo,
+}
+
+#[derive(RustEmbed)]
+#[folder = "migrations/"]
+struct Migrations;
+
+/// Returns a new cubbyhole secret engine.
+///
+/// Secrets are scoped to the token used to write them, so no other token is
+/// able to read them. The [`TokenAccessor`] of the calling token is expected
+/// to be available as a request extension.
+///
+/// # Errors
+///
+/// Returns an error if it fails to read the migration scripts.
+pub fn new_cubbyhole_backend(storage: BackendStoragePool) -> Result<Backend, MigrationError> {
+    let ctx = Context {
+        secrets_repo: SecretsRepo::new(storage),
+    };
+
+    let router = Router::new()
+        .route(
+            "/*path",
+            create(write_secret)
+                .update(write_secret)
+                .read(read_secret)
+                .delete(delete_secret),
+        )
+        .layer(Extension(Arc::new(ctx)))
+        .build()
+        .into_service();
+
+    let migrations = migration_scripts::<Migrations>()?;
+
+    Ok(Backend {
+        handler: router,
+        category: BackendCategory::Logical,
+        variant: BackendType::Cubbyhole,
+        migrations,
+    })
+}
+
+/// Destroy all secrets written by the token in the cubbyhole storage.
+///
+/// # Errors
+///
+/// Returns an error if the secrets cannot be removed from the storage.
+pub async fn destroy_token_storage(
+    storage: BackendStoragePool,
+    accessor: &TokenAccessor,
+) -> Result<u64, Error> {
+    SecretsRepo::new(storage)
+        .remove_all(accessor.as_str())
+        .await
+}
+
+fn sanitize_path(path: &str) -> Result<String, Error> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Err(ErrorType::InvalidPath {
+            path: path.to_string(),
+        }
+        .into());
+    }
+    Ok(path.to_string())
+}
+
+#[tracing::instrument(skip_all)]
+async fn write_secret(
+    Json(params): Json<WriteSecretParams>,
+    Path(path): Path<String>,
+    Extension(accessor): Extension<TokenAccessor>,
+    Extension(ctx): Extension<Arc<Context>>,
+) -> Result<Response, Error> {
+    let path = sanitize_path(&path)?;
+    let value = serde_json::to_string(&params.data)?;
+    ctx.secrets_repo
+        .set(accessor.as_str(), &path, &value)
+        .await?;
+
+    let resp = WriteSecretResponse { path };
+    Response::raw(resp).map_err(Into::into)
+}
+
+#[tracing::instrument(skip_all)]
+async fn read_secret(
+    Path(path): Path<String>,
+    Extension(accessor): Extension<TokenAccessor>,
+    Extension(ctx): Extension<Arc<Context>>,
+) -> Result<Response, Error> {
+    let path = sanitize_path(&path)?;
+    let secret = ctx
+        .secrets_repo
+        .get(accessor.as_str(), &path)
+        .await?
+        .ok_or_else(|| ErrorType::SecretNotFound { path })?;
+
+    let resp = ReadSecretResponse {
+        data: serde_json::from_str(&secret.value)?,
+    };
+    Response::raw(resp).map_err(Into::into)
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_secret(
+    Path(path): Path<String>,
+    Extension(accessor): Extension<TokenAccessor>,
+    Extension(ctx): Extension<Arc<Context>>,
+) -> Result<Response, Error> {
+    let path = sanitize_path(&path)?;
+    if !ctx.secrets_repo.remove(accessor.as_str(), &path).await? {
+        return Err(ErrorType::SecretNotFound { path }.into());
+    }
+
+    let resp = DeleteSecretResponse { path };
+    Response::raw(resp).map_err(Into::into)
+}
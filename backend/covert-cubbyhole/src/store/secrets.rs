@@ -0,0 +1,143 @@
+use covert_storage::BackendStoragePool;
+
+use crate::error::Error;
+
+const SECRETS_TABLE: &str = "SECRETS";
+
+#[derive(Debug, sqlx::FromRow, PartialEq, Eq)]
+pub struct Secret {
+    pub key: String,
+    pub value: String,
+}
+
+/// Secrets are always scoped to the accessor of the token that owns them.
+#[derive(Debug, Clone)]
+pub struct Repo {
+    pool: BackendStoragePool,
+}
+
+impl Repo {
+    pub fn new(pool: BackendStoragePool) -> Self {
+        Self { pool }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn set(&self, accessor: &str, key: &str, value: &str) -> Result<(), Error> {
+        self.pool
+            .query(&format!(
+                "INSERT INTO {SECRETS_TABLE} (accessor, key, value)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT(accessor, key) DO UPDATE SET value = excluded.value"
+            ))?
+            .bind(accessor)
+            .bind(key)
+            .bind(value)
+            .execute()
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get(&self, accessor: &str, key: &str) -> Result<Option<Secret>, Error> {
+        self.pool
+            .query(&format!(
+                "SELECT key, value FROM {SECRETS_TABLE} WHERE
+                    accessor = $1 AND
+                    key = $2"
+            ))?
+            .bind(accessor)
+            .bind(key)
+            .fetch_optional()
+            .await
+            .map_err(Into::into)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn remove(&self, accessor: &str, key: &str) -> Result<bool, Error> {
+        self.pool
+            .query(&format!(
+                "DELETE FROM {SECRETS_TABLE} WHERE
+                    accessor = $1 AND
+                    key = $2"
+            ))?
+            .bind(accessor)
+            .bind(key)
+            .execute()
+            .await
+            .map(|res| res.rows_affected() == 1)
+            .map_err(Into::into)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn remove_all(&self, accessor: &str) -> Result<u64, Error> {
+        self.pool
+            .query(&format!("DELETE FROM {SECRETS_TABLE} WHERE accessor = $1"))?
+            .bind(accessor)
+            .execute()
+            .await
+            .map(|res| res.rows_affected())
+            .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use covert_storage::{migrator::migrate_backend, EncryptedPool};
+
+    use crate::Migrations;
+
+    use super::*;
+
+    pub async fn pool() -> BackendStoragePool {
+        let pool = Arc::new(EncryptedPool::new_tmp());
+
+        let storage = BackendStoragePool::new("foo_", pool);
+
+        migrate_backend::<Migrations>(&storage).await.unwrap();
+
+        storage
+    }
+
+    #[sqlx::test]
+    async fn crud() {
+        let store = Repo::new(pool().await);
+
+        store.set("a", "foo", "1").await.unwrap();
+        store.set("b", "foo", "2").await.unwrap();
+        store.set("a", "bar", "3").await.unwrap();
+
+        // Secrets are partitioned by token
+        assert_eq!(
+            store.get("a", "foo").await.unwrap(),
+            Some(Secret {
+                key: "foo".into(),
+                value: "1".into()
+            })
+        );
+        assert_eq!(
+            store.get("b", "foo").await.unwrap(),
+            Some(Secret {
+                key: "foo".into(),
+                value: "2".into()
+            })
+        );
+        assert_eq!(store.get("c", "foo").await.unwrap(), None);
+
+        // Overwrite
+        store.set("a", "foo", "4").await.unwrap();
+        assert_eq!(store.get("a", "foo").await.unwrap().unwrap().value, "4");
+
+        assert!(store.remove("a", "foo").await.unwrap());
+        assert!(!store.remove("a", "foo").await.unwrap());
+        assert_eq!(store.get("b", "foo").await.unwrap().unwrap().value, "2");
+
+        // Remove everything for a token
+        store.set("a", "foo", "5").await.unwrap();
+        assert_eq!(store.remove_all("a").await.unwrap(), 2);
+        assert_eq!(store.get("a", "bar").await.unwrap(), None);
+        assert_eq!(store.get("b", "foo").await.unwrap().unwrap().value, "2");
+    }
+}
@@ -10,6 +10,7 @@ mod create_secret;
 mod domain;
 mod error;
 mod hard_delete_secret;
+mod list_secrets;
 mod soft_delete_secret;
 mod store;
 
@@ -26,9 +27,10 @@ use self::{
     config::{read_config, set_config},
     create_secret::{add_secret, read_secret},
     hard_delete_secret::hard_delete_secret,
+    list_secrets::{list_root_secrets, list_secrets},
     soft_delete_secret::{path_undelete_write, soft_delete_secret},
 };
-use covert_framework::{create, extract::Extension, read, Backend, Router};
+use covert_framework::{create, extract::Extension, list, read, Backend, Router};
 use covert_types::backend::{BackendCategory, BackendType};
 
 #[derive(RustEmbed)]
@@ -52,6 +54,8 @@ pub fn new_versioned_kv_backend(storage: BackendStoragePool) -> Result<Backend,
             "/data/*path",
             read(read_secret).create(add_secret).update(add_secret),
         )
+        .route("/metadata", list(list_root_secrets))
+        .route("/metadata/*path", list(list_secrets))
         .route(
             "/delete/*path",
             create(soft_delete_secret).update(soft_delete_secret),
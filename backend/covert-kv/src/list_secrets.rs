@@ -0,0 +1,69 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use covert_framework::extract::{Extension, Path};
+use covert_types::{methods::kv::ListSecretsResponse, response::Response};
+
+use crate::error::Error;
+
+use super::Context;
+
+#[tracing::instrument(skip_all)]
+pub async fn list_secrets(
+    Extension(ctx): Extension<Arc<Context>>,
+    Path(prefix): Path<String>,
+) -> Result<Response, Error> {
+    list(&ctx, &prefix).await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_root_secrets(Extension(ctx): Extension<Arc<Context>>) -> Result<Response, Error> {
+    list(&ctx, "").await
+}
+
+async fn list(ctx: &Context, prefix: &str) -> Result<Response, Error> {
+    let prefix = if prefix.is_empty() || prefix.ends_with('/') {
+        prefix.to_string()
+    } else {
+        format!("{prefix}/")
+    };
+
+    let keys = ctx.repos.secrets.list_keys(&prefix).await?;
+
+    let resp = ListSecretsResponse {
+        keys: child_keys(&prefix, &keys),
+    };
+    Response::raw(resp).map_err(Into::into)
+}
+
+/// Immediate children of the prefix. Keys nested deeper are collapsed into
+/// their top most folder, which ends with a `/`.
+fn child_keys(prefix: &str, keys: &[String]) -> Vec<String> {
+    keys.iter()
+        .filter_map(|key| key.strip_prefix(prefix))
+        .filter(|rest| !rest.is_empty())
+        .map(|rest| match rest.find('/') {
+            Some(idx) => rest[..=idx].to_string(),
+            None => rest.to_string(),
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapses_nested_keys_into_folders() {
+        let keys = ["foo", "foo/bar", "foo/baz/qux", "foobar"]
+            .into_iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(child_keys("", &keys), vec!["foo", "foo/", "foobar"]);
+        assert_eq!(child_keys("foo/", &keys), vec!["bar", "baz/"]);
+        assert_eq!(child_keys("foo/baz/", &keys), vec!["qux"]);
+        assert!(child_keys("bar/", &keys).is_empty());
+    }
+}
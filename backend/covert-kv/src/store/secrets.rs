@@ -140,6 +140,21 @@ impl Repo {
         Ok(not_deleted)
     }
 
+    #[tracing::instrument(skip_all)]
+    pub async fn list_keys(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        self.pool
+            .query(&format!(
+                "SELECT DISTINCT key FROM {SECRETS_TABLE} WHERE
+                    substr(key, 1, length($1)) = $1
+                ORDER BY key"
+            ))?
+            .bind(prefix)
+            .fetch_all::<(String,)>()
+            .await
+            .map(|rows| rows.into_iter().map(|(key,)| key).collect())
+            .map_err(Into::into)
+    }
+
     #[tracing::instrument(skip_all)]
     pub async fn insert(&self, secret: &Secret) -> Result<bool, Error> {
         self
@@ -293,6 +308,31 @@ pub mod tests {
         }
     }
 
+    #[sqlx::test]
+    fn list_keys() {
+        let ctx = setup().await;
+        let repo = &ctx.repos.secrets;
+
+        for (key, version) in [("foo", 1), ("foo", 2), ("foo/bar", 1), ("foobar", 1)] {
+            let secret = Secret {
+                key: key.into(),
+                value: Some("bar".into()),
+                created_time: Utc::now(),
+                deleted: false,
+                destroyed: false,
+                version,
+            };
+            assert!(repo.insert(&secret).await.unwrap());
+        }
+
+        assert_eq!(
+            repo.list_keys("").await.unwrap(),
+            vec!["foo", "foo/bar", "foobar"]
+        );
+        assert_eq!(repo.list_keys("foo/").await.unwrap(), vec!["foo/bar"]);
+        assert!(repo.list_keys("bar").await.unwrap().is_empty());
+    }
+
     #[sqlx::test]
     fn hard_delete() {
         let ctx = setup().await;
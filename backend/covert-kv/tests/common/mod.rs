@@ -1,6 +1,9 @@
 use covert_sdk::{
     mounts::{BackendType, CreateMountParams, MountConfig},
-    operator::{InitializeParams, InitializeResponse, UnsealParams, UnsealResponse},
+    operator::{
+        generate_otp, GenerateRootParams, GenerateRootUpdateParams, GenerateRootUpdateResponse,
+        InitializeParams, InitializeResponse, Token, UnsealParams, UnsealResponse,
+    },
     Client,
 };
 use tokio::sync::oneshot;
@@ -15,6 +18,11 @@ pub async fn setup() -> Client {
         port_tx: Some(port_tx),
         storage_path: ":memory:".into(),
         replication: None,
+        listener: covert_system::ListenerConfig::default(),
+        seal: None,
+        metrics: None,
+        standby: None,
+        ha: None,
     };
 
     tokio::spawn(async move {
@@ -36,6 +44,7 @@ pub async fn setup_unseal() -> Client {
         .initialize(&InitializeParams {
             shares: 1,
             threshold: 1,
+            pgp_keys: None,
         })
         .await
         .unwrap()
@@ -43,10 +52,18 @@ pub async fn setup_unseal() -> Client {
         InitializeResponse::NewKeyShares(shares) => shares.shares,
         _ => panic!("should get new shares"),
     };
-    let resp = sdk.operator.unseal(&UnsealParams { shares }).await.unwrap();
-    if let UnsealResponse::Complete { root_token } = resp {
-        sdk.set_token(Some(root_token.to_string())).await;
-    }
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    let root_token = generate_root_token(&sdk, shares).await;
+    sdk.set_token(Some(root_token)).await;
 
     sdk.mount
         .create(
@@ -61,3 +78,30 @@ pub async fn setup_unseal() -> Client {
 
     sdk
 }
+
+/// Generate a root token with a quorum of the key shares.
+pub async fn generate_root_token(sdk: &Client, shares: Vec<String>) -> String {
+    let otp = generate_otp();
+    let attempt = sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: Some(otp.clone()),
+            pgp_key: None,
+        })
+        .await
+        .unwrap();
+    let resp = sdk
+        .operator
+        .generate_root_update(&GenerateRootUpdateParams {
+            nonce: attempt.nonce.unwrap(),
+            shares,
+        })
+        .await
+        .unwrap();
+    let GenerateRootUpdateResponse::Complete { encoded_token } = resp else {
+        panic!("should complete generate root");
+    };
+    Token::decode_with_otp(&encoded_token, &otp)
+        .unwrap()
+        .to_string()
+}
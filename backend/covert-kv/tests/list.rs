@@ -0,0 +1,34 @@
+mod common;
+
+use std::collections::HashMap;
+
+use covert_sdk::kv::CreateSecretParams;
+
+use crate::common::{setup_unseal, MOUNT_PATH};
+
+#[tokio::test]
+async fn list_keys_under_prefix() {
+    let sdk = setup_unseal().await;
+
+    let data: HashMap<_, _> = [("foo".to_string(), "bar".to_string())]
+        .into_iter()
+        .collect();
+    for key in ["app/db", "app/api/token", "app/api/cert", "global"] {
+        sdk.kv
+            .create(MOUNT_PATH, key, &CreateSecretParams { data: data.clone() })
+            .await
+            .unwrap();
+    }
+
+    let resp = sdk.kv.list(MOUNT_PATH, "").await.unwrap();
+    assert_eq!(resp.keys, vec!["app/", "global"]);
+
+    let resp = sdk.kv.list(MOUNT_PATH, "app").await.unwrap();
+    assert_eq!(resp.keys, vec!["api/", "db"]);
+
+    let resp = sdk.kv.list(MOUNT_PATH, "app/api/").await.unwrap();
+    assert_eq!(resp.keys, vec!["cert", "token"]);
+
+    let resp = sdk.kv.list(MOUNT_PATH, "missing").await.unwrap();
+    assert!(resp.keys.is_empty());
+}
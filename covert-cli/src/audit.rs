@@ -0,0 +1,83 @@
+use clap::{Args, Subcommand};
+use covert_sdk::{
+    audit::{AuditDeviceConfig, EnableAuditDeviceParams},
+    Client,
+};
+
+use crate::handle_resp;
+
+#[derive(Args, Debug)]
+pub struct Audit {
+    #[clap(subcommand)]
+    subcommand: AuditSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditSubcommand {
+    #[command(about = "enable audit device")]
+    Enable {
+        #[command(subcommand)]
+        device: AuditDeviceSubcommand,
+    },
+    #[command(about = "disable audit device")]
+    Disable {
+        #[arg(help = "path of the audit device to disable")]
+        path: String,
+    },
+    #[command(about = "list audit devices")]
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditDeviceSubcommand {
+    #[command(about = "append audit entries to a file")]
+    File {
+        #[arg(long)]
+        file_path: String,
+        #[arg(short, long, default_value = "file")]
+        path: String,
+    },
+    #[command(about = "write audit entries to a unix socket")]
+    Socket {
+        #[arg(long)]
+        socket_path: String,
+        #[arg(short, long, default_value = "socket")]
+        path: String,
+    },
+    #[command(about = "write audit entries to stdout of the server")]
+    Stdout {
+        #[arg(short, long, default_value = "stdout")]
+        path: String,
+    },
+}
+
+impl Audit {
+    pub async fn handle(self, sdk: &Client) {
+        match self.subcommand {
+            AuditSubcommand::Enable { device } => {
+                let (path, config) = match device {
+                    AuditDeviceSubcommand::File { file_path, path } => {
+                        (path, AuditDeviceConfig::File { file_path })
+                    }
+                    AuditDeviceSubcommand::Socket { socket_path, path } => {
+                        (path, AuditDeviceConfig::Socket { socket_path })
+                    }
+                    AuditDeviceSubcommand::Stdout { path } => (path, AuditDeviceConfig::Stdout),
+                };
+                let resp = sdk
+                    .audit
+                    .enable(&path, &EnableAuditDeviceParams { config })
+                    .await;
+                handle_resp(resp);
+            }
+            AuditSubcommand::Disable { path } => {
+                let resp = sdk.audit.disable(&path).await;
+                handle_resp(resp);
+            }
+            AuditSubcommand::List => {
+                let resp = sdk.audit.list().await;
+                handle_resp(resp);
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use clap::{Args, Subcommand};
+use covert_sdk::{cubbyhole::WriteSecretParams, Client};
+use serde_json::{Map, Value};
+
+use crate::{handle_resp, kv::parse_key_val};
+
+#[derive(Args, Debug)]
+pub struct Cubbyhole {
+    #[clap(subcommand)]
+    subcommand: CubbyholeSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CubbyholeSubcommand {
+    #[command(about = "write secret to the cubbyhole of the token")]
+    Put {
+        #[arg(help = "key to write secret to")]
+        key: String,
+        #[arg(short, long, value_parser = parse_key_val::<String, String>)]
+        data: Vec<(String, String)>,
+    },
+    #[command(about = "read secret from the cubbyhole of the token")]
+    Get {
+        #[arg(help = "key to read")]
+        key: String,
+    },
+    #[command(about = "delete secret from the cubbyhole of the token")]
+    Delete {
+        #[arg(help = "key to delete")]
+        key: String,
+    },
+}
+
+impl Cubbyhole {
+    pub async fn handle(self, sdk: &Client) {
+        match self.subcommand {
+            CubbyholeSubcommand::Put { key, data } => {
+                let data = data
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::String(value)))
+                    .collect::<Map<_, _>>();
+                let resp = sdk
+                    .cubbyhole
+                    .write(
+                        &key,
+                        &WriteSecretParams {
+                            data: Value::Object(data),
+                        },
+                    )
+                    .await;
+                handle_resp(resp);
+            }
+            CubbyholeSubcommand::Get { key } => {
+                let resp = sdk.cubbyhole.read(&key).await;
+                handle_resp(resp);
+            }
+            CubbyholeSubcommand::Delete { key } => {
+                let resp = sdk.cubbyhole.delete(&key).await;
+                handle_resp(resp);
+            }
+        }
+    }
+}
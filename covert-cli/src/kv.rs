@@ -18,7 +18,7 @@ pub struct Kv {
 }
 
 /// Parse a single key-value pair
-fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
+pub(crate) fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
 where
     T: std::str::FromStr,
     T::Err: Error + Send + Sync + 'static,
@@ -51,6 +51,16 @@ pub enum KvSubcommand {
         #[arg(short, long)]
         version: Option<u32>,
     },
+    #[command(about = "list keys and folders under a prefix")]
+    List {
+        #[arg(
+            help = "prefix to list, lists the root when omitted",
+            default_value = ""
+        )]
+        prefix: String,
+        #[arg(short, long)]
+        path: String,
+    },
     #[command(about = "soft-delete secret, can be recovered with the \"recover\" subcommand")]
     Delete {
         #[arg(help = "key to delete")]
@@ -112,6 +122,10 @@ impl Kv {
                 let resp = sdk.kv.read(&path, &key, version).await;
                 handle_resp(resp);
             }
+            KvSubcommand::List { prefix, path } => {
+                let resp = sdk.kv.list(&path, &prefix).await;
+                handle_resp(resp);
+            }
             KvSubcommand::Recover {
                 key,
                 path,
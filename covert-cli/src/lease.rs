@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use clap::{Args, Subcommand};
-use covert_sdk::Client;
+use covert_sdk::{token::TokenAccessor, Client};
 
 use crate::handle_resp;
 
@@ -25,6 +25,10 @@ pub enum LeasesSubcommand {
     Lookup { lease_id: String },
     #[command(about = "revoke leases by mount path prefix")]
     RevokeMount { prefix: String },
+    #[command(about = "revoke leases requested by a token")]
+    RevokeToken { accessor: String },
+    #[command(about = "revoke leases requested by the tokens of an entity")]
+    RevokeEntity { name: String },
     #[command(about = "list leases by mount path prefix")]
     ListMount { prefix: String },
 }
@@ -53,6 +57,17 @@ impl Leases {
                 let resp = sdk.lease.revoke_by_mount(&prefix).await;
                 handle_resp(resp);
             }
+            LeasesSubcommand::RevokeToken { accessor } => {
+                let resp = sdk
+                    .lease
+                    .revoke_by_token(TokenAccessor::from(accessor))
+                    .await;
+                handle_resp(resp);
+            }
+            LeasesSubcommand::RevokeEntity { name } => {
+                let resp = sdk.lease.revoke_by_entity(&name).await;
+                handle_resp(resp);
+            }
         }
     }
 }
@@ -1,6 +1,8 @@
 //! Covert command-line interface
 
+mod audit;
 mod auth;
+mod cubbyhole;
 mod entity;
 mod kv;
 mod lease;
@@ -8,14 +10,19 @@ mod namespace;
 mod operator;
 mod policy;
 mod psql;
+mod restore;
 mod secrets;
 mod server;
 mod status;
+mod token;
 mod userpass;
+mod wrapping;
 
+use audit::Audit;
 use auth::Auth;
 use clap::{arg, command, Parser, Subcommand};
 use covert_sdk::Client;
+use cubbyhole::Cubbyhole;
 use entity::Entity;
 use kv::Kv;
 use lease::Leases;
@@ -23,11 +30,14 @@ use namespace::Namespace;
 use operator::Operator;
 use policy::Policy;
 use psql::Psql;
+use restore::Restore;
 use secrets::Secrets;
 use serde::Serialize;
 use server::Server;
 use status::handle_status;
+use token::Token;
 use userpass::Userpass;
+use wrapping::Wrapping;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -38,6 +48,13 @@ struct Cli {
     #[arg(long, env = "COVERT_TOKEN")]
     covert_token: Option<String>,
 
+    #[arg(
+        long,
+        env = "COVERT_WRAP_TTL",
+        help = "wrap the response in a single use wrapping token with the given TTL"
+    )]
+    wrap_ttl: Option<humantime::Duration>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -52,22 +69,32 @@ enum Commands {
     Entity(Entity),
     #[command(about = "manage policies")]
     Policy(Policy),
+    #[command(about = "manage audit devices")]
+    Audit(Audit),
     #[command(about = "manage auth methods")]
     Auth(Auth),
     #[command(about = "manage secret engines")]
     Secrets(Secrets),
     #[command(about = "start a Covert server")]
     Server(Server),
+    #[command(about = "restore the storage of a stopped server from its replica")]
+    Restore(Restore),
     #[command(about = "interact with a KV secrets engine")]
     Kv(Kv),
+    #[command(about = "interact with the cubbyhole of the token")]
+    Cubbyhole(Cubbyhole),
     #[command(about = "interact with a PostgreSQL secrets engine")]
     Psql(Psql),
     #[command(about = "interact with the userpass auth method")]
     Userpass(Userpass),
     #[command(about = "manage leases")]
     Lease(Leases),
+    #[command(about = "manage tokens")]
+    Token(Token),
     #[command(alias = "ns", about = "manage namespaces")]
     Namespace(Namespace),
+    #[command(about = "wrap and unwrap responses")]
+    Wrapping(Wrapping),
 }
 
 #[tokio::main]
@@ -76,20 +103,26 @@ async fn main() {
 
     let sdk = Client::new(cli.covert_addr.clone());
     sdk.set_token(cli.covert_token).await;
+    sdk.set_wrap_ttl(cli.wrap_ttl.map(Into::into)).await;
 
     match cli.command {
         Commands::Entity(entity) => entity.handle(&sdk).await,
         Commands::Policy(policy) => policy.handle(&sdk).await,
         Commands::Server(server) => server.handle().await,
+        Commands::Restore(restore) => restore.handle().await,
         Commands::Operator(operator) => operator.handle(&sdk).await,
         Commands::Status => handle_status(&sdk).await,
+        Commands::Audit(audit) => audit.handle(&sdk).await,
         Commands::Auth(auth) => auth.handle(&sdk).await,
         Commands::Secrets(secret) => secret.handle(&sdk).await,
         Commands::Kv(kv) => kv.handle(&sdk).await,
+        Commands::Cubbyhole(cubbyhole) => cubbyhole.handle(&sdk).await,
         Commands::Psql(psql) => psql.handle(&sdk).await,
         Commands::Userpass(userpass) => userpass.handle(&sdk).await,
         Commands::Lease(lease) => lease.handle(&sdk).await,
+        Commands::Token(token) => token.handle(&sdk).await,
         Commands::Namespace(ns) => ns.handle(&sdk).await,
+        Commands::Wrapping(wrapping) => wrapping.handle(&sdk).await,
     }
 }
 
@@ -1,6 +1,11 @@
+use std::path::{Path, PathBuf};
+
 use clap::{Args, Subcommand};
 use covert_sdk::{
-    operator::{InitializeParams, UnsealParams},
+    operator::{
+        generate_otp, GenerateRootParams, GenerateRootUpdateParams, InitializeParams, RekeyParams,
+        RekeyUpdateParams, RotateParams, SnapshotRestoreParams, Token, UnsealParams,
+    },
     Client,
 };
 
@@ -18,7 +23,18 @@ pub enum OperatorSubcommands {
     Unseal {
         #[arg(long, use_value_delimiter = true, value_delimiter = ',')]
         unseal_keys: Vec<String>,
+        #[arg(long, help = "nonce of the unseal attempt the unseal keys are for")]
+        nonce: Option<String>,
+        #[arg(
+            long,
+            help = "discard the unseal keys provided so far and start a new unseal attempt"
+        )]
+        reset: bool,
     },
+    #[command(about = "show the seal status and the progress of the unseal attempt")]
+    SealStatus,
+    #[command(about = "show the active server of a high availability cluster")]
+    Leader,
     #[command(about = "seal the Covert server")]
     Seal,
     #[command(about = "initialize the Covert server")]
@@ -27,32 +43,372 @@ pub enum OperatorSubcommands {
         shares: u8,
         #[arg(long)]
         threshold: u8,
+        #[arg(
+            long,
+            use_value_delimiter = true,
+            value_delimiter = ',',
+            help = "files with the ASCII armored PGP public keys to encrypt the unseal keys for, one for each share"
+        )]
+        pgp_keys: Vec<PathBuf>,
+    },
+    #[command(about = "generate a new root token with a quorum of the unseal keys")]
+    GenerateRoot(GenerateRoot),
+    #[command(about = "replace the unseal keys with a new set of unseal keys")]
+    Rekey(Rekey),
+    #[command(about = "rotate the master key, the unseal keys are replaced with new unseal keys")]
+    Rotate {
+        #[arg(long, use_value_delimiter = true, value_delimiter = ',')]
+        unseal_keys: Vec<String>,
+    },
+    #[command(about = "show the generation of the master key")]
+    KeyStatus,
+    #[command(about = "save and restore snapshots of the storage")]
+    Snapshot(Snapshot),
+}
+
+#[derive(Args, Debug)]
+pub struct GenerateRoot {
+    #[clap(subcommand)]
+    subcommand: GenerateRootSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum GenerateRootSubcommand {
+    #[command(
+        about = "start a generate root attempt, a one-time pad is generated unless a PGP key is given"
+    )]
+    Init {
+        #[arg(
+            long,
+            help = "file with the ASCII armored PGP public key to encrypt the token for"
+        )]
+        pgp_key: Option<PathBuf>,
+    },
+    #[command(about = "show the progress of the generate root attempt")]
+    Status,
+    #[command(about = "cancel the generate root attempt")]
+    Cancel,
+    #[command(about = "provide unseal keys for the generate root attempt")]
+    Update {
+        #[arg(long)]
+        nonce: String,
+        #[arg(long, use_value_delimiter = true, value_delimiter = ',')]
+        unseal_keys: Vec<String>,
+    },
+    #[command(about = "decode a root token encoded with a one-time pad")]
+    Decode {
+        #[arg(long)]
+        encoded_token: String,
+        #[arg(long)]
+        otp: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Rekey {
+    #[clap(subcommand)]
+    subcommand: RekeySubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RekeySubcommand {
+    #[command(about = "start a rekey attempt")]
+    Init {
+        #[arg(long)]
+        shares: u8,
+        #[arg(long)]
+        threshold: u8,
+        #[arg(
+            long,
+            help = "require a threshold of the new unseal keys before the old unseal keys stop working"
+        )]
+        require_verification: bool,
+    },
+    #[command(about = "show the progress of the rekey attempt")]
+    Status,
+    #[command(about = "cancel the rekey attempt")]
+    Cancel,
+    #[command(about = "provide current unseal keys for the rekey attempt")]
+    Update {
+        #[arg(long)]
+        nonce: String,
+        #[arg(long, use_value_delimiter = true, value_delimiter = ',')]
+        unseal_keys: Vec<String>,
+    },
+    #[command(about = "provide new unseal keys to verify the rekey attempt")]
+    Verify {
+        #[arg(long)]
+        nonce: String,
+        #[arg(long, use_value_delimiter = true, value_delimiter = ',')]
+        unseal_keys: Vec<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Snapshot {
+    #[clap(subcommand)]
+    subcommand: SnapshotSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotSubcommand {
+    #[command(
+        about = "save a snapshot of the storage, it is unsealed with the current unseal keys"
+    )]
+    Save {
+        #[arg(help = "file to write the snapshot to")]
+        file: PathBuf,
+        #[arg(
+            long,
+            help = "file to write the seal metadata of the snapshot to, defaults to the snapshot file with a `.seal.json` extension"
+        )]
+        seal_file: Option<PathBuf>,
+    },
+    #[command(about = "replace the storage of a sealed Covert server with a snapshot")]
+    Restore {
+        #[arg(help = "file with the snapshot to restore")]
+        file: PathBuf,
+        #[arg(
+            long,
+            help = "file with the seal metadata of the snapshot, defaults to the snapshot file with a `.seal.json` extension"
+        )]
+        seal_file: Option<PathBuf>,
+        #[arg(
+            long,
+            use_value_delimiter = true,
+            value_delimiter = ',',
+            help = "current unseal keys, not required if the server is uninitialized"
+        )]
+        unseal_keys: Vec<String>,
     },
 }
 
 impl Operator {
     pub async fn handle(self, sdk: &Client) {
         match self.subcommand {
-            OperatorSubcommands::Init { shares, threshold } => {
+            OperatorSubcommands::Init {
+                shares,
+                threshold,
+                pgp_keys,
+            } => {
+                let pgp_keys = if pgp_keys.is_empty() {
+                    None
+                } else {
+                    Some(
+                        pgp_keys
+                            .into_iter()
+                            .map(|path| {
+                                std::fs::read_to_string(path)
+                                    .expect("failed to read PGP public key")
+                            })
+                            .collect(),
+                    )
+                };
                 let resp = sdk
                     .operator
-                    .initialize(&InitializeParams { shares, threshold })
+                    .initialize(&InitializeParams {
+                        shares,
+                        threshold,
+                        pgp_keys,
+                    })
                     .await;
                 handle_resp(resp);
             }
-            OperatorSubcommands::Unseal { unseal_keys } => {
+            OperatorSubcommands::Unseal {
+                unseal_keys,
+                nonce,
+                reset,
+            } => {
                 let resp = sdk
                     .operator
                     .unseal(&UnsealParams {
                         shares: unseal_keys,
+                        nonce,
+                        reset,
                     })
                     .await;
                 handle_resp(resp);
             }
+            OperatorSubcommands::SealStatus => {
+                let resp = sdk.status.seal_status().await;
+                handle_resp(resp);
+            }
+            OperatorSubcommands::Leader => {
+                let resp = sdk.status.leader().await;
+                handle_resp(resp);
+            }
             OperatorSubcommands::Seal => {
                 let resp = sdk.operator.seal().await;
                 handle_resp(resp);
             }
+            OperatorSubcommands::GenerateRoot(generate_root) => generate_root.handle(sdk).await,
+            OperatorSubcommands::Rekey(rekey) => rekey.handle(sdk).await,
+            OperatorSubcommands::Rotate { unseal_keys } => {
+                let resp = sdk
+                    .operator
+                    .rotate(&RotateParams {
+                        shares: unseal_keys,
+                    })
+                    .await;
+                handle_resp(resp);
+            }
+            OperatorSubcommands::KeyStatus => {
+                let resp = sdk.operator.key_status().await;
+                handle_resp(resp);
+            }
+            OperatorSubcommands::Snapshot(snapshot) => snapshot.handle(sdk).await,
+        }
+    }
+}
+
+impl GenerateRoot {
+    pub async fn handle(self, sdk: &Client) {
+        match self.subcommand {
+            GenerateRootSubcommand::Init { pgp_key } => {
+                let params = if let Some(path) = pgp_key {
+                    let pgp_key =
+                        std::fs::read_to_string(path).expect("failed to read PGP public key");
+                    GenerateRootParams {
+                        otp: None,
+                        pgp_key: Some(pgp_key),
+                    }
+                } else {
+                    let otp = generate_otp();
+                    println!("One-time pad: {otp}");
+                    GenerateRootParams {
+                        otp: Some(otp),
+                        pgp_key: None,
+                    }
+                };
+                let resp = sdk.operator.generate_root_init(&params).await;
+                handle_resp(resp);
+            }
+            GenerateRootSubcommand::Status => {
+                let resp = sdk.operator.generate_root_status().await;
+                handle_resp(resp);
+            }
+            GenerateRootSubcommand::Cancel => {
+                let resp = sdk.operator.generate_root_cancel().await;
+                handle_resp(resp);
+            }
+            GenerateRootSubcommand::Update { nonce, unseal_keys } => {
+                let resp = sdk
+                    .operator
+                    .generate_root_update(&GenerateRootUpdateParams {
+                        nonce,
+                        shares: unseal_keys,
+                    })
+                    .await;
+                handle_resp(resp);
+            }
+            GenerateRootSubcommand::Decode { encoded_token, otp } => {
+                match Token::decode_with_otp(&encoded_token, &otp) {
+                    Some(token) => println!("{}", token.to_string()),
+                    None => println!("Error: unable to decode the root token"),
+                }
+            }
+        }
+    }
+}
+
+impl Rekey {
+    pub async fn handle(self, sdk: &Client) {
+        match self.subcommand {
+            RekeySubcommand::Init {
+                shares,
+                threshold,
+                require_verification,
+            } => {
+                let resp = sdk
+                    .operator
+                    .rekey_init(&RekeyParams {
+                        shares,
+                        threshold,
+                        require_verification,
+                    })
+                    .await;
+                handle_resp(resp);
+            }
+            RekeySubcommand::Status => {
+                let resp = sdk.operator.rekey_status().await;
+                handle_resp(resp);
+            }
+            RekeySubcommand::Cancel => {
+                let resp = sdk.operator.rekey_cancel().await;
+                handle_resp(resp);
+            }
+            RekeySubcommand::Update { nonce, unseal_keys } => {
+                let resp = sdk
+                    .operator
+                    .rekey_update(&RekeyUpdateParams {
+                        nonce,
+                        shares: unseal_keys,
+                    })
+                    .await;
+                handle_resp(resp);
+            }
+            RekeySubcommand::Verify { nonce, unseal_keys } => {
+                let resp = sdk
+                    .operator
+                    .rekey_verify(&RekeyUpdateParams {
+                        nonce,
+                        shares: unseal_keys,
+                    })
+                    .await;
+                handle_resp(resp);
+            }
+        }
+    }
+}
+
+/// The seal metadata of a snapshot is kept next to the snapshot by default.
+fn seal_file(file: &Path, seal_file: Option<PathBuf>) -> PathBuf {
+    seal_file.unwrap_or_else(|| {
+        let mut seal_file = file.as_os_str().to_owned();
+        seal_file.push(".seal.json");
+        seal_file.into()
+    })
+}
+
+impl Snapshot {
+    pub async fn handle(self, sdk: &Client) {
+        match self.subcommand {
+            SnapshotSubcommand::Save {
+                file,
+                seal_file: seal_path,
+            } => match sdk.operator.snapshot(&file).await {
+                Ok(seal) => {
+                    let seal_path = seal_file(&file, seal_path);
+                    let seal = serde_json::to_vec(&seal).unwrap();
+                    std::fs::write(&seal_path, seal).expect("failed to write seal metadata");
+                    println!(
+                        "Saved snapshot to `{}` and its seal metadata to `{}`",
+                        file.display(),
+                        seal_path.display()
+                    );
+                }
+                Err(e) => println!("Error: {e}"),
+            },
+            SnapshotSubcommand::Restore {
+                file,
+                seal_file: seal_path,
+                unseal_keys,
+            } => {
+                let seal = std::fs::read(seal_file(&file, seal_path))
+                    .expect("failed to read seal metadata");
+                let seal = serde_json::from_slice(&seal).expect("failed to parse seal metadata");
+                let resp = sdk
+                    .operator
+                    .snapshot_restore(
+                        &SnapshotRestoreParams {
+                            shares: unseal_keys,
+                            seal,
+                        },
+                        &file,
+                    )
+                    .await;
+                handle_resp(resp);
+            }
         }
     }
 }
@@ -0,0 +1,25 @@
+use clap::Args;
+use covert_system::Config;
+
+#[derive(Args, Debug)]
+pub struct Restore {
+    #[arg(short, long, help = "config of the server to restore the storage of")]
+    config: String,
+    #[arg(
+        long,
+        help = "restore the storage as it was at this RFC 3339 timestamp instead of the latest state"
+    )]
+    timestamp: Option<humantime::Timestamp>,
+}
+
+impl Restore {
+    pub async fn handle(self) {
+        let config_file = std::fs::read_to_string(&self.config).expect("failed to read config");
+        let config: Config = toml::from_str(&config_file).expect("failed to parse config file");
+
+        match covert_system::restore_storage(&config, self.timestamp.map(Into::into)).await {
+            Ok(()) => println!("Restored the storage to `{}`", config.storage_path),
+            Err(e) => println!("Error: {e}"),
+        }
+    }
+}
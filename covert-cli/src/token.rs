@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+use covert_sdk::{
+    token::{CreateTokenParams, RenewTokenSelfParams, TokenAccessor},
+    Client,
+};
+
+use crate::handle_resp;
+
+#[derive(Args, Debug)]
+pub struct Token {
+    #[clap(subcommand)]
+    subcommand: TokenSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TokenSubcommand {
+    #[command(about = "create a token with a subset of the policies of the current token")]
+    Create {
+        #[arg(long = "policy", help = "policy of the new token, can be repeated")]
+        policies: Vec<String>,
+        #[arg(long)]
+        ttl: Option<humantime::Duration>,
+        #[arg(long, help = "the token can not be renewed beyond this TTL")]
+        explicit_max_ttl: Option<humantime::Duration>,
+        #[arg(long, default_value_t = 0, help = "number of uses, unlimited if zero")]
+        num_uses: u32,
+        #[arg(long, help = "create the token without a parent")]
+        orphan: bool,
+    },
+    #[command(about = "revoke all root tokens")]
+    RevokeRoot,
+    #[command(about = "lookup the current token")]
+    LookupSelf,
+    #[command(about = "renew the current token")]
+    RenewSelf {
+        #[arg(long)]
+        ttl: Option<humantime::Duration>,
+    },
+    #[command(about = "revoke the current token")]
+    RevokeSelf,
+    #[command(about = "lookup the capabilities of the current token on a list of paths")]
+    Capabilities {
+        #[arg(help = "paths to lookup")]
+        paths: Vec<String>,
+    },
+    #[command(about = "lookup a token by its accessor")]
+    LookupAccessor {
+        #[arg(help = "token accessor")]
+        accessor: String,
+    },
+    #[command(about = "revoke a token by its accessor")]
+    RevokeAccessor {
+        #[arg(help = "token accessor")]
+        accessor: String,
+    },
+}
+
+impl Token {
+    pub async fn handle(self, sdk: &Client) {
+        match self.subcommand {
+            TokenSubcommand::Create {
+                policies,
+                ttl,
+                explicit_max_ttl,
+                num_uses,
+                orphan,
+            } => {
+                let params = CreateTokenParams {
+                    policies: (!policies.is_empty()).then_some(policies),
+                    ttl: ttl.map(|ttl| Duration::from_millis(ttl.as_millis() as u64)),
+                    explicit_max_ttl: explicit_max_ttl
+                        .map(|ttl| Duration::from_millis(ttl.as_millis() as u64)),
+                    num_uses,
+                };
+                let resp = if orphan {
+                    sdk.token.create_orphan(&params).await
+                } else {
+                    sdk.token.create(&params).await
+                };
+                handle_resp(resp);
+            }
+            TokenSubcommand::RevokeRoot => {
+                let resp = sdk.token.revoke_root().await;
+                handle_resp(resp);
+            }
+            TokenSubcommand::LookupSelf => {
+                let resp = sdk.token.lookup_self().await;
+                handle_resp(resp);
+            }
+            TokenSubcommand::RenewSelf { ttl } => {
+                let params = RenewTokenSelfParams {
+                    ttl: ttl.map(|ttl| Duration::from_millis(ttl.as_millis() as u64)),
+                };
+                let resp = sdk.token.renew_self(&params).await;
+                handle_resp(resp);
+            }
+            TokenSubcommand::RevokeSelf => {
+                let resp = sdk.token.revoke_self().await;
+                handle_resp(resp);
+            }
+            TokenSubcommand::Capabilities { paths } => {
+                let resp = sdk.token.capabilities_self(paths).await;
+                handle_resp(resp);
+            }
+            TokenSubcommand::LookupAccessor { accessor } => {
+                let resp = sdk
+                    .token
+                    .lookup_accessor(TokenAccessor::from(accessor))
+                    .await;
+                handle_resp(resp);
+            }
+            TokenSubcommand::RevokeAccessor { accessor } => {
+                let resp = sdk
+                    .token
+                    .revoke_accessor(TokenAccessor::from(accessor))
+                    .await;
+                handle_resp(resp);
+            }
+        }
+    }
+}
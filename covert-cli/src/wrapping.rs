@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+use clap::{Args, Subcommand};
+use covert_sdk::{wrapping::Token, Client};
+use serde_json::Value;
+
+use crate::handle_resp;
+
+#[derive(Args, Debug)]
+pub struct Wrapping {
+    #[clap(subcommand)]
+    subcommand: WrappingSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WrappingSubcommand {
+    #[command(about = "wrap JSON data in a single use wrapping token")]
+    Wrap {
+        #[arg(help = "JSON data to wrap")]
+        data: String,
+    },
+    #[command(about = "retrieve the response wrapped by a wrapping token")]
+    Unwrap {
+        #[arg(help = "wrapping token")]
+        token: String,
+    },
+    #[command(about = "lookup information about a wrapping token")]
+    Lookup {
+        #[arg(help = "wrapping token")]
+        token: String,
+    },
+    #[command(about = "move a wrapped response to a new wrapping token")]
+    Rewrap {
+        #[arg(help = "wrapping token")]
+        token: String,
+    },
+}
+
+impl Wrapping {
+    pub async fn handle(self, sdk: &Client) {
+        match self.subcommand {
+            WrappingSubcommand::Wrap { data } => {
+                let resp = match serde_json::from_str::<Value>(&data) {
+                    Ok(data) => sdk.wrapping.wrap(&data).await,
+                    Err(_) => Err("Data to wrap must be valid JSON".to_string()),
+                };
+                handle_resp(resp);
+            }
+            WrappingSubcommand::Unwrap { token } => {
+                let resp = match parse_token(&token) {
+                    Ok(token) => sdk.wrapping.unwrap::<Value>(token).await,
+                    Err(err) => Err(err),
+                };
+                handle_resp(resp);
+            }
+            WrappingSubcommand::Lookup { token } => {
+                let resp = match parse_token(&token) {
+                    Ok(token) => sdk.wrapping.lookup(token).await,
+                    Err(err) => Err(err),
+                };
+                handle_resp(resp);
+            }
+            WrappingSubcommand::Rewrap { token } => {
+                let resp = match parse_token(&token) {
+                    Ok(token) => sdk.wrapping.rewrap(token).await,
+                    Err(err) => Err(err),
+                };
+                handle_resp(resp);
+            }
+        }
+    }
+}
+
+fn parse_token(token: &str) -> Result<Token, String> {
+    Token::from_str(token).map_err(|_| format!("`{token}` is not a valid wrapping token"))
+}
@@ -21,6 +21,9 @@ pub struct Route {
 pub struct RouteConfig {
     pub policy: AuthPolicy,
     pub state: Vec<StorageState>,
+    /// Require the `sudo` capability on the path in addition to the
+    /// capability for the operation.
+    pub root_protected: bool,
 }
 
 impl RouteConfig {
@@ -31,6 +34,14 @@ impl RouteConfig {
             ..Default::default()
         }
     }
+
+    #[must_use]
+    pub fn root_protected() -> Self {
+        Self {
+            root_protected: true,
+            ..Default::default()
+        }
+    }
 }
 
 impl Default for RouteConfig {
@@ -38,6 +49,7 @@ impl Default for RouteConfig {
         Self {
             policy: AuthPolicy::Authenticated,
             state: vec![StorageState::Unsealed],
+            root_protected: false,
         }
     }
 }
@@ -75,8 +87,12 @@ impl Service<Request> for Route {
             return Box::pin(async { Err(ApiError::unauthorized()) });
         };
         let auth = match self.config.policy {
-            AuthPolicy::Authenticated => *policy == AuthPolicy::Authenticated,
+            _ if self.config.root_protected => *policy == AuthPolicy::Sudo,
+            AuthPolicy::Authenticated => {
+                matches!(policy, AuthPolicy::Authenticated | AuthPolicy::Sudo)
+            }
             AuthPolicy::Unauthenticated => true,
+            AuthPolicy::Sudo => *policy == AuthPolicy::Sudo,
         };
         if !auth {
             return Box::pin(async { Err(ApiError::unauthorized()) });
@@ -149,6 +165,7 @@ top_level_handlers!(Create, create, create_with_config);
 top_level_handlers!(Read, read, read_with_config);
 top_level_handlers!(Update, update, update_with_config);
 top_level_handlers!(Delete, delete, delete_with_config);
+top_level_handlers!(List, list, list_with_config);
 top_level_handlers!(Revoke, revoke, revoke_with_config);
 top_level_handlers!(Renew, renew, renew_with_config);
 
@@ -164,6 +181,7 @@ impl MethodRouter {
     chained_handlers!(Read, read, read_with_config);
     chained_handlers!(Update, update, update_with_config);
     chained_handlers!(Delete, delete, delete_with_config);
+    chained_handlers!(List, list, list_with_config);
     chained_handlers!(Revoke, revoke, revoke_with_config);
     chained_handlers!(Renew, renew, renew_with_config);
 
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+pub use covert_types::methods::system::{
+    AuditDevice, AuditDeviceConfig, DisableAuditDeviceResponse, EnableAuditDeviceParams,
+    EnableAuditDeviceResponse, ListAuditDevicesResponse,
+};
+
+use crate::base::BaseClient;
+
+pub struct Client {
+    client: Arc<BaseClient>,
+}
+
+impl Client {
+    pub(crate) fn new(client: Arc<BaseClient>) -> Self {
+        Self { client }
+    }
+
+    pub async fn enable(
+        &self,
+        path: &str,
+        params: &EnableAuditDeviceParams,
+    ) -> Result<EnableAuditDeviceResponse, String> {
+        self.client.post(format!("/sys/audit/{path}"), params).await
+    }
+
+    pub async fn list(&self) -> Result<ListAuditDevicesResponse, String> {
+        self.client.get("/sys/audit".into()).await
+    }
+
+    pub async fn disable(&self, path: &str) -> Result<DisableAuditDeviceResponse, String> {
+        self.client.delete(format!("/sys/audit/{path}")).await
+    }
+}
@@ -1,6 +1,8 @@
-use reqwest::RequestBuilder;
+use std::{path::Path, time::Duration};
+
+use reqwest::{header::HeaderMap, Method, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
+use tokio::{fs::File, io::AsyncWriteExt, sync::RwLock};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Response<T> {
@@ -14,6 +16,7 @@ pub(crate) struct BaseClient {
     api_url: String,
     token: RwLock<Option<String>>,
     namespace: RwLock<Option<String>>,
+    wrap_ttl: RwLock<Option<Duration>>,
 }
 
 impl BaseClient {
@@ -24,6 +27,7 @@ impl BaseClient {
             api_url: api_url.to_string(),
             token: RwLock::new(None),
             namespace: RwLock::new(namespace),
+            wrap_ttl: RwLock::new(None),
         }
     }
 
@@ -37,10 +41,12 @@ impl BaseClient {
         *ns_l = namespace;
     }
 
-    pub async fn send<T: for<'de> serde::de::Deserialize<'de>>(
-        &self,
-        mut rb: RequestBuilder,
-    ) -> Result<T, String> {
+    pub async fn set_wrap_ttl(&self, ttl: Option<Duration>) {
+        let mut ttl_l = self.wrap_ttl.write().await;
+        *ttl_l = ttl;
+    }
+
+    async fn with_headers(&self, mut rb: RequestBuilder) -> RequestBuilder {
         let token_l = self.token.read().await;
         if let Some(token) = token_l.as_ref() {
             rb = rb.header("X-Covert-Token", token);
@@ -53,7 +59,22 @@ impl BaseClient {
         }
         drop(ns_l);
 
-        rb.send()
+        let ttl_l = self.wrap_ttl.read().await;
+        if let Some(ttl) = ttl_l.as_ref() {
+            rb = rb.header("X-Covert-Wrap-TTL", ttl.as_secs());
+        }
+        drop(ttl_l);
+
+        rb
+    }
+
+    pub async fn send<T: for<'de> serde::de::Deserialize<'de>>(
+        &self,
+        rb: RequestBuilder,
+    ) -> Result<T, String> {
+        self.with_headers(rb)
+            .await
+            .send()
             .await
             .map_err(|e| format!("{e:#?}"))?
             .json::<Response<T>>()
@@ -79,6 +100,16 @@ impl BaseClient {
         self.send(request_builder).await
     }
 
+    pub async fn list<T: for<'de> serde::de::Deserialize<'de>>(
+        &self,
+        path: String,
+    ) -> Result<T, String> {
+        let client = reqwest::Client::new();
+        let method = Method::from_bytes(b"LIST").map_err(|e| format!("{e:#?}"))?;
+        let request_builder = client.request(method, format!("{}{}", self.api_url, path));
+        self.send(request_builder).await
+    }
+
     pub async fn delete<T: for<'de> serde::de::Deserialize<'de>>(
         &self,
         path: String,
@@ -107,4 +138,52 @@ impl BaseClient {
         let request_builder = client.post(format!("{}{}", self.api_url, path)).json(body);
         self.send(request_builder).await
     }
+
+    /// Write the body of the response to a file instead of parsing it. The
+    /// headers of the response are returned.
+    pub async fn get_file(&self, path: String, file: &Path) -> Result<HeaderMap, String> {
+        let client = reqwest::Client::new();
+        let request_builder = client.get(format!("{}{}", self.api_url, path));
+        let mut resp = self
+            .with_headers(request_builder)
+            .await
+            .send()
+            .await
+            .map_err(|e| format!("{e:#?}"))?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(resp
+                .json::<Response<()>>()
+                .await
+                .ok()
+                .and_then(|res| res.error)
+                .unwrap_or_else(|| format!("Unexpected status code {status}")));
+        }
+
+        let headers = resp.headers().clone();
+        let mut file = File::create(file).await.map_err(|e| format!("{e:#?}"))?;
+        while let Some(chunk) = resp.chunk().await.map_err(|e| format!("{e:#?}"))? {
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| format!("{e:#?}"))?;
+        }
+        file.sync_all().await.map_err(|e| format!("{e:#?}"))?;
+        Ok(headers)
+    }
+
+    /// Stream a file as the body of the request.
+    pub async fn post_file<U: for<'de> serde::de::Deserialize<'de>>(
+        &self,
+        path: String,
+        headers: HeaderMap,
+        file: &Path,
+    ) -> Result<U, String> {
+        let file = File::open(file).await.map_err(|e| format!("{e:#?}"))?;
+        let client = reqwest::Client::new();
+        let request_builder = client
+            .post(format!("{}{}", self.api_url, path))
+            .headers(headers)
+            .body(file);
+        self.send(request_builder).await
+    }
 }
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+pub use covert_types::methods::cubbyhole::{
+    DeleteSecretResponse, ReadSecretResponse, WriteSecretParams, WriteSecretResponse,
+};
+
+use crate::base::BaseClient;
+
+pub struct Client {
+    client: Arc<BaseClient>,
+}
+
+impl Client {
+    pub(crate) fn new(client: Arc<BaseClient>) -> Self {
+        Self { client }
+    }
+
+    pub async fn write(
+        &self,
+        key: &str,
+        params: &WriteSecretParams,
+    ) -> Result<WriteSecretResponse, String> {
+        self.client.post(format!("/cubbyhole/{key}"), params).await
+    }
+
+    pub async fn read(&self, key: &str) -> Result<ReadSecretResponse, String> {
+        self.client.get(format!("/cubbyhole/{key}")).await
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<DeleteSecretResponse, String> {
+        self.client.delete(format!("/cubbyhole/{key}")).await
+    }
+}
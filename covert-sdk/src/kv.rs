@@ -2,9 +2,9 @@ use std::sync::Arc;
 
 use covert_types::methods::kv::CreateSecretResponse;
 pub use covert_types::methods::kv::{
-    CreateSecretParams, HardDeleteSecretParams, HardDeleteSecretResponse, ReadConfigResponse,
-    ReadSecretResponse, RecoverSecretParams, RecoverSecretResponse, SetConfigParams,
-    SetConfigResponse, SoftDeleteSecretParams, SoftDeleteSecretResponse,
+    CreateSecretParams, HardDeleteSecretParams, HardDeleteSecretResponse, ListSecretsResponse,
+    ReadConfigResponse, ReadSecretResponse, RecoverSecretParams, RecoverSecretResponse,
+    SetConfigParams, SetConfigResponse, SoftDeleteSecretParams, SoftDeleteSecretResponse,
 };
 
 use crate::{base::BaseClient, utils::get_mount_path};
@@ -41,6 +41,16 @@ impl Client {
         self.config.get(path).await
     }
 
+    /// List the keys and folders directly under a prefix.
+    pub async fn list(&self, mount: &str, prefix: &str) -> Result<ListSecretsResponse, String> {
+        let path = if prefix.is_empty() {
+            get_mount_path(mount, "metadata")
+        } else {
+            get_mount_path(mount, &format!("metadata/{prefix}"))
+        };
+        self.config.list(path).await
+    }
+
     pub async fn set_config(
         &self,
         mount: &str,
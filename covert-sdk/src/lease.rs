@@ -1,10 +1,11 @@
 use std::{sync::Arc, time::Duration};
 
-use covert_types::methods::system::RenewLeaseParams;
 pub use covert_types::methods::system::{
     ListLeasesResponse, LookupLeaseResponse, RenewLeaseResponse, RevokedLeaseResponse,
     RevokedLeasesResponse,
 };
+use covert_types::methods::system::{RenewLeaseParams, TokenAccessorParams};
+use covert_types::token::TokenAccessor;
 
 use crate::base::BaseClient;
 
@@ -48,6 +49,26 @@ impl Client {
             .await
     }
 
+    /// Revoke all leases requested by a token.
+    pub async fn revoke_by_token(
+        &self,
+        accessor: TokenAccessor,
+    ) -> Result<RevokedLeasesResponse, String> {
+        self.client
+            .put(
+                "/sys/leases/revoke-token".into(),
+                &TokenAccessorParams { accessor },
+            )
+            .await
+    }
+
+    /// Revoke all leases requested by the tokens of an entity.
+    pub async fn revoke_by_entity(&self, name: &str) -> Result<RevokedLeasesResponse, String> {
+        self.client
+            .put(format!("/sys/leases/revoke-entity/{name}"), &())
+            .await
+    }
+
     pub async fn list_by_mount(&self, prefix: &str) -> Result<ListLeasesResponse, String> {
         self.client
             .get(format!("/sys/leases/lookup-mount/{prefix}"))
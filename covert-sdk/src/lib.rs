@@ -1,8 +1,10 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use base::BaseClient;
 
+pub mod audit;
 pub(crate) mod base;
+pub mod cubbyhole;
 pub mod entity;
 pub mod kv;
 pub mod lease;
@@ -12,20 +14,26 @@ pub mod operator;
 pub mod policy;
 pub mod psql;
 pub mod status;
+pub mod token;
 pub mod userpass;
 pub(crate) mod utils;
+pub mod wrapping;
 
 pub struct Client {
+    pub audit: crate::audit::Client,
+    pub cubbyhole: crate::cubbyhole::Client,
     pub entity: crate::entity::Client,
     pub policy: crate::policy::Client,
     pub operator: crate::operator::Client,
     pub status: crate::status::Client,
+    pub token: crate::token::Client,
     pub mount: crate::mounts::Client,
     pub kv: crate::kv::Client,
     pub psql: crate::psql::Client,
     pub userpass: crate::userpass::Client,
     pub lease: crate::lease::Client,
     pub namespace: crate::namespace::Client,
+    pub wrapping: crate::wrapping::Client,
     base: Arc<BaseClient>,
 }
 
@@ -33,28 +41,36 @@ impl Client {
     pub fn new(api_url: impl ToString) -> Self {
         let base_client = Arc::new(BaseClient::new(api_url));
 
+        let audit = crate::audit::Client::new(Arc::clone(&base_client));
+        let cubbyhole = crate::cubbyhole::Client::new(Arc::clone(&base_client));
         let entity = crate::entity::Client::new(Arc::clone(&base_client));
         let policy = crate::policy::Client::new(Arc::clone(&base_client));
         let operator = crate::operator::Client::new(Arc::clone(&base_client));
         let status = crate::status::Client::new(Arc::clone(&base_client));
+        let token = crate::token::Client::new(Arc::clone(&base_client));
         let mounts = crate::mounts::Client::new(Arc::clone(&base_client));
         let kv = crate::kv::Client::new(Arc::clone(&base_client));
         let psql = crate::psql::Client::new(Arc::clone(&base_client));
         let userpass = crate::userpass::Client::new(Arc::clone(&base_client));
         let lease = crate::lease::Client::new(Arc::clone(&base_client));
         let namespace = crate::namespace::Client::new(Arc::clone(&base_client));
+        let wrapping = crate::wrapping::Client::new(Arc::clone(&base_client));
 
         Self {
+            audit,
+            cubbyhole,
             entity,
             policy,
             operator,
             status,
+            token,
             mount: mounts,
             kv,
             psql,
             userpass,
             lease,
             namespace,
+            wrapping,
             base: base_client,
         }
     }
@@ -66,4 +82,9 @@ impl Client {
     pub async fn set_namespace(&self, namespace: Option<String>) {
         self.base.set_namespace(namespace).await
     }
+
+    /// Request that all subsequent responses are wrapped with the given TTL.
+    pub async fn set_wrap_ttl(&self, ttl: Option<Duration>) {
+        self.base.set_wrap_ttl(ttl).await
+    }
 }
@@ -1,8 +1,15 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 pub use covert_types::methods::system::{
-    InitializeParams, InitializeResponse, SealResponse, UnsealParams, UnsealResponse,
+    GenerateRootParams, GenerateRootStatusResponse, GenerateRootUpdateParams,
+    GenerateRootUpdateResponse, InitializeParams, InitializeResponse, KeyStatusResponse,
+    RekeyParams, RekeyStatusResponse, RekeyUpdateParams, RekeyUpdateResponse, RekeyVerifyResponse,
+    RotateParams, RotateResponse, SealResponse, SealSnapshot, SnapshotRestoreParams,
+    SnapshotRestoreResponse, UnsealParams, UnsealResponse,
 };
+use covert_types::methods::system::{SNAPSHOT_SEAL_HEADER, SNAPSHOT_SHARES_HEADER};
+pub use covert_types::token::{generate_otp, Token};
+use reqwest::header::HeaderMap;
 
 use crate::base::BaseClient;
 
@@ -29,4 +36,116 @@ impl Client {
     pub async fn seal(&self) -> Result<SealResponse, String> {
         self.client.post("/sys/seal".into(), &()).await
     }
+
+    /// Start an attempt to generate a new root token.
+    pub async fn generate_root_init(
+        &self,
+        params: &GenerateRootParams,
+    ) -> Result<GenerateRootStatusResponse, String> {
+        self.client
+            .post("/sys/generate-root/attempt".into(), params)
+            .await
+    }
+
+    pub async fn generate_root_status(&self) -> Result<GenerateRootStatusResponse, String> {
+        self.client.get("/sys/generate-root/attempt".into()).await
+    }
+
+    pub async fn generate_root_cancel(&self) -> Result<GenerateRootStatusResponse, String> {
+        self.client
+            .delete("/sys/generate-root/attempt".into())
+            .await
+    }
+
+    /// Provide key shares for the generate root attempt. The encoded root
+    /// token is returned once the threshold is reached.
+    pub async fn generate_root_update(
+        &self,
+        params: &GenerateRootUpdateParams,
+    ) -> Result<GenerateRootUpdateResponse, String> {
+        self.client
+            .post("/sys/generate-root/update".into(), params)
+            .await
+    }
+
+    /// Start an attempt to replace the key shares with a new set of shares.
+    pub async fn rekey_init(&self, params: &RekeyParams) -> Result<RekeyStatusResponse, String> {
+        self.client.post("/sys/rekey/init".into(), params).await
+    }
+
+    pub async fn rekey_status(&self) -> Result<RekeyStatusResponse, String> {
+        self.client.get("/sys/rekey/init".into()).await
+    }
+
+    pub async fn rekey_cancel(&self) -> Result<RekeyStatusResponse, String> {
+        self.client.delete("/sys/rekey/init".into()).await
+    }
+
+    /// Provide current key shares for the rekey attempt. The new key shares
+    /// are returned once the threshold is reached.
+    pub async fn rekey_update(
+        &self,
+        params: &RekeyUpdateParams,
+    ) -> Result<RekeyUpdateResponse, String> {
+        self.client.post("/sys/rekey/update".into(), params).await
+    }
+
+    /// Provide new key shares to verify a rekey attempt that requires
+    /// verification.
+    pub async fn rekey_verify(
+        &self,
+        params: &RekeyUpdateParams,
+    ) -> Result<RekeyVerifyResponse, String> {
+        self.client.post("/sys/rekey/verify".into(), params).await
+    }
+
+    /// Rotate the master key. The new key shares are returned.
+    pub async fn rotate(&self, params: &RotateParams) -> Result<RotateResponse, String> {
+        self.client.post("/sys/rotate".into(), params).await
+    }
+
+    pub async fn key_status(&self) -> Result<KeyStatusResponse, String> {
+        self.client.get("/sys/key-status".into()).await
+    }
+
+    /// Take a snapshot of the storage and write it to a file. It stays
+    /// encrypted with the master key, the seal metadata needed to unseal it is
+    /// returned.
+    pub async fn snapshot(&self, file: &Path) -> Result<SealSnapshot, String> {
+        let headers = self
+            .client
+            .get_file("/sys/storage/snapshot".into(), file)
+            .await?;
+        let seal = headers
+            .get(SNAPSHOT_SEAL_HEADER)
+            .and_then(|seal| seal.to_str().ok())
+            .ok_or_else(|| "Snapshot seal metadata is missing".to_string())?;
+        serde_json::from_str(seal).map_err(|e| format!("{e:#?}"))
+    }
+
+    /// Replace the storage of a sealed server with a snapshot written to a
+    /// file. The server is left sealed.
+    pub async fn snapshot_restore(
+        &self,
+        params: &SnapshotRestoreParams,
+        file: &Path,
+    ) -> Result<SnapshotRestoreResponse, String> {
+        let seal = serde_json::to_string(&params.seal).map_err(|e| format!("{e:#?}"))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SNAPSHOT_SEAL_HEADER,
+            seal.parse().map_err(|e| format!("{e:#?}"))?,
+        );
+        headers.insert(
+            SNAPSHOT_SHARES_HEADER,
+            params
+                .shares
+                .join(",")
+                .parse()
+                .map_err(|e| format!("{e:#?}"))?,
+        );
+        self.client
+            .post_file("/sys/storage/snapshot-restore".into(), headers, file)
+            .await
+    }
 }
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-pub use covert_types::methods::system::StatusResponse;
+pub use covert_types::methods::system::{
+    HealthResponse, LeaderResponse, SealStatusResponse, StatusResponse,
+};
 
 use crate::base::BaseClient;
 
@@ -16,4 +18,19 @@ impl Client {
     pub async fn status(&self) -> Result<StatusResponse, String> {
         self.client.get("/sys/status".into()).await
     }
+
+    /// Status of the seal and the progress of the unseal attempt.
+    pub async fn seal_status(&self) -> Result<SealStatusResponse, String> {
+        self.client.get("/sys/seal-status".into()).await
+    }
+
+    /// Health of the server, returned with any of the health status codes.
+    pub async fn health(&self) -> Result<HealthResponse, String> {
+        self.client.get("/sys/health".into()).await
+    }
+
+    /// The active server of a high availability cluster.
+    pub async fn leader(&self) -> Result<LeaderResponse, String> {
+        self.client.get("/sys/leader".into()).await
+    }
 }
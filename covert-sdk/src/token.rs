@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+pub use covert_types::methods::system::{
+    CapabilitiesParams, CapabilitiesResponse, CreateTokenParams, CreateTokenResponse,
+    LookupTokenResponse, RenewTokenSelfParams, RevokedRootTokensResponse, RevokedTokenResponse,
+    TokenAccessorParams,
+};
+pub use covert_types::token::TokenAccessor;
+
+use crate::base::BaseClient;
+
+pub struct Client {
+    client: Arc<BaseClient>,
+}
+
+impl Client {
+    pub(crate) fn new(client: Arc<BaseClient>) -> Self {
+        Self { client }
+    }
+
+    pub async fn create(&self, params: &CreateTokenParams) -> Result<CreateTokenResponse, String> {
+        self.client.post("/sys/token/create".into(), params).await
+    }
+
+    /// Create a token without a parent, so it is not revoked together with
+    /// the calling token.
+    pub async fn create_orphan(
+        &self,
+        params: &CreateTokenParams,
+    ) -> Result<CreateTokenResponse, String> {
+        self.client
+            .post("/sys/token/create-orphan".into(), params)
+            .await
+    }
+
+    /// Revoke all root tokens.
+    pub async fn revoke_root(&self) -> Result<RevokedRootTokensResponse, String> {
+        self.client.post("/sys/token/revoke-root".into(), &()).await
+    }
+
+    pub async fn lookup_self(&self) -> Result<LookupTokenResponse, String> {
+        self.client.get("/auth/token/lookup-self".into()).await
+    }
+
+    pub async fn renew_self(
+        &self,
+        params: &RenewTokenSelfParams,
+    ) -> Result<LookupTokenResponse, String> {
+        self.client
+            .post("/auth/token/renew-self".into(), params)
+            .await
+    }
+
+    pub async fn revoke_self(&self) -> Result<RevokedTokenResponse, String> {
+        self.client
+            .post("/auth/token/revoke-self".into(), &())
+            .await
+    }
+
+    /// Lookup the capabilities of the calling token on a list of paths.
+    pub async fn capabilities_self(
+        &self,
+        paths: Vec<String>,
+    ) -> Result<CapabilitiesResponse, String> {
+        self.client
+            .post(
+                "/sys/capabilities-self".into(),
+                &CapabilitiesParams { paths },
+            )
+            .await
+    }
+
+    pub async fn lookup_accessor(
+        &self,
+        accessor: TokenAccessor,
+    ) -> Result<LookupTokenResponse, String> {
+        self.client
+            .post(
+                "/sys/token/lookup-accessor".into(),
+                &TokenAccessorParams { accessor },
+            )
+            .await
+    }
+
+    pub async fn revoke_accessor(
+        &self,
+        accessor: TokenAccessor,
+    ) -> Result<RevokedTokenResponse, String> {
+        self.client
+            .post(
+                "/sys/token/revoke-accessor".into(),
+                &TokenAccessorParams { accessor },
+            )
+            .await
+    }
+}
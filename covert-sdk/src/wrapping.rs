@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+pub use covert_types::methods::system::{
+    LookupWrappingParams, LookupWrappingResponse, RewrapParams, UnwrapParams, WrapInfo,
+};
+pub use covert_types::token::Token;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::base::BaseClient;
+
+pub struct Client {
+    client: Arc<BaseClient>,
+}
+
+impl Client {
+    pub(crate) fn new(client: Arc<BaseClient>) -> Self {
+        Self { client }
+    }
+
+    pub async fn wrap<T: Serialize>(&self, data: &T) -> Result<WrapInfo, String> {
+        self.client.post("/sys/wrapping/wrap".into(), data).await
+    }
+
+    pub async fn unwrap<T: DeserializeOwned>(&self, token: Token) -> Result<T, String> {
+        self.client
+            .post("/sys/wrapping/unwrap".into(), &UnwrapParams { token })
+            .await
+    }
+
+    pub async fn lookup(&self, token: Token) -> Result<LookupWrappingResponse, String> {
+        self.client
+            .post(
+                "/sys/wrapping/lookup".into(),
+                &LookupWrappingParams { token },
+            )
+            .await
+    }
+
+    pub async fn rewrap(&self, token: Token) -> Result<WrapInfo, String> {
+        self.client
+            .post("/sys/wrapping/rewrap".into(), &RewrapParams { token })
+            .await
+    }
+}
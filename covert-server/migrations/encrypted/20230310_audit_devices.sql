@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS AUDIT_DEVICES (
+    "path" TEXT NOT NULL PRIMARY KEY,
+    -- JSON encoded device config
+    config TEXT NOT NULL,
+    -- Used to HMAC sensitive values written by the device
+    salt BLOB NOT NULL,
+    CONSTRAINT VALID_PATH CHECK(
+        (LENGTH(path) > 0) AND 
+        (INSTR(path, " ") = 0)
+    )
+) STRICT;
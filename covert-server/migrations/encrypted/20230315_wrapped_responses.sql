@@ -0,0 +1,11 @@
+-- Single use cubbyholes holding wrapped responses. Each row is only
+-- accessible with the wrapping token that was issued when it was created.
+CREATE TABLE IF NOT EXISTS WRAPPED_RESPONSES (
+    token TEXT NOT NULL PRIMARY KEY,
+    namespace_id TEXT NOT NULL REFERENCES NAMESPACES(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    creation_path TEXT NOT NULL,
+    -- JSON encoded response
+    response TEXT NOT NULL,
+    issued_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+) STRICT;
@@ -0,0 +1,5 @@
+-- Root protected routes require the `sudo` capability, grant it to the
+-- root policy created on the first unseal.
+UPDATE POLICIES
+SET policy = json_set(policy, '$[0].sudo', json('true'))
+WHERE "name" = 'root' AND json_extract(policy, '$[0].path') = '*';
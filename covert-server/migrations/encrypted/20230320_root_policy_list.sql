@@ -0,0 +1,8 @@
+-- Grant the `list` capability to the root policy created on the first unseal.
+UPDATE POLICIES
+SET policy = json_insert(policy, '$[0].operations[#]', 'List')
+WHERE "name" = 'root'
+    AND json_extract(policy, '$[0].path') = '*'
+    AND NOT EXISTS (
+        SELECT 1 FROM json_each(policy, '$[0].operations') WHERE "value" = 'List'
+    );
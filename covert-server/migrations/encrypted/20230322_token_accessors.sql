@@ -0,0 +1,29 @@
+-- Key used to HMAC tokens before they are stored. Only a single key can
+-- exist.
+CREATE TABLE IF NOT EXISTS TOKEN_HMAC_KEY (
+    lock INTEGER NOT NULL PRIMARY KEY CHECK (lock = 1),
+    "key" BLOB NOT NULL
+) STRICT;
+
+INSERT INTO TOKEN_HMAC_KEY (lock, "key") VALUES (1, randomblob(32));
+
+-- Tokens used to be stored in plaintext. They are hashed, and given an
+-- accessor, after the migrations have run, and the table is then dropped.
+ALTER TABLE TOKENS RENAME TO PLAINTEXT_TOKENS;
+-- Accessor given to the token once it is converted
+ALTER TABLE PLAINTEXT_TOKENS ADD COLUMN accessor TEXT;
+
+CREATE TABLE TOKENS (
+    -- HMAC of the token with the key in TOKEN_HMAC_KEY
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    -- Non secret reference to the token
+    accessor TEXT NOT NULL UNIQUE,
+    issued_at TEXT NOT NULL,
+    expires_at TEXT,
+    namespace_id TEXT NOT NULL,
+    entity_name TEXT NOT NULL,
+    CONSTRAINT FK_ENTITY
+        FOREIGN KEY (namespace_id, entity_name)
+        REFERENCES ENTITIES (namespace_id, "name")
+        ON DELETE CASCADE ON UPDATE CASCADE
+) STRICT;
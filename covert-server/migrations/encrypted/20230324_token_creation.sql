@@ -0,0 +1,12 @@
+-- Tokens created by another token are revoked together with their parent
+ALTER TABLE TOKENS ADD COLUMN parent_accessor TEXT
+    REFERENCES TOKENS(accessor) ON DELETE CASCADE;
+-- JSON encoded list of policy names. The token has the policies of its
+-- entity when NULL.
+ALTER TABLE TOKENS ADD COLUMN policies TEXT;
+-- Number of uses left, unlimited when NULL
+ALTER TABLE TOKENS ADD COLUMN num_uses INTEGER;
+-- The token can not be renewed beyond this time
+ALTER TABLE TOKENS ADD COLUMN max_expires_at TEXT;
+
+CREATE INDEX IF NOT EXISTS TOKENS_PARENT ON TOKENS(parent_accessor);
@@ -0,0 +1,5 @@
+-- Leases record the token that requested them, so they can be revoked
+-- together with the token
+ALTER TABLE LEASES ADD COLUMN token_accessor TEXT;
+
+CREATE INDEX LEASES_TOKEN_ACCESSOR ON LEASES(token_accessor);
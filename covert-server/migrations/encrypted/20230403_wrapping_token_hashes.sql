@@ -0,0 +1,14 @@
+-- Wrapping tokens used to be stored in plaintext. They are hashed after the
+-- migrations have run, and the table is then dropped.
+ALTER TABLE WRAPPED_RESPONSES RENAME TO PLAINTEXT_WRAPPED_RESPONSES;
+
+CREATE TABLE WRAPPED_RESPONSES (
+    -- HMAC of the wrapping token with the key in TOKEN_HMAC_KEY
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    namespace_id TEXT NOT NULL REFERENCES NAMESPACES(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    creation_path TEXT NOT NULL,
+    -- JSON encoded response
+    response TEXT NOT NULL,
+    issued_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+) STRICT;
@@ -0,0 +1,19 @@
+-- In progress attempt to generate a root token
+CREATE TABLE IF NOT EXISTS GENERATE_ROOT (
+    lock INTEGER PRIMARY KEY DEFAULT 1,
+
+    nonce TEXT NOT NULL,
+    -- Encrypted one-time pad
+    otp BLOB,
+    otp_nonce BLOB,
+    pgp_key TEXT,
+
+    -- Used to ensure that maximum one attempt is ever in progress
+    CONSTRAINT GENERATE_ROOT_LOCK CHECK (lock=1)
+) STRICT;
+
+-- Encrypted key shares provided for the generate root attempt
+CREATE TABLE IF NOT EXISTS GENERATE_ROOT_KEY_SHARES (
+    nonce BLOB NOT NULL PRIMARY KEY,
+    "key" BLOB NOT NULL UNIQUE
+) STRICT;
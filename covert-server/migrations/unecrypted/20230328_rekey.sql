@@ -0,0 +1,34 @@
+-- Master key encrypted with the unseal key that is split into the key shares.
+-- Storage initialized before this table existed splits the master key itself.
+CREATE TABLE IF NOT EXISTS MASTER_KEY (
+    lock INTEGER PRIMARY KEY DEFAULT 1,
+
+    "key" BLOB NOT NULL,
+    nonce BLOB NOT NULL,
+
+    CONSTRAINT MASTER_KEY_LOCK CHECK (lock=1)
+) STRICT;
+
+-- In progress attempt to rekey the key shares
+CREATE TABLE IF NOT EXISTS REKEY (
+    lock INTEGER PRIMARY KEY DEFAULT 1,
+
+    nonce TEXT NOT NULL,
+    shares INTEGER NOT NULL,
+    threshold INTEGER NOT NULL,
+    require_verification INTEGER NOT NULL,
+    -- Set when the new key shares have been dealt and are awaiting verification
+    verification_nonce TEXT,
+    -- Master key encrypted with the new unseal key
+    master_key BLOB,
+    master_key_nonce BLOB,
+
+    -- Used to ensure that maximum one attempt is ever in progress
+    CONSTRAINT REKEY_LOCK CHECK (lock=1)
+) STRICT;
+
+-- Encrypted key shares provided for the rekey attempt
+CREATE TABLE IF NOT EXISTS REKEY_KEY_SHARES (
+    nonce BLOB NOT NULL PRIMARY KEY,
+    "key" BLOB NOT NULL UNIQUE
+) STRICT;
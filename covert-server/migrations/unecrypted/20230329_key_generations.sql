@@ -0,0 +1,6 @@
+-- Generations of the master key, a new generation is recorded every time the
+-- master key is rotated
+CREATE TABLE IF NOT EXISTS KEY_GENERATIONS (
+    generation INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL
+) STRICT;
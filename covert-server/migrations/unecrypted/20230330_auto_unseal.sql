@@ -0,0 +1,8 @@
+-- Master key encrypted with the key encryption key of the auto-unseal provider
+CREATE TABLE IF NOT EXISTS KEK_MASTER_KEY (
+    lock INTEGER PRIMARY KEY DEFAULT 1,
+
+    "key" BLOB NOT NULL,
+
+    CONSTRAINT KEK_MASTER_KEY_LOCK CHECK (lock=1)
+) STRICT;
@@ -0,0 +1,14 @@
+-- In progress attempt to unseal, the provided key shares are in KEY_SHARES
+CREATE TABLE IF NOT EXISTS UNSEAL (
+    lock INTEGER PRIMARY KEY DEFAULT 1,
+
+    nonce TEXT NOT NULL,
+
+    -- Used to ensure that maximum one attempt is ever in progress
+    CONSTRAINT UNSEAL_LOCK CHECK (lock=1)
+) STRICT;
+
+-- Concatenated SHA-256 digests of the key shares of the unseal key, used to
+-- tell which of the provided key shares is invalid
+ALTER TABLE MASTER_KEY ADD COLUMN share_digests BLOB;
+ALTER TABLE REKEY ADD COLUMN master_key_share_digests BLOB;
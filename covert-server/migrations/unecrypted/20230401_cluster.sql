@@ -0,0 +1,8 @@
+-- Identifier of the cluster, generated on the first start
+CREATE TABLE IF NOT EXISTS CLUSTER (
+    lock INTEGER PRIMARY KEY DEFAULT 1,
+
+    id TEXT NOT NULL,
+
+    CONSTRAINT CLUSTER_LOCK CHECK (lock=1)
+) STRICT;
@@ -0,0 +1,17 @@
+-- New master key of a rotation in progress, staged before the storage is
+-- re-encrypted and made the current master key once it is
+CREATE TABLE IF NOT EXISTS STAGED_MASTER_KEY (
+    lock INTEGER PRIMARY KEY DEFAULT 1,
+
+    "key" BLOB NOT NULL,
+    nonce BLOB NOT NULL,
+    share_digests BLOB,
+    -- Master key encrypted with the key encryption key of the auto-unseal
+    -- provider
+    kek_key BLOB,
+    -- Key generation recorded once the master key is made current
+    generation INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+
+    CONSTRAINT STAGED_MASTER_KEY_LOCK CHECK (lock=1)
+) STRICT;
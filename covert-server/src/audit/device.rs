@@ -0,0 +1,89 @@
+use covert_types::methods::system::AuditDeviceConfig;
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use crate::repos::audit::AuditDeviceEntry;
+
+/// An enabled audit device. The underlying file or socket is opened lazily
+/// and reopened on the next write if a write fails.
+pub struct AuditDevice {
+    pub entry: AuditDeviceEntry,
+    sink: Mutex<Option<Box<dyn AsyncWrite + Send + Unpin>>>,
+}
+
+impl AuditDevice {
+    #[must_use]
+    pub fn new(entry: AuditDeviceEntry) -> Self {
+        Self {
+            entry,
+            sink: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.entry.path
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        &self.entry.salt
+    }
+
+    /// Open the underlying file or socket if it is not already open.
+    pub async fn open(&self) -> std::io::Result<()> {
+        let mut sink = self.sink.lock().await;
+        if sink.is_none() {
+            *sink = Some(open_sink(&self.entry.config).await?);
+        }
+        Ok(())
+    }
+
+    /// Write a single line to the device.
+    pub async fn write_line(&self, line: &[u8]) -> std::io::Result<()> {
+        let mut sink = self.sink.lock().await;
+        let writer = match sink.as_mut() {
+            Some(writer) => writer,
+            None => sink.insert(open_sink(&self.entry.config).await?),
+        };
+
+        let res = async {
+            writer.write_all(line).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await
+        }
+        .await;
+        if res.is_err() {
+            // Try to open the device again on the next write
+            *sink = None;
+        }
+        res
+    }
+}
+
+async fn open_sink(
+    config: &AuditDeviceConfig,
+) -> std::io::Result<Box<dyn AsyncWrite + Send + Unpin>> {
+    match config {
+        AuditDeviceConfig::File { file_path } => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_path)
+                .await?;
+            Ok(Box::new(file))
+        }
+        #[cfg(unix)]
+        AuditDeviceConfig::Socket { socket_path } => {
+            let stream = tokio::net::UnixStream::connect(socket_path).await?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(not(unix))]
+        AuditDeviceConfig::Socket { .. } => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "socket audit devices are only supported on unix",
+        )),
+        AuditDeviceConfig::Stdout => Ok(Box::new(tokio::io::stdout())),
+    }
+}
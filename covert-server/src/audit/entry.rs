@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use covert_types::{error::ApiError, request::Operation, request::Request};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::response::ResponseWithCtx;
+
+const HMAC_PREFIX: &str = "hmac-sha256:";
+
+/// HMAC a sensitive value with the salt of an audit device. The same value
+/// always produces the same hash for a given device, so operators can search
+/// the audit log for a known token or secret without it being stored in
+/// plaintext.
+#[must_use]
+pub fn hash(salt: &[u8], value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt).expect("HMAC can take key of any size");
+    mac.update(value.as_bytes());
+    format!("{HMAC_PREFIX}{}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Replace every string in the JSON value with its HMAC. Keys are left as is
+/// while numbers, booleans and nulls are not considered sensitive.
+pub fn redact(salt: &[u8], value: &mut Value) {
+    match value {
+        Value::String(s) => *s = hash(salt, s),
+        Value::Array(values) => {
+            for value in values {
+                redact(salt, value);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                redact(salt, value);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => (),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEntryType {
+    Request,
+    Response,
+}
+
+/// The parts of a request that are recorded in the audit log. Sensitive values
+/// are stored in plaintext until [`AuditRequest::redacted`] is called.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRequest {
+    pub id: Uuid,
+    pub operation: Operation,
+    pub namespace: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl AuditRequest {
+    #[must_use]
+    pub fn new(req: &Request) -> Self {
+        let data =
+            if req.data.is_empty() {
+                None
+            } else {
+                // Bodies that are not JSON are recorded as a single string so
+                // they are still redacted.
+                Some(serde_json::from_slice(&req.data).unwrap_or_else(|_| {
+                    Value::String(String::from_utf8_lossy(&req.data).to_string())
+                }))
+            };
+
+        Self {
+            id: req.id,
+            operation: req.operation,
+            namespace: req.namespace.join("/"),
+            path: req.path.clone(),
+            client_token: req.token.clone(),
+            data,
+        }
+    }
+
+    #[must_use]
+    pub fn redacted(&self, salt: &[u8]) -> Self {
+        let mut req = self.clone();
+        req.client_token = req.client_token.map(|token| hash(salt, &token));
+        if let Some(data) = req.data.as_mut() {
+            redact(salt, data);
+        }
+        req
+    }
+}
+
+/// The outcome of a request that is recorded in the audit log.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditResponse {
+    Data(Value),
+    Error { status_code: u16, message: String },
+}
+
+impl AuditResponse {
+    #[must_use]
+    pub fn new(resp: &Result<ResponseWithCtx, ApiError>) -> Self {
+        match resp {
+            Ok(resp) => Self::Data(serde_json::to_value(&resp.response).unwrap_or_default()),
+            Err(error) => Self::Error {
+                status_code: error.status_code.as_u16(),
+                message: error.error.to_string(),
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn redacted(&self, salt: &[u8]) -> Self {
+        let mut resp = self.clone();
+        if let Self::Data(data) = &mut resp {
+            redact(salt, data);
+        }
+        resp
+    }
+}
+
+/// A single line in the audit log.
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    #[serde(rename = "type")]
+    pub entry_type: AuditEntryType,
+    pub time: DateTime<Utc>,
+    pub request: AuditRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<AuditResponse>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn redact_all_strings() {
+        let salt = b"salt";
+        let mut value = json!({
+            "token": "s.foo",
+            "nested": {
+                "shares": ["a", "b"],
+                "ttl": 3600,
+                "renewable": true,
+                "lease": null,
+            }
+        });
+        redact(salt, &mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "token": hash(salt, "s.foo"),
+                "nested": {
+                    "shares": [hash(salt, "a"), hash(salt, "b")],
+                    "ttl": 3600,
+                    "renewable": true,
+                    "lease": null,
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn hash_depends_on_salt() {
+        assert!(hash(b"salt", "secret").starts_with(HMAC_PREFIX));
+        assert_eq!(hash(b"salt", "secret"), hash(b"salt", "secret"));
+        assert_ne!(hash(b"salt", "secret"), hash(b"other salt", "secret"));
+        assert_ne!(hash(b"salt", "secret"), hash(b"salt", "other secret"));
+    }
+}
@@ -0,0 +1,221 @@
+mod device;
+mod entry;
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use covert_types::error::ApiError;
+use tokio::sync::RwLock;
+use tracing::error;
+
+pub use device::AuditDevice;
+pub use entry::AuditRequest;
+
+use self::entry::{AuditEntry, AuditEntryType, AuditResponse};
+use crate::{
+    error::{Error, ErrorType},
+    repos::audit::AuditDeviceEntry,
+    response::ResponseWithCtx,
+};
+
+/// Fans out audit entries to all the enabled audit devices.
+///
+/// Devices are stored in the encrypted storage so the broker is only populated
+/// while the storage is unsealed.
+#[derive(Default)]
+pub struct AuditBroker {
+    devices: RwLock<Vec<Arc<AuditDevice>>>,
+}
+
+impl AuditBroker {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the enabled devices, used when the storage is unsealed.
+    pub async fn load(&self, entries: Vec<AuditDeviceEntry>) {
+        let devices = entries
+            .into_iter()
+            .map(|entry| Arc::new(AuditDevice::new(entry)))
+            .collect();
+        *self.devices.write().await = devices;
+    }
+
+    /// Remove all devices, used when the storage is sealed.
+    pub async fn clear(&self) {
+        self.devices.write().await.clear();
+    }
+
+    pub async fn enable(&self, device: AuditDevice) {
+        self.devices.write().await.push(Arc::new(device));
+    }
+
+    pub async fn disable(&self, path: &str) {
+        self.devices
+            .write()
+            .await
+            .retain(|device| device.path() != path);
+    }
+
+    /// Record a request before it is handled.
+    pub async fn log_request(&self, req: &AuditRequest) -> Result<(), Error> {
+        self.log(req, None).await
+    }
+
+    /// Record the outcome of a request.
+    pub async fn log_response(
+        &self,
+        req: &AuditRequest,
+        resp: &Result<ResponseWithCtx, ApiError>,
+    ) -> Result<(), Error> {
+        self.log(req, Some(&AuditResponse::new(resp))).await
+    }
+
+    /// Write the entry to all the enabled devices. This only fails if there
+    /// are enabled devices and none of them were able to record the entry.
+    async fn log(&self, req: &AuditRequest, resp: Option<&AuditResponse>) -> Result<(), Error> {
+        let devices = self.devices.read().await;
+        if devices.is_empty() {
+            return Ok(());
+        }
+
+        let time = Utc::now();
+        let writes = devices.iter().map(|device| async move {
+            let entry = AuditEntry {
+                entry_type: if resp.is_some() {
+                    AuditEntryType::Response
+                } else {
+                    AuditEntryType::Request
+                },
+                time,
+                request: req.redacted(device.salt()),
+                response: resp.map(|resp| resp.redacted(device.salt())),
+            };
+            let line = serde_json::to_vec(&entry).map_err(|err| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+            })?;
+            device.write_line(&line).await.map_err(|error| {
+                error!(?error, path = device.path(), "Failed to write audit entry");
+                error
+            })
+        });
+
+        let recorded = futures::future::join_all(writes)
+            .await
+            .into_iter()
+            .flatten()
+            .count();
+        if recorded == 0 {
+            return Err(ErrorType::AuditFailed.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use covert_types::{methods::system::AuditDeviceConfig, request::Operation};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use super::{entry::hash, *};
+
+    fn file_device(path: &str, file_path: &std::path::Path) -> AuditDeviceEntry {
+        AuditDeviceEntry {
+            path: path.to_string(),
+            config: AuditDeviceConfig::File {
+                file_path: file_path.to_str().unwrap().to_string(),
+            },
+            salt: path.as_bytes().to_vec(),
+        }
+    }
+
+    fn request() -> AuditRequest {
+        AuditRequest {
+            id: Uuid::new_v4(),
+            operation: Operation::Create,
+            namespace: "root".into(),
+            path: "kv/data/foo".into(),
+            client_token: Some("s.token".into()),
+            data: Some(json!({ "data": { "password": "hunter2" } })),
+        }
+    }
+
+    fn read_lines(path: &std::path::Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn no_devices_enabled() {
+        let broker = AuditBroker::new();
+        assert!(broker.log_request(&request()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn writes_redacted_entries_to_all_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_a = dir.path().join("a.log");
+        let file_b = dir.path().join("b.log");
+
+        let broker = AuditBroker::new();
+        broker
+            .load(vec![file_device("a", &file_a), file_device("b", &file_b)])
+            .await;
+
+        let req = request();
+        broker.log_request(&req).await.unwrap();
+        broker
+            .log_response(&req, &Err(ApiError::not_found()))
+            .await
+            .unwrap();
+
+        for (file, salt) in [(&file_a, b"a"), (&file_b, b"b")] {
+            let lines = read_lines(file);
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[0]["type"], "request");
+            assert_eq!(lines[1]["type"], "response");
+            for line in &lines {
+                assert_eq!(line["request"]["path"], "kv/data/foo");
+                assert_eq!(line["request"]["client_token"], hash(salt, "s.token"));
+                assert_eq!(
+                    line["request"]["data"]["data"]["password"],
+                    hash(salt, "hunter2")
+                );
+            }
+            assert_eq!(lines[1]["response"]["error"]["status_code"], 404);
+        }
+
+        // Not logged after the device is disabled
+        broker.disable("a").await;
+        broker.log_request(&req).await.unwrap();
+        assert_eq!(read_lines(&file_a).len(), 2);
+        assert_eq!(read_lines(&file_b).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn fails_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("audit.log");
+
+        let broker = AuditBroker::new();
+        // Directories can not be opened for writing
+        broker.load(vec![file_device("dir", dir.path())]).await;
+        assert!(broker.log_request(&request()).await.is_err());
+
+        // One device is enough to record the request
+        broker
+            .enable(AuditDevice::new(file_device("file", &file)))
+            .await;
+        assert!(broker.log_request(&request()).await.is_ok());
+        assert_eq!(read_lines(&file).len(), 1);
+
+        broker.clear().await;
+        assert!(broker.log_request(&request()).await.is_ok());
+        assert_eq!(read_lines(&file).len(), 1);
+    }
+}
@@ -1,4 +1,4 @@
-use std::process::Command;
+use std::time::Duration;
 
 use serde::Deserialize;
 use tokio::sync::oneshot;
@@ -11,6 +11,18 @@ pub struct Config {
     pub port_tx: Option<oneshot::Sender<u16>>,
     pub replication: Option<ReplicationConfig>,
     pub storage_path: String,
+    #[serde(default)]
+    pub listener: ListenerConfig,
+    /// Unseal automatically at startup with a key encryption key when present.
+    pub seal: Option<AutoUnsealConfig>,
+    /// Serve Prometheus metrics at `sys/metrics` when present.
+    pub metrics: Option<MetricsConfig>,
+    /// Run as a read-only standby that follows the replica of an active
+    /// server when present.
+    pub standby: Option<StandbyConfig>,
+    /// Elect the active server among the servers that share a lock when
+    /// present. The other servers are standbys that follow its replica.
+    pub ha: Option<HaConfig>,
 }
 
 impl Config {
@@ -48,25 +60,60 @@ impl Config {
     }
 
     pub fn sanitize(&self) -> anyhow::Result<()> {
-        if self.replication.is_some() {
-            if self.using_inmemory_storage() {
-                return Err(anyhow::Error::msg(
-                    "Replication is not supported for inmemory storage",
-                ));
-            }
+        if self.replication.is_some() && self.using_inmemory_storage() {
+            return Err(anyhow::Error::msg(
+                "Replication is not supported for inmemory storage",
+            ));
+        }
+
+        if self.standby.is_some() && self.replication.is_none() {
+            return Err(anyhow::Error::msg(
+                "A standby requires replication to be configured, it follows the replica",
+            ));
+        }
 
-            // Check if litestream is installed
-            let cmd = Command::new("litestream").arg("version").status();
-            match cmd {
-                Ok(s) if s.success() => (),
-                _ => {
-                    return Err(anyhow::Error::msg(
-                        "Litestream command not found, can not perform replication",
-                    ));
+        if self.ha.is_some() && self.replication.is_none() {
+            return Err(anyhow::Error::msg(
+                "High availability requires replication to be configured, the standbys follow the replica",
+            ));
+        }
+        if self.ha.is_some() && self.standby.is_some() {
+            return Err(anyhow::Error::msg(
+                "A standby is elected in high availability mode, it can't follow a fixed active server",
+            ));
+        }
+
+        if let Some(tls) = self.listener.tls.as_ref() {
+            for file in [
+                Some(&tls.cert_file),
+                Some(&tls.key_file),
+                tls.client_ca_file.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                if !std::path::Path::new(file).is_file() {
+                    return Err(anyhow::Error::msg(format!("TLS file `{file}` not found")));
                 }
             }
         }
 
+        if let Some(AutoUnsealConfig::File(file)) = self.seal.as_ref() {
+            if !std::path::Path::new(&file.key_file).is_file() {
+                return Err(anyhow::Error::msg(format!(
+                    "Key encryption key file `{}` not found",
+                    file.key_file
+                )));
+            }
+        }
+        if let Some(AutoUnsealConfig::Command(command)) = self.seal.as_ref() {
+            if command.encrypt_command.is_empty() || command.decrypt_command.is_empty() {
+                return Err(anyhow::Error::msg(
+                    "The encrypt and decrypt commands of the seal config must not be empty",
+                ));
+            }
+        }
+
         if !self.using_inmemory_storage() {
             let storage_path = std::path::Path::new(&self.storage_path);
             if !storage_path.exists()
@@ -92,30 +139,170 @@ impl Config {
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReplicationConfig {
+    /// Where the databases are replicated to.
+    #[serde(flatten)]
+    pub target: ReplicaTargetConfig,
+    /// How often new changes are shipped to the replica.
+    #[serde(default = "default_sync_interval", with = "humantime_serde")]
+    pub sync_interval: Duration,
+}
+
+fn default_sync_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "target", rename_all = "kebab-case")]
+pub enum ReplicaTargetConfig {
+    Directory(DirectoryReplicaConfig),
+    S3(S3ReplicaConfig),
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DirectoryReplicaConfig {
+    /// Directory the replica is written to, e.g. a mounted network volume.
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct S3ReplicaConfig {
     pub access_key_id: String,
     pub secret_access_key: String,
-    // S3 url format: https://<bucket-name>.s3.<region-code>.amazonaws.com/
+    /// Bucket and key prefix of the replica, in the format `s3://<bucket>/<path>/`.
     pub bucket_url: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    /// Endpoint of a S3 compatible object store, e.g. `http://localhost:9000`.
+    /// Objects are addressed path-style when set.
+    pub endpoint: Option<String>,
 }
 
-impl ReplicationConfig {
-    #[must_use]
-    pub fn seal_bucket_url(&self) -> String {
-        let maybe_slash = if self.bucket_url.ends_with('/') {
-            ""
-        } else {
-            "/"
-        };
-        format!("{}{maybe_slash}{}", self.bucket_url, "seal.db")
-    }
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
 
-    #[must_use]
-    pub fn encrypted_bucket_url(&self) -> String {
-        let maybe_slash = if self.bucket_url.ends_with('/') {
-            ""
-        } else {
-            "/"
-        };
-        format!("{}{maybe_slash}{}", self.bucket_url, "covert.db")
-    }
+#[derive(Debug, Deserialize, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ListenerConfig {
+    /// Serve HTTPS instead of plain HTTP when present.
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct TlsConfig {
+    /// PEM encoded certificate chain, leaf certificate first.
+    pub cert_file: String,
+    /// PEM encoded private key (PKCS#8, PKCS#1 or SEC1).
+    pub key_file: String,
+    /// PEM encoded CA bundle. Clients are required to present a certificate
+    /// signed by one of these CAs when set.
+    pub client_ca_file: Option<String>,
+    #[serde(default)]
+    pub min_version: TlsVersion,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct MetricsConfig {
+    /// Serve the metrics on a dedicated plain HTTP listener on this port
+    /// instead of the main listener.
+    pub port: Option<u16>,
+    /// Serve the metrics on the main listener without a token. Otherwise a
+    /// token with `read` on `sys/metrics` is required.
+    #[serde(default)]
+    pub unauthenticated_metrics_access: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StandbyConfig {
+    /// Address of the active server that writes are redirected to, e.g.
+    /// `https://covert-active:8080`.
+    pub active_address: String,
+    /// The standby is reported as lagging when it has not caught up with the
+    /// replica for longer than this.
+    #[serde(default = "default_max_lag", with = "humantime_serde")]
+    pub max_lag: Duration,
+}
+
+fn default_max_lag() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct HaConfig {
+    /// Address of this server that the standbys redirect requests to while it
+    /// is the active server, e.g. `https://covert-1:8080`.
+    pub address: String,
+    /// Lock the servers compete for.
+    #[serde(flatten)]
+    pub lock: HaLockConfig,
+    /// How long the lock is held after it was last renewed. The active server
+    /// steps down when it is unable to renew the lock for this long.
+    #[serde(default = "default_lock_ttl", with = "humantime_serde")]
+    pub lock_ttl: Duration,
+    /// A standby is reported as lagging when it has not caught up with the
+    /// replica for longer than this.
+    #[serde(default = "default_max_lag", with = "humantime_serde")]
+    pub max_lag: Duration,
+}
+
+fn default_lock_ttl() -> Duration {
+    Duration::from_secs(15)
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "lock", rename_all = "kebab-case")]
+pub enum HaLockConfig {
+    /// File lock on storage shared by the servers, e.g. a network volume.
+    File(FileLockConfig),
+    /// Lock object next to the replica in the replication target.
+    Replica,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct FileLockConfig {
+    /// Lock file, it holds the address of the active server.
+    pub path: String,
+}
+
+/// Provider of the key encryption key that the master key is encrypted with
+/// for auto-unseal.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "provider", rename_all = "kebab-case")]
+pub enum AutoUnsealConfig {
+    File(FileKekConfig),
+    Command(CommandKekConfig),
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct FileKekConfig {
+    /// File with a hex encoded 256 bit key.
+    pub key_file: String,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CommandKekConfig {
+    /// Program and arguments that read plaintext from stdin and write the
+    /// ciphertext to stdout.
+    pub encrypt_command: Vec<String>,
+    /// Program and arguments that read ciphertext from stdin and write the
+    /// plaintext to stdout.
+    pub decrypt_command: Vec<String>,
 }
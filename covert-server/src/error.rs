@@ -4,6 +4,7 @@ use covert_storage::{migrator::MigrationError, EncryptedPoolError};
 use covert_types::{
     backend::BackendType,
     error::{ApiError, StatusCode},
+    policy::PolicyParseError,
 };
 use sqlx::{error::DatabaseError, sqlite::SqliteError};
 use thiserror::Error;
@@ -85,6 +86,20 @@ pub enum ErrorType {
     AuthBackendNotUnderAuthPath,
     #[error("Secret engines cannot be mounted under `auth/`")]
     LogicalBackendUnderAuthPath,
+    #[error("Unable to record the request in any of the enabled audit devices")]
+    AuditFailed,
+    #[error("Unable to open audit device at `{path}`")]
+    AuditDeviceUnavailable {
+        #[source]
+        error: std::io::Error,
+        path: String,
+    },
+    #[error("Audit devices can only be managed from the root namespace")]
+    AuditInNonRootNamespace,
+    #[error("Malformed policy: {0}")]
+    MalformedPolicy(#[from] PolicyParseError),
+    #[error("This server is a standby, the request has to be sent to the active server")]
+    Standby,
 }
 
 #[derive(Error, Debug)]
@@ -122,6 +137,13 @@ impl From<sqlx::Error> for Error {
                             span_trace: SpanTrace::capture(),
                         };
                     }
+                    // Write to the read-only storage of a standby
+                    "8" => {
+                        return Self {
+                            variant: ErrorType::Standby,
+                            span_trace: SpanTrace::capture(),
+                        };
+                    }
                     _ => {}
                 }
             }
@@ -173,20 +195,25 @@ impl From<Error> for ApiError {
             | ErrorType::Migration { .. }
             | ErrorType::StateTransition(_)
             | ErrorType::BackendMigration { .. }
-            | ErrorType::Recovery { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            | ErrorType::Recovery { .. }
+            | ErrorType::AuditFailed => StatusCode::INTERNAL_SERVER_ERROR,
             ErrorType::Unauthorized(_) | ErrorType::MasterKeyRecovery => StatusCode::UNAUTHORIZED,
             ErrorType::NotFound(_) | ErrorType::MountNotFound { .. } => StatusCode::NOT_FOUND,
             ErrorType::BadRequest(_)
             | ErrorType::InvalidMountPath { .. }
             | ErrorType::InvalidInitializeParams
-            | ErrorType::InvalidMountType { .. } => StatusCode::BAD_REQUEST,
+            | ErrorType::InvalidMountType { .. }
+            | ErrorType::AuditDeviceUnavailable { .. }
+            | ErrorType::MalformedPolicy(_) => StatusCode::BAD_REQUEST,
             ErrorType::MountPathConflict { .. } | ErrorType::UniqueConstraintViolation { .. } => {
                 StatusCode::CONFLICT
             }
             ErrorType::ForeignKeyViolation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
             ErrorType::SealInNonRootNamespace
             | ErrorType::AuthBackendNotUnderAuthPath
-            | ErrorType::LogicalBackendUnderAuthPath => StatusCode::FORBIDDEN,
+            | ErrorType::LogicalBackendUnderAuthPath
+            | ErrorType::AuditInNonRootNamespace => StatusCode::FORBIDDEN,
+            ErrorType::Standby => StatusCode::TEMPORARY_REDIRECT,
         };
 
         ApiError {
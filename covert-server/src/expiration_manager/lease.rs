@@ -17,6 +17,8 @@ pub struct LeaseEntry {
     pub last_renewal_time: DateTime<Utc>,
     pub failed_revocation_attempts: u32,
     pub namespace_id: String,
+    /// Accessor of the token that requested the lease
+    pub token_accessor: Option<String>,
 }
 
 impl LeaseEntry {
@@ -53,6 +55,7 @@ impl LeaseEntry {
             last_renewal_time,
             failed_revocation_attempts: 0,
             namespace_id,
+            token_accessor: None,
         })
     }
 
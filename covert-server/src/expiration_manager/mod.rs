@@ -3,15 +3,17 @@ mod lease;
 
 use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use std::sync::Arc;
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use covert_types::auth::AuthPolicy;
 use covert_types::error::ApiError;
 use covert_types::methods::psql::RenewLeaseResponse;
 use covert_types::methods::RenewLeaseParams;
 use covert_types::request::{Operation, Request};
 use covert_types::state::StorageState;
+use covert_types::token::TokenAccessor;
 use covert_types::ttl::calculate_ttl;
 use futures::stream::FuturesOrdered;
 use futures::{Future, StreamExt};
@@ -28,6 +30,10 @@ use crate::repos::Repos;
 use self::clock::Clock;
 pub use self::lease::LeaseEntry;
 
+/// Revocation endpoint of the tokens, which also revokes the tokens created
+/// by them.
+const TOKEN_REVOKE_PATH: &str = "sys/token/revoke";
+
 use super::router::Router;
 
 /// The expiration manager is resposible for revoking and renewing leases.
@@ -52,6 +58,21 @@ pub struct ExpirationManager {
     revocation_worker_concurrency: usize,
     /// Provides time information. Gives us deterministic time in tests.
     clock: Arc<dyn Clock>,
+    /// Number of leases registered since startup
+    lease_registrations: AtomicU64,
+    /// Number of failed revocations that have been scheduled for a retry
+    revocation_retries: AtomicU64,
+    /// Number of revocations currently handled by the revocation worker
+    revocations_in_progress: AtomicI64,
+}
+
+/// Point in time view of the expiration manager activity, exported as metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpirationStats {
+    pub lease_registrations: u64,
+    pub revocation_retries: u64,
+    pub revocations_in_progress: i64,
+    pub revocation_worker_concurrency: usize,
 }
 
 impl ExpirationManager {
@@ -70,6 +91,20 @@ impl ExpirationManager {
             revocation_timeout: std::time::Duration::from_secs(10),
             revocation_worker_concurrency: 100,
             clock: Arc::new(clock),
+            lease_registrations: AtomicU64::new(0),
+            revocation_retries: AtomicU64::new(0),
+            revocations_in_progress: AtomicI64::new(0),
+        }
+    }
+
+    /// Counters and gauges describing the work done by the expiration manager.
+    #[must_use]
+    pub fn stats(&self) -> ExpirationStats {
+        ExpirationStats {
+            lease_registrations: self.lease_registrations.load(Ordering::Relaxed),
+            revocation_retries: self.revocation_retries.load(Ordering::Relaxed),
+            revocations_in_progress: self.revocations_in_progress.load(Ordering::Relaxed),
+            revocation_worker_concurrency: self.revocation_worker_concurrency,
         }
     }
 
@@ -79,11 +114,18 @@ impl ExpirationManager {
     /// directly to the [`LeaseStore`] without going throught the expiration manager.
     pub async fn register(&self, le: LeaseEntry) -> Result<(), Error> {
         self.repos.lease.create(&le).await?;
+        self.lease_registrations.fetch_add(1, Ordering::Relaxed);
         // Let the revocation worker know about the lease.
         self.background_task.notify_one();
         Ok(())
     }
 
+    /// Let the revocation worker know about a new token without a lease, so
+    /// it is revoked once it expires.
+    pub fn register_token(&self) {
+        self.background_task.notify_one();
+    }
+
     /// Revoke all leases issued by mounts under a given path prefix.
     pub async fn revoke_leases_by_mount_prefix(
         &self,
@@ -95,7 +137,35 @@ impl ExpirationManager {
             .lease
             .list_by_mount_prefix(prefix, namespace_id)
             .await?;
+        Ok(self.revoke_leases(leases).await)
+    }
+
+    /// Revoke all leases requested by a token.
+    pub async fn revoke_leases_by_token(
+        &self,
+        accessor: &TokenAccessor,
+    ) -> Result<Vec<LeaseEntry>, Error> {
+        let leases = self.repos.lease.list_by_token(accessor).await?;
+        Ok(self.revoke_leases(leases).await)
+    }
+
+    /// Revoke all leases requested by the tokens of an entity.
+    pub async fn revoke_leases_by_entity(
+        &self,
+        entity_name: &str,
+        namespace_id: &str,
+    ) -> Result<Vec<LeaseEntry>, Error> {
+        let leases = self
+            .repos
+            .lease
+            .list_by_entity(entity_name, namespace_id)
+            .await?;
+        Ok(self.revoke_leases(leases).await)
+    }
 
+    /// Revoke leases concurrently. Returns the leases that were successfully
+    /// revoked, failed revocations are retried by the revocation worker.
+    async fn revoke_leases(&self, leases: Vec<LeaseEntry>) -> Vec<LeaseEntry> {
         let mut revoke_futures = FuturesOrdered::new();
 
         for lease in leases {
@@ -103,7 +173,7 @@ impl ExpirationManager {
                 .push_back(async move { self.revoke_lease_entry(&lease).await.map(|_| lease) });
         }
 
-        let revoked_leases = revoke_futures
+        revoke_futures
             .collect::<Vec<_>>()
             .await
             .into_iter()
@@ -114,9 +184,7 @@ impl ExpirationManager {
                     None
                 }
             })
-            .collect::<Vec<_>>();
-
-        Ok(revoked_leases)
+            .collect()
     }
 
     /// List all leases issued by mounts under a given path prefix.
@@ -168,13 +236,55 @@ impl ExpirationManager {
     /// leased data.
     #[tracing::instrument(skip_all, fields(lease_id = le.id, issued_mount_path = le.issued_mount_path))]
     async fn send_lease_revoke_request(&self, le: &LeaseEntry) -> Result<(), ApiError> {
+        let revoke_path = le.revoke_path.as_ref().map_or_else(
+            || TOKEN_REVOKE_PATH.into(),
+            |revoke_path| format!("{}{revoke_path}", le.issued_mount_path),
+        );
+        self.send_revoke_request(&le.namespace_id, revoke_path, le.revoke_data.clone())
+            .await
+    }
+
+    /// Revoke the expired tokens that have no lease, like the tokens created
+    /// by another token.
+    async fn revoke_expired_tokens(&self, now: DateTime<Utc>) {
+        #[allow(clippy::cast_possible_truncation)]
+        let tokens = match self
+            .repos
+            .token
+            .pull_expired(self.revocation_worker_concurrency as u32, now)
+            .await
+        {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                error!(?error, "Failed to pull expired tokens for revocation");
+                return;
+            }
+        };
+
+        for token in tokens {
+            let data = serde_json::json!({ "accessor": token.accessor }).to_string();
+            if let Err(error) = self
+                .send_revoke_request(&token.namespace_id, TOKEN_REVOKE_PATH.into(), data)
+                .await
+            {
+                error!(?error, accessor = %token.accessor, "Failed to revoke expired token");
+            }
+        }
+    }
+
+    async fn send_revoke_request(
+        &self,
+        namespace_id: &str,
+        revoke_path: String,
+        revoke_data: String,
+    ) -> Result<(), ApiError> {
         let ns = self
             .repos
             .namespace
-            .lookup(&le.namespace_id)
+            .lookup(namespace_id)
             .await?
             .ok_or_else(ApiError::internal_error)?;
-        let ns_path = self.repos.namespace.get_full_path(&le.namespace_id).await?;
+        let ns_path = self.repos.namespace.get_full_path(namespace_id).await?;
 
         // Perform revocation
         let mut extensions = http::Extensions::new();
@@ -182,17 +292,12 @@ impl ExpirationManager {
         extensions.insert(StorageState::Unsealed);
         extensions.insert(ns);
 
-        let revoke_path = le.revoke_path.as_ref().map_or_else(
-            || "sys/token/revoke".into(),
-            |revoke_path| format!("{}{revoke_path}", le.issued_mount_path),
-        );
-
         let req = Request {
             id: Uuid::default(),
             namespace: ns_path.split('/').map(From::from).collect(),
             operation: Operation::Revoke,
             path: revoke_path,
-            data: le.revoke_data.clone().into(),
+            data: revoke_data.into(),
             extensions,
             token: None,
             params: Vec::default(),
@@ -338,6 +443,10 @@ impl ExpirationManager {
 
         loop {
             let now = self.clock.now();
+            // Tokens that fail to be revoked are retried the next time the
+            // worker wakes up
+            self.revoke_expired_tokens(now).await;
+
             #[allow(clippy::cast_possible_truncation)]
             let leases = match self
                 .repos
@@ -357,12 +466,13 @@ impl ExpirationManager {
             debug!("Fetched {} leases ready for revocation", number_of_leases);
             if number_of_leases == 0 {
                 // TODO: this might need more care to ensure no leases are lost
-                let next_lease_fut = self
-                    .repos
-                    .lease
-                    .peek()
-                    .await?
-                    .map(|le| le.expires_at - self.clock.now())
+                let next_lease = self.repos.lease.peek().await?.map(|le| le.expires_at);
+                let next_token = self.repos.token.peek_expiry(now).await?;
+                let next_lease_fut = next_lease
+                    .into_iter()
+                    .chain(next_token)
+                    .min()
+                    .map(|expires_at| expires_at - self.clock.now())
                     .and_then(|duration| duration.to_std().ok())
                     .map_or_else::<Pin<Box<dyn Future<Output = ()> + Send + Sync + 'static>>, _, _>(
                         || Box::pin(std::future::pending()),
@@ -388,9 +498,11 @@ impl ExpirationManager {
 
             futures::stream::iter(leases)
                 .for_each_concurrent(self.revocation_worker_concurrency, |le| async move {
+                    self.revocations_in_progress.fetch_add(1, Ordering::Relaxed);
                     // Errors are handled by this function, no more logging
                     // or error handling is required at this point.
                     let _ = self.revoke_lease_entry(&le).await;
+                    self.revocations_in_progress.fetch_sub(1, Ordering::Relaxed);
                 })
                 .await;
         }
@@ -427,6 +539,7 @@ impl ExpirationManager {
                         error!(?error, "failed to delete lease from store that has passed max number of revocation retries");
                     };
                 } else {
+                    self.revocation_retries.fetch_add(1, Ordering::Relaxed);
                     // Increase failed count
                     if let Err(error) = self
                         .repos
@@ -461,6 +574,7 @@ mod tests {
     use covert_framework::{Backend, SyncService};
     use covert_types::{
         backend::{BackendCategory, BackendType},
+        entity::Entity,
         mount::{MountConfig, MountEntry},
         response::Response,
     };
@@ -468,7 +582,7 @@ mod tests {
 
     use crate::{
         expiration_manager::clock::test::TestClock,
-        repos::{mount::tests::pool, namespace::Namespace},
+        repos::{mount::tests::pool, namespace::Namespace, token::TokenEntry},
         system::SYSTEM_MOUNT_PATH,
     };
 
@@ -779,6 +893,81 @@ mod tests {
         assert_eq!(leases, vec![]);
     }
 
+    #[tokio::test]
+    async fn revoke_token_without_lease_after_ttl_expires() {
+        let clock = TestClock::new();
+        let recorder = Arc::new(RequestRecorder(RwLock::new(Vec::new())));
+
+        let pool = Arc::new(pool().await);
+        let u_pool = SqlitePool::connect(":memory:").await.unwrap();
+        let repos = Repos::new(pool, u_pool);
+
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        repos.namespace.create(&ns).await.unwrap();
+
+        let router = Arc::new(Router::new(repos.mount.clone()));
+        let exp_m = Arc::new(ExpirationManager::new(
+            Arc::clone(&router),
+            repos.clone(),
+            clock.clone(),
+        ));
+
+        let expiration_manager = Arc::clone(&exp_m);
+        tokio::spawn(async move {
+            expiration_manager.start().await.unwrap();
+        });
+        tokio::task::yield_now().await;
+
+        let recorder_moved = Arc::clone(&recorder);
+        let clock_moved = clock.clone();
+        let handler = SyncService::new(tower::service_fn(move |req| {
+            let recorder = Arc::clone(&recorder_moved);
+            let clock = clock_moved.clone();
+            async move { system_handle(req, recorder, None, clock).await }
+        }));
+        let backend = Arc::new(Backend {
+            category: BackendCategory::Logical,
+            migrations: vec![],
+            variant: BackendType::System,
+            handler,
+        });
+        router.mount_system(backend);
+
+        // Tokens created by other tokens have no lease
+        let entity = Entity {
+            name: "foo".to_string(),
+            namespace_id: ns.id.clone(),
+        };
+        repos.entity.create(&entity).await.unwrap();
+        let ttl = Duration::hours(4);
+        let mut te = TokenEntry::new(entity.name.clone(), ttl, ns.id.clone());
+        te.issued_at = clock.now();
+        te.expires_at = Some(clock.now() + ttl);
+        repos.token.create(&te).await.unwrap();
+        exp_m.register_token();
+
+        // Wait ttl - 1 hours and it should not be revoked yet
+        advance(&clock, ttl - Duration::hours(1)).await;
+        assert!(recorder.0.read().await.is_empty());
+
+        // Go to revocation time
+        advance(&clock, Duration::hours(1)).await;
+
+        let requests = recorder.0.read().await;
+        assert_eq!(
+            requests.first(),
+            Some(&RequestInfo {
+                path: "token/revoke".into(),
+                operation: Operation::Revoke,
+                reveived_at: te.expires_at
+            })
+        );
+    }
+
     #[tokio::test]
     async fn revoke_before_ttl_expires() {
         let clock = TestClock::new();
@@ -1090,6 +1279,14 @@ mod tests {
         // Lease should be deleted
         let leases = repos.lease.list().await.unwrap();
         assert_eq!(leases, vec![]);
+
+        let stats = exp_m.stats();
+        assert_eq!(stats.lease_registrations, 1);
+        assert_eq!(
+            stats.revocation_retries,
+            u64::from(exp_m.revocation_max_retries - 1)
+        );
+        assert_eq!(stats.revocations_in_progress, 0);
     }
 
     #[tokio::test]
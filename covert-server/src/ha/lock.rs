@@ -0,0 +1,242 @@
+use std::{
+    fs::{File, TryLockError},
+    io::{Read, Seek, Write},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::replication::ReplicaTarget;
+
+/// Server that holds the lock, written to the lock so the standbys know
+/// where to redirect requests to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LockHolder {
+    pub id: String,
+    pub address: String,
+    pub active_since: DateTime<Utc>,
+    /// The lock can be taken by another server after this.
+    pub expires_at: DateTime<Utc>,
+}
+
+pub trait HaLock: Send + Sync {
+    /// Take the lock, or renew it if `node` already holds it. Returns the
+    /// holder of the lock afterwards, if any.
+    fn acquire<'a>(
+        &'a self,
+        node: &'a LockHolder,
+    ) -> BoxFuture<'a, anyhow::Result<Option<LockHolder>>>;
+
+    /// Give up the lock if `node` holds it.
+    fn release<'a>(&'a self, node: &'a LockHolder) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Whether only one server at a time is able to take the lock.
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+}
+
+/// Lock file on storage shared by the servers. The lock is held until it is
+/// released or the process holding it exits, so it never expires.
+pub struct FileLock {
+    path: String,
+    /// Open while the lock is held.
+    file: Mutex<Option<File>>,
+}
+
+impl FileLock {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            file: Mutex::default(),
+        }
+    }
+
+    fn try_acquire(&self, node: &LockHolder) -> anyhow::Result<Option<LockHolder>> {
+        let mut held = self
+            .file
+            .lock()
+            .map_err(|_| anyhow::Error::msg("lock file mutex poisoned"))?;
+        if held.is_some() {
+            return Ok(Some(node.clone()));
+        }
+
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        match file.try_lock() {
+            Ok(()) => {
+                file.set_len(0)?;
+                file.rewind()?;
+                file.write_all(&serde_json::to_vec(node)?)?;
+                file.sync_all()?;
+                *held = Some(file);
+                Ok(Some(node.clone()))
+            }
+            Err(TryLockError::WouldBlock) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                // The holder might not have written itself to the file yet
+                Ok(serde_json::from_str(&content).ok())
+            }
+            Err(TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+}
+
+impl HaLock for FileLock {
+    fn acquire<'a>(
+        &'a self,
+        node: &'a LockHolder,
+    ) -> BoxFuture<'a, anyhow::Result<Option<LockHolder>>> {
+        Box::pin(async move { self.try_acquire(node) })
+    }
+
+    fn release<'a>(&'a self, _node: &'a LockHolder) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            // Closing the file unlocks it
+            self.file
+                .lock()
+                .map_err(|_| anyhow::Error::msg("lock file mutex poisoned"))?
+                .take();
+            Ok(())
+        })
+    }
+}
+
+/// Key of the lock object in the replication target.
+pub(super) const LOCK_KEY: &str = "ha/lock";
+
+/// Lock object in the replication target, renewed by its holder before it
+/// expires.
+///
+/// Object stores have no compare-and-swap, so two servers that take an
+/// expired lock at the same time both believe they hold it until one of them
+/// renews it and finds the other server as the holder. The lock is not
+/// exclusive for that reason, a server only becomes active once it kept the
+/// lock for a lock TTL.
+pub struct ReplicaLock {
+    target: Arc<dyn ReplicaTarget>,
+}
+
+impl ReplicaLock {
+    pub fn new(target: Arc<dyn ReplicaTarget>) -> Self {
+        Self { target }
+    }
+
+    async fn holder(&self) -> anyhow::Result<Option<LockHolder>> {
+        let keys = self.target.list(LOCK_KEY).await?;
+        if !keys.iter().any(|key| key == LOCK_KEY) {
+            return Ok(None);
+        }
+        let data = self.target.get(LOCK_KEY).await?;
+        Ok(Some(serde_json::from_slice(&data)?))
+    }
+}
+
+impl HaLock for ReplicaLock {
+    fn acquire<'a>(
+        &'a self,
+        node: &'a LockHolder,
+    ) -> BoxFuture<'a, anyhow::Result<Option<LockHolder>>> {
+        Box::pin(async move {
+            let holder = self.holder().await?;
+            if let Some(holder) =
+                holder.filter(|holder| holder.id != node.id && holder.expires_at > Utc::now())
+            {
+                return Ok(Some(holder));
+            }
+
+            self.target.put(LOCK_KEY, serde_json::to_vec(node)?).await?;
+            // Another server might have taken the lock at the same time, the
+            // last write wins
+            self.holder().await
+        })
+    }
+
+    fn release<'a>(&'a self, node: &'a LockHolder) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            if self
+                .holder()
+                .await?
+                .is_some_and(|holder| holder.id == node.id)
+            {
+                let expired = LockHolder {
+                    expires_at: Utc::now(),
+                    ..node.clone()
+                };
+                self.target
+                    .put(LOCK_KEY, serde_json::to_vec(&expired)?)
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn is_exclusive(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::replication::DirectoryTarget;
+
+    fn node(id: &str) -> LockHolder {
+        LockHolder {
+            id: id.into(),
+            address: format!("http://{id}:8080"),
+            active_since: Utc::now(),
+            expires_at: Utc::now() + Duration::seconds(15),
+        }
+    }
+
+    #[tokio::test]
+    async fn file_lock() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("lock").to_str().unwrap().to_string();
+        let lock_a = FileLock::new(path.clone());
+        let lock_b = FileLock::new(path);
+        let (a, b) = (node("a"), node("b"));
+
+        assert_eq!(lock_a.acquire(&a).await.unwrap(), Some(a.clone()));
+        assert_eq!(lock_b.acquire(&b).await.unwrap(), Some(a.clone()));
+        assert_eq!(lock_a.acquire(&a).await.unwrap(), Some(a.clone()));
+
+        lock_a.release(&a).await.unwrap();
+        assert_eq!(lock_b.acquire(&b).await.unwrap(), Some(b.clone()));
+        assert_eq!(lock_a.acquire(&a).await.unwrap(), Some(b));
+    }
+
+    #[tokio::test]
+    async fn replica_lock() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let lock = ReplicaLock::new(Arc::new(DirectoryTarget::new(tmpdir.path())));
+        let (a, b) = (node("a"), node("b"));
+
+        assert_eq!(lock.acquire(&a).await.unwrap(), Some(a.clone()));
+        assert_eq!(lock.acquire(&b).await.unwrap(), Some(a.clone()));
+
+        // An expired lock is taken over
+        let expired = LockHolder {
+            expires_at: Utc::now() - Duration::seconds(1),
+            ..a.clone()
+        };
+        lock.acquire(&expired).await.unwrap();
+        assert_eq!(lock.acquire(&b).await.unwrap(), Some(b.clone()));
+
+        // Releasing a lock that is held by another server does nothing
+        lock.release(&a).await.unwrap();
+        assert_eq!(lock.acquire(&a).await.unwrap(), Some(b.clone()));
+        lock.release(&b).await.unwrap();
+        assert_eq!(lock.acquire(&a).await.unwrap(), Some(a));
+    }
+}
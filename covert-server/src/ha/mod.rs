@@ -0,0 +1,269 @@
+//! Which server of a cluster is the active server.
+//!
+//! The active server accepts writes, ships its changes to the replica and
+//! runs the expiration manager. Standbys follow the replica and redirect the
+//! requests they can't serve to the active server. In high availability mode
+//! the servers compete for a lock and the server that holds it is active.
+
+mod lock;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+
+pub use self::lock::{FileLock, HaLock, LockHolder, ReplicaLock};
+use crate::{replication::replica_target, HaConfig, HaLockConfig, ReplicationConfig};
+
+/// The active server as known by this server.
+#[derive(Debug, Clone)]
+pub struct Leader {
+    pub address: String,
+    pub active_since: Option<DateTime<Utc>>,
+}
+
+struct Election {
+    lock: Box<dyn HaLock>,
+    id: String,
+    address: String,
+    lock_ttl: Duration,
+    /// Unix timestamp in milliseconds of when this server became active.
+    active_since: AtomicI64,
+    /// Unix timestamp in milliseconds of when the lock held by this server
+    /// expires.
+    held_until: AtomicI64,
+}
+
+pub struct Leadership {
+    active: AtomicBool,
+    leader: RwLock<Option<Leader>>,
+    /// Only set in high availability mode.
+    election: Option<Election>,
+}
+
+impl Leadership {
+    /// A server without standbys, it is always active.
+    #[must_use]
+    pub fn single() -> Self {
+        Self {
+            active: AtomicBool::new(true),
+            leader: RwLock::new(None),
+            election: None,
+        }
+    }
+
+    /// A standby of a fixed active server.
+    #[must_use]
+    pub fn follow(active_address: String) -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            leader: RwLock::new(Some(Leader {
+                address: active_address,
+                active_since: None,
+            })),
+            election: None,
+        }
+    }
+
+    /// A server that is active while it holds the lock.
+    pub fn elect(config: &HaConfig, replication: &ReplicationConfig) -> anyhow::Result<Self> {
+        let lock: Box<dyn HaLock> = match &config.lock {
+            HaLockConfig::File(file) => Box::new(FileLock::new(file.path.clone())),
+            HaLockConfig::Replica => Box::new(ReplicaLock::new(replica_target(replication)?)),
+        };
+        Ok(Self {
+            active: AtomicBool::new(false),
+            leader: RwLock::new(None),
+            election: Some(Election {
+                lock,
+                id: Uuid::new_v4().to_string(),
+                address: config.address.clone(),
+                lock_ttl: config.lock_ttl,
+                active_since: AtomicI64::new(0),
+                held_until: AtomicI64::new(0),
+            }),
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    pub fn set_active(&self, active: bool) {
+        if let Some(election) = self.election.as_ref() {
+            let active_since = if active {
+                Utc::now().timestamp_millis()
+            } else {
+                0
+            };
+            election.active_since.store(active_since, Ordering::SeqCst);
+        }
+        self.active.store(active, Ordering::SeqCst);
+    }
+
+    pub fn ha_enabled(&self) -> bool {
+        self.election.is_some()
+    }
+
+    /// The active server, unless this is a server without standbys.
+    pub fn leader(&self) -> Option<Leader> {
+        match self.election.as_ref() {
+            Some(election) if self.is_active() => Some(Leader {
+                address: election.address.clone(),
+                active_since: timestamp(election.active_since.load(Ordering::SeqCst)),
+            }),
+            _ => self.leader.read().ok().and_then(|leader| leader.clone()),
+        }
+    }
+
+    /// How often the lock is renewed, or taken by a standby once it is free.
+    pub fn renew_interval(&self) -> Duration {
+        self.election
+            .as_ref()
+            .map_or(Duration::MAX, |election| election.lock_ttl / 3)
+    }
+
+    /// Take or renew the lock. Returns whether this server holds the lock.
+    pub async fn campaign(&self) -> anyhow::Result<bool> {
+        let Some(election) = self.election.as_ref() else {
+            return Ok(self.is_active());
+        };
+        let now = Utc::now();
+        let ttl = chrono::Duration::from_std(election.lock_ttl)?;
+        let node = LockHolder {
+            id: election.id.clone(),
+            address: election.address.clone(),
+            active_since: timestamp(election.active_since.load(Ordering::SeqCst)).unwrap_or(now),
+            expires_at: now + ttl,
+        };
+
+        let holder = election.lock.acquire(&node).await?;
+        let held = holder.as_ref().is_some_and(|holder| holder.id == node.id);
+        if held {
+            election
+                .held_until
+                .store(node.expires_at.timestamp_millis(), Ordering::SeqCst);
+        }
+        if let Ok(mut leader) = self.leader.write() {
+            *leader = holder.map(|holder| Leader {
+                address: holder.address,
+                active_since: Some(holder.active_since),
+            });
+        }
+        Ok(held)
+    }
+
+    /// Take the lock as a standby. Returns whether this server holds the lock
+    /// and can become the active server.
+    ///
+    /// A lock that is not exclusive is renewed for a lock TTL first, in which
+    /// any other server that took it at the same time finds out that it lost
+    /// the lock.
+    pub async fn take_over(&self) -> anyhow::Result<bool> {
+        let Some(election) = self.election.as_ref() else {
+            return Ok(self.is_active());
+        };
+        if !self.campaign().await? {
+            return Ok(false);
+        }
+        if election.lock.is_exclusive() {
+            return Ok(true);
+        }
+
+        let settled_at = Instant::now() + election.lock_ttl;
+        while Instant::now() < settled_at {
+            tokio::time::sleep(self.renew_interval()).await;
+            if !self.campaign().await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether the lock this server held has expired without being renewed.
+    pub fn lock_expired(&self) -> bool {
+        self.election.as_ref().is_some_and(|election| {
+            election.held_until.load(Ordering::SeqCst) < Utc::now().timestamp_millis()
+        })
+    }
+
+    /// Give up the lock so a standby can take over right away.
+    pub async fn resign(&self) -> anyhow::Result<()> {
+        let Some(election) = self.election.as_ref() else {
+            return Ok(());
+        };
+        let node = LockHolder {
+            id: election.id.clone(),
+            address: election.address.clone(),
+            active_since: Utc::now(),
+            expires_at: Utc::now(),
+        };
+        election.lock.release(&node).await
+    }
+}
+
+fn timestamp(millis: i64) -> Option<DateTime<Utc>> {
+    (millis > 0)
+        .then(|| Utc.timestamp_millis_opt(millis).single())
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{lock::LOCK_KEY, *};
+    use crate::replication::{DirectoryTarget, ReplicaTarget};
+
+    fn leadership(id: &str, target: Arc<dyn ReplicaTarget>) -> Leadership {
+        Leadership {
+            active: AtomicBool::new(false),
+            leader: RwLock::new(None),
+            election: Some(Election {
+                lock: Box::new(ReplicaLock::new(target)),
+                id: id.into(),
+                address: format!("http://{id}:8080"),
+                lock_ttl: Duration::from_millis(300),
+                active_since: AtomicI64::new(0),
+                held_until: AtomicI64::new(0),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn replica_lock_is_kept_for_a_lock_ttl_before_taking_over() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let target: Arc<dyn ReplicaTarget> = Arc::new(DirectoryTarget::new(tmpdir.path()));
+        let a = leadership("a", Arc::clone(&target));
+        let b = leadership("b", Arc::clone(&target));
+
+        let started_at = Instant::now();
+        assert!(a.take_over().await.unwrap());
+        assert!(started_at.elapsed() >= Duration::from_millis(300));
+
+        // Another server that took the expired lock at the same time, with
+        // its write landing last, is found while taking over
+        a.resign().await.unwrap();
+        let b_holder = LockHolder {
+            id: "b".into(),
+            address: "http://b:8080".into(),
+            active_since: Utc::now(),
+            expires_at: Utc::now() + chrono::Duration::seconds(15),
+        };
+        let (a_held, ()) = tokio::join!(a.take_over(), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            target
+                .put(LOCK_KEY, serde_json::to_vec(&b_holder).unwrap())
+                .await
+                .unwrap();
+        });
+        assert!(!a_held.unwrap());
+        assert!(b.take_over().await.unwrap());
+    }
+}
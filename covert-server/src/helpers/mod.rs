@@ -1 +1,2 @@
+pub mod pgp;
 pub mod sqlite;
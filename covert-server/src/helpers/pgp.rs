@@ -0,0 +1,127 @@
+//! Encryption of secrets with a PGP public key, so only the holder of the
+//! private key can read them.
+
+use pgp::{
+    composed::{Deserializable, Message, SignedPublicKey},
+    crypto::sym::SymmetricKeyAlgorithm,
+    types::KeyTrait,
+};
+
+use crate::error::{Error, ErrorType};
+
+#[derive(Debug)]
+pub struct PgpKey(SignedPublicKey);
+
+impl PgpKey {
+    /// Parse an ASCII armored public key.
+    pub fn parse(armored: &str) -> Result<Self, Error> {
+        let (key, _headers) = SignedPublicKey::from_string(armored)
+            .map_err(|_| ErrorType::BadRequest("Malformed PGP public key".into()))?;
+        key.verify()
+            .map_err(|_| ErrorType::BadRequest("Invalid PGP public key".into()))?;
+        let key = Self(key);
+        if !key.can_encrypt() {
+            return Err(
+                ErrorType::BadRequest("PGP public key is not an encryption key".into()).into(),
+            );
+        }
+        Ok(key)
+    }
+
+    #[must_use]
+    pub fn fingerprint(&self) -> String {
+        hex::encode(self.0.fingerprint())
+    }
+
+    fn can_encrypt(&self) -> bool {
+        self.0.is_encryption_key()
+            || self
+                .0
+                .public_subkeys
+                .iter()
+                .any(KeyTrait::is_encryption_key)
+    }
+
+    /// Encrypt the data for the key. Returns an ASCII armored PGP message.
+    pub fn encrypt(&self, data: &[u8]) -> Result<String, Error> {
+        let mut rng = rand::thread_rng();
+        let message = Message::new_literal_bytes("", data);
+        let alg = SymmetricKeyAlgorithm::AES256;
+
+        // Prefer an encryption subkey over the primary key
+        let encrypted = match self
+            .0
+            .public_subkeys
+            .iter()
+            .find(KeyTrait::is_encryption_key)
+        {
+            Some(subkey) => message.encrypt_to_keys(&mut rng, alg, &[subkey]),
+            None => message.encrypt_to_keys(&mut rng, alg, &[&self.0]),
+        };
+        encrypted
+            .and_then(|message| message.to_armored_string(None))
+            .map_err(|err| ErrorType::InternalError(anyhow::Error::msg(err.to_string())).into())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use pgp::{
+        composed::{KeyType, SecretKeyParamsBuilder, SignedSecretKey, SubkeyParamsBuilder},
+        types::SecretKeyTrait,
+    };
+
+    use super::*;
+
+    /// Generate a key pair. Returns the secret key and the ASCII armored
+    /// public key.
+    pub(crate) fn generate_key() -> (SignedSecretKey, String) {
+        let params = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_sign(true)
+            .can_create_certificates(true)
+            .primary_user_id("covert <covert@example.com>".into())
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let secret_key = params.generate().unwrap().sign(String::new).unwrap();
+        let public_key = secret_key
+            .public_key()
+            .sign(&secret_key, String::new)
+            .unwrap()
+            .to_armored_string(None)
+            .unwrap();
+        (secret_key, public_key)
+    }
+
+    pub(crate) fn decrypt(secret_key: &SignedSecretKey, armored: &str) -> Vec<u8> {
+        let (message, _) = Message::from_string(armored).unwrap();
+        let (mut decrypter, _) = message.decrypt(String::new, &[secret_key]).unwrap();
+        decrypter
+            .next()
+            .unwrap()
+            .unwrap()
+            .get_content()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn encrypt_for_public_key() {
+        let (secret_key, public_key) = generate_key();
+        let key = PgpKey::parse(&public_key).unwrap();
+        assert_eq!(key.fingerprint(), hex::encode(secret_key.fingerprint()));
+
+        let encrypted = key.encrypt(b"secret").unwrap();
+        assert!(encrypted.starts_with("-----BEGIN PGP MESSAGE-----"));
+        assert_eq!(decrypt(&secret_key, &encrypted), b"secret");
+
+        assert!(PgpKey::parse("not a key").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, OsRng},
+    Aes256Gcm, KeyInit, Nonce,
+};
+
+use crate::{AutoUnsealConfig, CommandKekConfig, FileKekConfig};
+
+/// Provider of a key encryption key (KEK) used to encrypt the master key for
+/// auto-unseal.
+pub trait KekProvider: Send + Sync {
+    fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+impl AutoUnsealConfig {
+    pub fn provider(&self) -> anyhow::Result<Box<dyn KekProvider>> {
+        match self {
+            AutoUnsealConfig::File(config) => Ok(Box::new(FileKekProvider::new(config)?)),
+            AutoUnsealConfig::Command(config) => Ok(Box::new(CommandKekProvider {
+                config: config.clone(),
+            })),
+        }
+    }
+}
+
+const NONCE_LENGTH: usize = 12;
+
+/// AES-256-GCM with a key read from a file. The ciphertext is prefixed with
+/// the nonce.
+pub struct FileKekProvider {
+    cipher: Aes256Gcm,
+}
+
+impl FileKekProvider {
+    pub fn new(config: &FileKekConfig) -> anyhow::Result<Self> {
+        let key = std::fs::read_to_string(&config.key_file)?;
+        let key = hex::decode(key.trim())
+            .map_err(|_| anyhow::Error::msg("Key encryption key file is not hex encoded"))?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow::Error::msg("Key encryption key must be 256 bits"))?;
+        Ok(Self { cipher })
+    }
+}
+
+impl KekProvider for FileKekProvider {
+    fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::Error::msg("Unable to encrypt with key encryption key"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if ciphertext.len() < NONCE_LENGTH {
+            return Err(anyhow::Error::msg("Ciphertext is too short"));
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(NONCE_LENGTH);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::Error::msg("Unable to decrypt with key encryption key"))
+    }
+}
+
+/// Runs external commands, e.g. the CLI of a KMS or HSM, that read the input
+/// from stdin and write the output to stdout.
+pub struct CommandKekProvider {
+    config: CommandKekConfig,
+}
+
+impl KekProvider for CommandKekProvider {
+    fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        run(&self.config.encrypt_command, plaintext)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        run(&self.config.decrypt_command, ciphertext)
+    }
+}
+
+fn run(command: &[String], input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow::Error::msg("Empty key encryption key command"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Write in another thread to not block on a full stdout pipe
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::Error::msg("Unable to open stdin of command"))?;
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .map_err(|_| anyhow::Error::msg("Unable to write to stdin of command"))??;
+    if !output.status.success() {
+        return Err(anyhow::Error::msg(format!(
+            "Key encryption key command `{program}` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("kek");
+        std::fs::write(&key_file, format!("{}\n", hex::encode([7; 32]))).unwrap();
+        let config = AutoUnsealConfig::File(FileKekConfig {
+            key_file: key_file.to_str().unwrap().to_string(),
+        });
+        let provider = config.provider().unwrap();
+
+        let ciphertext = provider.encrypt(b"master-key").unwrap();
+        assert_ne!(ciphertext, b"master-key");
+        assert_eq!(provider.decrypt(&ciphertext).unwrap(), b"master-key");
+        assert!(provider.decrypt(&ciphertext[1..]).is_err());
+
+        std::fs::write(&key_file, hex::encode([7; 16])).unwrap();
+        assert!(config.provider().is_err());
+    }
+
+    #[test]
+    fn command_provider() {
+        let provider = AutoUnsealConfig::Command(CommandKekConfig {
+            encrypt_command: vec!["tr".into(), "a-z".into(), "n-za-m".into()],
+            decrypt_command: vec!["tr".into(), "n-za-m".into(), "a-z".into()],
+        })
+        .provider()
+        .unwrap();
+        let ciphertext = provider.encrypt(b"master").unwrap();
+        assert_eq!(ciphertext, b"znfgre");
+        assert_eq!(provider.decrypt(&ciphertext).unwrap(), b"master");
+
+        let provider = AutoUnsealConfig::Command(CommandKekConfig {
+            encrypt_command: vec!["false".into()],
+            decrypt_command: vec!["false".into()],
+        })
+        .provider()
+        .unwrap();
+        assert!(provider.encrypt(b"master").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use covert_types::{error::ApiError, request::Request};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use crate::{
+    audit::{AuditBroker, AuditRequest},
+    response::ResponseWithCtx,
+};
+
+/// Records every request and response in the enabled audit devices. The
+/// request is rejected if it can not be recorded.
+#[derive(Clone)]
+pub struct AuditService<S> {
+    inner: S,
+    broker: Arc<AuditBroker>,
+}
+
+impl<S> AuditService<S> {
+    pub fn new(inner: S, broker: Arc<AuditBroker>) -> Self {
+        Self { inner, broker }
+    }
+}
+
+impl<S> Service<Request> for AuditService<S>
+where
+    S: Service<Request, Response = ResponseWithCtx, Error = ApiError> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = ResponseWithCtx;
+
+    type Error = ApiError;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let mut this = self.clone();
+        Box::pin(async move {
+            let audit_req = AuditRequest::new(&req);
+            this.broker.log_request(&audit_req).await?;
+
+            let resp = this.inner.call(req).await;
+
+            this.broker.log_response(&audit_req, &resp).await?;
+            resp
+        })
+    }
+}
+
+pub struct AuditLayer {
+    broker: Arc<AuditBroker>,
+}
+
+impl AuditLayer {
+    pub fn new(broker: Arc<AuditBroker>) -> Self {
+        Self { broker }
+    }
+}
+
+impl<S> Layer<S> for AuditLayer {
+    type Service = AuditService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuditService::new(inner, Arc::clone(&self.broker))
+    }
+}
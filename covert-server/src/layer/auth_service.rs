@@ -1,31 +1,38 @@
 use std::str::FromStr;
 
 use covert_types::{
-    auth::AuthPolicy, error::ApiError, request::Request, state::StorageState, token::Token,
+    auth::AuthPolicy,
+    error::ApiError,
+    policy::Policy,
+    request::{Operation, Request},
+    state::StorageState,
+    token::Token,
 };
 use futures::future::BoxFuture;
+use serde_json::{Map, Value};
 use tower::{Layer, Service};
 use tracing::error;
 
 use crate::{
-    repos::{namespace::NamespaceRepo, token::TokenRepo},
+    context::Context,
+    error::Error,
+    repos::{
+        namespace::NamespaceRepo,
+        token::{TokenRepo, TokenUse},
+    },
     response::ResponseWithCtx,
+    system::{revoke_token_and_lease, CUBBYHOLE_MOUNT_PATH, TOKEN_SELF_PATHS},
 };
 
 #[derive(Clone)]
 pub struct AuthService<S: Service<Request>> {
     inner: S,
-    token_repo: TokenRepo,
-    namespace_repo: NamespaceRepo,
+    ctx: Context,
 }
 
 impl<S: Service<Request>> AuthService<S> {
-    pub fn new(inner: S, token_repo: TokenRepo, namespace_repo: NamespaceRepo) -> Self {
-        Self {
-            inner,
-            token_repo,
-            namespace_repo,
-        }
+    pub fn new(inner: S, ctx: Context) -> Self {
+        Self { inner, ctx }
     }
 }
 
@@ -50,29 +57,56 @@ where
     fn call(&mut self, mut req: Request) -> Self::Future {
         let mut this = self.clone();
         Box::pin(async move {
-            let authorized = is_authorized(&req, &this.token_repo, &this.namespace_repo).await?;
-            if authorized {
-                req.extensions.insert(AuthPolicy::Authenticated);
-            } else {
-                req.extensions.insert(AuthPolicy::Unauthenticated);
+            let token_repo = &this.ctx.repos.token;
+            let mut policy = authorize(&req, token_repo, &this.ctx.repos.namespace).await?;
+            let mut exhausted = None;
+            if policy != AuthPolicy::Unauthenticated {
+                if let Some(token) = req.token.as_deref().map(Token::from_str).transpose()? {
+                    // Backends scoping data to the caller, like the cubbyhole,
+                    // need the verified token and its accessor.
+                    let info = token_repo.lookup(&token).await?;
+
+                    // Every authorized request uses up one of the uses of
+                    // tokens with a limited number of uses.
+                    match token_repo.consume_use(&token).await? {
+                        TokenUse::Denied => policy = AuthPolicy::Unauthenticated,
+                        token_use => {
+                            if let Some(info) = info {
+                                req.extensions.insert(info.accessor.clone());
+                                if token_use == TokenUse::Remaining(0) {
+                                    exhausted = Some(info);
+                                }
+                            }
+                            req.extensions.insert(token);
+                        }
+                    }
+                }
+            }
+            req.extensions.insert(policy);
+
+            let resp = this.inner.call(req).await;
+
+            // A token is revoked once the request using its last use is done
+            if let Some(info) = exhausted {
+                if let Err(error) =
+                    revoke_token_and_lease(&this.ctx, &info.accessor, &info.namespace_id).await
+                {
+                    error!(?error, "Failed to revoke token without uses left");
+                }
             }
 
-            this.inner.call(req).await
+            resp
         })
     }
 }
 
 pub struct AuthServiceLayer {
-    token_repo: TokenRepo,
-    namespace_repo: NamespaceRepo,
+    ctx: Context,
 }
 
 impl AuthServiceLayer {
-    pub fn new(token_repo: TokenRepo, namespace_repo: NamespaceRepo) -> Self {
-        Self {
-            token_repo,
-            namespace_repo,
-        }
+    pub fn new(ctx: Context) -> Self {
+        Self { ctx }
     }
 }
 
@@ -80,50 +114,132 @@ impl<S: Service<Request>> Layer<S> for AuthServiceLayer {
     type Service = AuthService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        AuthService::new(inner, self.token_repo.clone(), self.namespace_repo.clone())
+        AuthService::new(inner, self.ctx.clone())
     }
 }
 
-async fn is_authorized(
+/// Resolve the level of access the token of the request has to the requested
+/// path and operation.
+async fn authorize(
     req: &Request,
     token_repo: &TokenRepo,
     namespace_repo: &NamespaceRepo,
-) -> Result<bool, ApiError> {
+) -> Result<AuthPolicy, ApiError> {
     if req.extensions.get::<StorageState>() != Some(&StorageState::Unsealed) {
-        return Ok(false);
+        return Ok(AuthPolicy::Unauthenticated);
     }
 
     let Some(token) = req.token.as_ref() else {
-        return Ok(false);
+        return Ok(AuthPolicy::Unauthenticated);
     };
     let token = Token::from_str(token)?;
-    let mut policies = token_repo.lookup_policies(&token).await?;
+    let Some((policy_namespace_prefix, policies)) =
+        token_policies(&token, token_repo, namespace_repo).await?
+    else {
+        return Ok(AuthPolicy::Unauthenticated);
+    };
+
+    Ok(path_policy(
+        &policy_namespace_prefix,
+        &policies,
+        &req.namespace.join("/"),
+        &req.path,
+        req.operation,
+        Some(&request_parameters(req)),
+    ))
+}
+
+/// Resolve the level of access a token, with the policies returned by
+/// [`token_policies`], has to an operation on a path of a namespace. The
+/// parameter constraints are only checked when the parameters are known.
+pub(crate) fn path_policy(
+    policy_namespace_prefix: &str,
+    policies: &[Policy],
+    namespace_prefix: &str,
+    path: &str,
+    operation: Operation,
+    parameters: Option<&Map<String, Value>>,
+) -> AuthPolicy {
+    // Every token has access to its own cubbyhole, and can manage itself, in
+    // the namespace it was created in.
+    if namespace_prefix == policy_namespace_prefix
+        && (path.starts_with(CUBBYHOLE_MOUNT_PATH) || TOKEN_SELF_PATHS.contains(&path))
+    {
+        return AuthPolicy::Authenticated;
+    }
+    let path = format!("{namespace_prefix}/{path}");
+
+    let Some(rule) = Policy::resolve(policies, &path) else {
+        return AuthPolicy::Unauthenticated;
+    };
+
+    if !rule.allows(&[operation])
+        || parameters.is_some_and(|parameters| !rule.allows_parameters(parameters))
+    {
+        return AuthPolicy::Unauthenticated;
+    }
+
+    if rule.sudo {
+        AuthPolicy::Sudo
+    } else {
+        AuthPolicy::Authenticated
+    }
+}
+
+/// Lookup the policies of a token, with the identity templates rendered and
+/// the paths prefixed with the namespace the policies were created in.
+///
+/// Returns `None` if the token is not valid or has no policies, otherwise the
+/// full path of the namespace of the token together with the policies.
+pub(crate) async fn token_policies(
+    token: &Token,
+    token_repo: &TokenRepo,
+    namespace_repo: &NamespaceRepo,
+) -> Result<Option<(String, Vec<Policy>)>, Error> {
+    let mut policies = token_repo.lookup_policies(token).await?;
 
     let Some(policy_namespace_id) = policies.get(0).map(|p| &p.namespace_id).cloned() else {
-        return Ok(false);
+        return Ok(None);
     };
     let policy_namespace_prefix = namespace_repo.get_full_path(&policy_namespace_id).await?;
 
-    let namespace_prefix = req.namespace.join("/");
-    let path = format!("{}/{}", namespace_prefix, req.path);
-
-    let is_authorized = policies.iter_mut().any(|policy| {
-        // This should never happen, but it is a nice extra safeguard.
-        if policy.namespace_id != policy_namespace_id {
-            error!("Token had attached policies from different namespaces");
-            return false;
+    // Render identity templates for the entity of the token
+    if policies.iter().any(Policy::is_templated) {
+        let Some(identity) = token_repo.lookup_identity(token).await? else {
+            return Ok(None);
+        };
+        for policy in &mut policies {
+            policy.render_templates(&identity.entity_name, &identity.aliases);
         }
+    }
 
-        // Attach the namespace prefix to the policy paths from where the
-        // namespace they were created in.
-        for path in &mut policy.paths {
-            let maybe_slash = if path.path.starts_with('/') { "" } else { "/" };
-            path.path = format!("{policy_namespace_prefix}{maybe_slash}{}", path.path);
+    // This should never happen, but it is a nice extra safeguard.
+    policies.retain(|policy| {
+        if policy.namespace_id == policy_namespace_id {
+            true
+        } else {
+            error!("Token had attached policies from different namespaces");
+            false
         }
-        policy.is_authorized(&path, &[req.operation])
     });
 
-    Ok(is_authorized)
+    // Attach the namespace prefix to the policy paths from where the
+    // namespace they were created in.
+    for path in policies.iter_mut().flat_map(|policy| &mut policy.paths) {
+        let maybe_slash = if path.path.starts_with('/') { "" } else { "/" };
+        path.path = format!("{policy_namespace_prefix}{maybe_slash}{}", path.path);
+    }
+
+    Ok(Some((policy_namespace_prefix, policies)))
+}
+
+/// The top level fields of the JSON body of a request. Bodies that are not a
+/// JSON object have no parameters.
+fn request_parameters(req: &Request) -> Map<String, Value> {
+    if req.data.is_empty() {
+        return Map::new();
+    }
+    serde_json::from_slice(&req.data).unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -133,9 +249,12 @@ mod tests {
     use bytes::Bytes;
     use chrono::{Duration, Utc};
     use covert_types::{
-        entity::Entity,
-        policy::{PathPolicy, Policy},
+        backend::BackendType,
+        entity::{Entity, EntityAlias},
+        mount::{MountConfig, MountEntry},
+        policy::PathPolicy,
         request::Operation,
+        token::TokenAccessor,
     };
     use hyper::http::Extensions;
     use sqlx::SqlitePool;
@@ -169,10 +288,7 @@ mod tests {
 
         let policy = Policy {
             name: "foo-policy".to_string(),
-            paths: vec![PathPolicy {
-                path: "*".to_string(),
-                operations: vec![Operation::Create],
-            }],
+            paths: vec![PathPolicy::new("*".to_string(), vec![Operation::Create])],
             namespace_id: ns.id.clone(),
         };
         repos.policy.create(&policy).await.unwrap();
@@ -185,6 +301,11 @@ mod tests {
         // Create token for entity
         let token = TokenEntry {
             id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
             entity_name: entity.name.clone(),
             expires_at: None,
             issued_at: Utc::now(),
@@ -206,10 +327,10 @@ mod tests {
             headers: HashMap::default(),
         };
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(authorized);
+        assert_eq!(authorized, AuthPolicy::Authenticated);
     }
 
     #[tokio::test]
@@ -236,10 +357,7 @@ mod tests {
 
         let policy = Policy {
             name: "foo-policy".to_string(),
-            paths: vec![PathPolicy {
-                path: "*".to_string(),
-                operations: vec![Operation::Create],
-            }],
+            paths: vec![PathPolicy::new("*".to_string(), vec![Operation::Create])],
             namespace_id: ns.id.clone(),
         };
         repos.policy.create(&policy).await.unwrap();
@@ -252,6 +370,11 @@ mod tests {
         // Create token for entity
         let token = TokenEntry {
             id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
             entity_name: entity.name.clone(),
             expires_at: Some(Utc::now() - Duration::hours(1)),
             issued_at: Utc::now() - Duration::hours(2),
@@ -273,10 +396,10 @@ mod tests {
             headers: HashMap::default(),
         };
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(!authorized);
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
     }
 
     #[tokio::test]
@@ -307,10 +430,10 @@ mod tests {
             headers: HashMap::default(),
         };
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(!authorized);
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
     }
 
     #[tokio::test]
@@ -337,10 +460,7 @@ mod tests {
 
         let policy = Policy {
             name: "foo-policy".to_string(),
-            paths: vec![PathPolicy {
-                path: "*".to_string(),
-                operations: vec![Operation::Create],
-            }],
+            paths: vec![PathPolicy::new("*".to_string(), vec![Operation::Create])],
             namespace_id: ns.id.clone(),
         };
         repos.policy.create(&policy).await.unwrap();
@@ -353,6 +473,11 @@ mod tests {
         // Create token for entity
         let token = TokenEntry {
             id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
             entity_name: entity.name.clone(),
             expires_at: None,
             issued_at: Utc::now(),
@@ -372,25 +497,25 @@ mod tests {
             query_string: String::default(),
             headers: HashMap::default(),
         };
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(!authorized);
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
 
         for state in [StorageState::Uninitialized, StorageState::Sealed] {
             req.extensions.insert(state);
-            let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+            let authorized = authorize(&req, &repos.token, &repos.namespace)
                 .await
                 .unwrap();
-            assert!(!authorized);
+            assert_eq!(authorized, AuthPolicy::Unauthenticated);
         }
 
         // Unsealed and we can authenticate
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(authorized);
+        assert_eq!(authorized, AuthPolicy::Authenticated);
     }
 
     #[tokio::test]
@@ -425,10 +550,7 @@ mod tests {
 
         let policy = Policy {
             name: "foo-policy".to_string(),
-            paths: vec![PathPolicy {
-                path: "*".to_string(),
-                operations: vec![Operation::Create],
-            }],
+            paths: vec![PathPolicy::new("*".to_string(), vec![Operation::Create])],
             namespace_id: foo_ns.id.clone(),
         };
         repos.policy.create(&policy).await.unwrap();
@@ -441,6 +563,11 @@ mod tests {
         // Create token for entity
         let token = TokenEntry {
             id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
             entity_name: entity.name.clone(),
             expires_at: None,
             issued_at: Utc::now(),
@@ -463,10 +590,10 @@ mod tests {
             headers: HashMap::default(),
         };
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(authorized);
+        assert_eq!(authorized, AuthPolicy::Authenticated);
 
         // But accessing sys/ in root namespace does not work
         let mut req = Request {
@@ -482,10 +609,10 @@ mod tests {
             headers: HashMap::default(),
         };
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(!authorized);
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
     }
 
     #[tokio::test]
@@ -512,10 +639,10 @@ mod tests {
 
         let policy = Policy {
             name: "foo-policy".to_string(),
-            paths: vec![PathPolicy {
-                path: "secrets/marketing/*".to_string(),
-                operations: vec![Operation::Create],
-            }],
+            paths: vec![PathPolicy::new(
+                "secrets/marketing/*".to_string(),
+                vec![Operation::Create],
+            )],
             namespace_id: ns.id.clone(),
         };
         repos.policy.create(&policy).await.unwrap();
@@ -528,6 +655,11 @@ mod tests {
         // Create token for entity
         let token = TokenEntry {
             id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
             entity_name: entity.name.clone(),
             expires_at: None,
             issued_at: Utc::now(),
@@ -550,25 +682,25 @@ mod tests {
             headers: HashMap::default(),
         };
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(authorized);
+        assert_eq!(authorized, AuthPolicy::Authenticated);
 
         // Accessing secrets/marketing/* with read is *not* allowed by policy
         req.operation = Operation::Read;
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(!authorized);
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
 
         // Accessing secrets/not-marketing/* with create is *not* allowed by policy
         req.operation = Operation::Create;
         req.path = "secrets/not-marketing/some-key".to_string();
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(!authorized);
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
     }
 
     #[tokio::test]
@@ -610,10 +742,7 @@ mod tests {
 
         let policy = Policy {
             name: "f-root-policy".to_string(),
-            paths: vec![PathPolicy {
-                path: "*".to_string(),
-                operations: vec![Operation::Create],
-            }],
+            paths: vec![PathPolicy::new("*".to_string(), vec![Operation::Create])],
             namespace_id: f_ns.id.clone(),
         };
         repos.policy.create(&policy).await.unwrap();
@@ -626,6 +755,11 @@ mod tests {
         // Create token for entity
         let token = TokenEntry {
             id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
             entity_name: entity.name.clone(),
             expires_at: None,
             issued_at: Utc::now(),
@@ -647,16 +781,381 @@ mod tests {
             headers: HashMap::default(),
         };
         req.extensions.insert(StorageState::Unsealed);
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(authorized);
+        assert_eq!(authorized, AuthPolicy::Authenticated);
 
         // Is *not* authorized in foo_ns
         req.namespace = vec![ns.name.clone(), foo_ns.name.clone()];
-        let authorized = is_authorized(&req, &repos.token, &repos.namespace)
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_when_parameters_violate_policy() {
+        let pool = Arc::new(pool().await);
+        let u_pool = SqlitePool::connect(":memory:").await.unwrap();
+        let repos = Repos::new(pool, u_pool);
+
+        // Setup root namespace
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        repos.namespace.create(&ns).await.unwrap();
+
+        // Create entity and policy in root ns
+        let entity = Entity {
+            name: "ci".to_string(),
+
+            namespace_id: ns.id.clone(),
+        };
+        repos.entity.create(&entity).await.unwrap();
+
+        let paths = PathPolicy::parse(
+            r#"
+            path "psql/creds/ci" {
+                capabilities = ["create"]
+                allowed_parameters = { "ttl" = ["1m", "30m", "1h"] }
+                required_parameters = ["ttl"]
+            }
+            "#,
+        )
+        .unwrap();
+        let policy = Policy {
+            name: "ci-policy".to_string(),
+            paths,
+            namespace_id: ns.id.clone(),
+        };
+        repos.policy.create(&policy).await.unwrap();
+        repos
+            .entity
+            .attach_policy(&entity.name, &policy.name, &ns.id)
+            .await
+            .unwrap();
+
+        // Create token for entity
+        let token = TokenEntry {
+            id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
+            entity_name: entity.name.clone(),
+            expires_at: None,
+            issued_at: Utc::now(),
+            namespace_id: ns.id.clone(),
+        };
+        repos.token.create(&token).await.unwrap();
+
+        let mut req = Request {
+            id: Uuid::default(),
+            operation: Operation::Create,
+            namespace: vec![ns.name.clone()],
+            path: "psql/creds/ci".to_string(),
+            data: Bytes::from_static(br#"{"ttl":"30m"}"#),
+            extensions: Extensions::default(),
+            token: Some(token.id.to_string()),
+            params: Vec::default(),
+            query_string: String::default(),
+            headers: HashMap::default(),
+        };
+        req.extensions.insert(StorageState::Unsealed);
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Authenticated);
+
+        // TTL value is not allowed
+        req.data = Bytes::from_static(br#"{"ttl":"24h"}"#);
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
+
+        // Parameter is not allowed
+        req.data = Bytes::from_static(br#"{"ttl":"1h","name":"admin"}"#);
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
+
+        // Required parameter is missing
+        req.data = Bytes::default();
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn renders_identity_templates_in_policies() {
+        let pool = Arc::new(pool().await);
+        let u_pool = SqlitePool::connect(":memory:").await.unwrap();
+        let repos = Repos::new(pool, u_pool);
+
+        // Setup root namespace
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        repos.namespace.create(&ns).await.unwrap();
+
+        let userpass_mount = MountEntry {
+            id: Uuid::new_v4(),
+            backend_type: BackendType::Userpass,
+            config: MountConfig::default(),
+            path: "auth/userpass/".into(),
+            namespace_id: ns.id.clone(),
+        };
+        repos.mount.create(&userpass_mount).await.unwrap();
+
+        // One templated policy shared by every entity
+        let paths = PathPolicy::parse(
+            r#"
+            path "kv/users/{{identity.entity.name}}/*" {
+                capabilities = ["read"]
+            }
+            path "kv/logins/{{identity.entity.aliases.auth/userpass/.name}}" {
+                capabilities = ["read"]
+            }
+            "#,
+        )
+        .unwrap();
+        let policy = Policy {
+            name: "users".to_string(),
+            paths,
+            namespace_id: ns.id.clone(),
+        };
+        repos.policy.create(&policy).await.unwrap();
+
+        let entity = Entity {
+            name: "alice".to_string(),
+            namespace_id: ns.id.clone(),
+        };
+        repos.entity.create(&entity).await.unwrap();
+        repos
+            .entity
+            .attach_policy(&entity.name, &policy.name, &ns.id)
+            .await
+            .unwrap();
+        repos
+            .entity
+            .attach_alias(
+                &entity.name,
+                &EntityAlias {
+                    name: "alice-login".to_string(),
+                    mount_path: userpass_mount.path.clone(),
+                },
+                &ns.id,
+            )
+            .await
+            .unwrap();
+
+        // Create token for entity
+        let token = TokenEntry {
+            id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
+            entity_name: entity.name.clone(),
+            expires_at: None,
+            issued_at: Utc::now(),
+            namespace_id: ns.id.clone(),
+        };
+        repos.token.create(&token).await.unwrap();
+
+        let mut req = Request {
+            id: Uuid::default(),
+            operation: Operation::Read,
+            namespace: vec![ns.name.clone()],
+            path: "kv/users/alice/foo".to_string(),
+            data: Bytes::default(),
+            extensions: Extensions::default(),
+            token: Some(token.id.to_string()),
+            params: Vec::default(),
+            query_string: String::default(),
+            headers: HashMap::default(),
+        };
+        req.extensions.insert(StorageState::Unsealed);
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
             .await
             .unwrap();
-        assert!(!authorized);
+        assert_eq!(authorized, AuthPolicy::Authenticated);
+
+        // Other entities data is not accessible
+        req.path = "kv/users/bob/foo".to_string();
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
+
+        // Alias name is rendered
+        req.path = "kv/logins/alice-login".to_string();
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Authenticated);
+
+        // The template itself is not a valid path
+        req.path = "kv/users/{{identity.entity.name}}/foo".to_string();
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn sudo_only_when_most_specific_rule_grants_it() {
+        let pool = Arc::new(pool().await);
+        let u_pool = SqlitePool::connect(":memory:").await.unwrap();
+        let repos = Repos::new(pool, u_pool);
+
+        // Setup root namespace
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        repos.namespace.create(&ns).await.unwrap();
+
+        // Create entity and policy in root ns
+        let entity = Entity {
+            name: "operator".to_string(),
+
+            namespace_id: ns.id.clone(),
+        };
+        repos.entity.create(&entity).await.unwrap();
+
+        let paths = PathPolicy::parse(
+            r#"
+            path "sys/*" { capabilities = ["create", "sudo"] }
+            path "sys/mounts/*" { capabilities = ["create"] }
+            "#,
+        )
+        .unwrap();
+        let policy = Policy {
+            name: "operator".to_string(),
+            paths,
+            namespace_id: ns.id.clone(),
+        };
+        repos.policy.create(&policy).await.unwrap();
+        repos
+            .entity
+            .attach_policy(&entity.name, &policy.name, &ns.id)
+            .await
+            .unwrap();
+
+        // Create token for entity
+        let token = TokenEntry {
+            id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
+            entity_name: entity.name.clone(),
+            expires_at: None,
+            issued_at: Utc::now(),
+            namespace_id: ns.id.clone(),
+        };
+        repos.token.create(&token).await.unwrap();
+
+        let mut req = Request {
+            id: Uuid::default(),
+            operation: Operation::Create,
+            namespace: vec![ns.name.clone()],
+            path: "sys/seal".to_string(),
+            data: Bytes::default(),
+            extensions: Extensions::default(),
+            token: Some(token.id.to_string()),
+            params: Vec::default(),
+            query_string: String::default(),
+            headers: HashMap::default(),
+        };
+        req.extensions.insert(StorageState::Unsealed);
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Sudo);
+
+        // The more specific rule does not grant sudo
+        req.path = "sys/mounts/kv/".to_string();
+        let authorized = authorize(&req, &repos.token, &repos.namespace)
+            .await
+            .unwrap();
+        assert_eq!(authorized, AuthPolicy::Authenticated);
+    }
+
+    #[test]
+    fn path_policy_without_parameters() {
+        let paths = PathPolicy::parse(
+            r#"
+            path "psql/creds/ci" {
+                capabilities = ["create"]
+                required_parameters = ["ttl"]
+            }
+            "#,
+        )
+        .unwrap();
+        let policies = vec![Policy {
+            name: "ci-policy".to_string(),
+            paths: paths
+                .into_iter()
+                .map(|mut path| {
+                    path.path = format!("root/{}", path.path);
+                    path
+                })
+                .collect(),
+            namespace_id: Uuid::new_v4().to_string(),
+        }];
+        let policy = |namespace_prefix: &str, path: &str, operation, parameters| {
+            path_policy(
+                "root",
+                &policies,
+                namespace_prefix,
+                path,
+                operation,
+                parameters,
+            )
+        };
+
+        // Parameter constraints only apply when the parameters are known
+        let no_parameters = Map::new();
+        assert_eq!(
+            policy("root", "psql/creds/ci", Operation::Create, None),
+            AuthPolicy::Authenticated
+        );
+        assert_eq!(
+            policy(
+                "root",
+                "psql/creds/ci",
+                Operation::Create,
+                Some(&no_parameters)
+            ),
+            AuthPolicy::Unauthenticated
+        );
+        assert_eq!(
+            policy("root", "psql/creds/ci", Operation::Read, None),
+            AuthPolicy::Unauthenticated
+        );
+
+        // The own cubbyhole is only accessible in the namespace of the token
+        assert_eq!(
+            policy("root", "cubbyhole/foo", Operation::Delete, None),
+            AuthPolicy::Authenticated
+        );
+        assert_eq!(
+            policy("root/foo", "cubbyhole/foo", Operation::Delete, None),
+            AuthPolicy::Unauthenticated
+        );
     }
 }
@@ -0,0 +1,98 @@
+use futures::future::BoxFuture;
+use http_body::Limited;
+use hyper::{header::CONTENT_LENGTH, http, Body, StatusCode};
+use tower::{Layer, Service};
+
+use super::snapshot::SNAPSHOT_RESTORE_PATH;
+
+/// Limit of the request body for all paths but the ones with a limit of their
+/// own.
+const DEFAULT_LIMIT: usize = 1024 * 16;
+
+/// Paths that accept larger request bodies than the default limit.
+const PATH_LIMITS: &[(&str, usize)] = &[
+    // Snapshots contain the whole storage, which is streamed to a file
+    (SNAPSHOT_RESTORE_PATH, 1024 * 1024 * 1024),
+];
+
+fn limit(path: &str) -> usize {
+    PATH_LIMITS
+        .iter()
+        .find(|(limit_path, _)| *limit_path == path)
+        .map_or(DEFAULT_LIMIT, |(_, limit)| *limit)
+}
+
+/// Limits the size of the request body by path. Requests with a larger
+/// `Content-Length` are rejected right away, others fail when the body is
+/// read past the limit.
+#[derive(Clone)]
+pub struct BodyLimitService<S> {
+    inner: S,
+}
+
+impl<S> BodyLimitService<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S> Service<http::Request<Body>> for BodyLimitService<S>
+where
+    S: Service<http::Request<Limited<Body>>, Response = http::Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<Body>;
+
+    type Error = S::Error;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+        let limit = limit(req.uri().path());
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if content_length.is_some_and(|content_length| content_length > limit) {
+            let mut resp = http::Response::new(Body::from("length limit exceeded"));
+            *resp.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            return Box::pin(async move { Ok(resp) });
+        }
+
+        let req = req.map(|body| Limited::new(body, limit));
+        Box::pin(self.inner.call(req))
+    }
+}
+
+pub struct BodyLimitLayer;
+
+impl<S> Layer<S> for BodyLimitLayer {
+    type Service = BodyLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyLimitService::new(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_by_path() {
+        assert_eq!(
+            limit("/v1/sys/storage/snapshot-restore"),
+            1024 * 1024 * 1024
+        );
+        assert_eq!(limit("/v1/sys/storage/snapshot"), DEFAULT_LIMIT);
+        assert_eq!(limit("/v1/secret/foo"), DEFAULT_LIMIT);
+    }
+}
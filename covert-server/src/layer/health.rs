@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use covert_storage::EncryptedPool;
+use covert_types::{
+    error::ApiError,
+    methods::system::{HealthParams, HealthResponse},
+    state::StorageState,
+};
+use futures::future::BoxFuture;
+use hyper::{header::CONTENT_TYPE, http, Body, Method, StatusCode};
+use serde_json::json;
+use tower::{Layer, Service};
+
+use crate::{ha::Leadership, replication::Follower};
+
+const HEALTH_PATH: &str = "/v1/sys/health";
+
+/// Answers `sys/health` before the request reaches the logical request
+/// handling, which would look up the namespace and token in the encrypted
+/// storage. Load balancers are able to poll it as often as they like.
+#[derive(Clone)]
+pub struct HealthService<S> {
+    storage_pool: Arc<EncryptedPool>,
+    cluster_id: String,
+    leadership: Arc<Leadership>,
+    follower: Option<Arc<Follower>>,
+    inner: S,
+}
+
+impl<S> HealthService<S> {
+    pub fn new(
+        inner: S,
+        storage_pool: Arc<EncryptedPool>,
+        cluster_id: String,
+        leadership: Arc<Leadership>,
+        follower: Option<Arc<Follower>>,
+    ) -> Self {
+        Self {
+            storage_pool,
+            cluster_id,
+            leadership,
+            follower,
+            inner,
+        }
+    }
+
+    fn health(&self, query: &str, include_body: bool) -> http::Response<Body> {
+        let Ok(params) = serde_qs::from_str::<HealthParams>(query) else {
+            return ApiError::bad_request().into();
+        };
+
+        let state = self.storage_pool.state();
+        let resp = HealthResponse {
+            initialized: state != StorageState::Uninitialized,
+            sealed: state != StorageState::Unsealed,
+            standby: !self.leadership.is_active(),
+            replication_lagging: !self.leadership.is_active()
+                && self
+                    .follower
+                    .as_ref()
+                    .is_some_and(|follower| follower.is_lagging()),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            server_time: Utc::now(),
+            cluster_id: self.cluster_id.clone(),
+        };
+        let Ok(status_code) = StatusCode::from_u16(status_code(&resp, &params)) else {
+            return ApiError::bad_request().into();
+        };
+
+        let body = if include_body {
+            Body::from(json!({ "data": resp }).to_string())
+        } else {
+            Body::empty()
+        };
+        http::Response::builder()
+            .status(status_code)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap_or_else(|_| ApiError::internal_error().into())
+    }
+}
+
+fn status_code(resp: &HealthResponse, params: &HealthParams) -> u16 {
+    if !resp.initialized {
+        params.uninitcode
+    } else if resp.sealed {
+        params.sealedcode
+    } else if resp.standby && !params.standbyok {
+        params.standbycode
+    } else if resp.replication_lagging {
+        params.laggingcode
+    } else {
+        params.activecode
+    }
+}
+
+impl<S, B> Service<http::Request<B>> for HealthService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<Body>;
+
+    type Error = S::Error;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if req.uri().path() == HEALTH_PATH
+            && (req.method() == Method::GET || req.method() == Method::HEAD)
+        {
+            let resp = self.health(
+                req.uri().query().unwrap_or_default(),
+                req.method() == Method::GET,
+            );
+            return Box::pin(async move { Ok(resp) });
+        }
+        Box::pin(self.inner.call(req))
+    }
+}
+
+pub struct HealthLayer {
+    storage_pool: Arc<EncryptedPool>,
+    cluster_id: String,
+    leadership: Arc<Leadership>,
+    follower: Option<Arc<Follower>>,
+}
+
+impl HealthLayer {
+    pub fn new(
+        storage_pool: Arc<EncryptedPool>,
+        cluster_id: String,
+        leadership: Arc<Leadership>,
+        follower: Option<Arc<Follower>>,
+    ) -> Self {
+        Self {
+            storage_pool,
+            cluster_id,
+            leadership,
+            follower,
+        }
+    }
+}
+
+impl<S> Layer<S> for HealthLayer {
+    type Service = HealthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HealthService::new(
+            inner,
+            Arc::clone(&self.storage_pool),
+            self.cluster_id.clone(),
+            Arc::clone(&self.leadership),
+            self.follower.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_code_by_state() {
+        let mut resp = HealthResponse {
+            initialized: false,
+            sealed: true,
+            standby: false,
+            replication_lagging: false,
+            version: String::new(),
+            server_time: Utc::now(),
+            cluster_id: String::new(),
+        };
+        let mut params = HealthParams::default();
+        assert_eq!(status_code(&resp, &params), 501);
+
+        resp.initialized = true;
+        assert_eq!(status_code(&resp, &params), 503);
+
+        resp.sealed = false;
+        assert_eq!(status_code(&resp, &params), 200);
+
+        resp.standby = true;
+        assert_eq!(status_code(&resp, &params), 429);
+        params.standbyok = true;
+        assert_eq!(status_code(&resp, &params), 200);
+
+        resp.replication_lagging = true;
+        assert_eq!(status_code(&resp, &params), 472);
+        params.laggingcode = 200;
+        assert_eq!(status_code(&resp, &params), 200);
+    }
+}
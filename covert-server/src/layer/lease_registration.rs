@@ -7,6 +7,7 @@ use covert_types::{
     methods::{AuthResponse, SecretLeaseResponse},
     request::Request,
     response::Response,
+    token::TokenAccessor,
     ttl::calculate_ttl,
 };
 use futures::future::BoxFuture;
@@ -70,6 +71,7 @@ where
         let mut this = self.clone();
         Box::pin(async move {
             let ns = req.extensions.get::<Namespace>().cloned();
+            let token_accessor = req.extensions.get::<TokenAccessor>().cloned();
 
             let resp = this.inner.call(req).await?;
             let backend_mount_path = &resp.ctx.backend_mount_path;
@@ -84,7 +86,7 @@ where
                     let ttl = calculate_ttl(now, issued_at, backend_config, lease.ttl)
                         .map_err(|_| ApiError::internal_error())?;
 
-                    let le = LeaseEntry::new(
+                    let mut le = LeaseEntry::new(
                         backend_mount_path.clone(),
                         Some(lease.revoke.path),
                         &lease.revoke.data,
@@ -94,6 +96,7 @@ where
                         ttl,
                         ns.id.clone(),
                     )?;
+                    le.token_accessor = token_accessor.map(|accessor| accessor.to_string());
                     let lease_id = le.id().to_string();
                     this.expiration_manager.register(le).await?;
 
@@ -133,12 +136,14 @@ where
                             this.token_repo.create(&token_entry).await?;
                             let token = token_entry.id();
 
+                            // Leases only reference the token by its accessor so
+                            // the token is not stored in plaintext.
                             let revoke_data = RevokeTokenParams {
-                                token: token.clone(),
+                                accessor: token_entry.accessor().clone(),
                             };
                             // TODO: renew token endpoint not implemented yet
                             let renew_data = RevokeTokenParams {
-                                token: token.clone(),
+                                accessor: token_entry.accessor().clone(),
                             };
                             let lease = LeaseEntry::new(
                                 backend_mount_path.clone(),
@@ -155,6 +160,7 @@ where
 
                             let data = AuthResponse {
                                 token: token.clone(),
+                                accessor: token_entry.accessor().clone(),
                                 lease_id,
                                 ttl: ttl.to_std().map_err(|_| ApiError::internal_error())?,
                             };
@@ -316,8 +322,10 @@ mod tests {
         headers.insert("response-type".to_string(), "lease".to_string());
         headers.insert("mount-path".to_string(), mount.path.to_string());
 
+        let accessor = TokenAccessor::new();
         let mut extensions = Extensions::default();
         extensions.insert(ns.clone());
+        extensions.insert(accessor.clone());
 
         let req = Request {
             id: Uuid::new_v4(),
@@ -346,6 +354,7 @@ mod tests {
             .unwrap()
             .unwrap();
         assert_eq!(lease.issued_mount_path, mount.path);
+        assert_eq!(lease.token_accessor, Some(accessor.to_string()));
     }
 
     #[tokio::test]
@@ -396,10 +405,10 @@ mod tests {
 
         let policy = Policy::new(
             "default".to_string(),
-            vec![PathPolicy {
-                path: "secrets/marketing/".to_string(),
-                operations: vec![Operation::Read],
-            }],
+            vec![PathPolicy::new(
+                "secrets/marketing/".to_string(),
+                vec![Operation::Read],
+            )],
             ns.id.clone(),
         );
         repos.policy.create(&policy).await.unwrap();
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use covert_types::error::ApiError;
+use futures::future::BoxFuture;
+use hyper::{header::CONTENT_TYPE, http, Body, Method, StatusCode};
+use tower::{Layer, Service};
+
+use crate::metrics::Metrics;
+
+pub const METRICS_PATH: &str = "/v1/sys/metrics";
+
+/// How `sys/metrics` is served by a listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsAccess {
+    /// Not served, e.g. on the main listener when the metrics have a
+    /// dedicated listener.
+    Disabled,
+    /// Served to everyone.
+    Unauthenticated,
+    /// Served to tokens with `read` on `sys/metrics`. The request is
+    /// authorized by the `sys/metrics` route before the metrics are rendered.
+    Token,
+}
+
+/// Serves `sys/metrics` in the Prometheus text format and counts the
+/// requests that are rejected because of a missing or insufficient token.
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+    access: MetricsAccess,
+}
+
+impl<S> MetricsService<S> {
+    pub fn new(inner: S, metrics: Arc<Metrics>, access: MetricsAccess) -> Self {
+        Self {
+            inner,
+            metrics,
+            access,
+        }
+    }
+}
+
+async fn render(metrics: &Metrics) -> http::Response<Body> {
+    http::Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, prometheus::TEXT_FORMAT)
+        .body(Body::from(metrics.render().await))
+        .unwrap_or_else(|_| ApiError::internal_error().into())
+}
+
+impl<S, B> Service<http::Request<B>> for MetricsService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<Body>;
+
+    type Error = S::Error;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let metrics = Arc::clone(&self.metrics);
+
+        let is_metrics = req.uri().path() == METRICS_PATH && req.method() == Method::GET;
+        match self.access {
+            MetricsAccess::Disabled if is_metrics => {
+                return Box::pin(async { Ok(ApiError::not_found().into()) });
+            }
+            MetricsAccess::Unauthenticated if is_metrics => {
+                return Box::pin(async move { Ok(render(&metrics).await) });
+            }
+            _ => (),
+        }
+
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let resp = fut.await?;
+            if resp.status() == StatusCode::UNAUTHORIZED || resp.status() == StatusCode::FORBIDDEN {
+                metrics.inc_auth_failures();
+            } else if is_metrics && resp.status() == StatusCode::OK {
+                return Ok(render(&metrics).await);
+            }
+            Ok(resp)
+        })
+    }
+}
+
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+    access: MetricsAccess,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>, access: MetricsAccess) -> Self {
+        Self { metrics, access }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService::new(inner, Arc::clone(&self.metrics), self.access)
+    }
+}
@@ -1,5 +1,14 @@
+pub mod audit;
 pub mod auth_service;
+pub mod body_limit;
+pub mod health;
 pub mod lease_registration;
+pub mod metrics;
 pub mod namespace_extension;
 pub mod request_mapper;
+pub mod request_metrics;
+pub mod response_wrapping;
+pub mod snapshot;
+pub mod standby;
 pub mod storage_state_extension;
+pub mod timeout;
@@ -0,0 +1,90 @@
+use std::{sync::Arc, time::Instant};
+
+use covert_types::{error::ApiError, request::Request};
+use futures::future::BoxFuture;
+use hyper::StatusCode;
+use tower::{Layer, Service};
+
+use crate::{metrics::Metrics, response::ResponseWithCtx, router::RoutedMount};
+
+/// Records the number and latency of requests handled by the backends,
+/// labelled by mount path, backend type, operation and status code.
+#[derive(Clone)]
+pub struct RequestMetricsService<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> RequestMetricsService<S> {
+    pub fn new(inner: S, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+impl<S> Service<Request> for RequestMetricsService<S>
+where
+    S: Service<Request, Response = ResponseWithCtx, Error = ApiError> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = ResponseWithCtx;
+
+    type Error = ApiError;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let mut this = self.clone();
+        Box::pin(async move {
+            let routed = RoutedMount::default();
+            req.extensions.insert(routed.clone());
+            let operation = format!("{:?}", req.operation).to_lowercase();
+
+            let start = Instant::now();
+            let resp = this.inner.call(req).await;
+            let duration = start.elapsed();
+
+            let status = match &resp {
+                Ok(_) => StatusCode::OK,
+                Err(error) => error.status_code,
+            };
+            let (mount_path, backend_type) = routed
+                .get()
+                .map(|(path, backend_type)| (path.as_str(), backend_type.to_string()))
+                .unwrap_or_default();
+            this.metrics.observe_request(
+                mount_path,
+                &backend_type,
+                &operation,
+                status.as_u16(),
+                duration,
+            );
+
+            resp
+        })
+    }
+}
+
+pub struct RequestMetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl RequestMetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for RequestMetricsLayer {
+    type Service = RequestMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestMetricsService::new(inner, Arc::clone(&self.metrics))
+    }
+}
@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use covert_types::{
+    error::ApiError, methods::system::WRAP_TTL_HEADER, request::Request, response::Response,
+    state::StorageState,
+};
+use futures::future::BoxFuture;
+use humantime_serde::re::humantime;
+use tower::{Layer, Service};
+
+use crate::{
+    error::{Error, ErrorType},
+    repos::{namespace::Namespace, wrapping::WrappingRepo},
+    response::ResponseWithCtx,
+    system::{wrap_response, DEFAULT_WRAP_TTL},
+};
+
+const WRAP_PATH: &str = "sys/wrapping/wrap";
+
+/// Replaces the response with a wrapping token when the request asks for it
+/// with the wrap TTL header. The real response is stored until it is
+/// unwrapped or the TTL expires.
+#[derive(Clone)]
+pub struct ResponseWrappingService<S> {
+    inner: S,
+    wrapping_repo: WrappingRepo,
+}
+
+impl<S> ResponseWrappingService<S> {
+    pub fn new(inner: S, wrapping_repo: WrappingRepo) -> Self {
+        Self {
+            inner,
+            wrapping_repo,
+        }
+    }
+}
+
+impl<S> Service<Request> for ResponseWrappingService<S>
+where
+    S: Service<Request, Response = ResponseWithCtx, Error = ApiError> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = ResponseWithCtx;
+
+    type Error = ApiError;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let mut this = self.clone();
+        Box::pin(async move {
+            let mut ttl = wrap_ttl(&req)?;
+            if ttl.is_none() && req.path.trim_matches('/') == WRAP_PATH {
+                ttl = Some(DEFAULT_WRAP_TTL);
+            }
+            let Some(ttl) = ttl else {
+                return this.inner.call(req).await;
+            };
+
+            // Fail before the request is handled so that the response is not
+            // lost if it can't be stored.
+            if req.extensions.get::<StorageState>() != Some(&StorageState::Unsealed) {
+                return Err(Error::from(ErrorType::BadRequest(
+                    "Responses can only be wrapped when the storage is unsealed".to_string(),
+                ))
+                .into());
+            }
+            let ns = req
+                .extensions
+                .get::<Namespace>()
+                .cloned()
+                .ok_or_else(ApiError::internal_error)?;
+            let creation_path = req.path.trim_matches('/').to_string();
+
+            let resp = this.inner.call(req).await?;
+            let data = match resp.response {
+                Response::Raw(data) => data,
+                // Lease and auth responses are converted to raw responses by
+                // the lease registration layer.
+                Response::Lease(_) | Response::Auth(_) => return Err(ApiError::internal_error()),
+            };
+
+            let wrap_info =
+                wrap_response(&this.wrapping_repo, &ns.id, &creation_path, data, ttl).await?;
+            let data = serde_json::to_value(&wrap_info)
+                .map_err(|err| Error::from(ErrorType::BadResponseData(err)))?;
+
+            Ok(ResponseWithCtx {
+                response: Response::Raw(data),
+                ctx: resp.ctx,
+            })
+        })
+    }
+}
+
+/// Parse the wrap TTL header. Accepts a human readable duration like `5m` or
+/// a number of seconds.
+fn wrap_ttl(req: &Request) -> Result<Option<Duration>, Error> {
+    let Some(value) = req.headers.get(&WRAP_TTL_HEADER.to_lowercase()) else {
+        return Ok(None);
+    };
+    let value = value.trim();
+    let ttl = value
+        .parse::<u64>()
+        .map(Duration::from_secs)
+        .or_else(|_| humantime::parse_duration(value))
+        .map_err(|_| ErrorType::BadRequest(format!("`{value}` is not a valid wrap TTL")))?;
+    if ttl.is_zero() {
+        return Err(ErrorType::BadRequest("Wrap TTL must be greater than zero".to_string()).into());
+    }
+    Ok(Some(ttl))
+}
+
+pub struct ResponseWrappingLayer {
+    wrapping_repo: WrappingRepo,
+}
+
+impl ResponseWrappingLayer {
+    pub fn new(wrapping_repo: WrappingRepo) -> Self {
+        Self { wrapping_repo }
+    }
+}
+
+impl<S> Layer<S> for ResponseWrappingLayer {
+    type Service = ResponseWrappingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseWrappingService::new(inner, self.wrapping_repo.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use covert_types::{methods::system::WrapInfo, request::Operation, token::Token};
+    use hyper::http::Extensions;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use crate::{
+        repos::{mount::tests::pool, namespace::NamespaceRepo},
+        response::ResponseContext,
+    };
+
+    use super::*;
+
+    #[allow(clippy::unused_async)]
+    async fn handler(_req: Request) -> Result<ResponseWithCtx, ApiError> {
+        Ok(ResponseWithCtx {
+            response: Response::Raw(json!({ "password": "hunter2" })),
+            ctx: ResponseContext::default(),
+        })
+    }
+
+    fn request(ns: &Namespace, headers: HashMap<String, String>) -> Request {
+        let mut extensions = Extensions::default();
+        extensions.insert(ns.clone());
+        extensions.insert(StorageState::Unsealed);
+
+        Request {
+            id: Uuid::new_v4(),
+            namespace: vec!["root".to_string()],
+            data: Bytes::default(),
+            extensions,
+            headers,
+            operation: Operation::Read,
+            params: Vec::default(),
+            path: "kv/data/foo".to_string(),
+            query_string: String::default(),
+            token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn wraps_response_when_requested() {
+        let pool = Arc::new(pool().await);
+        let repo = WrappingRepo::new(Arc::clone(&pool));
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        NamespaceRepo::new(Arc::clone(&pool))
+            .create(&ns)
+            .await
+            .unwrap();
+
+        let svc = ResponseWrappingService::new(tower::service_fn(handler), repo.clone());
+
+        // Not wrapped without the header
+        let resp = svc
+            .clone()
+            .oneshot(request(&ns, HashMap::new()))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.response.data::<Value>().unwrap(),
+            json!({ "password": "hunter2" })
+        );
+
+        let mut headers = HashMap::new();
+        headers.insert("x-covert-wrap-ttl".to_string(), "2m".to_string());
+        let resp = svc.oneshot(request(&ns, headers)).await.unwrap();
+        let wrap_info = resp.response.data::<WrapInfo>().unwrap();
+        assert_eq!(wrap_info.ttl, Duration::from_mins(2));
+        assert_eq!(wrap_info.creation_path, "kv/data/foo");
+
+        let entry = repo.take(&wrap_info.token, &ns.id).await.unwrap().unwrap();
+        assert_eq!(entry.response, json!({ "password": "hunter2" }));
+        assert!(repo.take(&wrap_info.token, &ns.id).await.unwrap().is_none());
+        assert!(repo.take(&Token::new(), &ns.id).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_wrap_ttl() {
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        let ttl = |value: &str| {
+            let mut headers = HashMap::new();
+            headers.insert("x-covert-wrap-ttl".to_string(), value.to_string());
+            wrap_ttl(&request(&ns, headers)).map_err(|_| ())
+        };
+
+        assert_eq!(ttl("300"), Ok(Some(Duration::from_mins(5))));
+        assert_eq!(ttl("5m"), Ok(Some(Duration::from_mins(5))));
+        assert_eq!(ttl("1h 30m"), Ok(Some(Duration::from_mins(90))));
+        assert!(ttl("0").is_err());
+        assert!(ttl("foo").is_err());
+        assert_eq!(wrap_ttl(&request(&ns, HashMap::new())).unwrap(), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::sync::Arc;
+
+use covert_types::{
+    error::ApiError,
+    methods::system::{
+        SealSnapshot, SNAPSHOT_SEAL_HEADER, SNAPSHOT_SHARES_HEADER, WRAP_TTL_HEADER,
+    },
+};
+use futures::future::BoxFuture;
+use http_body::{Body as _, Limited};
+use hyper::{
+    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    http, Body, Method, StatusCode,
+};
+use serde::Deserialize;
+use tokio::{fs::File, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use crate::config::Config;
+
+pub const SNAPSHOT_PATH: &str = "/v1/sys/storage/snapshot";
+pub const SNAPSHOT_RESTORE_PATH: &str = "/v1/sys/storage/snapshot-restore";
+
+/// File the snapshot handler writes the storage to. It is streamed to the
+/// client once the handler succeeds.
+#[derive(Debug, Clone)]
+pub struct SnapshotFile(pub String);
+
+/// Snapshot sent to the snapshot restore handler. The storage is written to a
+/// file before the request is handled.
+#[derive(Debug, Clone)]
+pub struct SnapshotUpload {
+    pub path: String,
+    pub seal: SealSnapshot,
+    pub shares: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Data<T> {
+    data: T,
+}
+
+/// Streams the storage of snapshots between the client and a file, so the
+/// storage is never read into memory. The seal metadata of a snapshot is sent
+/// in the [`SNAPSHOT_SEAL_HEADER`] header and the handlers get the file from
+/// the request extensions.
+#[derive(Clone)]
+pub struct SnapshotService<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> SnapshotService<S> {
+    pub fn new(inner: S, config: Arc<Config>) -> Self {
+        Self { inner, config }
+    }
+
+    fn snapshot_path(&self) -> String {
+        if self.config.using_inmemory_storage() {
+            std::env::temp_dir()
+                .join(format!("covert.{}.snapshot", Uuid::new_v4()))
+                .to_string_lossy()
+                .to_string()
+        } else {
+            format!(
+                "{}.{}.snapshot",
+                self.config.encrypted_storage_path(),
+                Uuid::new_v4()
+            )
+        }
+    }
+}
+
+fn bad_request(message: &str) -> http::Response<Body> {
+    ApiError {
+        error: anyhow::Error::msg(message.to_string()),
+        status_code: StatusCode::BAD_REQUEST,
+        span_trace: None,
+    }
+    .into()
+}
+
+/// Parse the seal metadata and key shares of a snapshot to restore.
+fn upload_headers(req: &http::Request<Limited<Body>>) -> Option<(SealSnapshot, Vec<String>)> {
+    let seal = req.headers().get(SNAPSHOT_SEAL_HEADER)?.to_str().ok()?;
+    let seal = serde_json::from_str(seal).ok()?;
+    let shares = match req.headers().get(SNAPSHOT_SHARES_HEADER) {
+        Some(shares) => shares
+            .to_str()
+            .ok()?
+            .split(',')
+            .map(str::trim)
+            .filter(|share| !share.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+        None => vec![],
+    };
+    Some((seal, shares))
+}
+
+async fn write_body(path: &str, mut body: Limited<Body>) -> Result<(), ApiError> {
+    let mut file = File::create(path)
+        .await
+        .map_err(|_| ApiError::internal_error())?;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| ApiError::bad_request())?;
+        file.write_all(&chunk)
+            .await
+            .map_err(|_| ApiError::internal_error())?;
+    }
+    file.sync_all()
+        .await
+        .map_err(|_| ApiError::internal_error())
+}
+
+/// Turn the response of the snapshot handler, which contains the seal
+/// metadata, into a response with the storage as body.
+async fn stream_snapshot(
+    resp: http::Response<Body>,
+    path: &str,
+) -> Result<http::Response<Body>, ApiError> {
+    let body = hyper::body::to_bytes(resp.into_body())
+        .await
+        .map_err(|_| ApiError::internal_error())?;
+    let seal = serde_json::from_slice::<Data<SealSnapshot>>(&body)
+        .ok()
+        .and_then(|seal| serde_json::to_string(&seal.data).ok())
+        .and_then(|seal| HeaderValue::from_str(&seal).ok())
+        .ok_or_else(ApiError::internal_error)?;
+
+    // The file is removed right away, the opened file stays readable
+    let file = File::open(path)
+        .await
+        .map_err(|_| ApiError::internal_error())?;
+    let _ = tokio::fs::remove_file(path).await;
+    let len = file
+        .metadata()
+        .await
+        .map_err(|_| ApiError::internal_error())?
+        .len();
+
+    http::Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .header(CONTENT_LENGTH, len)
+        .header(SNAPSHOT_SEAL_HEADER, seal)
+        .body(Body::wrap_stream(ReaderStream::new(file)))
+        .map_err(|_| ApiError::internal_error())
+}
+
+impl<S> Service<http::Request<Limited<Body>>> for SnapshotService<S>
+where
+    S: Service<http::Request<Limited<Body>>, Response = http::Response<Body>>
+        + Clone
+        + Send
+        + 'static,
+    S::Error: Send,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<Body>;
+
+    type Error = S::Error;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<Limited<Body>>) -> Self::Future {
+        let path = req.uri().path();
+        if path == SNAPSHOT_PATH && req.method() == Method::GET {
+            if req.headers().contains_key(WRAP_TTL_HEADER) {
+                let resp = bad_request("Snapshots can't be response wrapped");
+                return Box::pin(async move { Ok(resp) });
+            }
+
+            let path = self.snapshot_path();
+            req.extensions_mut().insert(SnapshotFile(path.clone()));
+            let fut = self.inner.call(req);
+            return Box::pin(async move {
+                let resp = fut.await?;
+                if resp.status() != StatusCode::OK {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return Ok(resp);
+                }
+                match stream_snapshot(resp, &path).await {
+                    Ok(resp) => Ok(resp),
+                    Err(err) => {
+                        let _ = tokio::fs::remove_file(&path).await;
+                        Ok(err.into())
+                    }
+                }
+            });
+        }
+
+        if path == SNAPSHOT_RESTORE_PATH
+            && (req.method() == Method::POST || req.method() == Method::PUT)
+        {
+            let Some((seal, shares)) = upload_headers(&req) else {
+                let resp = bad_request("Snapshot seal metadata is missing or invalid");
+                return Box::pin(async move { Ok(resp) });
+            };
+
+            let path = self.snapshot_path();
+            let (mut parts, body) = req.into_parts();
+            parts.extensions.insert(SnapshotUpload {
+                path: path.clone(),
+                seal,
+                shares,
+            });
+            let req = http::Request::from_parts(parts, Limited::new(Body::empty(), 0));
+            // The storage has to be written before the request is handled
+            let clone = self.inner.clone();
+            let mut inner = std::mem::replace(&mut self.inner, clone);
+            return Box::pin(async move {
+                let resp = match write_body(&path, body).await {
+                    Ok(()) => inner.call(req).await,
+                    Err(err) => Ok(err.into()),
+                };
+                let _ = tokio::fs::remove_file(&path).await;
+                resp
+            });
+        }
+
+        Box::pin(self.inner.call(req))
+    }
+}
+
+pub struct SnapshotLayer {
+    config: Arc<Config>,
+}
+
+impl SnapshotLayer {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for SnapshotLayer {
+    type Service = SnapshotService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SnapshotService::new(inner, Arc::clone(&self.config))
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use covert_types::error::ApiError;
+use futures::future::BoxFuture;
+use hyper::{
+    header::{HeaderValue, LOCATION},
+    http, Body, Method, StatusCode,
+};
+use tower::{Layer, Service};
+
+use crate::ha::Leadership;
+
+/// Writes a standby serves itself, as they only change the local state of
+/// the standby.
+const LOCAL_WRITE_PATHS: &[&str] = &["/v1/sys/unseal", "/v1/sys/seal"];
+
+/// Whether a standby serves the request from its read-only copy of the
+/// storage. Lease operations are served by the active server, as only it
+/// runs the expiration manager.
+fn served_by_standby(method: &Method, path: &str) -> bool {
+    if path.starts_with("/v1/sys/leases/") {
+        return false;
+    }
+    matches!(*method, Method::GET | Method::HEAD)
+        || method.as_str() == "LIST"
+        || LOCAL_WRITE_PATHS.contains(&path)
+}
+
+/// Redirects the requests a standby can't serve to the active server. The
+/// redirect keeps the method and body of the request.
+#[derive(Clone)]
+pub struct StandbyService<S> {
+    leadership: Arc<Leadership>,
+    inner: S,
+}
+
+impl<S> StandbyService<S> {
+    pub fn new(inner: S, leadership: Arc<Leadership>) -> Self {
+        Self { leadership, inner }
+    }
+}
+
+fn redirect(location: Option<HeaderValue>) -> http::Response<Body> {
+    let (message, status_code) = if location.is_some() {
+        (
+            "This server is a standby, the request has to be sent to the active server",
+            StatusCode::TEMPORARY_REDIRECT,
+        )
+    } else {
+        (
+            "This server is a standby and no active server is known",
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+    };
+    let mut resp: http::Response<Body> = ApiError {
+        error: anyhow::Error::msg(message),
+        status_code,
+        span_trace: None,
+    }
+    .into();
+    if let Some(location) = location {
+        resp.headers_mut().insert(LOCATION, location);
+    }
+    resp
+}
+
+impl<S, B> Service<http::Request<B>> for StandbyService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<Body>;
+
+    type Error = S::Error;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if self.leadership.is_active() {
+            return Box::pin(self.inner.call(req));
+        }
+        let location = self.leadership.leader().map(|leader| {
+            format!(
+                "{}{}",
+                leader.address.trim_end_matches('/'),
+                req.uri()
+                    .path_and_query()
+                    .map_or(req.uri().path(), http::uri::PathAndQuery::as_str)
+            )
+        });
+        let Ok(location) = location
+            .map(|location| HeaderValue::from_str(&location))
+            .transpose()
+        else {
+            return Box::pin(async { Ok(ApiError::bad_request().into()) });
+        };
+
+        if !served_by_standby(req.method(), req.uri().path()) {
+            return Box::pin(async move { Ok(redirect(location)) });
+        }
+
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let mut resp = fut.await?;
+            // Requests the standby turned out to be unable to serve, e.g.
+            // writes to its read-only storage
+            if resp.status() == StatusCode::TEMPORARY_REDIRECT {
+                if let Some(location) = location {
+                    resp.headers_mut().insert(LOCATION, location);
+                }
+            }
+            Ok(resp)
+        })
+    }
+}
+
+pub struct StandbyLayer {
+    leadership: Arc<Leadership>,
+}
+
+impl StandbyLayer {
+    /// Requests are passed through while the server is active.
+    pub fn new(leadership: Arc<Leadership>) -> Self {
+        Self { leadership }
+    }
+}
+
+impl<S> Layer<S> for StandbyLayer {
+    type Service = StandbyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StandbyService::new(inner, Arc::clone(&self.leadership))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_served_by_standby() {
+        assert!(served_by_standby(&Method::GET, "/v1/kv/data/foo"));
+        assert!(served_by_standby(
+            &Method::from_bytes(b"LIST").unwrap(),
+            "/v1/kv/metadata/"
+        ));
+        assert!(served_by_standby(&Method::POST, "/v1/sys/unseal"));
+        assert!(served_by_standby(&Method::PUT, "/v1/sys/seal"));
+
+        assert!(!served_by_standby(&Method::POST, "/v1/kv/data/foo"));
+        assert!(!served_by_standby(&Method::DELETE, "/v1/kv/data/foo"));
+        assert!(!served_by_standby(&Method::POST, "/v1/sys/init"));
+        assert!(!served_by_standby(
+            &Method::GET,
+            "/v1/sys/leases/lookup/foo"
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use hyper::http;
+use tower::{timeout::error::Elapsed, BoxError, Layer, Service};
+
+use super::snapshot::{SNAPSHOT_PATH, SNAPSHOT_RESTORE_PATH};
+
+/// Paths that are not bound by the timeout, as they stream the whole storage.
+const UNBOUNDED_PATHS: &[&str] = &[SNAPSHOT_PATH, SNAPSHOT_RESTORE_PATH];
+
+fn is_bounded(path: &str) -> bool {
+    !UNBOUNDED_PATHS.contains(&path)
+}
+
+/// Fails requests that take longer than the timeout, except for the paths
+/// that stream the storage.
+#[derive(Clone)]
+pub struct TimeoutService<S> {
+    inner: S,
+    timeout: Duration,
+}
+
+impl<S> TimeoutService<S> {
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+impl<S, B> Service<http::Request<B>> for TimeoutService<S>
+where
+    S: Service<http::Request<B>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let bounded = is_bounded(req.uri().path());
+        let fut = self.inner.call(req);
+        if !bounded {
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+
+        let fut = tokio::time::timeout(self.timeout, fut);
+        Box::pin(async move {
+            match fut.await {
+                Ok(resp) => resp.map_err(Into::into),
+                Err(_) => Err(Elapsed::new().into()),
+            }
+        })
+    }
+}
+
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = TimeoutService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimeoutService::new(inner, self.timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_by_path() {
+        assert!(!is_bounded("/v1/sys/storage/snapshot"));
+        assert!(!is_bounded("/v1/sys/storage/snapshot-restore"));
+        assert!(is_bounded("/v1/sys/storage/snapshots"));
+        assert!(is_bounded("/v1/secret/foo"));
+    }
+}
@@ -5,42 +5,77 @@
 #![allow(clippy::module_name_repetitions)]
 #![allow(clippy::missing_errors_doc)]
 
+mod audit;
 mod config;
 mod context;
 mod error;
 mod expiration_manager;
+mod ha;
 mod helpers;
+mod kek;
 mod layer;
+mod listener;
+mod metrics;
 mod migrations;
-mod recovery;
+mod replication;
 mod repos;
 mod response;
 mod router;
 mod system;
 
-use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 pub use config::*;
-use context::ChildProcesses;
 use covert_storage::EncryptedPool;
-pub use expiration_manager::{ExpirationManager, LeaseEntry};
+use covert_types::error::ApiError;
+pub use expiration_manager::{ExpirationManager, ExpirationStats, LeaseEntry};
+pub use replication::restore_storage;
 pub use router::{Router, RouterService};
-use sqlx::sqlite::SqliteConnectOptions;
-use tower::{make::Shared, ServiceBuilder};
-use tower_http::{cors::CorsLayer, limit::RequestBodyLimitLayer};
-use tracing::info;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    Pool, Sqlite,
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tower::{make::Shared, service_fn, ServiceBuilder};
+use tower_http::cors::CorsLayer;
+use tracing::{error, info};
+use uuid::Uuid;
 
 use crate::{
+    audit::AuditBroker,
     context::Context,
     expiration_manager::clock::SystemClock,
+    ha::Leadership,
     layer::{
-        auth_service::AuthServiceLayer, lease_registration::LeaseRegistrationLayer,
-        namespace_extension::NamespaceExtensionLayer, request_mapper::LogicalRequestResponseLayer,
+        audit::AuditLayer,
+        auth_service::AuthServiceLayer,
+        body_limit::BodyLimitLayer,
+        health::HealthLayer,
+        lease_registration::LeaseRegistrationLayer,
+        metrics::{MetricsAccess, MetricsLayer},
+        namespace_extension::NamespaceExtensionLayer,
+        request_mapper::LogicalRequestResponseLayer,
+        request_metrics::RequestMetricsLayer,
+        response_wrapping::ResponseWrappingLayer,
+        snapshot::SnapshotLayer,
+        standby::StandbyLayer,
         storage_state_extension::StorageStateExtensionLayer,
+        timeout::TimeoutLayer,
     },
-    recovery::{recover, recover_encrypted_storage_snapshot, replicate},
+    listener::{incoming, ReloadableTlsConfig},
+    metrics::Metrics,
+    replication::{restore_missing, Database, Follower, Replicators, SEAL_DB},
     repos::Repos,
-    system::new_system_backend,
+    system::{auto_unseal, follow_replica, new_system_backend, promote, run_election},
 };
 
 pub async fn shutdown_signal() {
@@ -50,63 +85,116 @@ pub async fn shutdown_signal() {
         .expect("failed to install CTRL+C signal handler");
 }
 
-async fn shutdown_handler(child_processes: ChildProcesses) {
-    child_processes.kill_all().await;
+async fn shutdown_handler(replicators: Replicators, leadership: Arc<Leadership>) {
+    replicators.stop().await;
+    // Let a standby take over right away
+    if leadership.is_active() {
+        if let Err(error) = leadership.resign().await {
+            error!(?error, "Failed to release the HA lock");
+        }
+    }
 }
 
+/// Create the seal storage DB. It is unencrypted and holds what is needed to
+/// unseal the encrypted storage.
+async fn connect_seal_storage(config: &Config) -> Result<Pool<Sqlite>, sqlx::Error> {
+    sqlx::sqlite::SqlitePoolOptions::new()
+        .min_connections(1)
+        .max_connections(1)
+        // The WAL is checkpointed when the last connection is closed, which
+        // would break the continuity of the replication.
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(
+            SqliteConnectOptions::new()
+                .create_if_missing(true)
+                .journal_mode(SqliteJournalMode::Wal)
+                .foreign_keys(true)
+                .filename(config.seal_storage_path()),
+        )
+        .await
+}
+
+/// Serve `sys/metrics` on a dedicated plain HTTP listener, e.g. one that is
+/// only reachable from the monitoring network.
+fn serve_metrics(port: u16, metrics: Arc<Metrics>) -> anyhow::Result<JoinHandle<()>> {
+    let listener = std::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))?;
+    let svc = ServiceBuilder::new()
+        .layer(MetricsLayer::new(metrics, MetricsAccess::Unauthenticated))
+        .service(service_fn(|_| async {
+            Ok::<_, Infallible>(ApiError::not_found().into())
+        }));
+    let server = hyper::Server::from_tcp(listener)?.serve(Shared::new(svc));
+
+    info!("serving metrics on http://{}", server.local_addr());
+    Ok(tokio::spawn(async move {
+        if let Err(error) = server.await {
+            error!(?error, "Encountered metrics server error");
+        }
+    }))
+}
+
+#[allow(clippy::too_many_lines)]
 pub async fn start(
     mut config: Config,
     shutdown_signal: impl Future<Output = ()>,
 ) -> anyhow::Result<()> {
     config.sanitize()?;
 
-    let child_processes = ChildProcesses::default();
-    let shutdown_handler = async {
-        shutdown_signal.await;
-        info!("Shutdown signal received");
-        shutdown_handler(child_processes.clone()).await;
-    };
+    // Only the active server ships its changes to the replica
+    let replicators = Replicators::new(config.replication.as_ref())?;
 
     let port_tx = config.port_tx.take();
     let config = Arc::new(config);
 
-    // Try to recover as far as possible if replication has configured and we
-    // have a backup available
-    if let Some(replication) = config.replication.as_ref() {
-        // Recover seal storage
-        recover(
-            replication,
-            &config.seal_storage_path(),
-            &replication.seal_bucket_url(),
-        )?;
-
-        // Recover latest snapshot of encrypted storage. Changes applied to DB
-        // after latest snapshot will be applied after we have the encryption key
-        // available during unseal.
-        recover_encrypted_storage_snapshot(&config, replication);
-    }
+    let leadership = Arc::new(
+        match (
+            config.ha.as_ref(),
+            config.standby.as_ref(),
+            config.replication.as_ref(),
+        ) {
+            (Some(ha), _, Some(replication)) => Leadership::elect(ha, replication)?,
+            (None, Some(standby), _) => Leadership::follow(standby.active_address.clone()),
+            _ => Leadership::single(),
+        },
+    );
 
-    // Create seal storage DB
-    let seal_db = sqlx::sqlite::SqlitePoolOptions::new()
-        .min_connections(1)
-        .max_connections(1)
-        .connect_with(
-            SqliteConnectOptions::new()
-                .create_if_missing(true)
-                .foreign_keys(true)
-                .filename(config.seal_storage_path()),
-        )
-        .await?;
+    // A standby starts from the latest state of the replica, so does a server
+    // in high availability mode as it might become active later. Otherwise
+    // the storage is restored from the replica if replication is configured
+    // and there is no local storage.
+    let max_lag = config
+        .ha
+        .as_ref()
+        .map(|ha| ha.max_lag)
+        .or_else(|| config.standby.as_ref().map(|standby| standby.max_lag));
+    let follower = match (max_lag, config.replication.as_ref()) {
+        (Some(max_lag), Some(replication)) => {
+            let follower = Follower::restore(&config, replication, max_lag).await?;
+            // The first server of a cluster has no replica to follow
+            if follower.is_none() && !leadership.ha_enabled() {
+                return Err(anyhow::Error::msg(
+                    "No replica of the seal storage found to follow",
+                ));
+            }
+            follower.map(Arc::new)
+        }
+        (None, Some(replication)) => {
+            restore_missing(&config, replication).await?;
+            None
+        }
+        _ => None,
+    };
 
-    // Start replication of seal storage if configured
-    if let Some(replication) = config.replication.as_ref() {
-        let p = replicate(
-            replication,
-            None,
-            &config.seal_storage_path(),
-            &replication.seal_bucket_url(),
-        )?;
-        child_processes.set_seal_storage_replication(p).await;
+    let seal_db = connect_seal_storage(&config).await?;
+    if leadership.is_active() {
+        replicators
+            .replicate(
+                SEAL_DB,
+                Database::Seal(seal_db.clone()),
+                config.seal_storage_path(),
+            )
+            .await;
     }
 
     let encrypted_pool = Arc::new(EncryptedPool::new(&config.encrypted_storage_path()));
@@ -115,57 +203,142 @@ pub async fn start(
     // Run migration
     crate::migrations::migrate_unecrypted_db(&repos.unecrypted_pool).await?;
 
-    let router = Arc::new(Router::new(repos.mount.clone()));
+    let cluster_id = repos
+        .seal
+        .init_cluster_id(&Uuid::new_v4().to_string())
+        .await?;
+
+    let router = Arc::new(Router::new(repos.mount.clone()).leadership(Arc::clone(&leadership)));
     let expiration = Arc::new(ExpirationManager::new(
         Arc::clone(&router),
         repos.clone(),
         SystemClock::new(),
     ));
+    let audit = Arc::new(AuditBroker::new());
     let ctx = Context {
         config: Arc::clone(&config),
         repos: repos.clone(),
-        child_processes: child_processes.clone(),
+        replicators: replicators.clone(),
+        leadership: Arc::clone(&leadership),
+        follower: follower.clone(),
         expiration_manager: Arc::clone(&expiration),
         router: Arc::clone(&router),
+        audit: Arc::clone(&audit),
     };
 
     // Mount system backend
-    let system = new_system_backend(ctx);
+    let system = new_system_backend(ctx.clone());
     router.mount_system(Arc::new(system));
 
+    // A server in high availability mode starts as the active server if it
+    // takes the lock
+    if leadership.ha_enabled() && leadership.take_over().await? {
+        promote(&ctx).await?;
+    }
+
+    auto_unseal(&ctx).await;
+
+    let follower_task =
+        follower
+            .as_ref()
+            .zip(config.replication.as_ref())
+            .map(|(follower, replication)| {
+                follow_replica(ctx.clone(), Arc::clone(follower), replication.sync_interval)
+            });
+
+    let metrics = Arc::new(Metrics::new(
+        Arc::clone(&repos.pool),
+        repos.lease.clone(),
+        Arc::clone(&expiration),
+    )?);
+    let metrics_port = config.metrics.as_ref().and_then(|metrics| metrics.port);
+    let metrics_server = metrics_port
+        .map(|port| serve_metrics(port, Arc::clone(&metrics)))
+        .transpose()?;
+
+    let metrics_access = match config.metrics.as_ref() {
+        Some(metrics) if metrics.port.is_some() => MetricsAccess::Disabled,
+        Some(metrics) if metrics.unauthenticated_metrics_access => MetricsAccess::Unauthenticated,
+        Some(_) => MetricsAccess::Token,
+        None => MetricsAccess::Disabled,
+    };
     let server_router_svc = ServiceBuilder::new()
         .concurrency_limit(1000)
-        .timeout(Duration::from_secs(30))
-        .layer(RequestBodyLimitLayer::new(1024 * 16))
+        .layer(TimeoutLayer::new(Duration::from_secs(30)))
+        .layer(BodyLimitLayer)
         .layer(CorsLayer::permissive())
+        .layer(HealthLayer::new(
+            Arc::clone(&repos.pool),
+            cluster_id,
+            Arc::clone(&leadership),
+            follower.clone(),
+        ))
+        .layer(StandbyLayer::new(Arc::clone(&leadership)))
+        .layer(MetricsLayer::new(Arc::clone(&metrics), metrics_access))
+        .layer(SnapshotLayer::new(Arc::clone(&config)))
         .layer(LogicalRequestResponseLayer::new())
         .layer(StorageStateExtensionLayer::new(Arc::clone(&repos.pool)))
         .layer(NamespaceExtensionLayer::new(repos.namespace.clone()))
-        .layer(AuthServiceLayer::new(
-            repos.token.clone(),
-            repos.namespace.clone(),
-        ))
+        .layer(AuditLayer::new(audit))
+        .layer(AuthServiceLayer::new(ctx.clone()))
+        .layer(ResponseWrappingLayer::new(repos.wrapping.clone()))
         .layer(LeaseRegistrationLayer::new(
             expiration.clone(),
             repos.token.clone(),
             repos.entity.clone(),
         ))
+        .layer(RequestMetricsLayer::new(metrics))
         .service(RouterService::new(router.clone()));
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
-    let covert_server = hyper::Server::bind(&addr).serve(Shared::new(server_router_svc));
-    let addr = covert_server.local_addr();
-    let covert_server = covert_server.with_graceful_shutdown(shutdown_handler);
+    let tls = config
+        .listener
+        .tls
+        .clone()
+        .map(ReloadableTlsConfig::new)
+        .transpose()?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+
+    let stepped_down = AtomicBool::new(false);
+    let election = async {
+        if leadership.ha_enabled() {
+            run_election(ctx.clone()).await;
+            stepped_down.store(true, Ordering::SeqCst);
+        } else {
+            std::future::pending::<()>().await;
+        }
+    };
+    let shutdown_handler = async {
+        tokio::select! {
+            () = shutdown_signal => info!("Shutdown signal received"),
+            () = election => info!("Shutting down after stepping down as the active server"),
+        }
+        shutdown_handler(replicators.clone(), Arc::clone(&leadership)).await;
+    };
 
-    info!("listening on {addr}");
+    let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], config.port))).await?;
+    let addr = listener.local_addr()?;
+    let covert_server = hyper::Server::builder(incoming(listener, tls))
+        .serve(Shared::new(server_router_svc))
+        .with_graceful_shutdown(shutdown_handler);
+
+    info!("listening on {scheme}://{addr}");
     if let Some(tx) = port_tx {
         let _ = tx.send(addr.port());
     }
 
     // And run forever...
-    if let Err(error) = covert_server.await {
+    let res = covert_server.await;
+    for task in [metrics_server, follower_task].into_iter().flatten() {
+        task.abort();
+    }
+    if let Err(error) = res {
         tracing::error!(?error, "Encountered server error. Shutting down.");
         return Err(error.into());
     }
+    if stepped_down.load(Ordering::SeqCst) {
+        return Err(anyhow::Error::msg(
+            "Stepped down as the active server, restart to follow the new active server",
+        ));
+    }
     Ok(())
 }
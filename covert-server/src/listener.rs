@@ -0,0 +1,364 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    net::SocketAddr,
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, SystemTime},
+};
+
+use hyper::server::accept::Accept;
+use rustls::{server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info, warn};
+
+use crate::config::{TlsConfig, TlsVersion};
+
+/// How often the certificate files are checked for changes.
+const TLS_FILES_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Connections that have not completed the TLS handshake within this time are
+/// dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection accepted by the listener. Either a plain TCP stream or a TLS
+/// stream on top of one.
+pub trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+/// TLS server config that can be swapped out while the server is running,
+/// allowing certificates to be rotated without a restart.
+pub struct ReloadableTlsConfig {
+    config: TlsConfig,
+    current: RwLock<Arc<rustls::ServerConfig>>,
+    files_modified_at: RwLock<Vec<Option<SystemTime>>>,
+}
+
+impl ReloadableTlsConfig {
+    pub fn new(config: TlsConfig) -> anyhow::Result<Self> {
+        let server_config = load_server_config(&config)?;
+        let files_modified_at = files_modified_at(&config);
+        Ok(Self {
+            config,
+            current: RwLock::new(Arc::new(server_config)),
+            files_modified_at: RwLock::new(files_modified_at),
+        })
+    }
+
+    /// Read the certificate, key and client CA files again and use them for
+    /// all new connections. The current config is kept if any of them are
+    /// invalid.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        // Record the modification times up front so that invalid files are not
+        // retried until they change again.
+        if let Ok(mut modified_at) = self.files_modified_at.write() {
+            *modified_at = files_modified_at(&self.config);
+        }
+        let server_config = load_server_config(&self.config)?;
+
+        *self
+            .current
+            .write()
+            .map_err(|_| anyhow::Error::msg("TLS config lock poisoned"))? = Arc::new(server_config);
+        Ok(())
+    }
+
+    fn files_changed(&self) -> bool {
+        self.files_modified_at.read().map_or(true, |modified_at| {
+            *modified_at != files_modified_at(&self.config)
+        })
+    }
+
+    fn acceptor(&self) -> Option<TlsAcceptor> {
+        self.current
+            .read()
+            .ok()
+            .map(|config| TlsAcceptor::from(Arc::clone(&config)))
+    }
+}
+
+fn files_modified_at(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [
+        Some(&config.cert_file),
+        Some(&config.key_file),
+        config.client_ca_file.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|file| {
+        std::fs::metadata(file)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    })
+    .collect()
+}
+
+fn open(file: &str) -> anyhow::Result<BufReader<File>> {
+    File::open(file)
+        .map(BufReader::new)
+        .map_err(|err| anyhow::Error::msg(format!("Unable to open `{file}`: {err}")))
+}
+
+fn load_certs(file: &str) -> anyhow::Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut open(file)?)
+        .map_err(|_| anyhow::Error::msg(format!("Invalid certificates in `{file}`")))?;
+    if certs.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "No certificates found in `{file}`"
+        )));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_private_key(file: &str) -> anyhow::Result<PrivateKey> {
+    let items = rustls_pemfile::read_all(&mut open(file)?)
+        .map_err(|_| anyhow::Error::msg(format!("Invalid private key in `{file}`")))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::Error::msg(format!("No private key found in `{file}`")))
+}
+
+fn load_server_config(config: &TlsConfig) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = load_certs(&config.cert_file)?;
+    let key = load_private_key(&config.key_file)?;
+
+    let versions: &[&'static rustls::SupportedProtocolVersion] = match config.min_version {
+        TlsVersion::Tls12 => &[&rustls::version::TLS13, &rustls::version::TLS12],
+        TlsVersion::Tls13 => &[&rustls::version::TLS13],
+    };
+    let builder = rustls::ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)?;
+
+    let builder = if let Some(client_ca_file) = config.client_ca_file.as_ref() {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(client_ca_file)? {
+            roots.add(&cert).map_err(|err| {
+                anyhow::Error::msg(format!(
+                    "Invalid CA certificate in `{client_ca_file}`: {err}"
+                ))
+            })?;
+        }
+        builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let mut server_config = builder.with_single_cert(certs, key)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+/// Reload the TLS config on SIGHUP or when any of the configured files change
+/// on disk. Stops when the listener drops the config.
+async fn watch_tls_config(tls: Weak<ReloadableTlsConfig>) {
+    let mut interval = tokio::time::interval(TLS_FILES_POLL_INTERVAL);
+    #[cfg(unix)]
+    let mut sighup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(sighup) => Some(sighup),
+        Err(error) => {
+            warn!(
+                ?error,
+                "Unable to listen for SIGHUP, TLS reload on signal is disabled"
+            );
+            None
+        }
+    };
+
+    loop {
+        #[cfg(unix)]
+        let signaled = tokio::select! {
+            _ = interval.tick() => false,
+            Some(()) = async {
+                match sighup.as_mut() {
+                    Some(sighup) => sighup.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => true,
+        };
+        #[cfg(not(unix))]
+        let signaled = {
+            interval.tick().await;
+            false
+        };
+
+        let Some(tls) = tls.upgrade() else {
+            return;
+        };
+        if !signaled && !tls.files_changed() {
+            continue;
+        }
+        match tls.reload() {
+            Ok(()) => info!("Reloaded TLS certificates"),
+            Err(error) => error!(?error, "Failed to reload TLS certificates"),
+        }
+    }
+}
+
+/// Accept connections from `listener`, terminating TLS if `tls` is provided.
+///
+/// TLS handshakes are performed off the accept loop so a slow client can not
+/// block other connections from being accepted.
+pub fn incoming(
+    listener: TcpListener,
+    tls: Option<ReloadableTlsConfig>,
+) -> impl Accept<Conn = Box<dyn Connection>, Error = std::io::Error> {
+    let (tx, mut rx) = mpsc::channel::<Result<Box<dyn Connection>, std::io::Error>>(128);
+    let tls = tls.map(Arc::new);
+
+    if let Some(tls) = tls.as_ref() {
+        tokio::spawn(watch_tls_config(Arc::downgrade(tls)));
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, remote_addr) = tokio::select! {
+                () = tx.closed() => return,
+                conn = listener.accept() => match conn {
+                    Ok(conn) => conn,
+                    Err(error) => {
+                        warn!(?error, "Failed to accept connection");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                },
+            };
+
+            match tls.as_ref() {
+                Some(tls) => {
+                    let Some(acceptor) = tls.acceptor() else {
+                        error!("TLS config unavailable, dropping connection");
+                        continue;
+                    };
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        if let Some(conn) = tls_handshake(&acceptor, stream, remote_addr).await {
+                            let _ = tx.send(Ok(conn)).await;
+                        }
+                    });
+                }
+                None => {
+                    if tx.send(Ok(Box::new(stream))).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    hyper::server::accept::from_stream(futures::stream::poll_fn(move |cx| rx.poll_recv(cx)))
+}
+
+async fn tls_handshake(
+    acceptor: &TlsAcceptor,
+    stream: TcpStream,
+    remote_addr: SocketAddr,
+) -> Option<Box<dyn Connection>> {
+    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+        Ok(Ok(stream)) => Some(Box::new(stream)),
+        Ok(Err(error)) => {
+            warn!(?error, %remote_addr, "TLS handshake failed");
+            None
+        }
+        Err(_) => {
+            warn!(%remote_addr, "TLS handshake timed out");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn write_self_signed(dir: &Path, name: &str) -> (String, String) {
+        let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        let cert_file = dir.join(format!("{name}.crt"));
+        let key_file = dir.join(format!("{name}.key"));
+        std::fs::write(&cert_file, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+        (
+            cert_file.to_str().unwrap().to_string(),
+            key_file.to_str().unwrap().to_string(),
+        )
+    }
+
+    #[test]
+    fn loads_tls_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_file, key_file) = write_self_signed(dir.path(), "localhost");
+
+        let config = TlsConfig {
+            cert_file: cert_file.clone(),
+            key_file: key_file.clone(),
+            client_ca_file: None,
+            min_version: TlsVersion::Tls13,
+        };
+        assert!(ReloadableTlsConfig::new(config).is_ok());
+
+        // Client CA
+        let config = TlsConfig {
+            cert_file: cert_file.clone(),
+            key_file: key_file.clone(),
+            client_ca_file: Some(cert_file.clone()),
+            min_version: TlsVersion::Tls12,
+        };
+        assert!(ReloadableTlsConfig::new(config).is_ok());
+
+        // Key file used as cert
+        let config = TlsConfig {
+            cert_file: key_file.clone(),
+            key_file,
+            client_ca_file: None,
+            min_version: TlsVersion::Tls12,
+        };
+        assert!(ReloadableTlsConfig::new(config).is_err());
+
+        // Cert file used as key
+        let config = TlsConfig {
+            cert_file: cert_file.clone(),
+            key_file: cert_file,
+            client_ca_file: None,
+            min_version: TlsVersion::Tls12,
+        };
+        assert!(ReloadableTlsConfig::new(config).is_err());
+    }
+
+    #[test]
+    fn reload_keeps_current_config_on_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_file, key_file) = write_self_signed(dir.path(), "localhost");
+        let tls = ReloadableTlsConfig::new(TlsConfig {
+            cert_file: cert_file.clone(),
+            key_file,
+            client_ca_file: None,
+            min_version: TlsVersion::Tls12,
+        })
+        .unwrap();
+        let before = Arc::clone(&tls.current.read().unwrap());
+
+        std::fs::write(&cert_file, "not a certificate").unwrap();
+        assert!(tls.reload().is_err());
+        assert!(Arc::ptr_eq(&before, &tls.current.read().unwrap()));
+
+        // Valid files are picked up again
+        write_self_signed(dir.path(), "localhost");
+        assert!(tls.reload().is_ok());
+        assert!(!Arc::ptr_eq(&before, &tls.current.read().unwrap()));
+        assert!(!tls.files_changed());
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use covert_storage::EncryptedPool;
+use covert_types::state::StorageState;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::{repos::lease::LeaseRepo, ExpirationManager};
+
+/// Prometheus metrics exported by `sys/metrics`.
+///
+/// Request metrics are recorded by the layers as requests are handled while
+/// the lease and storage gauges are read at scrape time.
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    auth_failures: IntCounter,
+    lease_registrations: IntCounter,
+    revocation_retries: IntCounter,
+    pending_leases: IntGauge,
+    failed_revocations: IntGauge,
+    revocations_in_progress: IntGauge,
+    revocation_worker_concurrency: IntGauge,
+    storage_state: IntGaugeVec,
+    storage_pool: Arc<EncryptedPool>,
+    lease_repo: LeaseRepo,
+    expiration_manager: Arc<ExpirationManager>,
+    /// Serializes scrapes so the counters are caught up exactly once
+    scrape_lock: Mutex<()>,
+}
+
+const REQUEST_LABELS: [&str; 4] = ["mount_path", "backend_type", "operation", "status"];
+
+impl Metrics {
+    pub fn new(
+        storage_pool: Arc<EncryptedPool>,
+        lease_repo: LeaseRepo,
+        expiration_manager: Arc<ExpirationManager>,
+    ) -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("covert".to_string()), None)?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Number of requests routed to a backend"),
+            &REQUEST_LABELS,
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "request_duration_seconds",
+                "Time spent by a backend handling a request",
+            ),
+            &REQUEST_LABELS,
+        )?;
+        let auth_failures = IntCounter::new(
+            "auth_failures_total",
+            "Number of requests rejected with a missing, invalid or insufficient token",
+        )?;
+        let lease_registrations =
+            IntCounter::new("lease_registrations_total", "Number of leases registered")?;
+        let revocation_retries = IntCounter::new(
+            "lease_revocation_retries_total",
+            "Number of failed lease revocations scheduled for a retry",
+        )?;
+        let pending_leases = IntGauge::new("leases_pending", "Number of leases not yet revoked")?;
+        let failed_revocations = IntGauge::new(
+            "leases_failed_revocation",
+            "Number of leases with at least one failed revocation attempt",
+        )?;
+        let revocations_in_progress = IntGauge::new(
+            "lease_revocations_in_progress",
+            "Number of leases currently being revoked by the revocation worker",
+        )?;
+        let revocation_worker_concurrency = IntGauge::new(
+            "lease_revocation_worker_concurrency",
+            "Max number of leases the revocation worker revokes at the same time",
+        )?;
+        let storage_state = IntGaugeVec::new(
+            Opts::new("storage_state", "Current state of the encrypted storage"),
+            &["state"],
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(auth_failures.clone()))?;
+        registry.register(Box::new(lease_registrations.clone()))?;
+        registry.register(Box::new(revocation_retries.clone()))?;
+        registry.register(Box::new(pending_leases.clone()))?;
+        registry.register(Box::new(failed_revocations.clone()))?;
+        registry.register(Box::new(revocations_in_progress.clone()))?;
+        registry.register(Box::new(revocation_worker_concurrency.clone()))?;
+        registry.register(Box::new(storage_state.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests,
+            request_duration,
+            auth_failures,
+            lease_registrations,
+            revocation_retries,
+            pending_leases,
+            failed_revocations,
+            revocations_in_progress,
+            revocation_worker_concurrency,
+            storage_state,
+            storage_pool,
+            lease_repo,
+            expiration_manager,
+            scrape_lock: Mutex::new(()),
+        })
+    }
+
+    /// Record a request handled by a backend.
+    pub fn observe_request(
+        &self,
+        mount_path: &str,
+        backend_type: &str,
+        operation: &str,
+        status: u16,
+        duration: std::time::Duration,
+    ) {
+        let status = status.to_string();
+        let labels = [mount_path, backend_type, operation, status.as_str()];
+        self.requests.with_label_values(&labels).inc();
+        self.request_duration
+            .with_label_values(&labels)
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn inc_auth_failures(&self) {
+        self.auth_failures.inc();
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub async fn render(&self) -> String {
+        self.update_gauges().await;
+
+        let mut buf = Vec::new();
+        if let Err(error) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            error!(?error, "Failed to encode metrics");
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+
+    async fn update_gauges(&self) {
+        let _guard = self.scrape_lock.lock().await;
+
+        let state = self.storage_pool.state();
+        for s in [
+            StorageState::Uninitialized,
+            StorageState::Sealed,
+            StorageState::Unsealed,
+        ] {
+            self.storage_state
+                .with_label_values(&[&s.to_string()])
+                .set(i64::from(s == state));
+        }
+
+        // Counters can only be incremented so catch up with the expiration
+        // manager by adding the difference since the last scrape.
+        let expiration = self.expiration_manager.stats();
+        self.lease_registrations.inc_by(
+            expiration
+                .lease_registrations
+                .saturating_sub(self.lease_registrations.get()),
+        );
+        self.revocation_retries.inc_by(
+            expiration
+                .revocation_retries
+                .saturating_sub(self.revocation_retries.get()),
+        );
+        self.revocations_in_progress
+            .set(expiration.revocations_in_progress);
+        self.revocation_worker_concurrency
+            .set(i64::try_from(expiration.revocation_worker_concurrency).unwrap_or(i64::MAX));
+
+        // The leases are stored in the encrypted storage
+        if state == StorageState::Unsealed {
+            match self.lease_repo.count().await {
+                Ok((pending, failed)) => {
+                    self.pending_leases.set(pending);
+                    self.failed_revocations.set(failed);
+                }
+                Err(error) => error!(?error, "Failed to count leases for metrics"),
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use futures::future::BoxFuture;
+
+use super::ReplicaTarget;
+
+/// Replica stored in a local directory, e.g. a mounted network volume.
+pub struct DirectoryTarget {
+    root: PathBuf,
+}
+
+impl DirectoryTarget {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn key(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let parts = relative
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?;
+        Some(parts.join("/"))
+    }
+}
+
+impl ReplicaTarget for DirectoryTarget {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let path = self.root.join(key);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // Write to a temporary file first so a crash never leaves a
+            // partially written object behind.
+            let tmp_path = path.with_extension("tmp");
+            tokio::fs::write(&tmp_path, data).await?;
+            tokio::fs::rename(&tmp_path, &path).await?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(async move { Ok(tokio::fs::read(self.root.join(key)).await?) })
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        Box::pin(async move {
+            let mut keys = Vec::new();
+            let mut dirs = vec![self.root.clone()];
+            while let Some(dir) = dirs.pop() {
+                let mut entries = match tokio::fs::read_dir(&dir).await {
+                    Ok(entries) => entries,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if entry.file_type().await?.is_dir() {
+                        dirs.push(path);
+                    } else if path.extension() != Some(OsStr::new("tmp")) {
+                        if let Some(key) = self.key(&path).filter(|key| key.starts_with(prefix)) {
+                            keys.push(key);
+                        }
+                    }
+                }
+            }
+            keys.sort();
+            Ok(keys)
+        })
+    }
+}
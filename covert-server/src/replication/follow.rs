@@ -0,0 +1,278 @@
+//! Following the replica of the active server on a standby.
+//!
+//! The encrypted storage of the standby is a copy of the replica that is kept
+//! up to date by applying the segments shipped by the active server. Pages are
+//! written to the storage file while the follower holds the single connection
+//! of the pool, and the page cache of the connection is dropped afterwards so
+//! the new pages are read.
+
+use std::{
+    fs::File,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use covert_storage::EncryptedPool;
+use covert_types::state::StorageState;
+use sqlx::{pool::PoolConnection, Executor, Sqlite};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use super::{
+    latest_generation, replica_target, restore, restore_generation, segments, wal, ReplicaTarget,
+    ENCRYPTED_DB, SEAL_DB,
+};
+use crate::{Config, ReplicationConfig};
+
+/// Rejects writes on the connection of a standby, they would be overwritten
+/// by the next segment from the active server.
+pub const READ_ONLY_PRAGMA: &str = "PRAGMA query_only = 1";
+
+/// Accepts writes again once the standby took over as the active server.
+pub const READ_WRITE_PRAGMA: &str = "PRAGMA query_only = 0";
+
+/// How far the replica has been applied to the storage of the standby.
+struct FollowPosition {
+    generation: String,
+    /// Index of the next segment to apply
+    index: u64,
+}
+
+/// Outcome of applying the latest changes of the replica.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FollowUpdate {
+    Unchanged,
+    Applied,
+    /// The active server rotated the master key, so the storage can't be read
+    /// with the master key the standby was unsealed with.
+    MasterKeyChanged,
+}
+
+/// Keeps the encrypted storage of a standby up to date with the replica.
+pub struct Follower {
+    target: Arc<dyn ReplicaTarget>,
+    db_path: String,
+    max_lag: Duration,
+    position: Mutex<Option<FollowPosition>>,
+    /// Unix timestamp in milliseconds of when the standby last caught up with
+    /// the replica.
+    synced_at: AtomicI64,
+    /// Set once this server took over from the active server.
+    stopped: AtomicBool,
+}
+
+impl Follower {
+    /// Replace the local storage with the latest state of the replica and
+    /// follow the replica from there. Returns `None` if there is no replica
+    /// of the seal storage to follow.
+    ///
+    /// The seal storage is only restored here, so a standby is restarted to
+    /// pick up new key shares from a rekey or rotation on the active server.
+    pub async fn restore(
+        config: &Config,
+        replication: &ReplicationConfig,
+        max_lag: Duration,
+    ) -> anyhow::Result<Option<Self>> {
+        let target = replica_target(replication)?;
+        if !restore(target.as_ref(), SEAL_DB, &config.seal_storage_path(), None).await? {
+            return Ok(None);
+        }
+
+        let db_path = config.encrypted_storage_path();
+        let keys = target.list(&format!("{ENCRYPTED_DB}/")).await?;
+        let position = if let Some(generation) = latest_generation(&keys, ENCRYPTED_DB, Utc::now())
+        {
+            let segments = segments(&keys, ENCRYPTED_DB, generation, 0, Utc::now());
+            let index = restore_generation(
+                target.as_ref(),
+                ENCRYPTED_DB,
+                generation,
+                &segments,
+                &db_path,
+            )
+            .await?;
+            info!(generation, "Restored standby storage from replica");
+            Some(FollowPosition {
+                generation: generation.to_string(),
+                index,
+            })
+        } else {
+            info!("No replica of the encrypted storage found, waiting for the active server");
+            None
+        };
+
+        Ok(Some(Self {
+            target,
+            db_path,
+            max_lag,
+            position: Mutex::new(position),
+            synced_at: AtomicI64::new(Utc::now().timestamp_millis()),
+            stopped: AtomicBool::new(false),
+        }))
+    }
+
+    /// Apply the changes shipped to the replica since the last sync.
+    pub async fn sync(&self, pool: &EncryptedPool) -> anyhow::Result<FollowUpdate> {
+        let mut position = self.position.lock().await;
+        if self.stopped.load(Ordering::SeqCst) {
+            return Ok(FollowUpdate::Unchanged);
+        }
+        self.sync_position(pool, &mut position).await
+    }
+
+    /// Apply the last changes of the replica and stop following it, as this
+    /// server takes over from the active server.
+    pub async fn stop(&self, pool: &EncryptedPool) -> anyhow::Result<FollowUpdate> {
+        let mut position = self.position.lock().await;
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return Ok(FollowUpdate::Unchanged);
+        }
+        self.sync_position(pool, &mut position).await
+    }
+
+    async fn sync_position(
+        &self,
+        pool: &EncryptedPool,
+        position: &mut Option<FollowPosition>,
+    ) -> anyhow::Result<FollowUpdate> {
+        let keys = self.target.list(&format!("{ENCRYPTED_DB}/")).await?;
+        let now = Utc::now();
+
+        let Some(generation) = latest_generation(&keys, ENCRYPTED_DB, now) else {
+            self.synced_at
+                .store(now.timestamp_millis(), Ordering::Relaxed);
+            return Ok(FollowUpdate::Unchanged);
+        };
+        let same_generation = position
+            .as_ref()
+            .is_some_and(|pos| pos.generation == generation);
+        let start = match position.as_ref() {
+            Some(pos) if same_generation => pos.index,
+            _ => 0,
+        };
+        let segments = segments(&keys, ENCRYPTED_DB, generation, start, now);
+        if same_generation && segments.is_empty() {
+            self.synced_at
+                .store(now.timestamp_millis(), Ordering::Relaxed);
+            return Ok(FollowUpdate::Unchanged);
+        }
+
+        let update = if same_generation {
+            self.apply_segments(pool, &segments).await?
+        } else {
+            info!(generation, "Following new replication generation");
+            self.apply_generation(pool, generation, &segments).await?
+        };
+        *position = Some(FollowPosition {
+            generation: generation.to_string(),
+            index: start + segments.len() as u64,
+        });
+        self.synced_at
+            .store(now.timestamp_millis(), Ordering::Relaxed);
+        Ok(update)
+    }
+
+    async fn apply_segments(
+        &self,
+        pool: &EncryptedPool,
+        segments: &[&str],
+    ) -> anyhow::Result<FollowUpdate> {
+        let mut data = Vec::with_capacity(segments.len());
+        for key in segments {
+            data.push(self.target.get(key).await?);
+        }
+
+        let conn = acquire(pool).await?;
+        let mut db = File::options().write(true).open(&self.db_path)?;
+        for segment in &data {
+            wal::apply(&mut db, segment)?;
+        }
+        drop(db);
+        release(conn).await?;
+        Ok(FollowUpdate::Applied)
+    }
+
+    /// Replace the storage with the snapshot of a new generation.
+    async fn apply_generation(
+        &self,
+        pool: &EncryptedPool,
+        generation: &str,
+        segments: &[&str],
+    ) -> anyhow::Result<FollowUpdate> {
+        let tmp_path = format!("{}.follow", self.db_path);
+        restore_generation(
+            self.target.as_ref(),
+            ENCRYPTED_DB,
+            generation,
+            segments,
+            &tmp_path,
+        )
+        .await?;
+        if pool.state() != StorageState::Unsealed {
+            pool.restore(&tmp_path)?;
+            tokio::fs::remove_file(&tmp_path).await?;
+            return Ok(FollowUpdate::Applied);
+        }
+        let snapshot = tokio::fs::read(&tmp_path).await?;
+        tokio::fs::remove_file(&tmp_path).await?;
+
+        // The file is overwritten in place, the connection keeps it open
+        let conn = acquire(pool).await?;
+        let mut db = File::options().write(true).open(&self.db_path)?;
+        db.write_all(&snapshot)?;
+        db.set_len(snapshot.len() as u64)?;
+        db.sync_all()?;
+        drop(db);
+        release(conn).await?;
+
+        if sqlx::query("SELECT count(*) FROM sqlite_master")
+            .execute(pool)
+            .await
+            .is_ok()
+        {
+            Ok(FollowUpdate::Applied)
+        } else {
+            Ok(FollowUpdate::MasterKeyChanged)
+        }
+    }
+
+    /// When the standby last caught up with the replica.
+    pub fn synced_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(self.synced_at.load(Ordering::Relaxed))
+            .single()
+            .unwrap_or_default()
+    }
+
+    /// Time since the standby last caught up with the replica.
+    pub fn lag(&self) -> Duration {
+        (Utc::now() - self.synced_at()).to_std().unwrap_or_default()
+    }
+
+    pub fn is_lagging(&self) -> bool {
+        self.lag() > self.max_lag
+    }
+}
+
+/// Hold the single connection of an unsealed pool, so no queries run while
+/// pages are written to the storage file.
+async fn acquire(pool: &EncryptedPool) -> anyhow::Result<Option<PoolConnection<Sqlite>>> {
+    if pool.state() != StorageState::Unsealed {
+        return Ok(None);
+    }
+    let mut conn = sqlx::Acquire::acquire(pool).await?;
+    conn.execute(READ_ONLY_PRAGMA).await?;
+    Ok(Some(conn))
+}
+
+/// Drop the cached pages of the connection, they might have been replaced.
+async fn release(conn: Option<PoolConnection<Sqlite>>) -> anyhow::Result<()> {
+    if let Some(mut conn) = conn {
+        conn.execute("PRAGMA shrink_memory").await?;
+    }
+    Ok(())
+}
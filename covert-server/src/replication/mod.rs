@@ -0,0 +1,604 @@
+//! Streaming replication of the seal and encrypted storage.
+//!
+//! A replica consists of generations. A generation starts with a snapshot of
+//! the database and is followed by segments with the WAL frames committed
+//! after the snapshot, shipped every sync interval. A new generation is
+//! started whenever the replicator is unable to verify that it has seen every
+//! frame since the previous segment, e.g. when the WAL was checkpointed by
+//! another connection.
+//!
+//! The replicator is the only one checkpointing the WAL. It holds the single
+//! connection of the database pool while reading the WAL and checkpointing
+//! it, so no frames are written in the meantime.
+
+mod dir;
+mod follow;
+mod s3;
+mod wal;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use covert_storage::EncryptedPool;
+use covert_types::state::StorageState;
+use futures::future::BoxFuture;
+use sqlx::{pool::PoolConnection, Executor, Pool, Sqlite};
+use tokio::{sync::Mutex, task::JoinHandle};
+use tracing::{debug, error, info};
+
+use self::wal::WAL_HEADER_SIZE;
+pub use self::{
+    dir::DirectoryTarget,
+    follow::{FollowUpdate, Follower, READ_ONLY_PRAGMA, READ_WRITE_PRAGMA},
+    s3::S3Target,
+};
+use crate::{Config, ReplicaTargetConfig, ReplicationConfig};
+
+pub const SEAL_DB: &str = "seal.db";
+pub const ENCRYPTED_DB: &str = "covert.db";
+
+/// The WAL is checkpointed when it has grown past this size.
+const CHECKPOINT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Storage of a replica. Keys are `/` separated paths.
+pub trait ReplicaTarget: Send + Sync {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>>;
+
+    /// Sorted keys that start with `prefix`.
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<String>>>;
+}
+
+pub fn replica_target(config: &ReplicationConfig) -> anyhow::Result<Arc<dyn ReplicaTarget>> {
+    match &config.target {
+        ReplicaTargetConfig::Directory(dir) => Ok(Arc::new(DirectoryTarget::new(&dir.path))),
+        ReplicaTargetConfig::S3(s3) => Ok(Arc::new(S3Target::new(s3)?)),
+    }
+}
+
+/// Database that is replicated.
+#[derive(Clone)]
+pub enum Database {
+    Seal(Pool<Sqlite>),
+    Encrypted(Arc<EncryptedPool>),
+}
+
+impl Database {
+    fn is_open(&self) -> bool {
+        match self {
+            Database::Seal(_) => true,
+            Database::Encrypted(pool) => pool.state() == StorageState::Unsealed,
+        }
+    }
+
+    async fn acquire(&self) -> Result<PoolConnection<Sqlite>, sqlx::Error> {
+        let mut conn = match self {
+            Database::Seal(pool) => pool.acquire().await?,
+            Database::Encrypted(pool) => sqlx::Acquire::acquire(pool.as_ref()).await?,
+        };
+        // Only the replicator is allowed to checkpoint the WAL
+        conn.execute("PRAGMA wal_autocheckpoint = 0").await?;
+        Ok(conn)
+    }
+}
+
+/// Checkpoint all frames into the database and truncate the WAL. Returns
+/// false if the checkpoint could not complete.
+async fn checkpoint(conn: &mut PoolConnection<Sqlite>) -> Result<bool, sqlx::Error> {
+    let (busy, _, _): (i64, i64, i64) = sqlx::query_as("PRAGMA wal_checkpoint(TRUNCATE)")
+        .fetch_one(conn)
+        .await?;
+    Ok(busy == 0)
+}
+
+/// How far the WAL has been shipped.
+struct Position {
+    generation: String,
+    /// Index of the next segment
+    index: u64,
+    /// Header of the WAL the frames were read from, `None` if the WAL has
+    /// been truncated since
+    wal_header: Option<Vec<u8>>,
+    /// Offset in the WAL of the next frame to ship
+    wal_offset: usize,
+}
+
+fn time_key(time: DateTime<Utc>) -> String {
+    format!("{:016x}", time.timestamp_millis())
+}
+
+fn parse_time_key(key: &str) -> Option<DateTime<Utc>> {
+    let millis = i64::from_str_radix(key, 16).ok()?;
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+/// Ships the changes of a database to a replica.
+pub struct Replicator {
+    name: &'static str,
+    db: Database,
+    db_path: String,
+    target: Arc<dyn ReplicaTarget>,
+    position: Mutex<Option<Position>>,
+}
+
+impl Replicator {
+    pub fn new(
+        name: &'static str,
+        db: Database,
+        db_path: String,
+        target: Arc<dyn ReplicaTarget>,
+    ) -> Self {
+        Self {
+            name,
+            db,
+            db_path,
+            target,
+            position: Mutex::new(None),
+        }
+    }
+
+    /// Ship the transactions committed since the last sync.
+    pub async fn sync(&self) -> anyhow::Result<()> {
+        let mut position = self.position.lock().await;
+        if !self.db.is_open() {
+            return Ok(());
+        }
+        let Some(pos) = position.as_mut() else {
+            *position = Some(self.new_generation().await?);
+            return Ok(());
+        };
+
+        let mut conn = self.db.acquire().await?;
+        let wal = match tokio::fs::read(format!("{}-wal", self.db_path)).await {
+            Ok(wal) => wal,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let continuous = match pos.wal_header.as_ref() {
+            Some(header) => {
+                wal.len() >= pos.wal_offset && wal.get(..WAL_HEADER_SIZE) == Some(header)
+            }
+            None => true,
+        };
+        if !continuous {
+            drop(conn);
+            info!(db = self.name, "WAL was reset by another connection");
+            *position = None;
+            *position = Some(self.new_generation().await?);
+            return Ok(());
+        }
+        if wal.len() < WAL_HEADER_SIZE {
+            return Ok(());
+        }
+
+        let start = pos.wal_offset.max(WAL_HEADER_SIZE);
+        let end = wal::committed_end(&wal, start);
+        let truncated = end >= CHECKPOINT_SIZE && checkpoint(&mut conn).await?;
+        drop(conn);
+
+        if end > start {
+            let mut segment = wal[..WAL_HEADER_SIZE].to_vec();
+            segment.extend_from_slice(&wal[start..end]);
+            let key = format!(
+                "{}/{}/wal/{:016x}-{}.wal",
+                self.name,
+                pos.generation,
+                pos.index,
+                time_key(Utc::now())
+            );
+            if let Err(error) = self.target.put(&key, segment).await {
+                // The frames might be gone from the WAL, start over with a
+                // new snapshot.
+                *position = None;
+                return Err(error);
+            }
+            pos.index += 1;
+        }
+
+        if truncated {
+            pos.wal_header = None;
+            pos.wal_offset = 0;
+        } else {
+            pos.wal_header = Some(wal[..WAL_HEADER_SIZE].to_vec());
+            pos.wal_offset = end;
+        }
+        Ok(())
+    }
+
+    /// Start a new generation from a snapshot of the database.
+    async fn new_generation(&self) -> anyhow::Result<Position> {
+        let generation = format!("{}-{:08x}", time_key(Utc::now()), rand::random::<u32>());
+
+        let mut conn = self.db.acquire().await?;
+        if !checkpoint(&mut conn).await? {
+            return Err(anyhow::Error::msg(
+                "Unable to checkpoint the WAL before taking a snapshot",
+            ));
+        }
+        let snapshot = tokio::fs::read(&self.db_path).await?;
+        drop(conn);
+
+        self.target
+            .put(&format!("{}/{generation}/snapshot.db", self.name), snapshot)
+            .await?;
+        info!(
+            db = self.name,
+            generation, "Started new replication generation"
+        );
+
+        Ok(Position {
+            generation,
+            index: 0,
+            wal_header: None,
+            wal_offset: 0,
+        })
+    }
+
+    /// Start a new generation at the next sync.
+    pub async fn reset(&self) {
+        *self.position.lock().await = None;
+    }
+}
+
+struct RunningReplicator {
+    replicator: Arc<Replicator>,
+    task: JoinHandle<()>,
+}
+
+/// The replicators running in the background.
+#[derive(Clone, Default)]
+pub struct Replicators {
+    target: Option<Arc<dyn ReplicaTarget>>,
+    sync_interval: Duration,
+    running: Arc<Mutex<HashMap<&'static str, RunningReplicator>>>,
+}
+
+impl Replicators {
+    pub fn new(config: Option<&ReplicationConfig>) -> anyhow::Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self::default());
+        };
+        Ok(Self {
+            target: Some(replica_target(config)?),
+            sync_interval: config.sync_interval,
+            running: Arc::default(),
+        })
+    }
+
+    /// Start replicating a database if replication is configured. A
+    /// database that is already replicated starts on a new generation.
+    pub async fn replicate(&self, name: &'static str, db: Database, db_path: String) {
+        let Some(target) = self.target.as_ref() else {
+            return;
+        };
+        let mut running = self.running.lock().await;
+        if let Some(running) = running.get(name) {
+            running.replicator.reset().await;
+            return;
+        }
+
+        let replicator = Arc::new(Replicator::new(name, db, db_path, Arc::clone(target)));
+        let sync_interval = self.sync_interval;
+        let task = tokio::spawn({
+            let replicator = Arc::clone(&replicator);
+            async move {
+                loop {
+                    if let Err(error) = replicator.sync().await {
+                        error!(?error, db = name, "Failed to replicate database");
+                    }
+                    tokio::time::sleep(sync_interval).await;
+                }
+            }
+        });
+        info!(db = name, "Replication started");
+        running.insert(name, RunningReplicator { replicator, task });
+    }
+
+    /// Ship the latest changes of a database right away.
+    pub async fn sync(&self, name: &str) {
+        let replicator = self
+            .running
+            .lock()
+            .await
+            .get(name)
+            .map(|running| Arc::clone(&running.replicator));
+        if let Some(replicator) = replicator {
+            if let Err(error) = replicator.sync().await {
+                error!(?error, db = name, "Failed to replicate database");
+            }
+        }
+    }
+
+    /// Start a new generation of a database at the next sync.
+    pub async fn reset(&self, name: &str) {
+        if let Some(running) = self.running.lock().await.get(name) {
+            running.replicator.reset().await;
+        }
+    }
+
+    /// Ship the latest changes and stop all replication.
+    pub async fn stop(&self) {
+        for (name, running) in self.running.lock().await.drain() {
+            running.task.abort();
+            if let Err(error) = running.replicator.sync().await {
+                error!(
+                    ?error,
+                    db = name,
+                    "Failed to replicate database before shutdown"
+                );
+            }
+        }
+    }
+}
+
+/// Latest generation of a database in the replica that was started before
+/// `until`.
+fn latest_generation<'a>(keys: &'a [String], name: &str, until: DateTime<Utc>) -> Option<&'a str> {
+    keys.iter()
+        .filter_map(|key| {
+            key.strip_prefix(name)?
+                .strip_prefix('/')?
+                .strip_suffix("/snapshot.db")
+        })
+        .filter(|generation| {
+            generation
+                .split_once('-')
+                .and_then(|(time, _)| parse_time_key(time))
+                .is_some_and(|time| time <= until)
+        })
+        .max()
+}
+
+/// Keys of the consecutive segments of a generation, starting at segment
+/// `start`, that were shipped before `until`.
+fn segments<'a>(
+    keys: &'a [String],
+    name: &str,
+    generation: &str,
+    start: u64,
+    until: DateTime<Utc>,
+) -> Vec<&'a str> {
+    let segment_prefix = format!("{name}/{generation}/wal/");
+    let mut segments = Vec::new();
+    for key in keys {
+        let Some((index, time)) = key
+            .strip_prefix(&segment_prefix)
+            .and_then(|segment| segment.strip_suffix(".wal"))
+            .and_then(|segment| segment.split_once('-'))
+        else {
+            continue;
+        };
+        let (Ok(index), Some(time)) = (u64::from_str_radix(index, 16), parse_time_key(time)) else {
+            continue;
+        };
+        if index >= start && time <= until {
+            segments.push((index, key.as_str()));
+        }
+    }
+    segments.sort_unstable();
+
+    // A missing segment means the remaining ones can't be applied
+    (start..)
+        .zip(segments)
+        .take_while(|(expected_index, (index, _))| index == expected_index)
+        .map(|(_, (_, key))| key)
+        .collect()
+}
+
+/// Write the snapshot of a generation with its segments applied to
+/// `output_path`. Returns the number of segments applied.
+async fn restore_generation(
+    target: &dyn ReplicaTarget,
+    name: &str,
+    generation: &str,
+    segments: &[&str],
+    output_path: &str,
+) -> anyhow::Result<u64> {
+    let tmp_path = format!("{output_path}.tmp");
+    let snapshot = target
+        .get(&format!("{name}/{generation}/snapshot.db"))
+        .await?;
+    tokio::fs::write(&tmp_path, snapshot).await?;
+
+    let mut db = File::options().write(true).open(&tmp_path)?;
+    for key in segments {
+        let segment = target.get(key).await?;
+        wal::apply(&mut db, &segment)?;
+    }
+    drop(db);
+
+    for stale_file in [format!("{output_path}-wal"), format!("{output_path}-shm")] {
+        if Path::new(&stale_file).exists() {
+            tokio::fs::remove_file(stale_file).await?;
+        }
+    }
+    tokio::fs::rename(&tmp_path, output_path).await?;
+    Ok(segments.len() as u64)
+}
+
+/// Restore a database from the latest generation in the replica that was
+/// started before `until`, with the segments shipped before `until`.
+///
+/// Returns false if the replica has no generation to restore from.
+pub async fn restore(
+    target: &dyn ReplicaTarget,
+    name: &str,
+    output_path: &str,
+    until: Option<DateTime<Utc>>,
+) -> anyhow::Result<bool> {
+    let until = until.unwrap_or_else(Utc::now);
+    let keys = target.list(&format!("{name}/")).await?;
+
+    let Some(generation) = latest_generation(&keys, name, until) else {
+        return Ok(false);
+    };
+    let segments = segments(&keys, name, generation, 0, until);
+    restore_generation(target, name, generation, &segments, output_path).await?;
+    info!(db = name, generation, "Restored database from replica");
+    Ok(true)
+}
+
+/// Restore the databases that are missing locally from the replica.
+pub async fn restore_missing(
+    config: &Config,
+    replication: &ReplicationConfig,
+) -> anyhow::Result<()> {
+    let target = replica_target(replication)?;
+    for (name, path) in [
+        (SEAL_DB, config.seal_storage_path()),
+        (ENCRYPTED_DB, config.encrypted_storage_path()),
+    ] {
+        // Local storage should be more up to date, so don't overwrite it
+        if Path::new(&path).exists() {
+            debug!(db = name, "Found local storage, skipping restore");
+            continue;
+        }
+        if !restore(target.as_ref(), name, &path, None).await? {
+            info!(db = name, "No replica found");
+        }
+    }
+    Ok(())
+}
+
+/// Restore the storage to how it was at a point in time from the replica.
+///
+/// The server must not be running and there must be no local storage.
+pub async fn restore_storage(config: &Config, until: Option<SystemTime>) -> anyhow::Result<()> {
+    config.sanitize()?;
+    let replication = config.replication.as_ref().ok_or_else(|| {
+        anyhow::Error::msg("Replication has to be configured to restore from a replica")
+    })?;
+    let target = replica_target(replication)?;
+    let until = until.map(DateTime::<Utc>::from);
+
+    for path in [config.seal_storage_path(), config.encrypted_storage_path()] {
+        if Path::new(&path).exists() {
+            return Err(anyhow::Error::msg(format!(
+                "Storage `{path}` already exists, remove it before restoring"
+            )));
+        }
+    }
+
+    if !restore(target.as_ref(), SEAL_DB, &config.seal_storage_path(), until).await? {
+        return Err(anyhow::Error::msg(
+            "No replica of the seal storage found to restore from",
+        ));
+    }
+    // The encrypted storage is only replicated once it has been unsealed
+    if !restore(
+        target.as_ref(),
+        ENCRYPTED_DB,
+        &config.encrypted_storage_path(),
+        until,
+    )
+    .await?
+    {
+        info!("No replica of the encrypted storage found");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+
+    use super::*;
+
+    async fn connect(path: &str) -> Pool<Sqlite> {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .create_if_missing(true)
+                    .journal_mode(SqliteJournalMode::Wal)
+                    .filename(path),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn count(pool: &Pool<Sqlite>) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM items")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn replicate_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("db").to_str().unwrap().to_string();
+        let target: Arc<dyn ReplicaTarget> =
+            Arc::new(DirectoryTarget::new(dir.path().join("replica")));
+
+        let pool = connect(&db_path).await;
+        sqlx::query("CREATE TABLE items (value TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let replicator = Replicator::new(
+            "test",
+            Database::Seal(pool.clone()),
+            db_path.clone(),
+            Arc::clone(&target),
+        );
+
+        // The first sync takes a snapshot
+        replicator.sync().await.unwrap();
+        for i in 0..3 {
+            sqlx::query("INSERT INTO items (value) VALUES ($1)")
+                .bind(i.to_string())
+                .execute(&pool)
+                .await
+                .unwrap();
+            replicator.sync().await.unwrap();
+        }
+        // Nothing to ship
+        replicator.sync().await.unwrap();
+
+        let keys = target.list("test/").await.unwrap();
+        assert_eq!(keys.iter().filter(|key| key.contains("/wal/")).count(), 3);
+        assert_eq!(
+            keys.iter()
+                .filter(|key| key.ends_with("/snapshot.db"))
+                .count(),
+            1
+        );
+
+        let restored_path = dir.path().join("restored").to_str().unwrap().to_string();
+        assert!(restore(target.as_ref(), "test", &restored_path, None)
+            .await
+            .unwrap());
+        assert_eq!(count(&connect(&restored_path).await).await, 3);
+
+        // A replica is not found for other databases, nor before it was started
+        assert!(!restore(target.as_ref(), "other", &restored_path, None)
+            .await
+            .unwrap());
+        let before = Utc::now() - chrono::Duration::days(1);
+        assert!(
+            !restore(target.as_ref(), "test", &restored_path, Some(before))
+                .await
+                .unwrap()
+        );
+
+        // A reset starts a new generation with a new snapshot
+        replicator.reset().await;
+        replicator.sync().await.unwrap();
+        let keys = target.list("test/").await.unwrap();
+        assert_eq!(
+            keys.iter()
+                .filter(|key| key.ends_with("/snapshot.db"))
+                .count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Parsing of the sqlite [WAL file format](https://www.sqlite.org/fileformat2.html#walformat).
+//!
+//! Frames are handled as opaque pages, so the encrypted storage is replicated
+//! and restored without its encryption key.
+
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+};
+
+pub const WAL_HEADER_SIZE: usize = 32;
+const FRAME_HEADER_SIZE: usize = 24;
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+/// Size of the frames in a WAL that starts with `header`.
+fn frame_size(header: &[u8]) -> usize {
+    FRAME_HEADER_SIZE + read_u32(header, 8) as usize
+}
+
+/// Returns the offset right after the last commit frame that follows
+/// `offset`, or `offset` if no transaction was committed after it.
+///
+/// Frames left over from before the WAL was restarted have different salts
+/// than the WAL header and are ignored.
+pub fn committed_end(wal: &[u8], offset: usize) -> usize {
+    if wal.len() < WAL_HEADER_SIZE {
+        return offset;
+    }
+    let frame_size = frame_size(wal);
+    let salts = &wal[16..24];
+
+    let mut end = offset;
+    let mut frame_start = offset;
+    while frame_start + frame_size <= wal.len() {
+        let frame = &wal[frame_start..frame_start + frame_size];
+        if &frame[8..16] != salts {
+            break;
+        }
+        frame_start += frame_size;
+        if read_u32(frame, 4) != 0 {
+            end = frame_start;
+        }
+    }
+    end
+}
+
+/// Write the pages of the committed transactions in a WAL `segment` to the
+/// database file, like a checkpoint does. The segment is a WAL header
+/// followed by frames.
+pub fn apply(db: &mut File, segment: &[u8]) -> std::io::Result<()> {
+    if segment.len() < WAL_HEADER_SIZE {
+        return Ok(());
+    }
+    let page_size = read_u32(segment, 8) as usize;
+    let frame_size = FRAME_HEADER_SIZE + page_size;
+
+    let mut pending = Vec::new();
+    for frame in segment[WAL_HEADER_SIZE..].chunks_exact(frame_size) {
+        pending.push(frame);
+
+        let db_size = read_u32(frame, 4);
+        if db_size == 0 {
+            continue;
+        }
+        for frame in pending.drain(..) {
+            let page_index = u64::from(read_u32(frame, 0))
+                .checked_sub(1)
+                .ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid page number")
+                })?;
+            db.seek(SeekFrom::Start(page_index * page_size as u64))?;
+            db.write_all(&frame[FRAME_HEADER_SIZE..])?;
+        }
+        db.set_len(u64::from(db_size) * page_size as u64)?;
+    }
+    db.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 4;
+
+    fn header(salts: [u8; 8]) -> Vec<u8> {
+        let mut header = vec![0; WAL_HEADER_SIZE];
+        #[allow(clippy::cast_possible_truncation)]
+        header[8..12].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+        header[16..24].copy_from_slice(&salts);
+        header
+    }
+
+    fn frame(page_number: u32, db_size: u32, salts: [u8; 8], page: [u8; PAGE_SIZE]) -> Vec<u8> {
+        let mut frame = vec![0; FRAME_HEADER_SIZE];
+        frame[0..4].copy_from_slice(&page_number.to_be_bytes());
+        frame[4..8].copy_from_slice(&db_size.to_be_bytes());
+        frame[8..16].copy_from_slice(&salts);
+        frame.extend_from_slice(&page);
+        frame
+    }
+
+    #[test]
+    fn committed_frames() {
+        let salts = [1; 8];
+        let mut wal = header(salts);
+        assert_eq!(committed_end(&wal, WAL_HEADER_SIZE), WAL_HEADER_SIZE);
+
+        // Transaction of two frames
+        wal.extend(frame(1, 0, salts, [1; PAGE_SIZE]));
+        assert_eq!(committed_end(&wal, WAL_HEADER_SIZE), WAL_HEADER_SIZE);
+        wal.extend(frame(2, 2, salts, [2; PAGE_SIZE]));
+        let first_commit = wal.len();
+        assert_eq!(committed_end(&wal, WAL_HEADER_SIZE), first_commit);
+
+        // Uncommitted and partially written frames are not included
+        wal.extend(frame(3, 0, salts, [3; PAGE_SIZE]));
+        wal.extend_from_slice(&[0; 10]);
+        assert_eq!(committed_end(&wal, WAL_HEADER_SIZE), first_commit);
+
+        // Neither are frames from before the WAL was restarted
+        wal.truncate(first_commit);
+        wal.extend(frame(3, 3, [2; 8], [3; PAGE_SIZE]));
+        assert_eq!(committed_end(&wal, WAL_HEADER_SIZE), first_commit);
+        assert_eq!(committed_end(&wal, first_commit), first_commit);
+    }
+
+    #[test]
+    fn apply_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        std::fs::write(&path, [0; 3 * PAGE_SIZE]).unwrap();
+
+        let salts = [1; 8];
+        let mut segment = header(salts);
+        segment.extend(frame(2, 0, salts, [2; PAGE_SIZE]));
+        segment.extend(frame(1, 2, salts, [1; PAGE_SIZE]));
+        // Not committed
+        segment.extend(frame(1, 0, salts, [9; PAGE_SIZE]));
+
+        let mut db = File::options().write(true).open(&path).unwrap();
+        apply(&mut db, &segment).unwrap();
+        drop(db);
+
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            [[1; PAGE_SIZE], [2; PAGE_SIZE]].concat()
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use covert_storage::EncryptedPool;
+use covert_types::methods::system::AuditDeviceConfig;
+
+use crate::error::{Error, ErrorType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditDeviceEntry {
+    pub path: String,
+    pub config: AuditDeviceConfig,
+    pub salt: Vec<u8>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct AuditDeviceEntryRaw {
+    path: String,
+    config: String,
+    salt: Vec<u8>,
+}
+
+impl TryFrom<AuditDeviceEntryRaw> for AuditDeviceEntry {
+    type Error = Error;
+
+    fn try_from(raw: AuditDeviceEntryRaw) -> Result<Self, Self::Error> {
+        let config = serde_json::from_str(&raw.config).map_err(|_| {
+            ErrorType::BadData(format!(
+                "Unable to parse audit device config `{}`",
+                raw.config
+            ))
+        })?;
+        Ok(Self {
+            path: raw.path,
+            config,
+            salt: raw.salt,
+        })
+    }
+}
+
+pub struct AuditRepo {
+    pool: Arc<EncryptedPool>,
+}
+
+impl Clone for AuditRepo {
+    fn clone(&self) -> Self {
+        Self {
+            pool: Arc::clone(&self.pool),
+        }
+    }
+}
+
+impl AuditRepo {
+    pub fn new(pool: Arc<EncryptedPool>) -> Self {
+        Self { pool }
+    }
+
+    #[tracing::instrument(skip_all, fields(path = entry.path))]
+    pub async fn create(&self, entry: &AuditDeviceEntry) -> Result<(), Error> {
+        let config = serde_json::to_string(&entry.config)
+            .map_err(|_| ErrorType::BadRequest("Invalid audit device config".to_string()))?;
+
+        sqlx::query("INSERT INTO AUDIT_DEVICES (path, config, salt) VALUES (?, ?, ?)")
+            .bind(&entry.path)
+            .bind(config)
+            .bind(&entry.salt)
+            .execute(self.pool.as_ref())
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn list(&self) -> Result<Vec<AuditDeviceEntry>, Error> {
+        sqlx::query_as("SELECT * FROM AUDIT_DEVICES ORDER BY path")
+            .fetch_all(self.pool.as_ref())
+            .await
+            .map_err(Into::into)
+            .and_then(|devices: Vec<AuditDeviceEntryRaw>| {
+                devices.into_iter().map(TryInto::try_into).collect()
+            })
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn lookup(&self, path: &str) -> Result<Option<AuditDeviceEntry>, Error> {
+        sqlx::query_as("SELECT * FROM AUDIT_DEVICES WHERE path = ?")
+            .bind(path)
+            .fetch_optional(self.pool.as_ref())
+            .await
+            .map_err(Into::into)
+            .and_then(|d: Option<AuditDeviceEntryRaw>| d.map(TryInto::try_into).transpose())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn remove(&self, path: &str) -> Result<bool, Error> {
+        sqlx::query("DELETE FROM AUDIT_DEVICES WHERE path = ?")
+            .bind(path)
+            .execute(self.pool.as_ref())
+            .await
+            .map_err(Into::into)
+            .map(|res| res.rows_affected() == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repos::mount::tests::pool;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn crud() {
+        let pool = Arc::new(pool().await);
+        let store = AuditRepo::new(Arc::clone(&pool));
+
+        let file = AuditDeviceEntry {
+            path: "file".into(),
+            config: AuditDeviceConfig::File {
+                file_path: "/var/log/covert/audit.log".into(),
+            },
+            salt: vec![1, 2, 3],
+        };
+        let stdout = AuditDeviceEntry {
+            path: "stdout".into(),
+            config: AuditDeviceConfig::Stdout,
+            salt: vec![4, 5, 6],
+        };
+        assert!(store.create(&stdout).await.is_ok());
+        assert!(store.create(&file).await.is_ok());
+        // Path is unique
+        assert!(store.create(&file).await.is_err());
+
+        assert_eq!(
+            store.list().await.unwrap(),
+            vec![file.clone(), stdout.clone()]
+        );
+        assert_eq!(store.lookup("file").await.unwrap(), Some(file.clone()));
+        assert_eq!(store.lookup("foo").await.unwrap(), None);
+
+        assert!(store.remove("file").await.unwrap());
+        assert!(!store.remove("file").await.unwrap());
+        assert_eq!(store.list().await.unwrap(), vec![stdout]);
+    }
+}
@@ -2,6 +2,7 @@ use std::{cmp::min, sync::Arc};
 
 use chrono::{DateTime, Utc};
 use covert_storage::EncryptedPool;
+use covert_types::token::TokenAccessor;
 
 use crate::{
     error::{Error, ErrorType},
@@ -28,8 +29,8 @@ impl LeaseRepo {
     #[tracing::instrument(skip_all, fields(lease_id = le.id))]
     pub async fn create(&self, le: &LeaseEntry) -> Result<(), Error> {
         sqlx::query(
-            "INSERT INTO LEASES (id, issued_mount_path, revoke_path, revoke_data, renew_path, renew_data, issued_at, expires_at, last_renewal_time, failed_revocation_attempts, namespace_id)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO LEASES (id, issued_mount_path, revoke_path, revoke_data, renew_path, renew_data, issued_at, expires_at, last_renewal_time, failed_revocation_attempts, namespace_id, token_accessor)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
         .bind(&le.id)
         .bind(&le.issued_mount_path)
@@ -42,6 +43,7 @@ impl LeaseRepo {
         .bind(le.last_renewal_time)
         .bind(le.failed_revocation_attempts)
         .bind(&le.namespace_id)
+        .bind(&le.token_accessor)
         .execute(self.pool.as_ref())
         .await
         .map_err(Into::into)
@@ -153,6 +155,18 @@ impl LeaseRepo {
             .map(|res| res.rows_affected() == 1)
     }
 
+    /// Number of leases pending revocation and how many of them have at least
+    /// one failed revocation attempt.
+    #[tracing::instrument(skip_all)]
+    pub async fn count(&self) -> Result<(i64, i64), Error> {
+        sqlx::query_as(
+            "SELECT COUNT(*), COALESCE(SUM(failed_revocation_attempts > 0), 0) FROM LEASES",
+        )
+        .fetch_one(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+    }
+
     // TODO: this is only ever used in tests and should be deleted
     #[tracing::instrument(skip_all)]
     pub async fn list(&self) -> Result<Vec<LeaseEntry>, Error> {
@@ -177,6 +191,35 @@ impl LeaseRepo {
             .map_err(Into::into)
     }
 
+    /// List all leases requested by a token.
+    #[tracing::instrument(skip_all)]
+    pub async fn list_by_token(&self, accessor: &TokenAccessor) -> Result<Vec<LeaseEntry>, Error> {
+        sqlx::query_as("SELECT * FROM LEASES WHERE token_accessor = ?")
+            .bind(accessor.as_str())
+            .fetch_all(self.pool.as_ref())
+            .await
+            .map_err(Into::into)
+    }
+
+    /// List all leases requested by the tokens of an entity.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_by_entity(
+        &self,
+        entity_name: &str,
+        namespace_id: &str,
+    ) -> Result<Vec<LeaseEntry>, Error> {
+        sqlx::query_as(
+            "SELECT L.* FROM LEASES AS L
+            INNER JOIN TOKENS AS T ON T.accessor = L.token_accessor
+            WHERE T.entity_name = ? AND T.namespace_id = ?",
+        )
+        .bind(entity_name)
+        .bind(namespace_id)
+        .fetch_all(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn lookup(
         &self,
@@ -190,6 +233,25 @@ impl LeaseRepo {
             .await
             .map_err(Into::into)
     }
+
+    /// Lookup the lease of a token issued by an auth method.
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup_token_lease(
+        &self,
+        accessor: &TokenAccessor,
+        namespace_id: &str,
+    ) -> Result<Option<LeaseEntry>, Error> {
+        sqlx::query_as(
+            "SELECT * FROM LEASES
+            WHERE revoke_path IS NULL AND json_extract(revoke_data, '$.accessor') = ?
+                AND namespace_id = ?",
+        )
+        .bind(accessor.as_str())
+        .bind(namespace_id)
+        .fetch_optional(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+    }
 }
 
 #[cfg(test)]
@@ -197,13 +259,16 @@ mod tests {
     use chrono::{Duration, Utc};
     use covert_types::{
         backend::BackendType,
+        entity::Entity,
         mount::{MountConfig, MountEntry},
     };
     use uuid::Uuid;
 
     use crate::repos::{
+        entity::EntityRepo,
         mount::{tests::pool, MountRepo},
         namespace::{Namespace, NamespaceRepo},
+        token::{TokenEntry, TokenRepo},
     };
 
     use super::*;
@@ -237,6 +302,7 @@ mod tests {
 
         // Nothing in beginning
         assert!(lease_repo.peek().await.unwrap().is_none());
+        assert_eq!(lease_repo.count().await.unwrap(), (0, 0));
 
         // Create some leases
         let mut lease_foo_bar = LeaseEntry {
@@ -251,6 +317,7 @@ mod tests {
             last_renewal_time: Utc::now(),
             failed_revocation_attempts: 0,
             namespace_id: ns.id.clone(),
+            token_accessor: None,
         };
         assert!(lease_repo.create(&lease_foo_bar).await.is_ok());
         assert_eq!(
@@ -270,6 +337,7 @@ mod tests {
             last_renewal_time: Utc::now(),
             failed_revocation_attempts: 0,
             namespace_id: ns.id.clone(),
+            token_accessor: None,
         };
         assert!(lease_repo.create(&lease_bar_foo).await.is_ok());
         assert_eq!(
@@ -370,5 +438,82 @@ mod tests {
             lease_bar_foo.failed_revocation_attempts,
             lease_bar_foo_from_store.failed_revocation_attempts
         );
+        assert_eq!(lease_repo.count().await.unwrap(), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn list_by_token_and_entity() {
+        let pool = Arc::new(pool().await);
+        let mount_repo = MountRepo::new(Arc::clone(&pool));
+        let lease_repo = LeaseRepo::new(Arc::clone(&pool));
+        let ns_repo = NamespaceRepo::new(Arc::clone(&pool));
+        let entity_repo = EntityRepo::new(Arc::clone(&pool));
+        let token_repo = TokenRepo::new(Arc::clone(&pool));
+
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ns_repo.create(&ns).await.unwrap();
+        let mount = MountEntry {
+            id: Uuid::new_v4(),
+            backend_type: BackendType::Postgres,
+            config: MountConfig::default(),
+            path: "psql/".into(),
+            namespace_id: ns.id.clone(),
+        };
+        mount_repo.create(&mount).await.unwrap();
+        entity_repo
+            .create(&Entity::new("foo".into(), ns.id.clone()))
+            .await
+            .unwrap();
+        let token = TokenEntry::new("foo".into(), Duration::hours(1), ns.id.clone());
+        token_repo.create(&token).await.unwrap();
+
+        let mut lease = LeaseEntry::new(
+            mount.path.clone(),
+            Some("revoke".into()),
+            &(),
+            Some("renew".into()),
+            &(),
+            Utc::now(),
+            Duration::hours(1),
+            ns.id.clone(),
+        )
+        .unwrap();
+        lease.token_accessor = Some(token.accessor().to_string());
+        lease_repo.create(&lease).await.unwrap();
+        let other_lease = LeaseEntry::new(
+            mount.path.clone(),
+            Some("revoke".into()),
+            &(),
+            Some("renew".into()),
+            &(),
+            Utc::now(),
+            Duration::hours(1),
+            ns.id.clone(),
+        )
+        .unwrap();
+        lease_repo.create(&other_lease).await.unwrap();
+
+        assert_eq!(
+            lease_repo.list_by_token(token.accessor()).await.unwrap(),
+            vec![lease.clone()]
+        );
+        assert!(lease_repo
+            .list_by_token(&TokenAccessor::new())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            lease_repo.list_by_entity("foo", &ns.id).await.unwrap(),
+            vec![lease]
+        );
+        assert!(lease_repo
+            .list_by_entity("bar", &ns.id)
+            .await
+            .unwrap()
+            .is_empty());
     }
 }
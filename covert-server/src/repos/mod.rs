@@ -4,10 +4,12 @@ use covert_storage::EncryptedPool;
 use sqlx::{Pool, Sqlite};
 
 use self::{
-    entity::EntityRepo, lease::LeaseRepo, mount::MountRepo, namespace::NamespaceRepo,
-    policy::PolicyRepo, seal::SealRepo, token::TokenRepo,
+    audit::AuditRepo, entity::EntityRepo, lease::LeaseRepo, mount::MountRepo,
+    namespace::NamespaceRepo, policy::PolicyRepo, seal::SealRepo, token::TokenRepo,
+    wrapping::WrappingRepo,
 };
 
+pub mod audit;
 pub mod entity;
 pub mod lease;
 pub mod mount;
@@ -15,9 +17,11 @@ pub mod namespace;
 pub mod policy;
 pub mod seal;
 pub mod token;
+pub mod wrapping;
 
 #[derive(Clone)]
 pub struct Repos {
+    pub audit: AuditRepo,
     pub entity: EntityRepo,
     pub lease: LeaseRepo,
     pub mount: MountRepo,
@@ -25,6 +29,7 @@ pub struct Repos {
     pub token: TokenRepo,
     pub namespace: NamespaceRepo,
     pub seal: SealRepo,
+    pub wrapping: WrappingRepo,
     pub pool: Arc<EncryptedPool>,
     pub unecrypted_pool: Pool<Sqlite>,
 }
@@ -32,6 +37,7 @@ pub struct Repos {
 impl Repos {
     pub fn new(pool: Arc<EncryptedPool>, unecrypted_pool: Pool<Sqlite>) -> Self {
         Self {
+            audit: AuditRepo::new(Arc::clone(&pool)),
             entity: EntityRepo::new(Arc::clone(&pool)),
             lease: LeaseRepo::new(Arc::clone(&pool)),
             mount: MountRepo::new(Arc::clone(&pool)),
@@ -39,6 +45,7 @@ impl Repos {
             token: TokenRepo::new(Arc::clone(&pool)),
             namespace: NamespaceRepo::new(Arc::clone(&pool)),
             seal: SealRepo::new(unecrypted_pool.clone()),
+            wrapping: WrappingRepo::new(Arc::clone(&pool)),
             pool,
             unecrypted_pool,
         }
@@ -133,6 +133,34 @@ impl MountRepo {
             .map_err(Into::into)
     }
 
+    /// List the mounts of all namespaces.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_all(&self) -> Result<Vec<MountEntry>, Error> {
+        sqlx::query_as("SELECT * FROM MOUNTS ORDER BY namespace_id, path ASC")
+            .fetch_all(self.pool.as_ref())
+            .await
+            .map_err(Into::into)
+            .and_then(|mounts: Vec<MountEntryRaw>| {
+                mounts.into_iter().map(TryInto::try_into).collect()
+            })
+    }
+
+    /// List the mounts of a backend type across all namespaces.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_by_backend_type(
+        &self,
+        variant: BackendType,
+    ) -> Result<Vec<MountEntry>, Error> {
+        sqlx::query_as("SELECT * FROM MOUNTS WHERE variant = ? ORDER BY namespace_id, path ASC")
+            .bind(variant.to_string())
+            .fetch_all(self.pool.as_ref())
+            .await
+            .map_err(Into::into)
+            .and_then(|mounts: Vec<MountEntryRaw>| {
+                mounts.into_iter().map(TryInto::try_into).collect()
+            })
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn get_by_path(
         &self,
@@ -220,6 +248,15 @@ pub mod tests {
         };
         assert!(store.create(&me).await.is_ok());
         assert_eq!(store.list(&ns.id).await.unwrap(), vec![me.clone()]);
+        assert_eq!(
+            store.list_by_backend_type(BackendType::Kv).await.unwrap(),
+            vec![me.clone()]
+        );
+        assert!(store
+            .list_by_backend_type(BackendType::Userpass)
+            .await
+            .unwrap()
+            .is_empty());
 
         let new_config = MountConfig {
             default_lease_ttl: Duration::ZERO,
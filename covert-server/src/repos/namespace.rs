@@ -138,6 +138,24 @@ impl NamespaceRepo {
         })
     }
 
+    /// List all namespaces, parents before their children.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_all(&self) -> Result<Vec<Namespace>, Error> {
+        sqlx::query_as(&format!(
+            "WITH RECURSIVE tree(id, name, parent_namespace_id, depth) AS (
+                SELECT id, name, parent_namespace_id, 0 FROM {NAMESPACE_TABLE}
+                    WHERE parent_namespace_id IS NULL
+                UNION ALL
+                SELECT ns.id, ns.name, ns.parent_namespace_id, tree.depth + 1
+                    FROM {NAMESPACE_TABLE} ns JOIN tree ON ns.parent_namespace_id = tree.id
+            )
+            SELECT id, name, parent_namespace_id FROM tree ORDER BY depth, name ASC"
+        ))
+        .fetch_all(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn list(&self, id: &str) -> Result<Vec<Namespace>, Error> {
         sqlx::query_as(&format!(
@@ -209,6 +227,16 @@ mod tests {
             .await
             .unwrap()
             .is_empty());
+        assert_eq!(
+            ns_repo.list_all().await.unwrap(),
+            vec![
+                root_ns.clone(),
+                root_bar_ns.clone(),
+                root_foo_ns.clone(),
+                root_foo_bar_ns.clone(),
+                root_foo_bar_baz_ns.clone()
+            ]
+        );
 
         // Find by path
         assert!(ns_repo.find_by_path(&[]).await.unwrap().is_none());
@@ -2,8 +2,11 @@ use aes_gcm::{
     aead::{Aead, OsRng},
     Aes256Gcm, KeyInit, Nonce,
 };
+use std::future::Future;
+
+use chrono::{DateTime, Utc};
 use rand::distributions::{Alphanumeric, DistString};
-use sqlx::{Pool, Sqlite};
+use sqlx::{Pool, Sqlite, Transaction};
 
 use crate::error::{Error, ErrorType};
 
@@ -11,6 +14,26 @@ const SEAL_CONFIGURATION_TABLE: &str = "SEAL_CONFIG";
 
 const KEY_SHARES_TABLE: &str = "KEY_SHARES";
 
+const GENERATE_ROOT_TABLE: &str = "GENERATE_ROOT";
+
+const GENERATE_ROOT_KEY_SHARES_TABLE: &str = "GENERATE_ROOT_KEY_SHARES";
+
+const MASTER_KEY_TABLE: &str = "MASTER_KEY";
+
+const REKEY_TABLE: &str = "REKEY";
+
+const REKEY_KEY_SHARES_TABLE: &str = "REKEY_KEY_SHARES";
+
+const KEY_GENERATIONS_TABLE: &str = "KEY_GENERATIONS";
+
+const KEK_MASTER_KEY_TABLE: &str = "KEK_MASTER_KEY";
+
+const STAGED_MASTER_KEY_TABLE: &str = "STAGED_MASTER_KEY";
+
+const UNSEAL_TABLE: &str = "UNSEAL";
+
+const CLUSTER_TABLE: &str = "CLUSTER";
+
 #[derive(Debug, sqlx::FromRow, PartialEq, Eq)]
 pub struct SealConfig {
     pub threshold: u8,
@@ -23,6 +46,91 @@ pub struct KeyShare {
     pub nonce: Vec<u8>,
 }
 
+/// An attempt to generate a new root token.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GenerateRootAttempt {
+    pub nonce: String,
+    pub otp: Option<String>,
+    pub pgp_key: Option<String>,
+}
+
+/// Master key encrypted with the unseal key.
+#[derive(Debug, Clone, sqlx::FromRow, PartialEq, Eq)]
+pub struct WrappedKey {
+    pub key: Vec<u8>,
+    pub nonce: Vec<u8>,
+    /// Concatenated SHA-256 digests of the key shares of the unseal key. Not
+    /// set for master keys wrapped before the digests were recorded.
+    pub share_digests: Option<Vec<u8>>,
+}
+
+/// A generation of the master key.
+#[derive(Debug, sqlx::FromRow, PartialEq, Eq)]
+pub struct KeyGeneration {
+    pub generation: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// New master key of a rotation that is staged until the storage is
+/// re-encrypted with it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StagedMasterKey {
+    pub master_key: WrappedKey,
+    pub kek_master_key: Option<Vec<u8>>,
+    pub generation: KeyGeneration,
+}
+
+#[derive(sqlx::FromRow)]
+struct StagedMasterKeyRaw {
+    key: Vec<u8>,
+    nonce: Vec<u8>,
+    share_digests: Option<Vec<u8>>,
+    kek_key: Option<Vec<u8>>,
+    generation: u32,
+    created_at: DateTime<Utc>,
+}
+
+/// Seal metadata that a snapshot of the encrypted storage is unsealed with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SealSnapshot {
+    pub config: SealConfig,
+    pub master_key: Option<WrappedKey>,
+    pub kek_master_key: Option<Vec<u8>>,
+    pub key_generation: Option<KeyGeneration>,
+}
+
+/// An attempt to replace the key shares with a new set of shares.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RekeyAttempt {
+    pub nonce: String,
+    pub config: SealConfig,
+    pub require_verification: bool,
+    /// Set once the new key shares are dealt and are awaiting verification.
+    pub verification_nonce: Option<String>,
+    /// Master key encrypted with the new unseal key, awaiting verification.
+    pub master_key: Option<WrappedKey>,
+}
+
+#[derive(sqlx::FromRow)]
+struct RekeyAttemptRaw {
+    nonce: String,
+    shares: u8,
+    threshold: u8,
+    require_verification: bool,
+    verification_nonce: Option<String>,
+    master_key: Option<Vec<u8>>,
+    master_key_nonce: Option<Vec<u8>>,
+    master_key_share_digests: Option<Vec<u8>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct GenerateRootAttemptRaw {
+    nonce: String,
+    otp: Option<Vec<u8>>,
+    otp_nonce: Option<Vec<u8>>,
+    pgp_key: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct SealRepo {
     pool: Pool<Sqlite>,
@@ -58,49 +166,585 @@ impl SealRepo {
             .map_err(Into::into)
     }
 
-    pub async fn clear_key_shares(&self) -> Result<u64, Error> {
-        sqlx::query(&format!("DELETE FROM {KEY_SHARES_TABLE}"))
+    pub async fn insert_key_share(&self, key: &[u8]) -> Result<(), Error> {
+        self.insert_share(KEY_SHARES_TABLE, key).await
+    }
+
+    pub async fn get_key_shares(&self) -> Result<Vec<KeyShare>, Error> {
+        self.get_shares(KEY_SHARES_TABLE).await
+    }
+
+    /// Set the cluster id unless it is already set. Returns the cluster id.
+    pub async fn init_cluster_id(&self, id: &str) -> Result<String, Error> {
+        sqlx::query(&format!(
+            "INSERT OR IGNORE INTO {CLUSTER_TABLE} (id, lock) VALUES ($1, $2)"
+        ))
+        .bind(id)
+        .bind(1)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query_scalar(&format!("SELECT id FROM {CLUSTER_TABLE}"))
+            .fetch_one(&self.pool)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Start an unseal attempt unless one is already in progress. Returns the
+    /// nonce of the attempt in progress.
+    pub async fn start_unseal(&self, nonce: &str) -> Result<String, Error> {
+        sqlx::query(&format!(
+            "INSERT OR IGNORE INTO {UNSEAL_TABLE} (nonce, lock) VALUES ($1, $2)"
+        ))
+        .bind(nonce)
+        .bind(1)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query_scalar(&format!("SELECT nonce FROM {UNSEAL_TABLE}"))
+            .fetch_one(&self.pool)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn get_unseal_nonce(&self) -> Result<Option<String>, Error> {
+        sqlx::query_scalar(&format!("SELECT nonce FROM {UNSEAL_TABLE}"))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Remove the unseal attempt and its key shares.
+    pub async fn reset_unseal(&self) -> Result<(), Error> {
+        self.clear_shares(KEY_SHARES_TABLE).await?;
+        sqlx::query(&format!("DELETE FROM {UNSEAL_TABLE}"))
             .execute(&self.pool)
             .await
-            .map(|res| res.rows_affected())
+            .map(|_| ())
             .map_err(Into::into)
     }
 
-    pub async fn insert_key_share(&self, key: &[u8]) -> Result<(), Error> {
-        let nonce = random_nonce();
-        let nonce = nonce.as_bytes();
-        let nonce_array = Nonce::from_slice(nonce);
+    pub async fn start_generate_root(&self, attempt: &GenerateRootAttempt) -> Result<(), Error> {
+        let otp = attempt
+            .otp
+            .as_ref()
+            .map(|otp| self.encrypt(otp.as_bytes()))
+            .transpose()?;
+        let (otp, otp_nonce) = otp.unzip();
+
+        sqlx::query(&format!(
+            "INSERT INTO {GENERATE_ROOT_TABLE} (nonce, otp, otp_nonce, pgp_key, lock)
+                VALUES ($1, $2, $3, $4, $5)"
+        ))
+        .bind(&attempt.nonce)
+        .bind(otp)
+        .bind(otp_nonce)
+        .bind(&attempt.pgp_key)
+        .bind(1)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(Into::into)
+    }
+
+    pub async fn get_generate_root(&self) -> Result<Option<GenerateRootAttempt>, Error> {
+        let attempt: Option<GenerateRootAttemptRaw> =
+            sqlx::query_as(&format!("SELECT * FROM {GENERATE_ROOT_TABLE}"))
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some(attempt) = attempt else {
+            return Ok(None);
+        };
+
+        let otp = match (attempt.otp, attempt.otp_nonce) {
+            (Some(otp), Some(nonce)) => {
+                let otp = self.decrypt(&otp, &nonce).ok_or_else(|| {
+                    ErrorType::BadData("Unable to decrypt one-time pad from seal storage".into())
+                })?;
+                let otp = String::from_utf8(otp).map_err(|_| {
+                    ErrorType::BadData("Invalid one-time pad found in seal storage".into())
+                })?;
+                Some(otp)
+            }
+            _ => None,
+        };
+
+        Ok(Some(GenerateRootAttempt {
+            nonce: attempt.nonce,
+            otp,
+            pgp_key: attempt.pgp_key,
+        }))
+    }
 
-        let key = self.cipher.encrypt(nonce_array, key).map_err(|_| {
-            ErrorType::InternalError(anyhow::Error::msg("Unable to encrypt key share"))
+    /// Remove the generate root attempt and its key shares.
+    pub async fn cancel_generate_root(&self) -> Result<(), Error> {
+        self.clear_shares(GENERATE_ROOT_KEY_SHARES_TABLE).await?;
+        sqlx::query(&format!("DELETE FROM {GENERATE_ROOT_TABLE}"))
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    pub async fn insert_generate_root_key_share(&self, key: &[u8]) -> Result<(), Error> {
+        self.insert_share(GENERATE_ROOT_KEY_SHARES_TABLE, key).await
+    }
+
+    pub async fn get_generate_root_key_shares(&self) -> Result<Vec<KeyShare>, Error> {
+        self.get_shares(GENERATE_ROOT_KEY_SHARES_TABLE).await
+    }
+
+    /// Store the encrypted master key of newly initialized storage as the first
+    /// key generation. The master key encrypted with the key encryption key is
+    /// given when auto-unseal is configured.
+    pub async fn set_master_key(
+        &self,
+        master_key: &WrappedKey,
+        kek_master_key: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        store_master_key(&mut tx, master_key, kek_master_key).await?;
+
+        sqlx::query(&format!(
+            "INSERT INTO {KEY_GENERATIONS_TABLE} (generation, created_at) VALUES ($1, $2)"
+        ))
+        .bind(1)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await.map_err(Into::into)
+    }
+
+    /// Stage the new master key of a rotation before the storage is
+    /// re-encrypted with it, so the new master key is not lost if the current
+    /// master key can't be replaced afterwards. Returns the key generation the
+    /// new master key becomes once it is made current by `rotate_master_key`.
+    pub async fn stage_master_key(
+        &self,
+        master_key: &WrappedKey,
+        kek_master_key: Option<&[u8]>,
+    ) -> Result<KeyGeneration, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Storage initialized before key generations were recorded is on its
+        // first generation
+        let generation: u32 = sqlx::query_scalar(&format!(
+            "SELECT COALESCE(MAX(generation), 1) + 1 FROM {KEY_GENERATIONS_TABLE}"
+        ))
+        .fetch_one(&mut tx)
+        .await?;
+        let generation = KeyGeneration {
+            generation,
+            created_at: Utc::now(),
+        };
+
+        sqlx::query(&format!(
+            "INSERT OR REPLACE INTO {STAGED_MASTER_KEY_TABLE}
+                (key, nonce, share_digests, kek_key, generation, created_at, lock)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)"
+        ))
+        .bind(&master_key.key)
+        .bind(&master_key.nonce)
+        .bind(&master_key.share_digests)
+        .bind(kek_master_key)
+        .bind(generation.generation)
+        .bind(generation.created_at)
+        .bind(1)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(generation)
+    }
+
+    pub async fn get_staged_master_key(&self) -> Result<Option<StagedMasterKey>, Error> {
+        let staged: Option<StagedMasterKeyRaw> =
+            sqlx::query_as(&format!("SELECT * FROM {STAGED_MASTER_KEY_TABLE}"))
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(staged.map(|staged| StagedMasterKey {
+            master_key: WrappedKey {
+                key: staged.key,
+                nonce: staged.nonce,
+                share_digests: staged.share_digests,
+            },
+            kek_master_key: staged.kek_key,
+            generation: KeyGeneration {
+                generation: staged.generation,
+                created_at: staged.created_at,
+            },
+        }))
+    }
+
+    /// Remove the staged master key of a rotation that failed to re-encrypt
+    /// the storage.
+    pub async fn discard_staged_master_key(&self) -> Result<(), Error> {
+        sqlx::query(&format!("DELETE FROM {STAGED_MASTER_KEY_TABLE}"))
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    /// Replace the encrypted master key with the staged master key, once the
+    /// storage is re-encrypted with it, and record its key generation.
+    ///
+    /// Any rekey attempt is cancelled as it holds the old master key.
+    pub async fn rotate_master_key(&self) -> Result<KeyGeneration, Error> {
+        let staged = self.get_staged_master_key().await?.ok_or_else(|| {
+            ErrorType::InternalError(anyhow::Error::msg("No staged master key was found"))
         })?;
 
+        let mut tx = self.pool.begin().await?;
+
+        store_master_key(
+            &mut tx,
+            &staged.master_key,
+            staged.kek_master_key.as_deref(),
+        )
+        .await?;
         sqlx::query(&format!(
-            "INSERT INTO {KEY_SHARES_TABLE} (key, nonce) VALUES ($1, $2)"
+            "INSERT INTO {KEY_GENERATIONS_TABLE} (generation, created_at) VALUES ($1, $2)"
         ))
-        .bind(key)
-        .bind(nonce)
+        .bind(staged.generation.generation)
+        .bind(staged.generation.created_at)
+        .execute(&mut tx)
+        .await?;
+
+        for table in [STAGED_MASTER_KEY_TABLE, REKEY_KEY_SHARES_TABLE, REKEY_TABLE] {
+            sqlx::query(&format!("DELETE FROM {table}"))
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(staged.generation)
+    }
+
+    /// Read the seal metadata for a snapshot of the encrypted storage taken
+    /// by `snapshot`.
+    ///
+    /// The transaction holds the only connection of the seal storage while
+    /// the snapshot is taken, so the master key is not rotated in between.
+    pub async fn snapshot<F, Fut, T>(&self, snapshot: F) -> Result<(SealSnapshot, T), Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut tx = self.pool.begin().await?;
+
+        let data = snapshot().await?;
+
+        let config: SealConfig =
+            sqlx::query_as(&format!("SELECT * FROM {SEAL_CONFIGURATION_TABLE}"))
+                .fetch_optional(&mut tx)
+                .await?
+                .ok_or_else(|| {
+                    ErrorType::InternalError(anyhow::Error::msg(
+                        "Seal config was not found when taking a snapshot",
+                    ))
+                })?;
+        let master_key = sqlx::query_as(&format!(
+            "SELECT key, nonce, share_digests FROM {MASTER_KEY_TABLE}"
+        ))
+        .fetch_optional(&mut tx)
+        .await?;
+        let kek_master_key = sqlx::query_scalar(&format!("SELECT key FROM {KEK_MASTER_KEY_TABLE}"))
+            .fetch_optional(&mut tx)
+            .await?;
+        let key_generation = sqlx::query_as(&format!(
+            "SELECT * FROM {KEY_GENERATIONS_TABLE} ORDER BY generation DESC LIMIT 1"
+        ))
+        .fetch_optional(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok((
+            SealSnapshot {
+                config,
+                master_key,
+                kek_master_key,
+                key_generation,
+            },
+            data,
+        ))
+    }
+
+    /// Replace the seal metadata with the metadata of a snapshot while
+    /// `restore` replaces the encrypted storage. Nothing is changed if
+    /// `restore` fails.
+    ///
+    /// Attempts in progress are removed as they are for the replaced master
+    /// key.
+    pub async fn restore_snapshot<F, Fut>(
+        &self,
+        snapshot: &SealSnapshot,
+        restore: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(), Error>>,
+    {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(&format!(
+            "INSERT OR REPLACE INTO {SEAL_CONFIGURATION_TABLE} (shares, threshold, lock)
+                VALUES ($1, $2, $3)"
+        ))
+        .bind(snapshot.config.shares)
+        .bind(snapshot.config.threshold)
+        .bind(1)
+        .execute(&mut tx)
+        .await?;
+
+        match snapshot.master_key.as_ref() {
+            Some(master_key) => {
+                store_master_key(&mut tx, master_key, snapshot.kek_master_key.as_deref()).await?;
+            }
+            None => {
+                for table in [MASTER_KEY_TABLE, KEK_MASTER_KEY_TABLE] {
+                    sqlx::query(&format!("DELETE FROM {table}"))
+                        .execute(&mut tx)
+                        .await?;
+                }
+            }
+        }
+
+        sqlx::query(&format!("DELETE FROM {KEY_GENERATIONS_TABLE}"))
+            .execute(&mut tx)
+            .await?;
+        if let Some(generation) = snapshot.key_generation.as_ref() {
+            sqlx::query(&format!(
+                "INSERT INTO {KEY_GENERATIONS_TABLE} (generation, created_at) VALUES ($1, $2)"
+            ))
+            .bind(generation.generation)
+            .bind(generation.created_at)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        for table in [
+            STAGED_MASTER_KEY_TABLE,
+            KEY_SHARES_TABLE,
+            UNSEAL_TABLE,
+            GENERATE_ROOT_KEY_SHARES_TABLE,
+            GENERATE_ROOT_TABLE,
+            REKEY_KEY_SHARES_TABLE,
+            REKEY_TABLE,
+        ] {
+            sqlx::query(&format!("DELETE FROM {table}"))
+                .execute(&mut tx)
+                .await?;
+        }
+
+        restore().await?;
+
+        tx.commit().await.map_err(Into::into)
+    }
+
+    /// Get the current generation of the master key. Storage initialized
+    /// before key generations were recorded does not have one.
+    pub async fn get_key_generation(&self) -> Result<Option<KeyGeneration>, Error> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {KEY_GENERATIONS_TABLE} ORDER BY generation DESC LIMIT 1"
+        ))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Store the master key encrypted with the key encryption key of the
+    /// auto-unseal provider.
+    pub async fn set_kek_master_key(&self, kek_master_key: &[u8]) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "INSERT OR REPLACE INTO {KEK_MASTER_KEY_TABLE} (key, lock) VALUES ($1, $2)"
+        ))
+        .bind(kek_master_key)
+        .bind(1)
         .execute(&self.pool)
         .await
+        .map(|_| ())
         .map_err(Into::into)
+    }
+
+    pub async fn get_kek_master_key(&self) -> Result<Option<Vec<u8>>, Error> {
+        sqlx::query_scalar(&format!("SELECT key FROM {KEK_MASTER_KEY_TABLE}"))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Get the encrypted master key. Storage initialized before the master key
+    /// was wrapped does not have one, its key shares split the master key
+    /// itself.
+    pub async fn get_master_key(&self) -> Result<Option<WrappedKey>, Error> {
+        sqlx::query_as(&format!(
+            "SELECT key, nonce, share_digests FROM {MASTER_KEY_TABLE}"
+        ))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn start_rekey(&self, attempt: &RekeyAttempt) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "INSERT INTO {REKEY_TABLE} (nonce, shares, threshold, require_verification, lock)
+                VALUES ($1, $2, $3, $4, $5)"
+        ))
+        .bind(&attempt.nonce)
+        .bind(attempt.config.shares)
+        .bind(attempt.config.threshold)
+        .bind(attempt.require_verification)
+        .bind(1)
+        .execute(&self.pool)
+        .await
         .map(|_| ())
+        .map_err(Into::into)
     }
 
-    pub async fn get_key_shares(&self) -> Result<Vec<KeyShare>, Error> {
-        let key_shares: Vec<KeyShare> =
-            sqlx::query_as(&format!("SELECT * FROM {KEY_SHARES_TABLE}"))
-                .fetch_all(&self.pool)
+    pub async fn get_rekey(&self) -> Result<Option<RekeyAttempt>, Error> {
+        let attempt: Option<RekeyAttemptRaw> =
+            sqlx::query_as(&format!("SELECT * FROM {REKEY_TABLE}"))
+                .fetch_optional(&self.pool)
                 .await?;
 
+        Ok(attempt.map(|attempt| RekeyAttempt {
+            nonce: attempt.nonce,
+            config: SealConfig {
+                threshold: attempt.threshold,
+                shares: attempt.shares,
+            },
+            require_verification: attempt.require_verification,
+            verification_nonce: attempt.verification_nonce,
+            master_key: attempt
+                .master_key
+                .zip(attempt.master_key_nonce)
+                .map(|(key, nonce)| WrappedKey {
+                    key,
+                    nonce,
+                    share_digests: attempt.master_key_share_digests,
+                }),
+        }))
+    }
+
+    /// Remove the rekey attempt and its key shares.
+    pub async fn cancel_rekey(&self) -> Result<(), Error> {
+        self.clear_shares(REKEY_KEY_SHARES_TABLE).await?;
+        sqlx::query(&format!("DELETE FROM {REKEY_TABLE}"))
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    pub async fn insert_rekey_key_share(&self, key: &[u8]) -> Result<(), Error> {
+        self.insert_share(REKEY_KEY_SHARES_TABLE, key).await
+    }
+
+    pub async fn get_rekey_key_shares(&self) -> Result<Vec<KeyShare>, Error> {
+        self.get_shares(REKEY_KEY_SHARES_TABLE).await
+    }
+
+    pub async fn clear_rekey_key_shares(&self) -> Result<u64, Error> {
+        self.clear_shares(REKEY_KEY_SHARES_TABLE).await
+    }
+
+    /// Keep the master key encrypted with the new unseal key until a
+    /// threshold of the new key shares have been provided.
+    pub async fn start_rekey_verification(
+        &self,
+        verification_nonce: &str,
+        master_key: &WrappedKey,
+    ) -> Result<(), Error> {
+        self.clear_shares(REKEY_KEY_SHARES_TABLE).await?;
+        sqlx::query(&format!(
+            "UPDATE {REKEY_TABLE} SET verification_nonce = $1, master_key = $2, master_key_nonce = $3,
+                master_key_share_digests = $4"
+        ))
+        .bind(verification_nonce)
+        .bind(&master_key.key)
+        .bind(&master_key.nonce)
+        .bind(&master_key.share_digests)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(Into::into)
+    }
+
+    /// Replace the seal config and the encrypted master key in a single
+    /// transaction and remove the rekey attempt. The old key shares are
+    /// unable to decrypt the master key from here on.
+    pub async fn complete_rekey(
+        &self,
+        config: &SealConfig,
+        master_key: &WrappedKey,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(&format!(
+            "UPDATE {SEAL_CONFIGURATION_TABLE} SET shares = $1, threshold = $2"
+        ))
+        .bind(config.shares)
+        .bind(config.threshold)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(&format!(
+            "INSERT OR REPLACE INTO {MASTER_KEY_TABLE} (key, nonce, share_digests, lock)
+                VALUES ($1, $2, $3, $4)"
+        ))
+        .bind(&master_key.key)
+        .bind(&master_key.nonce)
+        .bind(&master_key.share_digests)
+        .bind(1)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(&format!("DELETE FROM {REKEY_KEY_SHARES_TABLE}"))
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(&format!("DELETE FROM {REKEY_TABLE}"))
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await.map_err(Into::into)
+    }
+
+    async fn clear_shares(&self, table: &str) -> Result<u64, Error> {
+        sqlx::query(&format!("DELETE FROM {table}"))
+            .execute(&self.pool)
+            .await
+            .map(|res| res.rows_affected())
+            .map_err(Into::into)
+    }
+
+    async fn insert_share(&self, table: &str, key: &[u8]) -> Result<(), Error> {
+        let (key, nonce) = self.encrypt(key)?;
+
+        sqlx::query(&format!("INSERT INTO {table} (key, nonce) VALUES ($1, $2)"))
+            .bind(key)
+            .bind(nonce)
+            .execute(&self.pool)
+            .await
+            .map_err(Into::into)
+            .map(|_| ())
+    }
+
+    async fn get_shares(&self, table: &str) -> Result<Vec<KeyShare>, Error> {
+        let key_shares: Vec<KeyShare> = sqlx::query_as(&format!("SELECT * FROM {table}"))
+            .fetch_all(&self.pool)
+            .await?;
+
         let mut decrypted_key_shares: Vec<KeyShare> = vec![];
         for key_share in key_shares {
-            let nonce = Nonce::from_slice(&key_share.nonce);
-
-            let Ok(decrypted_key) = self.cipher.decrypt(nonce, key_share.key.as_ref()) else {
+            let Some(decrypted_key) = self.decrypt(&key_share.key, &key_share.nonce) else {
                 // Clear all shares if there are any bad shares
-                self.clear_key_shares().await?;
+                self.clear_shares(table).await?;
 
-                return Err(ErrorType::BadData("Unable to decrypt key share from seal storage".into()))?;
+                return Err(ErrorType::BadData(
+                    "Unable to decrypt key share from seal storage".into(),
+                ))?;
             };
             if !decrypted_key_shares.iter().any(|k| k.key == decrypted_key) {
                 decrypted_key_shares.push(KeyShare {
@@ -112,6 +756,61 @@ impl SealRepo {
 
         Ok(decrypted_key_shares)
     }
+
+    /// Encrypt data with the in-memory key. Returns the ciphertext and the
+    /// nonce used.
+    fn encrypt(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let nonce = random_nonce().into_bytes();
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), data)
+            .map_err(|_| ErrorType::InternalError(anyhow::Error::msg("Unable to encrypt data")))?;
+        Ok((ciphertext, nonce))
+    }
+
+    fn decrypt(&self, ciphertext: &[u8], nonce: &[u8]) -> Option<Vec<u8>> {
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+}
+
+/// Replace the master key encrypted with the unseal key and the key encryption
+/// key. The latter is removed if auto-unseal is no longer configured as it
+/// would hold an old master key.
+async fn store_master_key(
+    tx: &mut Transaction<'_, Sqlite>,
+    master_key: &WrappedKey,
+    kek_master_key: Option<&[u8]>,
+) -> Result<(), Error> {
+    sqlx::query(&format!(
+        "INSERT OR REPLACE INTO {MASTER_KEY_TABLE} (key, nonce, share_digests, lock)
+            VALUES ($1, $2, $3, $4)"
+    ))
+    .bind(&master_key.key)
+    .bind(&master_key.nonce)
+    .bind(&master_key.share_digests)
+    .bind(1)
+    .execute(&mut *tx)
+    .await?;
+
+    match kek_master_key {
+        Some(kek_master_key) => {
+            sqlx::query(&format!(
+                "INSERT OR REPLACE INTO {KEK_MASTER_KEY_TABLE} (key, lock) VALUES ($1, $2)"
+            ))
+            .bind(kek_master_key)
+            .bind(1)
+            .execute(&mut *tx)
+            .await?;
+        }
+        None => {
+            sqlx::query(&format!("DELETE FROM {KEK_MASTER_KEY_TABLE}"))
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    Ok(())
 }
 
 fn random_nonce() -> String {
@@ -134,6 +833,10 @@ mod tests {
 
         assert!(seal.get_config().await.unwrap().is_none());
 
+        // Cluster id is only set once
+        assert_eq!(seal.init_cluster_id("id").await.unwrap(), "id");
+        assert_eq!(seal.init_cluster_id("other").await.unwrap(), "id");
+
         let config = SealConfig {
             shares: 5,
             threshold: 3,
@@ -164,7 +867,292 @@ mod tests {
         assert_eq!(key_shares[0].key, key_share_1.as_bytes());
         assert_eq!(key_shares[1].key, key_share_2.as_bytes());
 
-        assert!(seal.clear_key_shares().await.is_ok());
+        assert!(seal.reset_unseal().await.is_ok());
+        assert!(seal.get_key_shares().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unseal_attempt() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::migrations::migrate_unecrypted_db(&pool)
+            .await
+            .unwrap();
+        let seal = SealRepo::new(pool);
+        assert!(seal.get_unseal_nonce().await.unwrap().is_none());
+
+        assert_eq!(seal.start_unseal("nonce").await.unwrap(), "nonce");
+        // The attempt in progress is kept
+        assert_eq!(seal.start_unseal("other").await.unwrap(), "nonce");
+        assert_eq!(
+            seal.get_unseal_nonce().await.unwrap(),
+            Some("nonce".to_string())
+        );
+        seal.insert_key_share(b"share").await.unwrap();
+
+        seal.reset_unseal().await.unwrap();
+        assert!(seal.get_unseal_nonce().await.unwrap().is_none());
+        assert!(seal.get_key_shares().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn generate_root_attempt() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::migrations::migrate_unecrypted_db(&pool)
+            .await
+            .unwrap();
+        let seal = SealRepo::new(pool);
+
+        assert!(seal.get_generate_root().await.unwrap().is_none());
+
+        let attempt = GenerateRootAttempt {
+            nonce: "nonce".into(),
+            otp: Some("otp".into()),
+            pgp_key: None,
+        };
+        seal.start_generate_root(&attempt).await.unwrap();
+        assert_eq!(seal.get_generate_root().await.unwrap(), Some(attempt));
+
+        // Only one attempt at a time
+        assert!(seal
+            .start_generate_root(&GenerateRootAttempt {
+                nonce: "other".into(),
+                otp: None,
+                pgp_key: Some("key".into()),
+            })
+            .await
+            .is_err());
+
+        // Shares are kept apart from the unseal shares
+        seal.insert_generate_root_key_share(b"share").await.unwrap();
+        assert_eq!(seal.get_generate_root_key_shares().await.unwrap().len(), 1);
+        assert!(seal.get_key_shares().await.unwrap().is_empty());
+
+        seal.cancel_generate_root().await.unwrap();
+        assert!(seal.get_generate_root().await.unwrap().is_none());
+        assert!(seal
+            .get_generate_root_key_shares()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn rekey_attempt() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::migrations::migrate_unecrypted_db(&pool)
+            .await
+            .unwrap();
+        let seal = SealRepo::new(pool);
+        seal.set_config(&SealConfig {
+            shares: 1,
+            threshold: 1,
+        })
+        .await
+        .unwrap();
+        assert!(seal.get_master_key().await.unwrap().is_none());
+        assert!(seal.get_rekey().await.unwrap().is_none());
+
+        let mut attempt = RekeyAttempt {
+            nonce: "nonce".into(),
+            config: SealConfig {
+                shares: 5,
+                threshold: 3,
+            },
+            require_verification: true,
+            verification_nonce: None,
+            master_key: None,
+        };
+        seal.start_rekey(&attempt).await.unwrap();
+        assert_eq!(seal.get_rekey().await.unwrap().as_ref(), Some(&attempt));
+        // Only one attempt at a time
+        assert!(seal.start_rekey(&attempt).await.is_err());
+
+        seal.insert_rekey_key_share(b"share").await.unwrap();
+        assert_eq!(seal.get_rekey_key_shares().await.unwrap().len(), 1);
+
+        let master_key = WrappedKey {
+            key: b"key".to_vec(),
+            nonce: b"nonce".to_vec(),
+            share_digests: Some(b"digests".to_vec()),
+        };
+        seal.start_rekey_verification("verification", &master_key)
+            .await
+            .unwrap();
+        assert!(seal.get_rekey_key_shares().await.unwrap().is_empty());
+        attempt.verification_nonce = Some("verification".into());
+        attempt.master_key = Some(master_key.clone());
+        assert_eq!(seal.get_rekey().await.unwrap().as_ref(), Some(&attempt));
+
+        seal.complete_rekey(&attempt.config, &master_key)
+            .await
+            .unwrap();
+        assert!(seal.get_rekey().await.unwrap().is_none());
+        assert_eq!(seal.get_config().await.unwrap(), Some(attempt.config));
+        assert_eq!(seal.get_master_key().await.unwrap(), Some(master_key));
+    }
+
+    #[tokio::test]
+    async fn rotate_master_key() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::migrations::migrate_unecrypted_db(&pool)
+            .await
+            .unwrap();
+        let seal = SealRepo::new(pool);
+        assert!(seal.get_key_generation().await.unwrap().is_none());
+
+        let master_key = WrappedKey {
+            key: b"key".to_vec(),
+            nonce: b"nonce".to_vec(),
+            share_digests: None,
+        };
+        seal.set_master_key(&master_key, Some(b"kek-key"))
+            .await
+            .unwrap();
+        assert_eq!(
+            seal.get_kek_master_key().await.unwrap(),
+            Some(b"kek-key".to_vec())
+        );
+        assert_eq!(
+            seal.get_key_generation()
+                .await
+                .unwrap()
+                .map(|generation| generation.generation),
+            Some(1)
+        );
+
+        // Nothing is changed without a staged master key
+        assert!(seal.rotate_master_key().await.is_err());
+        assert_eq!(
+            seal.get_master_key().await.unwrap().as_ref(),
+            Some(&master_key)
+        );
+
+        // The current master key is kept while the new one is staged
+        let new_master_key = WrappedKey {
+            key: b"new-key".to_vec(),
+            nonce: b"new-nonce".to_vec(),
+            share_digests: Some(b"digests".to_vec()),
+        };
+        let staged_generation = seal.stage_master_key(&new_master_key, None).await.unwrap();
+        assert_eq!(staged_generation.generation, 2);
+        assert_eq!(
+            seal.get_master_key().await.unwrap().as_ref(),
+            Some(&master_key)
+        );
+        assert_eq!(
+            seal.get_key_generation()
+                .await
+                .unwrap()
+                .map(|generation| generation.generation),
+            Some(1)
+        );
+        seal.discard_staged_master_key().await.unwrap();
+        assert!(seal.get_staged_master_key().await.unwrap().is_none());
+
+        let staged_generation = seal.stage_master_key(&new_master_key, None).await.unwrap();
+        assert_eq!(
+            seal.get_staged_master_key().await.unwrap(),
+            Some(StagedMasterKey {
+                master_key: new_master_key.clone(),
+                kek_master_key: None,
+                generation: KeyGeneration {
+                    generation: staged_generation.generation,
+                    created_at: staged_generation.created_at,
+                },
+            })
+        );
+        let generation = seal.rotate_master_key().await.unwrap();
+        assert_eq!(generation, staged_generation);
+        assert_eq!(seal.get_key_generation().await.unwrap(), Some(generation));
+        assert_eq!(seal.get_master_key().await.unwrap(), Some(new_master_key));
+        assert!(seal.get_staged_master_key().await.unwrap().is_none());
+        // The old master key is not kept for auto-unseal
+        assert!(seal.get_kek_master_key().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn snapshot_and_restore() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::migrations::migrate_unecrypted_db(&pool)
+            .await
+            .unwrap();
+        let seal = SealRepo::new(pool);
+
+        // Seal config is required
+        assert!(seal.snapshot(|| async { Ok(()) }).await.is_err());
+
+        let config = SealConfig {
+            shares: 3,
+            threshold: 2,
+        };
+        seal.set_config(&config).await.unwrap();
+        let master_key = WrappedKey {
+            key: b"key".to_vec(),
+            nonce: b"nonce".to_vec(),
+            share_digests: None,
+        };
+        seal.set_master_key(&master_key, Some(b"kek-key"))
+            .await
+            .unwrap();
+        let (snapshot, data) = seal.snapshot(|| async { Ok("data") }).await.unwrap();
+        assert_eq!(data, "data");
+        assert_eq!(
+            snapshot,
+            SealSnapshot {
+                config: SealConfig {
+                    shares: 3,
+                    threshold: 2,
+                },
+                master_key: Some(master_key.clone()),
+                kek_master_key: Some(b"kek-key".to_vec()),
+                key_generation: seal.get_key_generation().await.unwrap(),
+            }
+        );
+
+        // Rotate and start an unseal attempt after the snapshot
+        seal.stage_master_key(
+            &WrappedKey {
+                key: b"new-key".to_vec(),
+                nonce: b"new-nonce".to_vec(),
+                share_digests: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        seal.rotate_master_key().await.unwrap();
+        seal.start_unseal("nonce").await.unwrap();
+        seal.insert_key_share(b"share").await.unwrap();
+
+        // Nothing is changed if the restore fails
+        assert!(seal
+            .restore_snapshot(&snapshot, || async {
+                Err(ErrorType::InternalError(anyhow::Error::msg("failed")).into())
+            })
+            .await
+            .is_err());
+        assert_eq!(
+            seal.get_key_generation()
+                .await
+                .unwrap()
+                .map(|generation| generation.generation),
+            Some(2)
+        );
+
+        seal.restore_snapshot(&snapshot, || async { Ok(()) })
+            .await
+            .unwrap();
+        assert_eq!(seal.get_config().await.unwrap(), Some(config));
+        assert_eq!(seal.get_master_key().await.unwrap(), Some(master_key));
+        assert_eq!(
+            seal.get_kek_master_key().await.unwrap(),
+            Some(b"kek-key".to_vec())
+        );
+        assert_eq!(
+            seal.get_key_generation().await.unwrap(),
+            snapshot.key_generation
+        );
+        assert!(seal.get_unseal_nonce().await.unwrap().is_none());
         assert!(seal.get_key_shares().await.unwrap().is_empty());
     }
 }
@@ -2,10 +2,16 @@ use std::sync::Arc;
 
 use chrono::{DateTime, Duration, Utc};
 use covert_storage::EncryptedPool;
-use covert_types::{policy::Policy, token::Token};
+use covert_types::{
+    entity::EntityAlias,
+    policy::Policy,
+    token::{Token, TokenAccessor},
+};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 
-use crate::error::Error;
+use crate::error::{Error, ErrorType};
 
 use super::policy::PolicyRaw;
 
@@ -26,16 +32,90 @@ impl TokenRepo {
         Self { pool }
     }
 
+    /// Tokens are only stored as a HMAC keyed with a secret held in the
+    /// storage, so a copy of the storage does not contain any usable token.
+    async fn hash(&self, token: &Token) -> Result<String, Error> {
+        let key: Vec<u8> = sqlx::query_scalar("SELECT key FROM TOKEN_HMAC_KEY")
+            .fetch_one(self.pool.as_ref())
+            .await?;
+        hmac_token(&key, &token.to_string())
+    }
+
+    /// Hash the tokens that were stored in plaintext, and give them an
+    /// accessor. Leases of the tokens are updated to reference the accessor
+    /// and the plaintext tokens are dropped.
     #[tracing::instrument(skip_all)]
-    pub async fn lookup_policies(&self, id: &Token) -> Result<Vec<Policy>, Error> {
-        sqlx::query_as(
-            "SELECT P.* FROM TOKENS T
-            INNER JOIN ENTITIES E ON T.entity_name = E.name AND T.namespace_id = E.namespace_id
-            INNER JOIN ENTITY_POLICIES EP ON E.name = EP.entity_name AND E.namespace_id = EP.namespace_id
-            INNER JOIN POLICIES P ON EP.policy_name = P.name AND EP.namespace_id = P.namespace_id
-            WHERE T.token = ? AND (T.expires_at IS NULL OR T.expires_at > ?)",
+    pub async fn convert_plaintext_tokens(&self) -> Result<(), Error> {
+        let exists: Option<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'PLAINTEXT_TOKENS'",
+        )
+        .fetch_optional(self.pool.as_ref())
+        .await?;
+        if exists.is_none() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let key: Vec<u8> = sqlx::query_scalar("SELECT key FROM TOKEN_HMAC_KEY")
+            .fetch_one(&mut tx)
+            .await?;
+        let tokens: Vec<PlaintextTokenRaw> = sqlx::query_as(
+            "SELECT token, issued_at, expires_at, namespace_id, entity_name
+            FROM PLAINTEXT_TOKENS",
         )
-        .bind(id.to_string())
+        .fetch_all(&mut tx)
+        .await?;
+        for token in tokens {
+            let accessor = TokenAccessor::new();
+            sqlx::query(
+                "INSERT INTO TOKENS (
+                    token_hash, accessor, issued_at, expires_at, entity_name, namespace_id
+                ) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(hmac_token(&key, &token.token)?)
+            .bind(accessor.as_str())
+            .bind(token.issued_at)
+            .bind(token.expires_at)
+            .bind(&token.entity_name)
+            .bind(&token.namespace_id)
+            .execute(&mut tx)
+            .await?;
+
+            let lease_data = serde_json::json!({ "accessor": accessor }).to_string();
+            sqlx::query(
+                "UPDATE LEASES SET revoke_data = ?, renew_data = ?
+                WHERE revoke_path IS NULL AND json_extract(revoke_data, '$.token') = ?",
+            )
+            .bind(&lease_data)
+            .bind(&lease_data)
+            .bind(&token.token)
+            .execute(&mut tx)
+            .await?;
+        }
+        sqlx::query("DROP TABLE PLAINTEXT_TOKENS")
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await.map_err(Into::into)
+    }
+
+    /// Policies of a valid token, matched on either the hash of the token or
+    /// its accessor.
+    async fn policies(&self, column: &str, value: &str) -> Result<Vec<Policy>, Error> {
+        sqlx::query_as(&format!(
+            "SELECT P.* FROM TOKENS T
+            INNER JOIN POLICIES P ON P.namespace_id = T.namespace_id
+            WHERE T.{column} = ? AND (T.expires_at IS NULL OR T.expires_at > ?)
+                AND CASE WHEN T.policies IS NULL
+                    THEN P.name IN (
+                        SELECT EP.policy_name FROM ENTITY_POLICIES EP
+                        WHERE EP.entity_name = T.entity_name AND EP.namespace_id = T.namespace_id
+                    )
+                    ELSE P.name IN (SELECT value FROM json_each(T.policies))
+                END
+            ORDER BY P.name"
+        ))
+        .bind(value)
         .bind(Utc::now())
         .fetch_all(self.pool.as_ref())
         .await
@@ -49,27 +129,253 @@ impl TokenRepo {
         })
     }
 
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup_policies(&self, id: &Token) -> Result<Vec<Policy>, Error> {
+        self.policies("token_hash", &self.hash(id).await?).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup_policies_by_accessor(
+        &self,
+        accessor: &TokenAccessor,
+    ) -> Result<Vec<Policy>, Error> {
+        self.policies("accessor", accessor.as_str()).await
+    }
+
+    /// Lookup the entity, and its aliases, that a valid token belongs to.
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup_identity(&self, id: &Token) -> Result<Option<TokenIdentity>, Error> {
+        sqlx::query_as(
+            "SELECT
+                T.entity_name AS entity_name,
+                EA.name AS alias_name,
+                EA.mount_path AS alias_mount_path
+            FROM TOKENS T
+                LEFT JOIN ENTITY_ALIASES EA
+                    ON EA.entity_name = T.entity_name AND EA.namespace_id = T.namespace_id
+            WHERE T.token_hash = ? AND (T.expires_at IS NULL OR T.expires_at > ?)",
+        )
+        .bind(self.hash(id).await?)
+        .bind(Utc::now())
+        .fetch_all(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+        .map(|rows: Vec<TokenIdentityRaw>| {
+            let entity_name = rows.first()?.entity_name.clone();
+            let aliases = rows
+                .into_iter()
+                .filter_map(|row| match (row.alias_name, row.alias_mount_path) {
+                    (Some(name), Some(mount_path)) => Some(EntityAlias { name, mount_path }),
+                    _ => None,
+                })
+                .collect();
+            Some(TokenIdentity {
+                entity_name,
+                aliases,
+            })
+        })
+    }
+
+    /// Lookup the accessor of a valid token.
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup_accessor(&self, id: &Token) -> Result<Option<TokenAccessor>, Error> {
+        sqlx::query_scalar(
+            "SELECT accessor FROM TOKENS
+            WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > ?)",
+        )
+        .bind(self.hash(id).await?)
+        .bind(Utc::now())
+        .fetch_optional(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+        .map(|accessor: Option<String>| accessor.map(TokenAccessor::from))
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup(&self, id: &Token) -> Result<Option<TokenInfo>, Error> {
+        sqlx::query_as(
+            "SELECT accessor, entity_name, issued_at, expires_at, max_expires_at, namespace_id
+            FROM TOKENS
+            WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > ?)",
+        )
+        .bind(self.hash(id).await?)
+        .bind(Utc::now())
+        .fetch_optional(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+        .map(|token: Option<TokenInfoRaw>| token.map(Into::into))
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup_by_accessor(
+        &self,
+        accessor: &TokenAccessor,
+        namespace_id: &str,
+    ) -> Result<Option<TokenInfo>, Error> {
+        sqlx::query_as(
+            "SELECT accessor, entity_name, issued_at, expires_at, max_expires_at, namespace_id
+            FROM TOKENS
+            WHERE accessor = ? AND namespace_id = ? AND (expires_at IS NULL OR expires_at > ?)",
+        )
+        .bind(accessor.as_str())
+        .bind(namespace_id)
+        .bind(Utc::now())
+        .fetch_optional(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+        .map(|token: Option<TokenInfoRaw>| token.map(Into::into))
+    }
+
     #[tracing::instrument(skip_all)]
     pub async fn create(&self, te: &TokenEntry) -> Result<(), Error> {
+        let policies = te
+            .policies
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|err| ErrorType::InternalError(err.into()))?;
+
         sqlx::query(
-            "INSERT INTO TOKENS (token, issued_at, expires_at, entity_name, namespace_id)
-            VALUES (?, ?, ?, ?, ?)",
+            "INSERT INTO TOKENS (
+                token_hash, accessor, issued_at, expires_at, entity_name, namespace_id,
+                parent_accessor, policies, num_uses, max_expires_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
-        .bind(te.id.to_string())
+        .bind(self.hash(&te.id).await?)
+        .bind(te.accessor.as_str())
         .bind(te.issued_at)
         .bind(te.expires_at)
         .bind(&te.entity_name)
         .bind(&te.namespace_id)
+        .bind(te.parent.as_ref().map(TokenAccessor::as_str))
+        .bind(policies)
+        .bind(te.num_uses)
+        .bind(te.max_expires_at)
         .execute(self.pool.as_ref())
         .await
         .map_err(Into::into)
         .map(|_| ())
     }
 
+    /// Use up one of the uses of a token. A token is still valid once its
+    /// last use is used up, so the request using it can complete, and is
+    /// expected to be revoked afterwards.
+    #[tracing::instrument(skip_all)]
+    pub async fn consume_use(&self, id: &Token) -> Result<TokenUse, Error> {
+        let token_hash = self.hash(id).await?;
+        let num_uses: Option<Option<u32>> =
+            sqlx::query_scalar("SELECT num_uses FROM TOKENS WHERE token_hash = ?")
+                .bind(&token_hash)
+                .fetch_optional(self.pool.as_ref())
+                .await?;
+
+        match num_uses {
+            None => Ok(TokenUse::Denied),
+            Some(None) => Ok(TokenUse::Unlimited),
+            Some(Some(_)) => sqlx::query_scalar(
+                "UPDATE TOKENS SET num_uses = num_uses - 1
+                WHERE token_hash = ? AND num_uses > 0
+                RETURNING num_uses",
+            )
+            .bind(&token_hash)
+            .fetch_optional(self.pool.as_ref())
+            .await
+            .map_err(Into::into)
+            .map(|num_uses: Option<u32>| num_uses.map_or(TokenUse::Denied, TokenUse::Remaining)),
+        }
+    }
+
+    /// Lookup the accessors of all the tokens created by the token, and by
+    /// its children, recursively.
     #[tracing::instrument(skip_all)]
-    pub async fn remove(&self, id: &Token, namespace_id: &str) -> Result<bool, Error> {
-        sqlx::query("DELETE FROM TOKENS WHERE token = ? AND namespace_id = ?")
-            .bind(id.to_string())
+    pub async fn descendants(&self, accessor: &TokenAccessor) -> Result<Vec<TokenAccessor>, Error> {
+        sqlx::query_scalar(
+            "WITH RECURSIVE DESCENDANTS(accessor) AS (
+                SELECT accessor FROM TOKENS WHERE parent_accessor = ?
+                UNION
+                SELECT T.accessor FROM TOKENS T
+                    INNER JOIN DESCENDANTS D ON T.parent_accessor = D.accessor
+            )
+            SELECT accessor FROM DESCENDANTS",
+        )
+        .bind(accessor.as_str())
+        .fetch_all(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+        .map(|accessors: Vec<String>| accessors.into_iter().map(TokenAccessor::from).collect())
+    }
+
+    /// Expired tokens that have no lease to revoke them, which are the tokens
+    /// created by another token.
+    #[tracing::instrument(skip(self))]
+    pub async fn pull_expired(
+        &self,
+        count: u32,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<TokenInfo>, Error> {
+        sqlx::query_as(
+            "SELECT accessor, entity_name, issued_at, expires_at, max_expires_at, namespace_id
+            FROM TOKENS T
+            WHERE expires_at <= ? AND NOT EXISTS (
+                SELECT 1 FROM LEASES L
+                WHERE L.revoke_path IS NULL
+                    AND json_extract(L.revoke_data, '$.accessor') = T.accessor
+            )
+            ORDER BY expires_at
+            LIMIT ?",
+        )
+        .bind(before)
+        .bind(count)
+        .fetch_all(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+        .map(|tokens: Vec<TokenInfoRaw>| tokens.into_iter().map(Into::into).collect())
+    }
+
+    /// When the next token without a lease expires after `after`.
+    #[tracing::instrument(skip(self))]
+    pub async fn peek_expiry(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, Error> {
+        sqlx::query_scalar(
+            "SELECT expires_at FROM TOKENS T
+            WHERE expires_at > ? AND NOT EXISTS (
+                SELECT 1 FROM LEASES L
+                WHERE L.revoke_path IS NULL
+                    AND json_extract(L.revoke_data, '$.accessor') = T.accessor
+            )
+            ORDER BY expires_at
+            LIMIT 1",
+        )
+        .bind(after)
+        .fetch_optional(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Lookup the accessors of the root tokens, which are the tokens of the
+    /// root entity that never expire.
+    #[tracing::instrument(skip_all)]
+    pub async fn root_tokens(&self, namespace_id: &str) -> Result<Vec<TokenAccessor>, Error> {
+        sqlx::query_scalar(
+            "SELECT accessor FROM TOKENS
+            WHERE entity_name = 'root' AND namespace_id = ?
+                AND expires_at IS NULL AND parent_accessor IS NULL",
+        )
+        .bind(namespace_id)
+        .fetch_all(self.pool.as_ref())
+        .await
+        .map_err(Into::into)
+        .map(|accessors: Vec<String>| accessors.into_iter().map(TokenAccessor::from).collect())
+    }
+
+    /// Remove a token. Tokens created by the token are removed as well.
+    #[tracing::instrument(skip_all)]
+    pub async fn remove(
+        &self,
+        accessor: &TokenAccessor,
+        namespace_id: &str,
+    ) -> Result<bool, Error> {
+        sqlx::query("DELETE FROM TOKENS WHERE accessor = ? AND namespace_id = ?")
+            .bind(accessor.as_str())
             .bind(namespace_id)
             .execute(self.pool.as_ref())
             .await
@@ -80,17 +386,17 @@ impl TokenRepo {
     #[tracing::instrument(skip_all)]
     pub async fn renew(
         &self,
-        id: &Token,
+        accessor: &TokenAccessor,
         namespace_id: &str,
         expires_at: DateTime<Utc>,
     ) -> Result<bool, Error> {
         sqlx::query(
             "UPDATE TOKENS SET
             expires_at = ?
-            WHERE token = ? AND namespace_id = ?",
+            WHERE accessor = ? AND namespace_id = ?",
         )
         .bind(expires_at)
-        .bind(id.to_string())
+        .bind(accessor.as_str())
         .bind(namespace_id)
         .execute(self.pool.as_ref())
         .await
@@ -99,10 +405,96 @@ impl TokenRepo {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub(super) fn hmac_token(key: &[u8], token: &str) -> Result<String, Error> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|_| ErrorType::InternalError(anyhow::Error::msg("Invalid token HMAC key")))?;
+    mac.update(token.as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(sqlx::FromRow)]
+struct PlaintextTokenRaw {
+    token: String,
+    issued_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    namespace_id: String,
+    entity_name: String,
+}
+
+/// Outcome of using up one of the uses of a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenUse {
+    /// The token can be used any number of times.
+    Unlimited,
+    /// Number of uses the token has left after this use.
+    Remaining(u32),
+    /// The token has no uses left, or does not exist.
+    Denied,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct TokenIdentityRaw {
+    entity_name: String,
+    alias_name: Option<String>,
+    alias_mount_path: Option<String>,
+}
+
+/// The entity a token belongs to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenIdentity {
+    pub entity_name: String,
+    pub aliases: Vec<EntityAlias>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct TokenInfoRaw {
+    accessor: String,
+    entity_name: String,
+    issued_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    max_expires_at: Option<DateTime<Utc>>,
+    namespace_id: String,
+}
+
+impl From<TokenInfoRaw> for TokenInfo {
+    fn from(raw: TokenInfoRaw) -> Self {
+        Self {
+            accessor: raw.accessor.into(),
+            entity_name: raw.entity_name,
+            issued_at: raw.issued_at,
+            expires_at: raw.expires_at,
+            max_expires_at: raw.max_expires_at,
+            namespace_id: raw.namespace_id,
+        }
+    }
+}
+
+/// A stored token. The token itself cannot be recovered from the storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub accessor: TokenAccessor,
+    pub entity_name: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_expires_at: Option<DateTime<Utc>>,
+    pub namespace_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TokenEntry {
     /// ID of this entry
     pub id: Token,
+    /// Non secret reference to the token
+    pub accessor: TokenAccessor,
+    /// Accessor of the token that created this token
+    pub parent: Option<TokenAccessor>,
+    /// Names of the policies of the token, the policies of the entity are
+    /// used if not set
+    pub policies: Option<Vec<String>>,
+    /// Number of uses left, unlimited if not set
+    pub num_uses: Option<u32>,
+    /// The token can not be renewed beyond this timestamp
+    pub max_expires_at: Option<DateTime<Utc>>,
     /// Entity this token belongs to
     pub entity_name: String,
     /// Valid until timestamp
@@ -118,6 +510,11 @@ impl TokenEntry {
         let now = Utc::now();
         Self {
             id: Token::new(),
+            accessor: TokenAccessor::new(),
+            parent: None,
+            policies: None,
+            num_uses: None,
+            max_expires_at: None,
             entity_name,
             issued_at: now,
             expires_at: Some(now + ttl),
@@ -128,16 +525,27 @@ impl TokenEntry {
     pub fn id(&self) -> &Token {
         &self.id
     }
+
+    pub fn accessor(&self) -> &TokenAccessor {
+        &self.accessor
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use covert_types::{entity::Entity, policy::PathPolicy, request::Operation};
+    use covert_types::{
+        backend::BackendType,
+        entity::Entity,
+        mount::{MountConfig, MountEntry},
+        policy::PathPolicy,
+        request::Operation,
+    };
     use uuid::Uuid;
 
     use crate::repos::{
         entity::EntityRepo,
-        mount::tests::pool,
+        lease::LeaseRepo,
+        mount::{tests::pool, MountRepo},
         namespace::{Namespace, NamespaceRepo},
         policy::PolicyRepo,
     };
@@ -194,11 +602,48 @@ mod tests {
             vec![bar_policy.clone(), foo_policy.clone()]
         );
 
+        // Lookup the entity of the token
+        assert_eq!(
+            store.lookup_identity(token.id()).await.unwrap(),
+            Some(TokenIdentity {
+                entity_name: entity.name().to_string(),
+                aliases: vec![]
+            })
+        );
+
+        // Lookup the token by its accessor
+        assert_eq!(
+            store.lookup_accessor(token.id()).await.unwrap().as_ref(),
+            Some(token.accessor())
+        );
+        let info = store
+            .lookup_by_accessor(token.accessor(), &ns.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.entity_name, entity.name());
+        assert_eq!(info.expires_at, token.expires_at);
+        assert!(store
+            .lookup_by_accessor(token.accessor(), "other-ns")
+            .await
+            .unwrap()
+            .is_none());
+
+        // Only a hash of the token is stored
+        let stored: Vec<String> = sqlx::query_scalar("SELECT token_hash FROM TOKENS")
+            .fetch_all(pool.as_ref())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_ne!(stored[0], token.id().to_string());
+
         // Delete token
-        assert!(store.remove(token.id(), &ns.id).await.unwrap());
+        assert!(store.remove(token.accessor(), &ns.id).await.unwrap());
 
         // No policies should be returned for token after deletion
         assert!(store.lookup_policies(token.id()).await.unwrap().is_empty());
+        assert!(store.lookup_identity(token.id()).await.unwrap().is_none());
+        assert!(store.lookup_accessor(token.id()).await.unwrap().is_none());
     }
 
     #[tokio::test]
@@ -252,10 +697,10 @@ mod tests {
         let update_resp = sqlx::query(
             "UPDATE TOKENS SET
             expires_at = ?
-            WHERE token = ?",
+            WHERE accessor = ?",
         )
         .bind(Utc::now() - Duration::hours(1))
-        .bind(token.id().to_string())
+        .bind(token.accessor().as_str())
         .execute(pool.as_ref())
         .await
         .unwrap();
@@ -263,5 +708,176 @@ mod tests {
 
         // Token no longer has any policies
         assert!(store.lookup_policies(token.id()).await.unwrap().is_empty());
+        assert!(store
+            .lookup_by_accessor(token.accessor(), &ns.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn explicit_policies_children_and_num_uses() {
+        let pool = Arc::new(pool().await);
+        let store = TokenRepo::new(Arc::clone(&pool));
+        let policy_repo = PolicyRepo::new(Arc::clone(&pool));
+        let entity_repo = EntityRepo::new(Arc::clone(&pool));
+        let ns_repo = NamespaceRepo::new(Arc::clone(&pool));
+
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ns_repo.create(&ns).await.unwrap();
+        let foo_policy = Policy::new(
+            "foo".into(),
+            vec![PathPolicy::new("foo/".into(), vec![Operation::Read])],
+            ns.id.clone(),
+        );
+        policy_repo.create(&foo_policy).await.unwrap();
+        let entity = Entity::new("John".into(), ns.id.clone());
+        entity_repo.create(&entity).await.unwrap();
+
+        let parent = TokenEntry::new(entity.name().to_string(), Duration::hours(1), ns.id.clone());
+        store.create(&parent).await.unwrap();
+        // The entity has no policies
+        assert!(store.lookup_policies(parent.id()).await.unwrap().is_empty());
+
+        let mut child =
+            TokenEntry::new(entity.name().to_string(), Duration::hours(1), ns.id.clone());
+        child.parent = Some(parent.accessor().clone());
+        child.policies = Some(vec!["foo".into()]);
+        child.num_uses = Some(1);
+        store.create(&child).await.unwrap();
+        let mut grandchild =
+            TokenEntry::new(entity.name().to_string(), Duration::hours(1), ns.id.clone());
+        grandchild.parent = Some(child.accessor().clone());
+        store.create(&grandchild).await.unwrap();
+
+        assert_eq!(
+            store.lookup_policies(child.id()).await.unwrap(),
+            vec![foo_policy.clone()]
+        );
+        assert_eq!(
+            store.descendants(parent.accessor()).await.unwrap(),
+            vec![child.accessor().clone(), grandchild.accessor().clone()]
+        );
+
+        // Tokens without a limit can be used any number of times
+        assert_eq!(
+            store.consume_use(parent.id()).await.unwrap(),
+            TokenUse::Unlimited
+        );
+        assert_eq!(
+            store.consume_use(parent.id()).await.unwrap(),
+            TokenUse::Unlimited
+        );
+        assert_eq!(
+            store.consume_use(child.id()).await.unwrap(),
+            TokenUse::Remaining(0)
+        );
+        // The request using the last use still sees the token
+        assert_eq!(
+            store.lookup_policies(child.id()).await.unwrap(),
+            vec![foo_policy]
+        );
+        assert_eq!(
+            store.consume_use(child.id()).await.unwrap(),
+            TokenUse::Denied
+        );
+
+        // Children are removed with their parent
+        assert!(store.remove(parent.accessor(), &ns.id).await.unwrap());
+        assert!(store
+            .lookup_accessor(grandchild.id())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            store.consume_use(grandchild.id()).await.unwrap(),
+            TokenUse::Denied
+        );
+    }
+
+    #[tokio::test]
+    async fn convert_plaintext_tokens() {
+        let pool = Arc::new(pool().await);
+        let store = TokenRepo::new(Arc::clone(&pool));
+        let entity_repo = EntityRepo::new(Arc::clone(&pool));
+        let ns_repo = NamespaceRepo::new(Arc::clone(&pool));
+        let mount_repo = MountRepo::new(Arc::clone(&pool));
+        let lease_repo = LeaseRepo::new(Arc::clone(&pool));
+
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ns_repo.create(&ns).await.unwrap();
+        let entity = Entity::new("John".into(), ns.id.clone());
+        entity_repo.create(&entity).await.unwrap();
+        mount_repo
+            .create(&MountEntry {
+                id: Uuid::new_v4(),
+                backend_type: BackendType::Userpass,
+                config: MountConfig::default(),
+                path: "auth/userpass/".into(),
+                namespace_id: ns.id.clone(),
+            })
+            .await
+            .unwrap();
+
+        // A token, and its lease, from before tokens were hashed
+        let token = Token::new();
+        sqlx::query(
+            "INSERT INTO PLAINTEXT_TOKENS (token, issued_at, expires_at, namespace_id, entity_name)
+            VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(token.to_string())
+        .bind(Utc::now())
+        .bind(Utc::now() + Duration::hours(1))
+        .bind(&ns.id)
+        .bind(entity.name())
+        .execute(pool.as_ref())
+        .await
+        .unwrap();
+        let lease_data = serde_json::json!({ "token": token }).to_string();
+        sqlx::query(
+            "INSERT INTO LEASES (
+                id, namespace_id, issued_mount_path, revoke_data, renew_data,
+                failed_revocation_attempts, issued_at, expires_at, last_renewal_time
+            ) VALUES ('auth/userpass/login/foo', ?, 'auth/userpass/', ?, ?, 0, ?, ?, ?)",
+        )
+        .bind(&ns.id)
+        .bind(&lease_data)
+        .bind(&lease_data)
+        .bind(Utc::now())
+        .bind(Utc::now() + Duration::hours(1))
+        .bind(Utc::now())
+        .execute(pool.as_ref())
+        .await
+        .unwrap();
+
+        store.convert_plaintext_tokens().await.unwrap();
+
+        // The token keeps working and its lease references the accessor
+        let info = store.lookup(&token).await.unwrap().unwrap();
+        assert_eq!(info.entity_name, entity.name());
+        assert!(lease_repo
+            .lookup_token_lease(&info.accessor, &ns.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        // The plaintext tokens are gone and converting again does nothing
+        let exists: Option<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'PLAINTEXT_TOKENS'",
+        )
+        .fetch_optional(pool.as_ref())
+        .await
+        .unwrap();
+        assert!(exists.is_none());
+        store.convert_plaintext_tokens().await.unwrap();
+        assert!(store.lookup(&token).await.unwrap().is_some());
     }
 }
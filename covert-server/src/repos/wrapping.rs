@@ -0,0 +1,368 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use covert_storage::EncryptedPool;
+use covert_types::token::Token;
+use serde_json::Value;
+
+use crate::error::{Error, ErrorType};
+
+use super::token::hmac_token;
+
+/// A response that has been wrapped and can only be retrieved with the
+/// wrapping token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WrappedResponse {
+    pub token: Token,
+    pub namespace_id: String,
+    pub creation_path: String,
+    pub response: Value,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl WrappedResponse {
+    pub fn new(
+        namespace_id: String,
+        creation_path: String,
+        response: Value,
+        ttl: Duration,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            token: Token::new(),
+            namespace_id,
+            creation_path,
+            response,
+            issued_at: now,
+            expires_at: now + ttl,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.expires_at - self.issued_at
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct WrappedResponseRaw {
+    namespace_id: String,
+    creation_path: String,
+    response: String,
+    issued_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl WrappedResponseRaw {
+    fn into_response(self, token: &Token) -> Result<WrappedResponse, Error> {
+        let response = serde_json::from_str(&self.response).map_err(|_| {
+            ErrorType::BadData(format!(
+                "Unable to parse wrapped response created at `{}`",
+                self.creation_path
+            ))
+        })?;
+        Ok(WrappedResponse {
+            token: token.clone(),
+            namespace_id: self.namespace_id,
+            creation_path: self.creation_path,
+            response,
+            issued_at: self.issued_at,
+            expires_at: self.expires_at,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PlaintextWrappedResponseRaw {
+    token: String,
+    namespace_id: String,
+    creation_path: String,
+    response: String,
+    issued_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+pub struct WrappingRepo {
+    pool: Arc<EncryptedPool>,
+}
+
+impl Clone for WrappingRepo {
+    fn clone(&self) -> Self {
+        Self {
+            pool: Arc::clone(&self.pool),
+        }
+    }
+}
+
+impl WrappingRepo {
+    pub fn new(pool: Arc<EncryptedPool>) -> Self {
+        Self { pool }
+    }
+
+    /// Wrapping tokens are only stored as a HMAC keyed with the same secret
+    /// as the tokens, so a copy of the storage can't unwrap any response.
+    async fn hash(&self, token: &Token) -> Result<String, Error> {
+        let key: Vec<u8> = sqlx::query_scalar("SELECT key FROM TOKEN_HMAC_KEY")
+            .fetch_one(self.pool.as_ref())
+            .await?;
+        hmac_token(&key, &token.to_string())
+    }
+
+    #[tracing::instrument(skip_all, fields(creation_path = entry.creation_path))]
+    pub async fn create(&self, entry: &WrappedResponse) -> Result<(), Error> {
+        let response =
+            serde_json::to_string(&entry.response).map_err(ErrorType::BadResponseData)?;
+
+        sqlx::query(
+            "INSERT INTO WRAPPED_RESPONSES (token_hash, namespace_id, creation_path, response, issued_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(self.hash(&entry.token).await?)
+        .bind(&entry.namespace_id)
+        .bind(&entry.creation_path)
+        .bind(response)
+        .bind(entry.issued_at)
+        .bind(entry.expires_at)
+        .execute(self.pool.as_ref())
+        .await
+        .map(|_| ())
+        .map_err(Into::into)
+    }
+
+    /// Lookup a wrapped response without consuming it. Expired responses are
+    /// never returned.
+    #[tracing::instrument(skip_all)]
+    pub async fn lookup(
+        &self,
+        token: &Token,
+        namespace_id: &str,
+    ) -> Result<Option<WrappedResponse>, Error> {
+        let entry: Option<WrappedResponseRaw> = sqlx::query_as(
+            "SELECT * FROM WRAPPED_RESPONSES
+            WHERE token_hash = ? AND namespace_id = ? AND expires_at > ?",
+        )
+        .bind(self.hash(token).await?)
+        .bind(namespace_id)
+        .bind(Utc::now())
+        .fetch_optional(self.pool.as_ref())
+        .await?;
+        entry.map(|entry| entry.into_response(token)).transpose()
+    }
+
+    /// Remove and return the wrapped response. A wrapped response can
+    /// therefore only be taken once. Expired responses are removed but never
+    /// returned.
+    #[tracing::instrument(skip_all)]
+    pub async fn take(
+        &self,
+        token: &Token,
+        namespace_id: &str,
+    ) -> Result<Option<WrappedResponse>, Error> {
+        let entry: Option<WrappedResponseRaw> = sqlx::query_as(
+            "DELETE FROM WRAPPED_RESPONSES
+            WHERE token_hash = ? AND namespace_id = ?
+            RETURNING *",
+        )
+        .bind(self.hash(token).await?)
+        .bind(namespace_id)
+        .fetch_optional(self.pool.as_ref())
+        .await?;
+        entry
+            .map(|entry| entry.into_response(token))
+            .transpose()
+            .map(|entry| entry.filter(|entry| entry.expires_at > Utc::now()))
+    }
+
+    /// Hash the wrapping tokens that were stored in plaintext, and drop the
+    /// plaintext wrapping tokens.
+    #[tracing::instrument(skip_all)]
+    pub async fn convert_plaintext_tokens(&self) -> Result<(), Error> {
+        let exists: Option<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'PLAINTEXT_WRAPPED_RESPONSES'",
+        )
+        .fetch_optional(self.pool.as_ref())
+        .await?;
+        if exists.is_none() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let key: Vec<u8> = sqlx::query_scalar("SELECT key FROM TOKEN_HMAC_KEY")
+            .fetch_one(&mut tx)
+            .await?;
+        let entries: Vec<PlaintextWrappedResponseRaw> =
+            sqlx::query_as("SELECT * FROM PLAINTEXT_WRAPPED_RESPONSES")
+                .fetch_all(&mut tx)
+                .await?;
+        for entry in entries {
+            sqlx::query(
+                "INSERT INTO WRAPPED_RESPONSES (token_hash, namespace_id, creation_path, response, issued_at, expires_at)
+                VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(hmac_token(&key, &entry.token)?)
+            .bind(&entry.namespace_id)
+            .bind(&entry.creation_path)
+            .bind(&entry.response)
+            .bind(entry.issued_at)
+            .bind(entry.expires_at)
+            .execute(&mut tx)
+            .await?;
+        }
+        sqlx::query("DROP TABLE PLAINTEXT_WRAPPED_RESPONSES")
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await.map_err(Into::into)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_expired(&self, before: DateTime<Utc>) -> Result<u64, Error> {
+        sqlx::query("DELETE FROM WRAPPED_RESPONSES WHERE expires_at <= ?")
+            .bind(before)
+            .execute(self.pool.as_ref())
+            .await
+            .map(|res| res.rows_affected())
+            .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::repos::{
+        mount::tests::pool,
+        namespace::{Namespace, NamespaceRepo},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn crud() {
+        let pool = Arc::new(pool().await);
+        let store = WrappingRepo::new(Arc::clone(&pool));
+        let ns_repo = NamespaceRepo::new(Arc::clone(&pool));
+
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ns_repo.create(&ns).await.unwrap();
+
+        let entry = WrappedResponse::new(
+            ns.id.clone(),
+            "kv/data/foo".into(),
+            json!({ "password": "hunter2" }),
+            Duration::minutes(5),
+        );
+        assert!(store.create(&entry).await.is_ok());
+        assert_eq!(entry.ttl(), Duration::minutes(5));
+
+        // Only a HMAC of the wrapping token is stored
+        let stored: i64 =
+            sqlx::query_scalar("SELECT count(*) FROM WRAPPED_RESPONSES WHERE token_hash = ?")
+                .bind(entry.token.to_string())
+                .fetch_one(pool.as_ref())
+                .await
+                .unwrap();
+        assert_eq!(stored, 0);
+
+        // Lookup does not consume the response
+        assert_eq!(
+            store.lookup(&entry.token, &ns.id).await.unwrap(),
+            Some(entry.clone())
+        );
+        assert_eq!(
+            store.lookup(&entry.token, &ns.id).await.unwrap(),
+            Some(entry.clone())
+        );
+        // Not visible from other namespaces
+        assert_eq!(store.lookup(&entry.token, "foo").await.unwrap(), None);
+        assert_eq!(store.take(&entry.token, "foo").await.unwrap(), None);
+
+        // Take is single use
+        assert_eq!(
+            store.take(&entry.token, &ns.id).await.unwrap(),
+            Some(entry.clone())
+        );
+        assert_eq!(store.take(&entry.token, &ns.id).await.unwrap(), None);
+        assert_eq!(store.lookup(&entry.token, &ns.id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn expired_responses_are_not_returned() {
+        let pool = Arc::new(pool().await);
+        let store = WrappingRepo::new(Arc::clone(&pool));
+        let ns_repo = NamespaceRepo::new(Arc::clone(&pool));
+
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ns_repo.create(&ns).await.unwrap();
+
+        let expired = WrappedResponse::new(
+            ns.id.clone(),
+            "kv/data/foo".into(),
+            json!({}),
+            Duration::minutes(-1),
+        );
+        let valid = WrappedResponse::new(
+            ns.id.clone(),
+            "kv/data/bar".into(),
+            json!({}),
+            Duration::minutes(5),
+        );
+        store.create(&expired).await.unwrap();
+        store.create(&valid).await.unwrap();
+
+        assert_eq!(store.lookup(&expired.token, &ns.id).await.unwrap(), None);
+        assert_eq!(store.remove_expired(Utc::now()).await.unwrap(), 1);
+        assert_eq!(store.take(&expired.token, &ns.id).await.unwrap(), None);
+        assert_eq!(store.take(&valid.token, &ns.id).await.unwrap(), Some(valid));
+    }
+
+    #[tokio::test]
+    async fn convert_plaintext_tokens() {
+        let pool = Arc::new(pool().await);
+        let store = WrappingRepo::new(Arc::clone(&pool));
+        let ns_repo = NamespaceRepo::new(Arc::clone(&pool));
+
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ns_repo.create(&ns).await.unwrap();
+
+        let entry = WrappedResponse::new(
+            ns.id.clone(),
+            "kv/data/foo".into(),
+            json!({ "password": "hunter2" }),
+            Duration::minutes(5),
+        );
+        sqlx::query(
+            "INSERT INTO PLAINTEXT_WRAPPED_RESPONSES (token, namespace_id, creation_path, response, issued_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(entry.token.to_string())
+        .bind(&entry.namespace_id)
+        .bind(&entry.creation_path)
+        .bind(entry.response.to_string())
+        .bind(entry.issued_at)
+        .bind(entry.expires_at)
+        .execute(pool.as_ref())
+        .await
+        .unwrap();
+        assert_eq!(store.lookup(&entry.token, &ns.id).await.unwrap(), None);
+
+        store.convert_plaintext_tokens().await.unwrap();
+        assert_eq!(store.take(&entry.token, &ns.id).await.unwrap(), Some(entry));
+
+        // Converting again does nothing once the plaintext tokens are dropped
+        store.convert_plaintext_tokens().await.unwrap();
+    }
+}
@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use covert_framework::Backend;
-use covert_types::{error::ApiError, mount::MountConfig, request::Request};
+use covert_types::{backend::BackendType, error::ApiError, mount::MountConfig, request::Request};
 use dashmap::DashMap;
 use futures::future::BoxFuture;
 use tower::Service;
@@ -9,9 +9,10 @@ use uuid::Uuid;
 
 use crate::{
     error::{Error, ErrorType},
+    ha::Leadership,
     repos::{mount::MountRepo, namespace::Namespace},
     response::{ResponseContext, ResponseWithCtx},
-    system::SYSTEM_MOUNT_PATH,
+    system::{SYSTEM_MOUNT_PATH, TOKEN_MOUNT_PATH},
 };
 
 /// Router is used to do prefix based routing of a request to a logical backend
@@ -19,6 +20,7 @@ pub struct Router {
     // mount id -> Backend
     backend_lookup: DashMap<String, Arc<Backend>>,
     mount_repo: MountRepo,
+    leadership: Arc<Leadership>,
 }
 
 impl Router {
@@ -27,9 +29,19 @@ impl Router {
         Router {
             backend_lookup: DashMap::default(),
             mount_repo,
+            leadership: Arc::new(Leadership::single()),
         }
     }
 
+    /// Route requests as a standby while the server is not active. Requests
+    /// to backends that issue leases are refused, as the leases can't be
+    /// stored.
+    #[must_use]
+    pub fn leadership(mut self, leadership: Arc<Leadership>) -> Self {
+        self.leadership = leadership;
+        self
+    }
+
     #[tracing::instrument(
         skip(self, req),
         fields(
@@ -50,6 +62,15 @@ impl Router {
                     MountConfig::default(),
                 )
             }
+            // The token endpoints of the system backend are also available
+            // at `auth/token/`, like the other auth methods.
+            Some(_) if req.path.starts_with(TOKEN_MOUNT_PATH) => {
+                let backend = self
+                    .get_system_mount()
+                    .ok_or_else(ApiError::internal_error)?;
+
+                (backend, "auth/".to_string(), MountConfig::default())
+            }
             Some(ns) => {
                 let mount = self
                     .mount_repo
@@ -87,6 +108,13 @@ impl Router {
             }
         };
 
+        if !self.leadership.is_active() && issues_leases(backend.variant()) {
+            return Err(Error::from(ErrorType::Standby).into());
+        }
+
+        if let Some(routed) = req.extensions.get::<RoutedMount>() {
+            routed.set(path.clone(), backend.variant());
+        }
         req.advance_path(&path);
         req.extensions.insert(config.clone());
 
@@ -128,6 +156,37 @@ impl Router {
     pub fn remove(&self, mount_id: Uuid) -> bool {
         self.backend_lookup.remove(&mount_id.to_string()).is_some()
     }
+
+    /// Ids of the mounted backends, except the system backend.
+    #[must_use]
+    pub fn mount_ids(&self) -> Vec<Uuid> {
+        self.backend_lookup
+            .iter()
+            .filter_map(|entry| Uuid::parse_str(entry.key()).ok())
+            .collect()
+    }
+}
+
+/// Backends that register a lease for secrets they generate on reads.
+fn issues_leases(variant: BackendType) -> bool {
+    matches!(variant, BackendType::Postgres)
+}
+
+/// Request extension that the [`Router`] fills in with the mount path and
+/// backend type a request was routed to. Lets the layers in front of the
+/// router know where a request ended up, also when the backend fails it.
+#[derive(Debug, Clone, Default)]
+pub struct RoutedMount(Arc<OnceLock<(String, BackendType)>>);
+
+impl RoutedMount {
+    fn set(&self, mount_path: String, backend_type: BackendType) {
+        let _ = self.0.set((mount_path, backend_type));
+    }
+
+    #[must_use]
+    pub fn get(&self) -> Option<&(String, BackendType)> {
+        self.0.get()
+    }
 }
 
 #[derive(Clone)]
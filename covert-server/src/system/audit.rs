@@ -0,0 +1,123 @@
+use covert_framework::extract::{Extension, Json, Path};
+use covert_types::{
+    methods::system::{
+        AuditDevice as AuditDeviceInfo, DisableAuditDeviceResponse, EnableAuditDeviceParams,
+        EnableAuditDeviceResponse, ListAuditDevicesResponse,
+    },
+    response::Response,
+};
+use rand::RngCore;
+
+use crate::{
+    audit::AuditDevice,
+    context::Context,
+    error::{Error, ErrorType},
+    repos::{audit::AuditDeviceEntry, namespace::Namespace},
+};
+
+const SALT_LENGTH: usize = 32;
+
+#[tracing::instrument(skip(ctx, ns))]
+pub async fn handle_enable_audit_device(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Path(path): Path<String>,
+    Json(body): Json<EnableAuditDeviceParams>,
+) -> Result<Response, Error> {
+    if ns.parent_namespace_id.is_some() {
+        return Err(ErrorType::AuditInNonRootNamespace.into());
+    }
+    let path = sanitize_path(&path)?;
+
+    let mut salt = vec![0; SALT_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let entry = AuditDeviceEntry {
+        path: path.clone(),
+        config: body.config.clone(),
+        salt,
+    };
+
+    // Make sure the device can be written to before it is enabled, otherwise
+    // all subsequent requests would be rejected.
+    let device = AuditDevice::new(entry.clone());
+    device
+        .open()
+        .await
+        .map_err(|error| ErrorType::AuditDeviceUnavailable {
+            error,
+            path: path.clone(),
+        })?;
+    ctx.repos.audit.create(&entry).await?;
+    ctx.audit.enable(device).await;
+
+    let resp = EnableAuditDeviceResponse {
+        device: AuditDeviceInfo {
+            path,
+            config: body.config,
+        },
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_list_audit_devices(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+) -> Result<Response, Error> {
+    if ns.parent_namespace_id.is_some() {
+        return Err(ErrorType::AuditInNonRootNamespace.into());
+    }
+
+    let devices = ctx
+        .repos
+        .audit
+        .list()
+        .await?
+        .into_iter()
+        .map(|entry| AuditDeviceInfo {
+            path: entry.path,
+            config: entry.config,
+        })
+        .collect();
+
+    let resp = ListAuditDevicesResponse { devices };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+#[tracing::instrument(skip(ctx, ns))]
+pub async fn handle_disable_audit_device(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Path(path): Path<String>,
+) -> Result<Response, Error> {
+    if ns.parent_namespace_id.is_some() {
+        return Err(ErrorType::AuditInNonRootNamespace.into());
+    }
+    let path = sanitize_path(&path)?;
+
+    let entry = ctx
+        .repos
+        .audit
+        .lookup(&path)
+        .await?
+        .ok_or_else(|| ErrorType::NotFound(format!("Audit device at `{path}` not found")))?;
+    ctx.repos.audit.remove(&path).await?;
+    ctx.audit.disable(&path).await;
+
+    let resp = DisableAuditDeviceResponse {
+        device: AuditDeviceInfo {
+            path: entry.path,
+            config: entry.config,
+        },
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+fn sanitize_path(path: &str) -> Result<String, Error> {
+    let path = path.trim_matches('/');
+    if path.is_empty() || path.contains(' ') {
+        return Err(
+            ErrorType::BadRequest(format!("`{path}` is not a valid audit device path")).into(),
+        );
+    }
+    Ok(path.to_string())
+}
@@ -0,0 +1,244 @@
+use chrono::Utc;
+use covert_framework::extract::{Extension, Json};
+use covert_types::{
+    entity::Entity,
+    methods::system::{
+        GenerateRootParams, GenerateRootStatusResponse, GenerateRootUpdateParams,
+        GenerateRootUpdateResponse,
+    },
+    policy::{PathPolicy, Policy},
+    request::Operation,
+    response::Response,
+    token::{Token, TokenAccessor, OTP_LENGTH},
+};
+use uuid::Uuid;
+
+use crate::{
+    context::Context,
+    error::{Error, ErrorType},
+    helpers::pgp::PgpKey,
+    repos::{seal::GenerateRootAttempt, token::TokenEntry, Repos},
+};
+
+use super::unseal::construct_master_key;
+
+/// Start an attempt to generate a root token. The token is only returned once
+/// a threshold of key shares have been provided with
+/// [`handle_generate_root_update`].
+pub async fn handle_generate_root_init(
+    Extension(ctx): Extension<Context>,
+    Json(body): Json<GenerateRootParams>,
+) -> Result<Response, Error> {
+    match (&body.otp, &body.pgp_key) {
+        (Some(otp), None) => {
+            if otp.len() != OTP_LENGTH {
+                return Err(ErrorType::BadRequest(format!(
+                    "The one-time pad must be {OTP_LENGTH} characters long"
+                ))
+                .into());
+            }
+        }
+        (None, Some(pgp_key)) => {
+            PgpKey::parse(pgp_key)?;
+        }
+        _ => {
+            return Err(ErrorType::BadRequest(
+                "Exactly one of `otp` and `pgp_key` must be provided".into(),
+            )
+            .into())
+        }
+    }
+
+    if ctx.repos.seal.get_generate_root().await?.is_some() {
+        return Err(
+            ErrorType::BadRequest("A generate root attempt is already in progress".into()).into(),
+        );
+    }
+    ctx.repos
+        .seal
+        .start_generate_root(&GenerateRootAttempt {
+            nonce: Uuid::new_v4().to_string(),
+            otp: body.otp,
+            pgp_key: body.pgp_key,
+        })
+        .await?;
+
+    let resp = generate_root_status(&ctx).await?;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_generate_root_status(
+    Extension(ctx): Extension<Context>,
+) -> Result<Response, Error> {
+    let resp = generate_root_status(&ctx).await?;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_generate_root_cancel(
+    Extension(ctx): Extension<Context>,
+) -> Result<Response, Error> {
+    ctx.repos.seal.cancel_generate_root().await?;
+
+    let resp = generate_root_status(&ctx).await?;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_generate_root_update(
+    Extension(ctx): Extension<Context>,
+    Json(body): Json<GenerateRootUpdateParams>,
+) -> Result<Response, Error> {
+    let seal_config = ctx.repos.seal.get_config().await?.ok_or_else(|| {
+        ErrorType::InternalError(anyhow::Error::msg(
+            "Seal config was not found when generate root handler was called",
+        ))
+    })?;
+    let attempt = ctx
+        .repos
+        .seal
+        .get_generate_root()
+        .await?
+        .ok_or_else(|| ErrorType::BadRequest("No generate root attempt in progress".into()))?;
+    if attempt.nonce != body.nonce {
+        return Err(
+            ErrorType::BadRequest("Nonce does not match the generate root attempt".into()).into(),
+        );
+    }
+
+    for key in body.shares {
+        ctx.repos
+            .seal
+            .insert_generate_root_key_share(key.as_bytes())
+            .await?;
+    }
+
+    let Ok(shares) = ctx
+        .repos
+        .seal
+        .get_generate_root_key_shares()
+        .await?
+        .into_iter()
+        .map(|k| String::from_utf8(k.key))
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        ctx.repos.seal.cancel_generate_root().await?;
+        return Err(ErrorType::BadData("Invalid share key found".into()).into());
+    };
+
+    if usize::from(seal_config.threshold) > shares.len() {
+        let resp = GenerateRootUpdateResponse::InProgress {
+            nonce: attempt.nonce,
+            progress: shares.len(),
+            required: seal_config.threshold,
+        };
+        return Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into());
+    }
+
+    // The attempt is over whether or not the key shares are valid
+    ctx.repos.seal.cancel_generate_root().await?;
+    let master_key = construct_master_key(&ctx, &shares, seal_config.threshold).await?;
+    if !ctx.repos.pool.verify_master_key(&master_key)? {
+        return Err(ErrorType::MasterKeyRecovery.into());
+    }
+
+    let root_token = generate_root_token(&ctx.repos).await?;
+    let encoded_token = match (attempt.otp, attempt.pgp_key) {
+        (Some(otp), _) => root_token.encode_with_otp(&otp).ok_or_else(|| {
+            ErrorType::InternalError(anyhow::Error::msg(
+                "One-time pad does not match the length of the root token",
+            ))
+        })?,
+        (None, Some(pgp_key)) => {
+            PgpKey::parse(&pgp_key)?.encrypt(root_token.to_string().as_bytes())?
+        }
+        (None, None) => {
+            return Err(ErrorType::InternalError(anyhow::Error::msg(
+                "Generate root attempt without a one-time pad or PGP key",
+            ))
+            .into())
+        }
+    };
+
+    let resp = GenerateRootUpdateResponse::Complete { encoded_token };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+async fn generate_root_status(ctx: &Context) -> Result<GenerateRootStatusResponse, Error> {
+    let seal_config = ctx.repos.seal.get_config().await?.ok_or_else(|| {
+        ErrorType::InternalError(anyhow::Error::msg(
+            "Seal config was not found when generate root handler was called",
+        ))
+    })?;
+
+    let resp = match ctx.repos.seal.get_generate_root().await? {
+        Some(attempt) => GenerateRootStatusResponse {
+            started: true,
+            nonce: Some(attempt.nonce),
+            progress: ctx.repos.seal.get_generate_root_key_shares().await?.len(),
+            required: seal_config.threshold,
+            pgp_fingerprint: attempt
+                .pgp_key
+                .map(|key| PgpKey::parse(&key).map(|key| key.fingerprint()))
+                .transpose()?,
+        },
+        None => GenerateRootStatusResponse {
+            started: false,
+            nonce: None,
+            progress: 0,
+            required: seal_config.threshold,
+            pgp_fingerprint: None,
+        },
+    };
+    Ok(resp)
+}
+
+pub async fn generate_root_token(repos: &Repos) -> Result<Token, Error> {
+    let ns = repos
+        .namespace
+        .find_by_path(&["root".to_string()])
+        .await?
+        .ok_or_else(|| ErrorType::InternalError(anyhow::Error::msg("Missing root namespace")))?;
+
+    // Generate root policy if not exist
+    let mut root_rule = PathPolicy::new(
+        "*".to_string(),
+        vec![
+            Operation::Read,
+            Operation::Delete,
+            Operation::Create,
+            Operation::Update,
+            Operation::List,
+        ],
+    );
+    root_rule.sudo = true;
+    let policy = Policy::new("root".into(), vec![root_rule], ns.id.clone());
+    let _res = repos.policy.create(&policy).await;
+
+    // Generate root entity if not exist
+    let entity = Entity::new("root".into(), ns.id.clone());
+    let _res = repos.entity.create(&entity).await;
+
+    // Attach root policy to root entity
+    let _res = repos
+        .entity
+        .attach_policy(&entity.name, &policy.name, &ns.id)
+        .await;
+
+    let te = TokenEntry {
+        id: Token::new(),
+        accessor: TokenAccessor::new(),
+        parent: None,
+        policies: None,
+        num_uses: None,
+        max_expires_at: None,
+        entity_name: entity.name,
+        expires_at: None,
+        issued_at: Utc::now(),
+        namespace_id: ns.id.clone(),
+    };
+    let token = te.id().clone();
+    repos.token.create(&te).await?;
+
+    let _res = repos.token.lookup_policies(&token).await;
+
+    Ok(token)
+}
@@ -5,13 +5,17 @@ use covert_types::{
     },
     response::Response,
 };
+use tracing::warn;
 
 use crate::{
     context::Context,
     error::{Error, ErrorType},
+    helpers::pgp::PgpKey,
     repos::seal::SealConfig,
 };
 
+use super::unseal::{deal_key_shares, generate_unseal_key, kek_provider, unseal, wrap_master_key};
+
 pub async fn handle_initialize(
     Extension(ctx): Extension<Context>,
     Json(body): Json<InitializeParams>,
@@ -20,6 +24,42 @@ pub async fn handle_initialize(
     if body.threshold == 0 || body.shares < body.threshold {
         return Err(ErrorType::InvalidInitializeParams.into());
     }
+    let pgp_keys = body
+        .pgp_keys
+        .map(|pgp_keys| {
+            if pgp_keys.len() != usize::from(body.shares) {
+                return Err(ErrorType::BadRequest(
+                    "The number of PGP keys must match the number of shares".into(),
+                )
+                .into());
+            }
+            pgp_keys
+                .iter()
+                .map(|key| PgpKey::parse(key))
+                .collect::<Result<Vec<_>, Error>>()
+        })
+        .transpose()?;
+
+    // Everything that could fail is done before the storage is initialized,
+    // the key shares can't be handed out again once it is
+    let kek_provider = kek_provider(&ctx)?;
+    let unseal_key = generate_unseal_key();
+    let key_shares = deal_key_shares(&unseal_key, body.shares, body.threshold);
+    let new_key_shares = match pgp_keys {
+        // Each key share is only readable by the holder of its PGP key
+        Some(pgp_keys) => InitializedKeyShares {
+            shares: key_shares
+                .iter()
+                .zip(&pgp_keys)
+                .map(|(share, key)| key.encrypt(share.as_bytes()))
+                .collect::<Result<_, _>>()?,
+            pgp_fingerprints: Some(pgp_keys.iter().map(PgpKey::fingerprint).collect()),
+        },
+        None => InitializedKeyShares {
+            shares: key_shares.clone(),
+            pgp_fingerprints: None,
+        },
+    };
 
     ctx.repos
         .seal
@@ -30,13 +70,36 @@ pub async fn handle_initialize(
         .await?;
 
     if let Some(master_key) = ctx.repos.pool.initialize()? {
-        let sharks = sharks::Sharks(body.threshold);
-        let key_shares = sharks
-            .dealer(master_key.as_bytes())
-            .map(|key_share| hex::encode(Vec::<u8>::from(&key_share)))
-            .take(usize::from(body.shares))
-            .collect::<Vec<_>>();
-        let resp = InitializeResponse::NewKeyShares(InitializedKeyShares { shares: key_shares });
+        let kek_master_key =
+            kek_provider.and_then(|provider| match provider.encrypt(master_key.as_bytes()) {
+                Ok(kek_master_key) => Some(kek_master_key),
+                Err(error) => {
+                    warn!(
+                        ?error,
+                        "Failed to encrypt the master key for auto-unseal, unseal with the recovery key shares"
+                    );
+                    None
+                }
+            });
+        ctx.repos
+            .seal
+            .set_master_key(
+                &wrap_master_key(&master_key, &unseal_key, &key_shares)?,
+                kek_master_key.as_deref(),
+            )
+            .await?;
+
+        // With auto-unseal the key shares are only needed for recovery
+        if kek_master_key.is_some() {
+            if let Err(error) = unseal(&ctx, master_key).await {
+                warn!(
+                    ?error,
+                    "Failed to auto-unseal the new storage, unseal with the recovery key shares"
+                );
+            }
+        }
+
+        let resp = InitializeResponse::NewKeyShares(new_key_shares);
         Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
     } else {
         let resp = InitializeResponse::ExistingKey(InitializedWithExistingKey {
@@ -2,7 +2,7 @@ use covert_framework::extract::{Extension, Json, Path};
 use covert_types::{
     methods::system::{
         LeaseEntry as LeaseEntryDTO, ListLeasesResponse, LookupLeaseResponse, RenewLeaseParams,
-        RenewLeaseResponse, RevokedLeaseResponse, RevokedLeasesResponse,
+        RenewLeaseResponse, RevokedLeaseResponse, RevokedLeasesResponse, TokenAccessorParams,
     },
     response::Response,
 };
@@ -22,6 +22,7 @@ impl From<&LeaseEntry> for LeaseEntryDTO {
             issue_time: le.issued_at.to_rfc3339(),
             expire_time: le.expires_at.to_rfc3339(),
             last_renewal_time: le.expires_at.to_rfc3339(),
+            token_accessor: le.token_accessor.clone(),
         }
     }
 }
@@ -41,6 +42,47 @@ pub async fn handle_lease_revocation_by_mount(
     Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
 }
 
+pub async fn handle_lease_revocation_by_token(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Json(body): Json<TokenAccessorParams>,
+) -> Result<Response, Error> {
+    if ctx
+        .repos
+        .token
+        .lookup_by_accessor(&body.accessor, &ns.id)
+        .await?
+        .is_none()
+    {
+        return Err(
+            ErrorType::NotFound(format!("Token accessor `{}` not found", body.accessor)).into(),
+        );
+    }
+    let revoked_leases = ctx
+        .expiration_manager
+        .revoke_leases_by_token(&body.accessor)
+        .await?;
+    let resp = RevokedLeasesResponse {
+        leases: revoked_leases.iter().map(LeaseEntryDTO::from).collect(),
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_lease_revocation_by_entity(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Path(name): Path<String>,
+) -> Result<Response, Error> {
+    let revoked_leases = ctx
+        .expiration_manager
+        .revoke_leases_by_entity(&name, &ns.id)
+        .await?;
+    let resp = RevokedLeasesResponse {
+        leases: revoked_leases.iter().map(LeaseEntryDTO::from).collect(),
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
 pub async fn handle_lease_revocation(
     Extension(ctx): Extension<Context>,
     Extension(ns): Extension<Namespace>,
@@ -1,17 +1,24 @@
+mod audit;
 mod entity;
+mod generate_root;
 mod initialize;
 mod lease;
 mod mount;
 mod namespace;
 mod policy;
+mod rekey;
+mod rotate;
 mod seal;
+mod snapshot;
+mod standby;
 mod status;
 mod token;
 mod unseal;
+mod wrapping;
 
 use covert_framework::{
-    create, create_with_config, delete, extract::Extension, read, read_with_config, renew, revoke,
-    update, Backend, RouteConfig, Router,
+    create, create_with_config, delete_with_config, extract::Extension, read, read_with_config,
+    renew, revoke, update, update_with_config, Backend, RouteConfig, Router,
 };
 use covert_types::{
     auth::AuthPolicy,
@@ -22,28 +29,61 @@ use covert_types::{
 use crate::context::Context;
 
 use self::{
+    audit::{handle_disable_audit_device, handle_enable_audit_device, handle_list_audit_devices},
     entity::{
         handle_attach_entity_alias, handle_attach_entity_policy, handle_entity_create,
         handle_list_entities, handle_remove_entity_alias, handle_remove_entity_policy,
     },
+    generate_root::{
+        handle_generate_root_cancel, handle_generate_root_init, handle_generate_root_status,
+        handle_generate_root_update,
+    },
     initialize::handle_initialize,
     lease::{
         handle_lease_lookup, handle_lease_renew, handle_lease_revocation,
-        handle_lease_revocation_by_mount, handle_list_leases,
+        handle_lease_revocation_by_entity, handle_lease_revocation_by_mount,
+        handle_lease_revocation_by_token, handle_list_leases,
     },
     mount::{handle_mount, handle_mount_disable, handle_mounts_list, handle_update_mount},
     namespace::{create_namespace_handler, delete_namespace_handler, list_namespaces_handler},
     policy::{handle_create_policy, handle_delete_policy, handle_list_policies},
+    rekey::{
+        handle_rekey_cancel, handle_rekey_init, handle_rekey_status, handle_rekey_update,
+        handle_rekey_verify,
+    },
+    rotate::{handle_key_status, handle_rotate},
     seal::handle_seal,
-    status::handle_status,
-    token::{handle_token_renewal, handle_token_revocation},
+    snapshot::{handle_snapshot, handle_snapshot_restore},
+    status::{handle_leader, handle_metrics, handle_seal_status, handle_status},
+    token::{
+        handle_capabilities_self, handle_token_create, handle_token_create_orphan,
+        handle_token_lookup_accessor, handle_token_lookup_self, handle_token_renew_self,
+        handle_token_renewal, handle_token_revocation, handle_token_revoke_accessor,
+        handle_token_revoke_root, handle_token_revoke_self,
+    },
     unseal::handle_unseal,
+    wrapping::{handle_lookup_wrapping, handle_rewrap, handle_unwrap, handle_wrap},
 };
 pub use mount::mount;
-pub use token::RevokeTokenParams;
+pub use standby::{follow_replica, promote, run_election};
+pub use token::{revoke_token_and_lease, RevokeTokenParams};
+pub use unseal::auto_unseal;
+pub use wrapping::{wrap_response, DEFAULT_WRAP_TTL};
 
 pub const SYSTEM_MOUNT_PATH: &str = "sys/";
+pub const CUBBYHOLE_MOUNT_PATH: &str = "cubbyhole/";
+pub const TOKEN_MOUNT_PATH: &str = "auth/token/";
+
+/// Paths every token has access to, so it can manage itself without a policy
+/// for it.
+pub const TOKEN_SELF_PATHS: &[&str] = &[
+    "auth/token/lookup-self",
+    "auth/token/renew-self",
+    "auth/token/revoke-self",
+    "sys/capabilities-self",
+];
 
+#[allow(clippy::too_many_lines)]
 pub fn new_system_backend(context: Context) -> Backend {
     let router = Router::new()
         .route(
@@ -53,6 +93,7 @@ pub fn new_system_backend(context: Context) -> Backend {
                 RouteConfig {
                     policy: AuthPolicy::Unauthenticated,
                     state: vec![StorageState::Sealed],
+                    root_protected: false,
                 },
             )
             .update_with_config(
@@ -60,25 +101,14 @@ pub fn new_system_backend(context: Context) -> Backend {
                 RouteConfig {
                     policy: AuthPolicy::Unauthenticated,
                     state: vec![StorageState::Sealed],
+                    root_protected: false,
                 },
             ),
         )
         .route(
             "/seal",
-            create_with_config(
-                handle_seal,
-                RouteConfig {
-                    policy: AuthPolicy::Unauthenticated,
-                    state: vec![StorageState::Unsealed],
-                },
-            )
-            .update_with_config(
-                handle_seal,
-                RouteConfig {
-                    policy: AuthPolicy::Unauthenticated,
-                    state: vec![StorageState::Unsealed],
-                },
-            ),
+            create_with_config(handle_seal, RouteConfig::root_protected())
+                .update_with_config(handle_seal, RouteConfig::root_protected()),
         )
         .route(
             "/init",
@@ -87,6 +117,7 @@ pub fn new_system_backend(context: Context) -> Backend {
                 RouteConfig {
                     policy: AuthPolicy::Unauthenticated,
                     state: vec![StorageState::Uninitialized],
+                    root_protected: false,
                 },
             )
             .update_with_config(
@@ -94,39 +125,118 @@ pub fn new_system_backend(context: Context) -> Backend {
                 RouteConfig {
                     policy: AuthPolicy::Unauthenticated,
                     state: vec![StorageState::Uninitialized],
+                    root_protected: false,
                 },
             ),
         )
         .route(
-            "/status",
-            read_with_config(
-                handle_status,
-                RouteConfig {
-                    policy: AuthPolicy::Unauthenticated,
-                    state: vec![
-                        StorageState::Uninitialized,
-                        StorageState::Sealed,
-                        StorageState::Unsealed,
-                    ],
-                },
-            ),
+            "/generate-root/attempt",
+            create_with_config(handle_generate_root_init, generate_root_config())
+                .update_with_config(handle_generate_root_init, generate_root_config())
+                .read_with_config(handle_generate_root_status, generate_root_config())
+                .delete_with_config(handle_generate_root_cancel, generate_root_config()),
+        )
+        .route(
+            "/generate-root/update",
+            create_with_config(handle_generate_root_update, generate_root_config())
+                .update_with_config(handle_generate_root_update, generate_root_config()),
+        )
+        .route(
+            "/rekey/init",
+            create_with_config(handle_rekey_init, RouteConfig::root_protected())
+                .update_with_config(handle_rekey_init, RouteConfig::root_protected())
+                .read_with_config(handle_rekey_status, RouteConfig::root_protected())
+                .delete_with_config(handle_rekey_cancel, RouteConfig::root_protected()),
+        )
+        .route(
+            "/rekey/update",
+            create_with_config(handle_rekey_update, RouteConfig::root_protected())
+                .update_with_config(handle_rekey_update, RouteConfig::root_protected()),
+        )
+        .route(
+            "/rekey/verify",
+            create_with_config(handle_rekey_verify, RouteConfig::root_protected())
+                .update_with_config(handle_rekey_verify, RouteConfig::root_protected()),
+        )
+        .route(
+            "/rotate",
+            create_with_config(handle_rotate, RouteConfig::root_protected())
+                .update_with_config(handle_rotate, RouteConfig::root_protected()),
+        )
+        .route("/key-status", read(handle_key_status))
+        .route(
+            "/storage/snapshot",
+            read_with_config(handle_snapshot, RouteConfig::root_protected()),
+        )
+        .route(
+            "/storage/snapshot-restore",
+            create_with_config(handle_snapshot_restore, snapshot_restore_config())
+                .update_with_config(handle_snapshot_restore, snapshot_restore_config()),
+        )
+        .route("/status", read_with_config(handle_status, status_config()))
+        .route("/leader", read_with_config(handle_leader, status_config()))
+        .route("/metrics", read(handle_metrics))
+        .route(
+            "/seal-status",
+            read_with_config(handle_seal_status, status_config()),
+        )
+        .route(
+            "/mounts",
+            read_with_config(handle_mounts_list, RouteConfig::root_protected()),
         )
-        .route("/mounts", read(handle_mounts_list))
         .route(
             "/mounts/*path",
-            create(handle_mount)
-                .update(handle_update_mount)
-                .delete(handle_mount_disable),
+            create_with_config(handle_mount, RouteConfig::root_protected())
+                .update_with_config(handle_update_mount, RouteConfig::root_protected())
+                .delete_with_config(handle_mount_disable, RouteConfig::root_protected()),
         )
         .route(
             "/policies",
-            update(handle_create_policy)
-                .create(handle_create_policy)
-                .read(handle_list_policies),
+            update_with_config(handle_create_policy, RouteConfig::root_protected())
+                .create_with_config(handle_create_policy, RouteConfig::root_protected())
+                .read_with_config(handle_list_policies, RouteConfig::root_protected()),
+        )
+        .route(
+            "/policies/*name",
+            delete_with_config(handle_delete_policy, RouteConfig::root_protected()),
         )
-        .route("/policies/*name", delete(handle_delete_policy))
         .route("/token/revoke", revoke(handle_token_revocation))
         .route("/token/renew", renew(handle_token_renewal))
+        .route(
+            "/token/create",
+            create(handle_token_create).update(handle_token_create),
+        )
+        .route(
+            "/token/create-orphan",
+            create_with_config(handle_token_create_orphan, RouteConfig::root_protected())
+                .update_with_config(handle_token_create_orphan, RouteConfig::root_protected()),
+        )
+        .route(
+            "/token/revoke-root",
+            create_with_config(handle_token_revoke_root, RouteConfig::root_protected())
+                .update_with_config(handle_token_revoke_root, RouteConfig::root_protected()),
+        )
+        .route("/token/lookup-self", read(handle_token_lookup_self))
+        .route(
+            "/token/renew-self",
+            create(handle_token_renew_self).update(handle_token_renew_self),
+        )
+        .route(
+            "/token/revoke-self",
+            create(handle_token_revoke_self).update(handle_token_revoke_self),
+        )
+        .route(
+            "/capabilities-self",
+            create(handle_capabilities_self).update(handle_capabilities_self),
+        )
+        .route(
+            "/token/lookup-accessor",
+            create(handle_token_lookup_accessor).update(handle_token_lookup_accessor),
+        )
+        .route(
+            "/token/revoke-accessor",
+            create(handle_token_revoke_accessor).update(handle_token_revoke_accessor),
+        )
         .route("/leases/revoke/*lease_id", update(handle_lease_revocation))
         .route("/leases/renew/*lease_id", update(handle_lease_renew))
         .route("/leases/lookup/*lease_id", read(handle_lease_lookup))
@@ -135,6 +245,14 @@ pub fn new_system_backend(context: Context) -> Backend {
             update(handle_lease_revocation_by_mount),
         )
         .route("/leases/lookup-mount/*prefix", read(handle_list_leases))
+        .route(
+            "/leases/revoke-token",
+            update(handle_lease_revocation_by_token),
+        )
+        .route(
+            "/leases/revoke-entity/*name",
+            update(handle_lease_revocation_by_entity),
+        )
         .route(
             "/entity",
             create(handle_entity_create).read(handle_list_entities),
@@ -145,9 +263,66 @@ pub fn new_system_backend(context: Context) -> Backend {
         .route("/entity/alias/*name", update(handle_remove_entity_alias))
         .route(
             "/namespaces",
-            create(create_namespace_handler).read(list_namespaces_handler),
+            create_with_config(create_namespace_handler, RouteConfig::root_protected())
+                .read_with_config(list_namespaces_handler, RouteConfig::root_protected()),
+        )
+        .route(
+            "/namespaces/*name",
+            delete_with_config(delete_namespace_handler, RouteConfig::root_protected()),
+        )
+        .route(
+            "/audit",
+            read_with_config(handle_list_audit_devices, RouteConfig::root_protected()),
+        )
+        .route(
+            "/audit/*path",
+            create_with_config(handle_enable_audit_device, RouteConfig::root_protected())
+                .update_with_config(handle_enable_audit_device, RouteConfig::root_protected())
+                .delete_with_config(handle_disable_audit_device, RouteConfig::root_protected()),
+        )
+        .route("/wrapping/wrap", create(handle_wrap).update(handle_wrap))
+        .route(
+            "/wrapping/unwrap",
+            create_with_config(
+                handle_unwrap,
+                RouteConfig {
+                    policy: AuthPolicy::Unauthenticated,
+                    state: vec![StorageState::Unsealed],
+                    root_protected: false,
+                },
+            )
+            .update_with_config(
+                handle_unwrap,
+                RouteConfig {
+                    policy: AuthPolicy::Unauthenticated,
+                    state: vec![StorageState::Unsealed],
+                    root_protected: false,
+                },
+            ),
+        )
+        .route(
+            "/wrapping/lookup",
+            create_with_config(
+                handle_lookup_wrapping,
+                RouteConfig {
+                    policy: AuthPolicy::Unauthenticated,
+                    state: vec![StorageState::Unsealed],
+                    root_protected: false,
+                },
+            )
+            .update_with_config(
+                handle_lookup_wrapping,
+                RouteConfig {
+                    policy: AuthPolicy::Unauthenticated,
+                    state: vec![StorageState::Unsealed],
+                    root_protected: false,
+                },
+            ),
+        )
+        .route(
+            "/wrapping/rewrap",
+            create(handle_rewrap).update(handle_rewrap),
         )
-        .route("/namespaces/*name", delete(delete_namespace_handler))
         .layer(Extension(context))
         .build()
         .into_service();
@@ -159,3 +334,36 @@ pub fn new_system_backend(context: Context) -> Backend {
         migrations: vec![],
     }
 }
+
+/// Status is available to everyone in every state of the storage.
+fn status_config() -> RouteConfig {
+    RouteConfig {
+        policy: AuthPolicy::Unauthenticated,
+        state: vec![
+            StorageState::Uninitialized,
+            StorageState::Sealed,
+            StorageState::Unsealed,
+        ],
+        root_protected: false,
+    }
+}
+
+/// A snapshot is restored before the storage is unsealed, so it requires the
+/// key shares of initialized storage instead of a token.
+fn snapshot_restore_config() -> RouteConfig {
+    RouteConfig {
+        policy: AuthPolicy::Unauthenticated,
+        state: vec![StorageState::Uninitialized, StorageState::Sealed],
+        root_protected: false,
+    }
+}
+
+/// Generating a root token does not require a token, as it is used when no
+/// root token is available, but requires a quorum of the key shares.
+fn generate_root_config() -> RouteConfig {
+    RouteConfig {
+        policy: AuthPolicy::Unauthenticated,
+        state: vec![StorageState::Unsealed],
+        root_protected: false,
+    }
+}
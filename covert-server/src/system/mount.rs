@@ -1,5 +1,6 @@
 use std::{str::FromStr, sync::Arc};
 
+use covert_cubbyhole::new_cubbyhole_backend;
 use covert_framework::{
     extract::{Extension, Json, Path},
     Backend,
@@ -27,7 +28,7 @@ use crate::{
     repos::{namespace::Namespace, Repos},
 };
 
-use super::{new_system_backend, SYSTEM_MOUNT_PATH};
+use super::{new_system_backend, CUBBYHOLE_MOUNT_PATH, SYSTEM_MOUNT_PATH, TOKEN_MOUNT_PATH};
 
 #[tracing::instrument(skip(ctx))]
 pub async fn handle_mount(
@@ -102,6 +103,13 @@ pub async fn handle_mount_disable(
     Extension(ns): Extension<Namespace>,
     Path(path): Path<String>,
 ) -> Result<Response, Error> {
+    // The cubbyhole is only removed together with its namespace
+    if path == CUBBYHOLE_MOUNT_PATH {
+        return Err(ErrorType::InvalidMountType {
+            variant: BackendType::Cubbyhole,
+        }
+        .into());
+    }
     let mount = remove_mount(&ctx, &path, &ns.id).await?;
     let resp = DisableMountResponse {
         mount: MountsListItemResponse {
@@ -216,6 +224,7 @@ async fn new_backend(
     variant: BackendType,
 ) -> Result<Backend, MigrationError> {
     match variant {
+        BackendType::Cubbyhole => new_cubbyhole_backend(storage),
         BackendType::Kv => new_versioned_kv_backend(storage),
         BackendType::Postgres => new_psql_backend(storage).await,
         BackendType::System => Ok(new_system_backend(ctx.clone())),
@@ -223,6 +232,26 @@ async fn new_backend(
     }
 }
 
+/// Mount the backends of mounts that were created by the active server and
+/// unmount the removed ones. Only used on a standby, where the mounts are
+/// changed behind its back.
+pub async fn sync_mounts(ctx: &Context) -> Result<(), Error> {
+    let mounts = ctx.repos.mount.list_all().await?;
+    let mounted = ctx.router.mount_ids();
+
+    for mount in &mounts {
+        if !mounted.contains(&mount.id) {
+            mount_route_entry(ctx, mount.id, mount.backend_type, &mount.namespace_id).await?;
+        }
+    }
+    for id in mounted {
+        if !mounts.iter().any(|mount| mount.id == id) {
+            let _ = ctx.router.remove(id);
+        }
+    }
+    Ok(())
+}
+
 /// Mount a new backend
 #[tracing::instrument(skip(ctx))]
 pub async fn mount(
@@ -232,18 +261,18 @@ pub async fn mount(
     variant: BackendType,
     mount_config: MountConfig,
 ) -> Result<Uuid, Error> {
-    if variant == BackendType::System {
-        return Err(ErrorType::InvalidMountType {
-            variant: BackendType::System,
-        })?;
+    if matches!(variant, BackendType::System | BackendType::Cubbyhole) {
+        return Err(ErrorType::InvalidMountType { variant })?;
     }
 
-    if path.starts_with(SYSTEM_MOUNT_PATH) || SYSTEM_MOUNT_PATH.starts_with(&path) {
-        return Err(ErrorType::MountPathConflict {
-            path,
-            existing_path: SYSTEM_MOUNT_PATH.to_string(),
+    for reserved_path in [SYSTEM_MOUNT_PATH, TOKEN_MOUNT_PATH] {
+        if path.starts_with(reserved_path) || reserved_path.starts_with(&path) {
+            return Err(ErrorType::MountPathConflict {
+                path,
+                existing_path: reserved_path.to_string(),
+            }
+            .into());
         }
-        .into());
     }
 
     // Check if conflicting path exist
@@ -266,6 +295,39 @@ pub async fn mount(
         return Err(ErrorType::LogicalBackendUnderAuthPath)?;
     }
 
+    create_mount(ctx, path, namespace_id, variant, mount_config).await
+}
+
+/// Mount the cubbyhole secret engine in the namespace if it is not already
+/// mounted.
+#[tracing::instrument(skip(ctx))]
+pub async fn mount_cubbyhole(ctx: &Context, namespace_id: String) -> Result<Uuid, Error> {
+    if let Some(me) = ctx
+        .repos
+        .mount
+        .get_by_path(CUBBYHOLE_MOUNT_PATH, &namespace_id)
+        .await?
+    {
+        return Ok(me.id);
+    }
+
+    create_mount(
+        ctx,
+        CUBBYHOLE_MOUNT_PATH.to_string(),
+        namespace_id,
+        BackendType::Cubbyhole,
+        MountConfig::default(),
+    )
+    .await
+}
+
+async fn create_mount(
+    ctx: &Context,
+    path: String,
+    namespace_id: String,
+    variant: BackendType,
+    mount_config: MountConfig,
+) -> Result<Uuid, Error> {
     // Mount internally
     let uuid = Uuid::new_v4();
     let (backend, prefix) = mount_route_entry(ctx, uuid, variant, &namespace_id).await?;
@@ -307,17 +369,18 @@ pub fn storage_pool_for_backend(
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use sqlx::SqlitePool;
 
     use crate::{
-        context::ChildProcesses, expiration_manager::clock::SystemClock, repos::mount::tests::pool,
-        Config, ExpirationManager, Router,
+        audit::AuditBroker, expiration_manager::clock::SystemClock, ha::Leadership,
+        replication::Replicators, repos::mount::tests::pool, Config, ExpirationManager,
+        ListenerConfig, Router,
     };
 
     use super::*;
 
-    async fn create_context() -> Context {
+    pub async fn create_context() -> Context {
         let pool = Arc::new(pool().await);
         let u_pool = SqlitePool::connect(":memory:").await.unwrap();
         let repos = Repos::new(pool, u_pool);
@@ -329,8 +392,15 @@ mod tests {
                 port_tx: None,
                 replication: None,
                 storage_path: String::new(),
+                listener: ListenerConfig::default(),
+                seal: None,
+                metrics: None,
+                standby: None,
+                ha: None,
             }),
-            child_processes: ChildProcesses::default(),
+            replicators: Replicators::default(),
+            leadership: Arc::new(Leadership::single()),
+            follower: None,
             expiration_manager: Arc::new(ExpirationManager::new(
                 router.clone(),
                 repos.clone(),
@@ -338,6 +408,7 @@ mod tests {
             )),
             repos,
             router,
+            audit: Arc::new(AuditBroker::new()),
         }
     }
 
@@ -413,4 +484,25 @@ mod tests {
             ));
         }
     }
+
+    #[tokio::test]
+    async fn cannot_mount_at_path_that_collides_with_token_endpoints() {
+        let ctx = create_context().await;
+
+        for path in ["auth/", "auth/token/", "auth/token/new/"] {
+            let err = mount(
+                &ctx,
+                path.to_string(),
+                Uuid::new_v4().to_string(),
+                BackendType::Userpass,
+                MountConfig::default(),
+            )
+            .await
+            .unwrap_err();
+            assert!(matches!(
+                err.variant,
+                ErrorType::MountPathConflict { existing_path, .. } if existing_path == TOKEN_MOUNT_PATH
+            ));
+        }
+    }
 }
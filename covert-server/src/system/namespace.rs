@@ -18,7 +18,7 @@ use crate::{
     repos::namespace::Namespace,
 };
 
-use super::mount::remove_mount;
+use super::mount::{mount_cubbyhole, remove_mount};
 
 #[tracing::instrument(skip(ctx))]
 pub async fn create_namespace_handler(
@@ -32,6 +32,7 @@ pub async fn create_namespace_handler(
         parent_namespace_id: Some(ns.id),
     };
     ctx.repos.namespace.create(&new_namespace).await?;
+    mount_cubbyhole(&ctx, new_namespace.id.clone()).await?;
 
     let resp = CreateNamespaceResponse {
         id: new_namespace.id,
@@ -18,8 +18,7 @@ pub async fn handle_create_policy(
     Extension(ns): Extension<Namespace>,
     Json(body): Json<CreatePolicyParams>,
 ) -> Result<Response, Error> {
-    let path_policies = PathPolicy::parse(&body.policy)
-        .map_err(|_| ErrorType::BadRequest("Malformed policy".into()))?;
+    let path_policies = PathPolicy::parse(&body.policy).map_err(ErrorType::MalformedPolicy)?;
     let policy = Policy::new(body.name, path_policies, ns.id.clone());
     ctx.repos.policy.create(&policy).await?;
     let resp = CreatePolicyResponse { policy };
@@ -0,0 +1,256 @@
+use covert_framework::extract::{Extension, Json};
+use covert_types::{
+    methods::system::{
+        RekeyParams, RekeyStatusResponse, RekeyUpdateParams, RekeyUpdateResponse,
+        RekeyVerifyResponse,
+    },
+    response::Response,
+};
+use uuid::Uuid;
+
+use crate::{
+    context::Context,
+    error::{Error, ErrorType},
+    repos::seal::{RekeyAttempt, SealConfig},
+};
+
+use super::unseal::{
+    construct_master_key, construct_secret, deal_key_shares, generate_unseal_key,
+    unwrap_master_key, wrap_master_key,
+};
+
+/// Start an attempt to replace the key shares. The new key shares are dealt
+/// once a threshold of the current key shares have been provided with
+/// [`handle_rekey_update`].
+pub async fn handle_rekey_init(
+    Extension(ctx): Extension<Context>,
+    Json(body): Json<RekeyParams>,
+) -> Result<Response, Error> {
+    if body.threshold == 0 || body.shares < body.threshold {
+        return Err(ErrorType::BadRequest(
+            "The threshold must be at least 1 and not exceed the number of shares".into(),
+        )
+        .into());
+    }
+
+    if ctx.repos.seal.get_rekey().await?.is_some() {
+        return Err(ErrorType::BadRequest("A rekey attempt is already in progress".into()).into());
+    }
+    ctx.repos
+        .seal
+        .start_rekey(&RekeyAttempt {
+            nonce: Uuid::new_v4().to_string(),
+            config: SealConfig {
+                shares: body.shares,
+                threshold: body.threshold,
+            },
+            require_verification: body.require_verification,
+            verification_nonce: None,
+            master_key: None,
+        })
+        .await?;
+
+    let resp = rekey_status(&ctx).await?;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_rekey_status(Extension(ctx): Extension<Context>) -> Result<Response, Error> {
+    let resp = rekey_status(&ctx).await?;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// Cancel the rekey attempt. The current key shares are kept, also if the new
+/// key shares have been dealt and are awaiting verification.
+pub async fn handle_rekey_cancel(Extension(ctx): Extension<Context>) -> Result<Response, Error> {
+    ctx.repos.seal.cancel_rekey().await?;
+
+    let resp = rekey_status(&ctx).await?;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_rekey_update(
+    Extension(ctx): Extension<Context>,
+    Json(body): Json<RekeyUpdateParams>,
+) -> Result<Response, Error> {
+    let seal_config = ctx.repos.seal.get_config().await?.ok_or_else(|| {
+        ErrorType::InternalError(anyhow::Error::msg(
+            "Seal config was not found when rekey handler was called",
+        ))
+    })?;
+    let attempt = ctx
+        .repos
+        .seal
+        .get_rekey()
+        .await?
+        .ok_or_else(|| ErrorType::BadRequest("No rekey attempt in progress".into()))?;
+    if attempt.nonce != body.nonce {
+        return Err(ErrorType::BadRequest("Nonce does not match the rekey attempt".into()).into());
+    }
+    if attempt.verification_nonce.is_some() {
+        return Err(ErrorType::BadRequest(
+            "The new key shares of the rekey attempt are awaiting verification".into(),
+        )
+        .into());
+    }
+
+    let shares = collect_key_shares(&ctx, body.shares).await?;
+    if usize::from(seal_config.threshold) > shares.len() {
+        let resp = RekeyUpdateResponse::InProgress {
+            nonce: attempt.nonce,
+            progress: shares.len(),
+            required: seal_config.threshold,
+        };
+        return Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into());
+    }
+
+    // The attempt is over whether or not the key shares are valid
+    ctx.repos.seal.clear_rekey_key_shares().await?;
+    let master_key = match construct_master_key(&ctx, &shares, seal_config.threshold).await {
+        Ok(master_key) if ctx.repos.pool.verify_master_key(&master_key)? => master_key,
+        _ => {
+            ctx.repos.seal.cancel_rekey().await?;
+            return Err(ErrorType::MasterKeyRecovery.into());
+        }
+    };
+
+    let unseal_key = generate_unseal_key();
+    let shares = deal_key_shares(&unseal_key, attempt.config.shares, attempt.config.threshold);
+    let master_key = wrap_master_key(&master_key, &unseal_key, &shares)?;
+
+    let verification_nonce = if attempt.require_verification {
+        let verification_nonce = Uuid::new_v4().to_string();
+        ctx.repos
+            .seal
+            .start_rekey_verification(&verification_nonce, &master_key)
+            .await?;
+        Some(verification_nonce)
+    } else {
+        ctx.repos
+            .seal
+            .complete_rekey(&attempt.config, &master_key)
+            .await?;
+        None
+    };
+
+    let resp = RekeyUpdateResponse::Complete {
+        shares,
+        verification_nonce,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// Provide the new key shares of a rekey attempt that requires verification.
+/// The new key shares replace the current key shares once a threshold of them
+/// have been provided.
+pub async fn handle_rekey_verify(
+    Extension(ctx): Extension<Context>,
+    Json(body): Json<RekeyUpdateParams>,
+) -> Result<Response, Error> {
+    let attempt = ctx
+        .repos
+        .seal
+        .get_rekey()
+        .await?
+        .ok_or_else(|| ErrorType::BadRequest("No rekey attempt in progress".into()))?;
+    let (Some(verification_nonce), Some(new_master_key)) =
+        (attempt.verification_nonce, attempt.master_key)
+    else {
+        return Err(
+            ErrorType::BadRequest("The rekey attempt is not awaiting verification".into()).into(),
+        );
+    };
+    if verification_nonce != body.nonce {
+        return Err(
+            ErrorType::BadRequest("Nonce does not match the rekey verification".into()).into(),
+        );
+    }
+
+    let shares = collect_key_shares(&ctx, body.shares).await?;
+    if usize::from(attempt.config.threshold) > shares.len() {
+        let resp = RekeyVerifyResponse::InProgress {
+            nonce: verification_nonce,
+            progress: shares.len(),
+            required: attempt.config.threshold,
+        };
+        return Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into());
+    }
+
+    // Let the operators try again if the new key shares are invalid
+    ctx.repos.seal.clear_rekey_key_shares().await?;
+    let unseal_key = construct_secret(&shares, attempt.config.threshold)?;
+    let master_key = unwrap_master_key(&new_master_key, &unseal_key)
+        .ok()
+        .and_then(|master_key| String::from_utf8(master_key).ok())
+        .ok_or(ErrorType::MasterKeyRecovery)?;
+    if !ctx.repos.pool.verify_master_key(&master_key)? {
+        return Err(ErrorType::MasterKeyRecovery.into());
+    }
+
+    ctx.repos
+        .seal
+        .complete_rekey(&attempt.config, &new_master_key)
+        .await?;
+
+    let resp = RekeyVerifyResponse::Complete;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+async fn collect_key_shares(ctx: &Context, shares: Vec<String>) -> Result<Vec<String>, Error> {
+    for key in shares {
+        ctx.repos
+            .seal
+            .insert_rekey_key_share(key.as_bytes())
+            .await?;
+    }
+
+    let Ok(shares) = ctx
+        .repos
+        .seal
+        .get_rekey_key_shares()
+        .await?
+        .into_iter()
+        .map(|k| String::from_utf8(k.key))
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        ctx.repos.seal.clear_rekey_key_shares().await?;
+        return Err(ErrorType::BadData("Invalid share key found".into()).into());
+    };
+    Ok(shares)
+}
+
+async fn rekey_status(ctx: &Context) -> Result<RekeyStatusResponse, Error> {
+    let seal_config = ctx.repos.seal.get_config().await?.ok_or_else(|| {
+        ErrorType::InternalError(anyhow::Error::msg(
+            "Seal config was not found when rekey handler was called",
+        ))
+    })?;
+
+    let resp = match ctx.repos.seal.get_rekey().await? {
+        Some(attempt) => RekeyStatusResponse {
+            started: true,
+            nonce: Some(attempt.nonce),
+            progress: ctx.repos.seal.get_rekey_key_shares().await?.len(),
+            // Verification needs a threshold of the new key shares
+            required: if attempt.verification_nonce.is_some() {
+                attempt.config.threshold
+            } else {
+                seal_config.threshold
+            },
+            shares: Some(attempt.config.shares),
+            threshold: Some(attempt.config.threshold),
+            require_verification: attempt.require_verification,
+            verification_nonce: attempt.verification_nonce,
+        },
+        None => RekeyStatusResponse {
+            started: false,
+            nonce: None,
+            progress: 0,
+            required: seal_config.threshold,
+            shares: None,
+            threshold: None,
+            require_verification: false,
+            verification_nonce: None,
+        },
+    };
+    Ok(resp)
+}
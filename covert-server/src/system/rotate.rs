@@ -0,0 +1,98 @@
+use covert_framework::extract::{Extension, Json};
+use covert_types::{
+    methods::system::{KeyStatusResponse, RotateParams, RotateResponse},
+    response::Response,
+};
+
+use covert_storage::create_master_key;
+use tracing::error;
+
+use crate::{
+    context::Context,
+    error::{Error, ErrorType},
+    replication::ENCRYPTED_DB,
+};
+
+use super::unseal::{
+    construct_master_key, deal_key_shares, generate_unseal_key, kek_wrap_master_key,
+    wrap_master_key,
+};
+
+/// Re-encrypt the storage with a new master key and split the new master key
+/// into the same number of key shares as the current key shares.
+pub async fn handle_rotate(
+    Extension(ctx): Extension<Context>,
+    Json(body): Json<RotateParams>,
+) -> Result<Response, Error> {
+    let seal_config = ctx.repos.seal.get_config().await?.ok_or_else(|| {
+        ErrorType::InternalError(anyhow::Error::msg(
+            "Seal config was not found when rotate handler was called",
+        ))
+    })?;
+    if usize::from(seal_config.threshold) > body.shares.len() {
+        return Err(ErrorType::BadRequest(format!(
+            "A threshold of {} key shares is required to rotate the master key",
+            seal_config.threshold
+        ))
+        .into());
+    }
+    let master_key = construct_master_key(&ctx, &body.shares, seal_config.threshold).await?;
+    if !ctx.repos.pool.verify_master_key(&master_key)? {
+        return Err(ErrorType::MasterKeyRecovery.into());
+    }
+
+    // The new master key is staged before the storage is re-encrypted with
+    // it, the current master key stays in place until the storage is
+    let new_master_key = create_master_key();
+    let unseal_key = generate_unseal_key();
+    let shares = deal_key_shares(&unseal_key, seal_config.shares, seal_config.threshold);
+    let kek_master_key = kek_wrap_master_key(&ctx, &new_master_key)?;
+    let generation = ctx
+        .repos
+        .seal
+        .stage_master_key(
+            &wrap_master_key(&new_master_key, &unseal_key, &shares)?,
+            kek_master_key.as_deref(),
+        )
+        .await?;
+
+    if let Err(err) = ctx.repos.pool.rotate(new_master_key).await {
+        if let Err(error) = ctx.repos.seal.discard_staged_master_key().await {
+            error!(?error, "Failed to discard the staged master key");
+        }
+        return Err(err.into());
+    }
+    // Frames written with the old key can't be mixed with frames written
+    // with the new key in a replica
+    ctx.replicators.reset(ENCRYPTED_DB).await;
+
+    // The storage is re-encrypted, so the new key shares are handed out even
+    // if the staged master key can't be made current. It is made current on
+    // the next unseal instead.
+    if let Err(error) = ctx.repos.seal.rotate_master_key().await {
+        error!(
+            ?error,
+            "Failed to replace the master key with the staged master key"
+        );
+    }
+
+    let resp = RotateResponse {
+        shares,
+        generation: generation.generation,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+pub async fn handle_key_status(Extension(ctx): Extension<Context>) -> Result<Response, Error> {
+    let resp = match ctx.repos.seal.get_key_generation().await? {
+        Some(generation) => KeyStatusResponse {
+            generation: generation.generation,
+            created_at: Some(generation.created_at),
+        },
+        None => KeyStatusResponse {
+            generation: 1,
+            created_at: None,
+        },
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
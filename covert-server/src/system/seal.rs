@@ -5,6 +5,7 @@ use tracing::info;
 use crate::{
     context::Context,
     error::{Error, ErrorType},
+    replication::ENCRYPTED_DB,
     repos::namespace::Namespace,
 };
 
@@ -24,12 +25,20 @@ pub async fn handle_seal(
 }
 
 #[tracing::instrument(skip_all)]
-async fn seal(ctx: &Context) -> Result<(), Error> {
+pub(super) async fn seal(ctx: &Context) -> Result<(), Error> {
     info!("Sealing the storage");
+    // The WAL is checkpointed when the storage is closed, so ship the last
+    // changes first.
+    ctx.replicators.sync(ENCRYPTED_DB).await;
     ctx.repos.pool.seal()?;
 
-    // Stop expiration manager
-    ctx.expiration_manager.stop().await;
+    // Audit devices are loaded from the encrypted storage on unseal
+    ctx.audit.clear().await;
+
+    // Stop expiration manager, it is only running on the active server
+    if ctx.leadership.is_active() {
+        ctx.expiration_manager.stop().await;
+    }
 
     // Clear all the route entries except system
     let system = ctx.router.get_system_mount().ok_or_else(|| {
@@ -0,0 +1,142 @@
+use covert_framework::extract::Extension;
+use covert_types::{
+    methods::system::{
+        SealSnapshot as SealSnapshotData, SnapshotMasterKey, SnapshotRestoreResponse,
+    },
+    response::Response,
+    state::StorageState,
+};
+use tracing::info;
+
+use crate::{
+    context::Context,
+    error::{Error, ErrorType},
+    layer::snapshot::{SnapshotFile, SnapshotUpload},
+    repos::seal::{KeyGeneration, SealConfig, SealSnapshot, WrappedKey},
+};
+
+use super::unseal::construct_master_key;
+
+/// Take a consistent snapshot of the storage into the file that is streamed
+/// to the client. The storage stays encrypted with the master key, so the
+/// snapshot is unsealed with the key shares that are in use when it is taken.
+pub async fn handle_snapshot(
+    Extension(ctx): Extension<Context>,
+    Extension(file): Extension<SnapshotFile>,
+) -> Result<Response, Error> {
+    let (seal, ()) = ctx
+        .repos
+        .seal
+        .snapshot(|| async { ctx.repos.pool.snapshot(&file.0).await.map_err(Into::into) })
+        .await?;
+
+    let resp = SealSnapshotData {
+        shares: seal.config.shares,
+        threshold: seal.config.threshold,
+        master_key: seal.master_key.map(|master_key| SnapshotMasterKey {
+            key: hex::encode(master_key.key),
+            nonce: hex::encode(master_key.nonce),
+            share_digests: master_key.share_digests.map(hex::encode),
+        }),
+        kek_master_key: seal.kek_master_key.map(hex::encode),
+        generation: seal
+            .key_generation
+            .as_ref()
+            .map_or(1, |generation| generation.generation),
+        generation_created_at: seal.key_generation.map(|generation| generation.created_at),
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// Replace the storage of a sealed or uninitialized server with an uploaded
+/// snapshot. The server is left sealed.
+pub async fn handle_snapshot_restore(
+    Extension(ctx): Extension<Context>,
+    Extension(upload): Extension<SnapshotUpload>,
+) -> Result<Response, Error> {
+    if ctx.config.using_inmemory_storage() {
+        return Err(ErrorType::BadRequest(
+            "Snapshots can't be restored into inmemory storage".into(),
+        )
+        .into());
+    }
+
+    // Replacing initialized storage requires the same quorum as unsealing it
+    if ctx.repos.pool.state() == StorageState::Sealed {
+        let seal_config = ctx.repos.seal.get_config().await?.ok_or_else(|| {
+            ErrorType::InternalError(anyhow::Error::msg(
+                "Seal config was not found when snapshot restore handler was called",
+            ))
+        })?;
+        if usize::from(seal_config.threshold) > upload.shares.len() {
+            return Err(ErrorType::BadRequest(format!(
+                "A threshold of {} key shares is required to restore a snapshot",
+                seal_config.threshold
+            ))
+            .into());
+        }
+        construct_master_key(&ctx, &upload.shares, seal_config.threshold).await?;
+    }
+
+    let snapshot = decode_seal_snapshot(&upload.seal)?;
+
+    ctx.repos
+        .seal
+        .restore_snapshot(&snapshot, || async {
+            ctx.repos.pool.restore(&upload.path)?;
+            Ok(())
+        })
+        .await?;
+    info!(
+        generation = upload.seal.generation,
+        "Restored the storage from a snapshot"
+    );
+
+    let resp = SnapshotRestoreResponse {
+        generation: upload.seal.generation,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+fn decode_seal_snapshot(seal: &SealSnapshotData) -> Result<SealSnapshot, Error> {
+    if seal.threshold == 0 || seal.threshold > seal.shares {
+        return Err(ErrorType::BadRequest("Invalid seal config in snapshot".into()).into());
+    }
+    let master_key = seal
+        .master_key
+        .as_ref()
+        .map(|master_key| {
+            Ok::<_, Error>(WrappedKey {
+                key: decode_hex(&master_key.key, "master key")?,
+                nonce: decode_hex(&master_key.nonce, "master key nonce")?,
+                share_digests: master_key
+                    .share_digests
+                    .as_deref()
+                    .map(|digests| decode_hex(digests, "share digests"))
+                    .transpose()?,
+            })
+        })
+        .transpose()?;
+
+    Ok(SealSnapshot {
+        config: SealConfig {
+            threshold: seal.threshold,
+            shares: seal.shares,
+        },
+        master_key,
+        kek_master_key: seal
+            .kek_master_key
+            .as_deref()
+            .map(|key| decode_hex(key, "key encryption key master key"))
+            .transpose()?,
+        key_generation: seal.generation_created_at.map(|created_at| KeyGeneration {
+            generation: seal.generation,
+            created_at,
+        }),
+    })
+}
+
+fn decode_hex(value: &str, name: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value)
+        .map_err(|_| ErrorType::BadRequest(format!("Snapshot {name} is not hex encoded")).into())
+}
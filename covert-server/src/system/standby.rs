@@ -0,0 +1,146 @@
+use std::{sync::Arc, time::Duration};
+
+use covert_types::state::StorageState;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{
+    context::Context,
+    error::{Error, ErrorType},
+    replication::{Database, FollowUpdate, Follower, READ_WRITE_PRAGMA, SEAL_DB},
+};
+
+use super::{
+    mount::sync_mounts,
+    seal::seal,
+    unseal::{activate_storage, start_expiration_manager},
+};
+
+/// Keep the storage of a standby up to date with the replica of the active
+/// server, and the mounts and audit devices with the storage.
+pub fn follow_replica(ctx: Context, follower: Arc<Follower>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while !ctx.leadership.is_active() {
+            tokio::time::sleep(interval).await;
+            match follower.sync(&ctx.repos.pool).await {
+                Ok(FollowUpdate::Unchanged) => (),
+                Ok(FollowUpdate::Applied) => {
+                    if ctx.repos.pool.state() != StorageState::Unsealed {
+                        continue;
+                    }
+                    if let Err(error) = reload(&ctx).await {
+                        error!(?error, "Failed to reload the mounts of the standby");
+                    }
+                }
+                Ok(FollowUpdate::MasterKeyChanged) => {
+                    error!(
+                        "The master key was rotated on the active server, restart the standby to unseal it with the new key shares"
+                    );
+                    if let Err(error) = seal(&ctx).await {
+                        error!(?error, "Failed to seal the standby");
+                    }
+                }
+                Err(error) => error!(?error, "Failed to follow the replica"),
+            }
+        }
+    })
+}
+
+async fn reload(ctx: &Context) -> Result<(), Error> {
+    sync_mounts(ctx).await?;
+    ctx.audit.load(ctx.repos.audit.list().await?).await;
+    Ok(())
+}
+
+/// Take over as the active server once this server holds the lock. The last
+/// changes of the previous active server are applied before the storage is
+/// written to.
+pub async fn promote(ctx: &Context) -> Result<(), Error> {
+    if let Some(follower) = ctx.follower.as_ref() {
+        let update = follower
+            .stop(&ctx.repos.pool)
+            .await
+            .map_err(ErrorType::InternalError)?;
+        if update == FollowUpdate::MasterKeyChanged {
+            warn!("The master key was rotated by the previous active server, unseal with the new key shares");
+            seal(ctx).await?;
+        }
+    }
+    ctx.replicators
+        .replicate(
+            SEAL_DB,
+            Database::Seal(ctx.repos.unecrypted_pool.clone()),
+            ctx.config.seal_storage_path(),
+        )
+        .await;
+
+    // A sealed server is set up as the active server when it is unsealed
+    if ctx.repos.pool.state() == StorageState::Unsealed {
+        sqlx::query(READ_WRITE_PRAGMA)
+            .execute(ctx.repos.pool.as_ref())
+            .await?;
+        reload(ctx).await?;
+        activate_storage(ctx).await?;
+        ctx.leadership.set_active(true);
+        start_expiration_manager(ctx);
+    } else {
+        ctx.leadership.set_active(true);
+    }
+    info!("Took over as the active server");
+    Ok(())
+}
+
+/// Stop acting as the active server. The storage is sealed, as it can't be
+/// written to by this server anymore.
+async fn step_down(ctx: &Context) {
+    if ctx.repos.pool.state() == StorageState::Unsealed {
+        if let Err(error) = seal(ctx).await {
+            error!(?error, "Failed to seal the storage");
+        }
+    }
+    ctx.leadership.set_active(false);
+    if let Err(error) = ctx.leadership.resign().await {
+        error!(?error, "Failed to release the HA lock");
+    }
+}
+
+/// Renew the lock while this server is active, and take it over once it is
+/// free while this server is a standby.
+///
+/// Returns when this server stepped down as the active server. It has to be
+/// restarted to follow the new active server.
+pub async fn run_election(ctx: Context) {
+    let leadership = Arc::clone(&ctx.leadership);
+    loop {
+        tokio::time::sleep(leadership.renew_interval()).await;
+        let campaign = if leadership.is_active() {
+            leadership.campaign().await
+        } else {
+            leadership.take_over().await
+        };
+        let held = match campaign {
+            Ok(held) => held,
+            Err(error) => {
+                error!(?error, "Failed to renew the HA lock");
+                // The active server keeps going until the lock expires
+                leadership.is_active() && !leadership.lock_expired()
+            }
+        };
+
+        match (held, leadership.is_active()) {
+            (true, false) => {
+                if let Err(error) = promote(&ctx).await {
+                    error!(?error, "Failed to take over as the active server");
+                    step_down(&ctx).await;
+                    return;
+                }
+            }
+            (false, true) => {
+                warn!("Lost the HA lock, stepping down as the active server");
+                step_down(&ctx).await;
+                return;
+            }
+            _ => (),
+        }
+    }
+}
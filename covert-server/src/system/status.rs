@@ -1,5 +1,8 @@
 use covert_framework::extract::Extension;
-use covert_types::{methods::system::StatusResponse, response::Response};
+use covert_types::{
+    methods::system::{LeaderResponse, SealStatusResponse, StandbyStatus, StatusResponse},
+    response::Response,
+};
 
 use crate::{
     context::Context,
@@ -8,8 +11,58 @@ use crate::{
 
 #[allow(clippy::unused_async)]
 pub async fn handle_status(Extension(ctx): Extension<Context>) -> Result<Response, Error> {
+    let standby = ctx
+        .follower
+        .as_ref()
+        .filter(|_| !ctx.leadership.is_active())
+        .map(|follower| StandbyStatus {
+            active_address: ctx
+                .leadership
+                .leader()
+                .map(|leader| leader.address)
+                .unwrap_or_default(),
+            synced_at: follower.synced_at(),
+            lag: follower.lag(),
+            lagging: follower.is_lagging(),
+        });
     let resp = StatusResponse {
         state: ctx.repos.pool.state(),
+        standby,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// The active server of the cluster, available on every server so clients
+/// can find the active server through any of them.
+#[allow(clippy::unused_async)]
+pub async fn handle_leader(Extension(ctx): Extension<Context>) -> Result<Response, Error> {
+    let leader = ctx.leadership.leader();
+    let resp = LeaderResponse {
+        ha_enabled: ctx.leadership.ha_enabled(),
+        is_self: ctx.leadership.is_active(),
+        leader_address: leader.as_ref().map(|leader| leader.address.clone()),
+        active_since: leader.and_then(|leader| leader.active_since),
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// Status of the seal and the progress of the unseal attempt. The unseal
+/// attempt nonce is only set while key shares are being provided.
+pub async fn handle_seal_status(Extension(ctx): Extension<Context>) -> Result<Response, Error> {
+    let seal_config = ctx.repos.seal.get_config().await?;
+    let resp = SealStatusResponse {
+        state: ctx.repos.pool.state(),
+        threshold: seal_config.as_ref().map(|config| config.threshold),
+        shares: seal_config.as_ref().map(|config| config.shares),
+        progress: ctx.repos.seal.get_key_shares().await?.len(),
+        nonce: ctx.repos.seal.get_unseal_nonce().await?,
     };
     Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
 }
+
+/// Authorizes `sys/metrics` on the main listener, the metrics themselves are
+/// rendered by the metrics layer once the request is authorized.
+#[allow(clippy::unused_async)]
+pub async fn handle_metrics(Extension(_ctx): Extension<Context>) -> Result<Response, Error> {
+    Ok(Response::ok())
+}
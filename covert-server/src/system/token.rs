@@ -1,21 +1,46 @@
-use chrono::Utc;
+use std::{cmp::min, str::FromStr, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use covert_cubbyhole::destroy_token_storage;
 use covert_framework::extract::{Extension, Json};
+use covert_storage::BackendStoragePool;
 use covert_types::{
-    methods::{psql::RenewLeaseResponse, RenewLeaseParams},
+    auth::AuthPolicy,
+    backend::BackendType,
+    methods::{
+        psql::RenewLeaseResponse,
+        system::{
+            CapabilitiesParams, CapabilitiesResponse, CreateTokenParams, CreateTokenResponse,
+            LookupTokenResponse, RenewTokenSelfParams, RevokedRootTokensResponse,
+            RevokedTokenResponse, TokenAccessorParams,
+        },
+        RenewLeaseParams,
+    },
+    mount::MountConfig,
+    policy::Policy,
+    request::Operation,
     response::Response,
-    token::Token,
+    token::{Token, TokenAccessor},
+    ttl::calculate_ttl,
 };
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::{
     context::Context,
     error::{Error, ErrorType},
-    repos::namespace::Namespace,
+    layer::auth_service::{path_policy, token_policies},
+    repos::{
+        namespace::Namespace,
+        token::{TokenEntry, TokenInfo},
+    },
 };
 
+use super::mount::storage_pool_for_backend;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RevokeTokenParams {
-    pub token: Token,
+    pub accessor: TokenAccessor,
 }
 
 #[tracing::instrument(skip_all)]
@@ -24,13 +49,437 @@ pub async fn handle_token_revocation(
     Extension(ns): Extension<Namespace>,
     Json(body): Json<RevokeTokenParams>,
 ) -> Result<Response, Error> {
-    ctx.repos.token.remove(&body.token, &ns.id).await?;
+    revoke_token(&ctx, &body.accessor, &ns.id).await?;
     Ok(Response::ok())
 }
 
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_create(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Extension(token): Extension<Token>,
+    Extension(accessor): Extension<TokenAccessor>,
+    Json(body): Json<CreateTokenParams>,
+) -> Result<Response, Error> {
+    create_token(&ctx, &ns, &token, body, Some(accessor)).await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_create_orphan(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Extension(token): Extension<Token>,
+    Json(body): Json<CreateTokenParams>,
+) -> Result<Response, Error> {
+    create_token(&ctx, &ns, &token, body, None).await
+}
+
+/// Create a token for the entity of the calling token with a subset of its
+/// policies. The new token is revoked together with the `parent` token.
+async fn create_token(
+    ctx: &Context,
+    ns: &Namespace,
+    caller: &Token,
+    params: CreateTokenParams,
+    parent: Option<TokenAccessor>,
+) -> Result<Response, Error> {
+    let mut caller_policies = ctx.repos.token.lookup_policies(caller).await?;
+    if caller_policies.iter().any(|p| p.namespace_id != ns.id) {
+        return Err(ErrorType::BadRequest(
+            "Tokens can only be created in the namespace of the calling token".into(),
+        )
+        .into());
+    }
+    let identity = ctx
+        .repos
+        .token
+        .lookup_identity(caller)
+        .await?
+        .ok_or_else(|| ErrorType::Unauthorized("Calling token not found".into()))?;
+
+    let mut policy_names = params.policies.unwrap_or_else(|| {
+        caller_policies
+            .iter()
+            .map(|policy| policy.name.clone())
+            .collect()
+    });
+    policy_names.sort();
+    policy_names.dedup();
+
+    let mut policies = ctx.repos.policy.batch_lookup(&policy_names, &ns.id).await;
+    if let Some(missing) = policy_names
+        .iter()
+        .find(|name| !policies.iter().any(|policy| &&policy.name == name))
+    {
+        return Err(ErrorType::BadRequest(format!("Policy `{missing}` not found")).into());
+    }
+
+    // The new token belongs to the same entity, so the templates are rendered
+    // the same way for both tokens.
+    for policy in caller_policies.iter_mut().chain(policies.iter_mut()) {
+        policy.render_templates(&identity.entity_name, &identity.aliases);
+    }
+    if !Policy::batch_is_authorized(&caller_policies, &policies) {
+        return Err(ErrorType::Unauthorized(
+            "Token policies must be a subset of the policies of the calling token".into(),
+        )
+        .into());
+    }
+
+    let defaults = MountConfig::default();
+    let config = MountConfig {
+        default_lease_ttl: defaults.default_lease_ttl,
+        max_lease_ttl: params.explicit_max_ttl.unwrap_or(defaults.max_lease_ttl),
+    };
+    let now = Utc::now();
+    let ttl = calculate_ttl(now, now, &config, params.ttl)
+        .map_err(|_| ErrorType::BadRequest("Invalid token TTL".into()))?;
+    let max_ttl = chrono::Duration::from_std(config.max_lease_ttl)
+        .map_err(|_| ErrorType::BadRequest("Invalid token max TTL".into()))?;
+
+    let mut te = TokenEntry::new(identity.entity_name, ttl, ns.id.clone());
+    te.max_expires_at = Some(te.issued_at + max_ttl);
+    // A child token is revoked with its parent, so it can't outlive it
+    if parent.is_some() {
+        let caller_info = ctx
+            .repos
+            .token
+            .lookup(caller)
+            .await?
+            .ok_or_else(|| ErrorType::Unauthorized("Calling token not found".into()))?;
+        te.expires_at = min_expiry(te.expires_at, caller_info.expires_at);
+        te.max_expires_at = min_expiry(te.max_expires_at, caller_info.max_expires_at);
+    }
+    te.parent = parent;
+    te.policies = Some(policy_names.clone());
+    te.num_uses = (params.num_uses > 0).then_some(params.num_uses);
+    ctx.repos.token.create(&te).await?;
+    ctx.expiration_manager.register_token();
+
+    let ttl = te
+        .expires_at
+        .map_or(ttl, |expires_at| expires_at - te.issued_at);
+    let resp = CreateTokenResponse {
+        token: te.id,
+        accessor: te.accessor,
+        policies: policy_names,
+        ttl: ttl
+            .to_std()
+            .map_err(|_| ErrorType::BadRequest("Invalid token TTL".into()))?,
+        num_uses: params.num_uses,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// The earliest of two expiry times, where `None` never expires.
+fn min_expiry(a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(min(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_lookup_accessor(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Json(body): Json<TokenAccessorParams>,
+) -> Result<Response, Error> {
+    let token = ctx
+        .repos
+        .token
+        .lookup_by_accessor(&body.accessor, &ns.id)
+        .await?
+        .ok_or_else(|| {
+            ErrorType::NotFound(format!("Token accessor `{}` not found", body.accessor))
+        })?;
+    lookup_response(&ctx, token).await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_revoke_accessor(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Json(body): Json<TokenAccessorParams>,
+) -> Result<Response, Error> {
+    if ctx
+        .repos
+        .token
+        .lookup_by_accessor(&body.accessor, &ns.id)
+        .await?
+        .is_none()
+    {
+        return Err(
+            ErrorType::NotFound(format!("Token accessor `{}` not found", body.accessor)).into(),
+        );
+    }
+    revoke_token_and_lease(&ctx, &body.accessor, &ns.id).await?;
+
+    let resp = RevokedTokenResponse {
+        accessor: body.accessor,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// Revoke all root tokens.
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_revoke_root(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+) -> Result<Response, Error> {
+    let accessors = ctx.repos.token.root_tokens(&ns.id).await?;
+    for accessor in &accessors {
+        revoke_token(&ctx, accessor, &ns.id).await?;
+    }
+
+    let resp = RevokedRootTokensResponse { accessors };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_lookup_self(
+    Extension(ctx): Extension<Context>,
+    Extension(token): Extension<Token>,
+) -> Result<Response, Error> {
+    let token = lookup_self(&ctx, &token).await?;
+    lookup_response(&ctx, token).await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_renew_self(
+    Extension(ctx): Extension<Context>,
+    Extension(token): Extension<Token>,
+    Json(body): Json<RenewTokenSelfParams>,
+) -> Result<Response, Error> {
+    let info = lookup_self(&ctx, &token).await?;
+    renew_token(&ctx, &info, body.ttl).await?;
+
+    let info = lookup_self(&ctx, &token).await?;
+    lookup_response(&ctx, info).await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_token_revoke_self(
+    Extension(ctx): Extension<Context>,
+    Extension(token): Extension<Token>,
+) -> Result<Response, Error> {
+    let info = lookup_self(&ctx, &token).await?;
+    revoke_token_and_lease(&ctx, &info.accessor, &info.namespace_id).await?;
+
+    let resp = RevokedTokenResponse {
+        accessor: info.accessor,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// Operations of a path that are reported as capabilities.
+const PATH_OPERATIONS: [Operation; 5] = [
+    Operation::Create,
+    Operation::Read,
+    Operation::Update,
+    Operation::Delete,
+    Operation::List,
+];
+
+/// Capabilities of the calling token on paths of the namespace, resolved the
+/// same way the access of a request is. The parameters of a request are not
+/// known, so parameter constraints are not considered.
+#[tracing::instrument(skip_all)]
+pub async fn handle_capabilities_self(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Extension(token): Extension<Token>,
+    Json(body): Json<CapabilitiesParams>,
+) -> Result<Response, Error> {
+    let namespace_prefix = ctx.repos.namespace.get_full_path(&ns.id).await?;
+    let token_policies = token_policies(&token, &ctx.repos.token, &ctx.repos.namespace).await?;
+
+    let capabilities = body
+        .paths
+        .into_iter()
+        .map(|path| {
+            let mut capabilities = vec![];
+            let mut sudo = false;
+            if let Some((policy_namespace_prefix, policies)) = &token_policies {
+                for operation in PATH_OPERATIONS {
+                    match path_policy(
+                        policy_namespace_prefix,
+                        policies,
+                        &namespace_prefix,
+                        path.trim_start_matches('/'),
+                        operation,
+                        None,
+                    ) {
+                        AuthPolicy::Unauthenticated => continue,
+                        AuthPolicy::Authenticated => (),
+                        AuthPolicy::Sudo => sudo = true,
+                    }
+                    capabilities.push(operation.to_string());
+                }
+            }
+            if sudo {
+                capabilities.push("sudo".to_string());
+            }
+            if capabilities.is_empty() {
+                capabilities.push("deny".to_string());
+            }
+            (path, capabilities)
+        })
+        .collect();
+
+    let resp = CapabilitiesResponse { capabilities };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+async fn lookup_self(ctx: &Context, token: &Token) -> Result<TokenInfo, Error> {
+    ctx.repos
+        .token
+        .lookup(token)
+        .await?
+        .ok_or_else(|| ErrorType::NotFound("Token not found".into()).into())
+}
+
+async fn lookup_response(ctx: &Context, token: TokenInfo) -> Result<Response, Error> {
+    let policies = ctx
+        .repos
+        .token
+        .lookup_policies_by_accessor(&token.accessor)
+        .await?
+        .into_iter()
+        .map(|policy| policy.name)
+        .collect();
+    let ttl = token.expires_at.map(|expires_at| {
+        (expires_at - Utc::now())
+            .to_std()
+            .unwrap_or(std::time::Duration::ZERO)
+    });
+
+    let resp = LookupTokenResponse {
+        accessor: token.accessor,
+        entity_name: token.entity_name,
+        policies,
+        issued_at: token.issued_at,
+        expires_at: token.expires_at,
+        ttl,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+/// Extend the TTL of a token.
+///
+/// Tokens issued by an auth method are renewed through their lease, so the
+/// max TTL of the auth method is respected. Tokens created with
+/// `sys/token/create` can be renewed up to their explicit max TTL.
+async fn renew_token(
+    ctx: &Context,
+    token: &TokenInfo,
+    ttl: Option<std::time::Duration>,
+) -> Result<(), Error> {
+    if let Some(lease) = ctx
+        .repos
+        .lease
+        .lookup_token_lease(&token.accessor, &token.namespace_id)
+        .await?
+    {
+        ctx.expiration_manager
+            .renew_lease_entry(lease.id(), &lease.namespace_id, ttl)
+            .await?;
+        return Ok(());
+    }
+
+    let Some(max_expires_at) = token.max_expires_at else {
+        return Err(ErrorType::BadRequest("Token does not have a TTL to renew".into()).into());
+    };
+    let ttl = chrono::Duration::from_std(ttl.unwrap_or(MountConfig::default().default_lease_ttl))
+        .map_err(|_| ErrorType::BadRequest("Invalid token TTL".into()))?;
+    let expires_at = min(Utc::now() + ttl, max_expires_at);
+    ctx.repos
+        .token
+        .renew(&token.accessor, &token.namespace_id, expires_at)
+        .await?;
+    Ok(())
+}
+
+/// Revoke a token. Tokens issued by an auth method are revoked by revoking
+/// their lease.
+pub async fn revoke_token_and_lease(
+    ctx: &Context,
+    accessor: &TokenAccessor,
+    namespace_id: &str,
+) -> Result<(), Error> {
+    match ctx
+        .repos
+        .lease
+        .lookup_token_lease(accessor, namespace_id)
+        .await?
+    {
+        Some(lease) => ctx
+            .expiration_manager
+            .revoke_lease_entry_by_id(lease.id(), &lease.namespace_id)
+            .await
+            .map(|_| ()),
+        None => revoke_token(ctx, accessor, namespace_id).await,
+    }
+}
+
+/// Remove the token, and all the tokens created by it, and destroy everything
+/// they have stored in their cubbyholes.
+///
+/// This is also the revocation endpoint for token leases, so the cubbyhole is
+/// destroyed when the expiration manager revokes an expired token.
+pub async fn revoke_token(
+    ctx: &Context,
+    accessor: &TokenAccessor,
+    namespace_id: &str,
+) -> Result<(), Error> {
+    let mut accessors = ctx.repos.token.descendants(accessor).await?;
+    accessors.push(accessor.clone());
+
+    for accessor in &accessors {
+        ctx.expiration_manager
+            .revoke_leases_by_token(accessor)
+            .await?;
+    }
+
+    // Children are removed with their parent
+    ctx.repos.token.remove(accessor, namespace_id).await?;
+
+    for storage in cubbyhole_storages(ctx).await? {
+        for accessor in &accessors {
+            destroy_token_storage(storage.clone(), accessor)
+                .await
+                .map_err(|err| ErrorType::InternalError(err.into()))?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn cubbyhole_storages(ctx: &Context) -> Result<Vec<BackendStoragePool>, Error> {
+    let cubbyholes = ctx
+        .repos
+        .mount
+        .list_by_backend_type(BackendType::Cubbyhole)
+        .await?;
+    cubbyholes
+        .into_iter()
+        .map(|mount| {
+            let namespace_id = Uuid::from_str(&mount.namespace_id).map_err(|_| {
+                ErrorType::InternalError(anyhow::Error::msg("Namespace id was not a valid UUID"))
+            })?;
+            Ok(storage_pool_for_backend(
+                Arc::clone(&ctx.repos.pool),
+                namespace_id,
+                mount.backend_type,
+                mount.id,
+            ))
+        })
+        .collect()
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RenewTokenParams {
-    pub token: Token,
+    pub accessor: TokenAccessor,
 }
 
 #[tracing::instrument(skip_all)]
@@ -51,9 +500,193 @@ pub async fn handle_token_renewal(
 
     ctx.repos
         .token
-        .renew(&data.token, &ns.id, expires_at)
+        .renew(&data.accessor, &ns.id, expires_at)
         .await?;
 
     let resp = RenewLeaseResponse { ttl: body.ttl };
     Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use covert_types::{
+        auth::AuthPolicy,
+        entity::Entity,
+        policy::Policy,
+        request::{Operation, Request},
+        state::StorageState,
+    };
+    use hyper::http::Extensions;
+    use serde_json::json;
+
+    use tower::ServiceExt;
+
+    use crate::{
+        layer::auth_service::AuthService,
+        repos::token::TokenEntry,
+        router::RouterService,
+        system::mount::{mount_cubbyhole, tests::create_context},
+    };
+
+    use super::*;
+
+    fn cubbyhole_request(
+        ns: &Namespace,
+        token: &Token,
+        accessor: &TokenAccessor,
+        operation: Operation,
+        data: Bytes,
+    ) -> Request {
+        let mut extensions = Extensions::default();
+        extensions.insert(ns.clone());
+        extensions.insert(accessor.clone());
+        extensions.insert(StorageState::Unsealed);
+        extensions.insert(AuthPolicy::Authenticated);
+
+        Request {
+            id: Uuid::new_v4(),
+            namespace: vec![ns.name.clone()],
+            data,
+            extensions,
+            headers: HashMap::new(),
+            operation,
+            params: Vec::default(),
+            path: "cubbyhole/foo".to_string(),
+            query_string: String::default(),
+            token: Some(token.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn revoke_token_destroys_cubbyhole() {
+        let ctx = create_context().await;
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ctx.repos.namespace.create(&ns).await.unwrap();
+        mount_cubbyhole(&ctx, ns.id.clone()).await.unwrap();
+
+        let te = TokenEntry::new("foo".into(), chrono::Duration::hours(1), ns.id.clone());
+        let data = Bytes::from(json!({ "data": { "foo": "bar" } }).to_string());
+        ctx.router
+            .route(cubbyhole_request(
+                &ns,
+                te.id(),
+                te.accessor(),
+                Operation::Create,
+                data,
+            ))
+            .await
+            .unwrap();
+        assert!(ctx
+            .router
+            .route(cubbyhole_request(
+                &ns,
+                te.id(),
+                te.accessor(),
+                Operation::Read,
+                Bytes::new()
+            ))
+            .await
+            .is_ok());
+
+        revoke_token(&ctx, te.accessor(), &ns.id).await.unwrap();
+
+        // The same token would no longer find anything in the cubbyhole
+        assert!(ctx
+            .router
+            .route(cubbyhole_request(
+                &ns,
+                te.id(),
+                te.accessor(),
+                Operation::Read,
+                Bytes::new()
+            ))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn token_is_revoked_after_its_last_use() {
+        let ctx = create_context().await;
+        let ns = Namespace {
+            id: Uuid::new_v4().to_string(),
+            name: "root".to_string(),
+            parent_namespace_id: None,
+        };
+        ctx.repos.namespace.create(&ns).await.unwrap();
+        mount_cubbyhole(&ctx, ns.id.clone()).await.unwrap();
+        let entity = Entity {
+            name: "foo".to_string(),
+            namespace_id: ns.id.clone(),
+        };
+        ctx.repos.entity.create(&entity).await.unwrap();
+        let policy = Policy {
+            name: "foo".to_string(),
+            paths: vec![],
+            namespace_id: ns.id.clone(),
+        };
+        ctx.repos.policy.create(&policy).await.unwrap();
+
+        let mut parent = TokenEntry::new(
+            entity.name.clone(),
+            chrono::Duration::hours(1),
+            ns.id.clone(),
+        );
+        parent.policies = Some(vec![policy.name.clone()]);
+        parent.num_uses = Some(1);
+        ctx.repos.token.create(&parent).await.unwrap();
+        let mut child = TokenEntry::new(
+            entity.name.clone(),
+            chrono::Duration::hours(1),
+            ns.id.clone(),
+        );
+        child.parent = Some(parent.accessor().clone());
+        ctx.repos.token.create(&child).await.unwrap();
+
+        let data = Bytes::from(json!({ "data": { "foo": "bar" } }).to_string());
+        ctx.router
+            .route(cubbyhole_request(
+                &ns,
+                parent.id(),
+                parent.accessor(),
+                Operation::Create,
+                data,
+            ))
+            .await
+            .unwrap();
+
+        // The request using the last use of the token still goes through
+        let service = AuthService::new(RouterService::new(ctx.router.clone()), ctx.clone());
+        assert!(service
+            .oneshot(cubbyhole_request(
+                &ns,
+                parent.id(),
+                parent.accessor(),
+                Operation::Read,
+                Bytes::new()
+            ))
+            .await
+            .is_ok());
+
+        // But afterwards the token, its children and its cubbyhole are gone
+        assert!(ctx.repos.token.lookup(parent.id()).await.unwrap().is_none());
+        assert!(ctx.repos.token.lookup(child.id()).await.unwrap().is_none());
+        assert!(ctx
+            .router
+            .route(cubbyhole_request(
+                &ns,
+                parent.id(),
+                parent.accessor(),
+                Operation::Read,
+                Bytes::new()
+            ))
+            .await
+            .is_err());
+    }
+}
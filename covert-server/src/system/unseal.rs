@@ -1,26 +1,28 @@
 use std::sync::Arc;
 
-use chrono::Utc;
+use aes_gcm::{
+    aead::{Aead, AeadCore, OsRng},
+    Aes256Gcm, KeyInit, Nonce,
+};
 use covert_framework::extract::{Extension, Json};
 use covert_types::{
-    entity::Entity,
     methods::system::{UnsealParams, UnsealResponse},
-    policy::{PathPolicy, Policy},
-    request::Operation,
     response::Response,
-    token::Token,
+    state::StorageState,
 };
-use tracing::error;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
 use uuid::Uuid;
 
 use crate::{
     context::Context,
     error::{Error, ErrorType},
-    recovery::replicate,
-    repos::{namespace::Namespace, token::TokenEntry, Repos},
+    kek::KekProvider,
+    replication::{Database, ENCRYPTED_DB, READ_ONLY_PRAGMA},
+    repos::{namespace::Namespace, seal::WrappedKey},
 };
 
-use super::mount::mount_route_entry;
+use super::mount::{mount_cubbyhole, mount_route_entry};
 
 pub async fn handle_unseal(
     Extension(ctx): Extension<Context>,
@@ -32,20 +34,65 @@ pub async fn handle_unseal(
         ))
     })?;
 
+    let nonce = ctx.repos.seal.get_unseal_nonce().await?;
+    if body.nonce.is_some() && body.nonce != nonce {
+        return Err(ErrorType::BadRequest("Nonce does not match the unseal attempt".into()).into());
+    }
+    let nonce = if body.reset {
+        ctx.repos.seal.reset_unseal().await?;
+        None
+    } else {
+        nonce
+    };
+
+    if body.shares.is_empty() {
+        let resp = UnsealResponse::InProgress {
+            threshold: seal_config.threshold,
+            key_shares_total: seal_config.shares,
+            key_shares_provided: ctx.repos.seal.get_key_shares().await?.len(),
+            nonce,
+        };
+        return Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into());
+    }
+
+    // Check every key share before storing any of them, an invalid key share
+    // is rejected without discarding the progress made by the others
+    let master_key = ctx.repos.seal.get_master_key().await?;
+    let staged = ctx.repos.seal.get_staged_master_key().await?;
+    let staged_share_digests = staged
+        .as_ref()
+        .and_then(|staged| staged.master_key.share_digests.as_deref());
+    let share_digests = master_key
+        .as_ref()
+        .and_then(|master_key| master_key.share_digests.as_deref())
+        .map(|share_digests| [share_digests, staged_share_digests.unwrap_or_default()].concat());
+    for (i, share) in body.shares.iter().enumerate() {
+        check_key_share(share, share_digests.as_deref()).map_err(|reason| {
+            ErrorType::BadRequest(format!("Key share number {} {reason}", i + 1))
+        })?;
+    }
+
+    let nonce = ctx
+        .repos
+        .seal
+        .start_unseal(&Uuid::new_v4().to_string())
+        .await?;
     for key in body.shares {
         ctx.repos.seal.insert_key_share(key.as_bytes()).await?;
     }
 
-    let Ok(shares) = ctx.repos
+    let Ok(shares) = ctx
+        .repos
         .seal
         .get_key_shares()
         .await?
         .into_iter()
         .map(|k| String::from_utf8(k.key))
-        .collect::<Result<Vec<_>, _>>() else {
-            ctx.repos.seal.clear_key_shares().await?;
-            return Err(ErrorType::BadData("Invalid share key found".into()).into());
-        };
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        ctx.repos.seal.reset_unseal().await?;
+        return Err(ErrorType::BadData("Invalid share key found".into()).into());
+    };
 
     if usize::from(seal_config.threshold) > shares.len() {
         // Return progress
@@ -53,26 +100,78 @@ pub async fn handle_unseal(
             threshold: seal_config.threshold,
             key_shares_total: seal_config.shares,
             key_shares_provided: shares.len(),
+            nonce: Some(nonce),
         };
         return Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into());
     }
 
-    let Ok(master_key) = construct_master_key(&shares, seal_config.threshold) else {
-        ctx.repos.seal.clear_key_shares().await?;
-        return Err(ErrorType::BadData("Unable to construct master key from key shares".into()).into());
+    let Ok(master_key) = construct_master_key(&ctx, &shares, seal_config.threshold).await else {
+        ctx.repos.seal.reset_unseal().await?;
+        return Err(
+            ErrorType::BadData("Unable to construct master key from key shares".into()).into(),
+        );
     };
-    // No longer needed so just clear them
-    ctx.repos.seal.clear_key_shares().await?;
 
-    unseal(&ctx, master_key).await?;
+    unseal(&ctx, master_key.clone()).await?;
+
+    if staged.is_some() {
+        let rotated = shares
+            .iter()
+            .all(|share| check_key_share(share, staged_share_digests).is_ok());
+        settle_staged_master_key(&ctx, rotated).await;
+    }
 
-    let root_token = generate_root_token(&ctx.repos).await?;
+    // Storage initialized before auto-unseal was configured gets the master
+    // key encrypted with the key encryption key on the first manual unseal
+    if ctx.config.seal.is_some() && ctx.repos.seal.get_kek_master_key().await?.is_none() {
+        if let Some(kek_master_key) = kek_wrap_master_key(&ctx, &master_key)? {
+            ctx.repos.seal.set_kek_master_key(&kek_master_key).await?;
+        }
+    }
 
-    let resp = UnsealResponse::Complete { root_token };
+    let resp = UnsealResponse::Complete;
     Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
 }
 
-fn construct_master_key(key_shares: &[String], threshold: u8) -> Result<String, Error> {
+/// Check that a key share is well-formed and, if the digests of the key shares
+/// are known, that it is one of the current key shares.
+fn check_key_share(share: &str, share_digests: Option<&[u8]>) -> Result<(), &'static str> {
+    let decoded = hex::decode(share).map_err(|_| "is not hex encoded")?;
+    sharks::Share::try_from(decoded.as_slice()).map_err(|_| "is malformed")?;
+    match share_digests {
+        Some(share_digests)
+            if !share_digests
+                .chunks(SHARE_DIGEST_LENGTH)
+                .any(|digest| digest == Sha256::digest(&decoded).as_slice()) =>
+        {
+            Err("is not one of the current key shares")
+        }
+        _ => Ok(()),
+    }
+}
+
+const SHARE_DIGEST_LENGTH: usize = 32;
+
+/// Concatenated SHA-256 digests of hex encoded key shares.
+fn digest_key_shares(shares: &[String]) -> Vec<u8> {
+    shares
+        .iter()
+        .filter_map(|share| hex::decode(share).ok())
+        .flat_map(|share| Sha256::digest(share).to_vec())
+        .collect()
+}
+
+/// Split a secret into hex encoded key shares where any `threshold` of them
+/// are able to reconstruct the secret.
+pub(super) fn deal_key_shares(secret: &[u8], shares: u8, threshold: u8) -> Vec<String> {
+    sharks::Sharks(threshold)
+        .dealer(secret)
+        .map(|key_share| hex::encode(Vec::<u8>::from(&key_share)))
+        .take(usize::from(shares))
+        .collect()
+}
+
+pub(super) fn construct_secret(key_shares: &[String], threshold: u8) -> Result<Vec<u8>, Error> {
     let key_shares = key_shares
         .iter()
         .map(|s| {
@@ -85,130 +184,278 @@ fn construct_master_key(key_shares: &[String], threshold: u8) -> Result<String,
         })
         .collect::<Result<Vec<_>, _>>()?;
     let sharks = sharks::Sharks(threshold);
-    let master_key = sharks
+    sharks
         .recover(key_shares.as_slice())
-        .map_err(|_| ErrorType::MasterKeyRecovery)?;
-    let master_key = String::from_utf8(master_key).map_err(|_| ErrorType::MasterKeyRecovery)?;
-    Ok(master_key)
+        .map_err(|_| ErrorType::MasterKeyRecovery.into())
 }
 
-async fn unseal(ctx: &Context, master_key: String) -> Result<(), Error> {
-    ctx.repos.pool.unseal(master_key.clone())?;
+/// Reconstruct the master key from the key shares.
+///
+/// The key shares split the unseal key that the stored master key is
+/// encrypted with. Storage initialized before the master key was encrypted
+/// has key shares that split the master key itself.
+pub(super) async fn construct_master_key(
+    ctx: &Context,
+    key_shares: &[String],
+    threshold: u8,
+) -> Result<String, Error> {
+    let secret = construct_secret(key_shares, threshold)?;
+    let master_key = match ctx.repos.seal.get_master_key().await? {
+        Some(master_key) => match unwrap_master_key(&master_key, &secret) {
+            Ok(master_key) => master_key,
+            // The key shares of a rotation that re-encrypted the storage but
+            // did not replace the master key unwrap the staged master key
+            Err(err) => match ctx.repos.seal.get_staged_master_key().await? {
+                Some(staged) => unwrap_master_key(&staged.master_key, &secret).map_err(|_| err)?,
+                None => return Err(err),
+            },
+        },
+        None => secret,
+    };
+    String::from_utf8(master_key).map_err(|_| ErrorType::MasterKeyRecovery.into())
+}
 
-    // Clear all shares now that master key is constructed
-    ctx.repos.seal.clear_key_shares().await?;
+/// Generate a new random key to encrypt the master key with.
+pub(super) fn generate_unseal_key() -> Vec<u8> {
+    Aes256Gcm::generate_key(&mut OsRng).to_vec()
+}
 
-    // TODO: seal pool again if anything below fails
+/// Encrypt the master key with the unseal key. The digests of the key shares
+/// the unseal key is split into are kept to tell which provided key share is
+/// invalid.
+pub(super) fn wrap_master_key(
+    master_key: &str,
+    unseal_key: &[u8],
+    shares: &[String],
+) -> Result<WrappedKey, Error> {
+    let cipher = Aes256Gcm::new_from_slice(unseal_key)
+        .map_err(|_| ErrorType::InternalError(anyhow::Error::msg("Invalid unseal key length")))?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let key = cipher.encrypt(&nonce, master_key.as_bytes()).map_err(|_| {
+        ErrorType::InternalError(anyhow::Error::msg("Unable to encrypt master key"))
+    })?;
 
-    // Try to recover encrypted storage if replication has not already started.
-    // Replication could have already started if sealed and then unsealed again.
-    let encrypted_storage_replication_started = ctx
-        .child_processes
-        .encrypted_storage_replication_started()
-        .await;
-    if let (Some(replication), false) = (
-        ctx.config.replication.as_ref(),
-        encrypted_storage_replication_started,
+    Ok(WrappedKey {
+        key,
+        nonce: nonce.to_vec(),
+        share_digests: Some(digest_key_shares(shares)),
+    })
+}
+
+pub(super) fn unwrap_master_key(
+    master_key: &WrappedKey,
+    unseal_key: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new_from_slice(unseal_key).map_err(|_| ErrorType::MasterKeyRecovery)?;
+    if master_key.nonce.len() != 12 {
+        return Err(ErrorType::BadData("Invalid master key found in seal storage".into()).into());
+    }
+    cipher
+        .decrypt(
+            Nonce::from_slice(&master_key.nonce),
+            master_key.key.as_slice(),
+        )
+        .map_err(|_| ErrorType::MasterKeyRecovery.into())
+}
+
+/// Encrypt the master key with the key encryption key of the auto-unseal
+/// provider. Returns `None` if auto-unseal is not configured.
+pub(super) fn kek_wrap_master_key(
+    ctx: &Context,
+    master_key: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    let Some(seal) = ctx.config.seal.as_ref() else {
+        return Ok(None);
+    };
+    seal.provider()
+        .and_then(|provider| provider.encrypt(master_key.as_bytes()))
+        .map(Some)
+        .map_err(|err| ErrorType::InternalError(err).into())
+}
+
+/// The key encryption key provider of auto-unseal, checked to decrypt what it
+/// encrypts. Returns `None` if auto-unseal is not configured.
+pub(super) fn kek_provider(ctx: &Context) -> Result<Option<Box<dyn KekProvider>>, Error> {
+    let Some(seal) = ctx.config.seal.as_ref() else {
+        return Ok(None);
+    };
+    let provider = seal.provider().map_err(ErrorType::InternalError)?;
+
+    let probe = hex::encode(generate_unseal_key());
+    let ciphertext = provider
+        .encrypt(probe.as_bytes())
+        .map_err(ErrorType::InternalError)?;
+    let plaintext = provider
+        .decrypt(&ciphertext)
+        .map_err(ErrorType::InternalError)?;
+    if plaintext != probe.as_bytes() {
+        return Err(ErrorType::InternalError(anyhow::Error::msg(
+            "The key encryption key provider does not decrypt what it encrypts",
+        ))
+        .into());
+    }
+
+    Ok(Some(provider))
+}
+
+/// Unseal the storage with the master key encrypted with the key encryption
+/// key of the auto-unseal provider.
+///
+/// The storage is left sealed if auto-unseal fails, it can still be unsealed
+/// with the recovery key shares.
+pub async fn auto_unseal(ctx: &Context) {
+    let Some(seal) = ctx.config.seal.as_ref() else {
+        return;
+    };
+    if ctx.repos.pool.state() != StorageState::Sealed {
+        return;
+    }
+
+    let (kek_master_key, staged) = match tokio::try_join!(
+        ctx.repos.seal.get_kek_master_key(),
+        ctx.repos.seal.get_staged_master_key()
     ) {
-        // Setup replication
-        match replicate(
-            replication,
-            Some(master_key.clone()),
-            &ctx.config.encrypted_storage_path(),
-            &replication.encrypted_bucket_url(),
-        ) {
-            Ok(p) => {
-                ctx.child_processes
-                    .set_encrypted_storage_replication(p)
-                    .await;
-            }
+        Ok(keys) => keys,
+        Err(err) => {
+            error!(?err, "Failed to read the master key for auto-unseal");
+            return;
+        }
+    };
+    let staged_kek_master_key = staged
+        .as_ref()
+        .and_then(|staged| staged.kek_master_key.clone());
+    if kek_master_key.is_none() && staged_kek_master_key.is_none() {
+        info!("No master key stored for auto-unseal, unseal with the key shares");
+        return;
+    }
+
+    // The storage might be re-encrypted with the staged master key of a
+    // rotation that did not replace the master key
+    for (kek_master_key, rotated) in [(kek_master_key, false), (staged_kek_master_key, true)] {
+        let Some(kek_master_key) = kek_master_key else {
+            continue;
+        };
+        let master_key = match seal
+            .provider()
+            .and_then(|provider| provider.decrypt(&kek_master_key))
+            .and_then(|master_key| String::from_utf8(master_key).map_err(Into::into))
+        {
+            Ok(master_key) => master_key,
             Err(err) => {
-                error!(?err, "Failed to setup replication");
+                error!(
+                    ?err,
+                    "Failed to decrypt the master key for auto-unseal, unseal with the recovery key shares"
+                );
+                continue;
             }
+        };
+
+        match unseal(ctx, master_key).await {
+            Ok(()) => {
+                info!("Storage was auto-unsealed");
+                if staged.is_some() {
+                    settle_staged_master_key(ctx, rotated).await;
+                }
+                return;
+            }
+            Err(err) => error!(?err, "Failed to auto-unseal storage"),
         }
     }
+}
 
-    // Run migrations
-    crate::migrations::migrate_ecrypted_db(ctx.repos.pool.as_ref()).await?;
+/// Make the staged master key of a rotation the current master key once the
+/// storage is unsealed with it, or discard it if the storage was unsealed
+/// with the current master key.
+async fn settle_staged_master_key(ctx: &Context, rotated: bool) {
+    // The seal storage of a standby follows the active server
+    if !ctx.leadership.is_active() {
+        return;
+    }
+    let res = if rotated {
+        ctx.repos.seal.rotate_master_key().await.map(|_| ())
+    } else {
+        ctx.repos.seal.discard_staged_master_key().await
+    };
+    if let Err(error) = res {
+        error!(?error, "Failed to settle the staged master key");
+    }
+}
+
+pub(super) async fn unseal(ctx: &Context, master_key: String) -> Result<(), Error> {
+    ctx.repos.pool.unseal(master_key.clone())?;
+
+    // Clear all shares now that master key is constructed
+    ctx.repos.seal.reset_unseal().await?;
+
+    // TODO: seal pool again if anything below fails
+
+    // The active server replicates and migrates the storage of a standby
+    if ctx.leadership.is_active() {
+        activate_storage(ctx).await?;
+    } else {
+        sqlx::query(READ_ONLY_PRAGMA)
+            .execute(ctx.repos.pool.as_ref())
+            .await?;
+    }
+
+    // Enable audit devices
+    ctx.audit.load(ctx.repos.audit.list().await?).await;
 
     // Setup root namespace
-    let ns = if let Some(ns) = ctx
+    if ctx
         .repos
         .namespace
         .find_by_path(&["root".to_string()])
         .await?
+        .is_none()
     {
-        ns
-    } else {
         let ns = Namespace {
             id: Uuid::new_v4().to_string(),
             name: "root".to_string(),
             parent_namespace_id: None,
         };
         ctx.repos.namespace.create(&ns).await?;
-        ns
-    };
+    }
 
-    let mounts = ctx.repos.mount.list(&ns.id).await?;
+    let mounts = ctx.repos.mount.list_all().await?;
     for mount in mounts {
-        mount_route_entry(ctx, mount.id, mount.backend_type, &ns.id).await?;
+        mount_route_entry(ctx, mount.id, mount.backend_type, &mount.namespace_id).await?;
     }
 
-    // Start expiration manager
-    let expiration_manager = Arc::clone(&ctx.expiration_manager);
-    tokio::spawn(async move {
-        if expiration_manager.start().await.is_err() {
-            // TODO: stop the server
-        }
-    });
+    // Every namespace has a cubbyhole, namespaces created before cubbyholes
+    // were introduced get one now
+    for ns in ctx.repos.namespace.list_all().await? {
+        mount_cubbyhole(ctx, ns.id).await?;
+    }
+
+    // Start expiration manager, leases are revoked by the active server
+    if ctx.leadership.is_active() {
+        start_expiration_manager(ctx);
+    }
 
     Ok(())
 }
 
-pub async fn generate_root_token(repos: &Repos) -> Result<Token, Error> {
-    let ns = repos
-        .namespace
-        .find_by_path(&["root".to_string()])
-        .await?
-        .ok_or_else(|| ErrorType::InternalError(anyhow::Error::msg("Missing root namespace")))?;
-
-    // Generate root policy if not exist
-    let policy = Policy::new(
-        "root".into(),
-        vec![PathPolicy {
-            path: "*".to_string(),
-            operations: vec![
-                Operation::Read,
-                Operation::Delete,
-                Operation::Create,
-                Operation::Update,
-            ],
-        }],
-        ns.id.clone(),
-    );
-    let _res = repos.policy.create(&policy).await;
-
-    // Generate root entity if not exist
-    let entity = Entity::new("root".into(), ns.id.clone());
-    let _res = repos.entity.create(&entity).await;
-
-    // Attach root policy to root entity
-    let _res = repos
-        .entity
-        .attach_policy(&entity.name, &policy.name, &ns.id)
+/// Replicate the unsealed storage and migrate it. Replication starts on a new
+/// generation if the storage was sealed and then unsealed again.
+pub(super) async fn activate_storage(ctx: &Context) -> Result<(), Error> {
+    ctx.replicators
+        .replicate(
+            ENCRYPTED_DB,
+            Database::Encrypted(Arc::clone(&ctx.repos.pool)),
+            ctx.config.encrypted_storage_path(),
+        )
         .await;
+    crate::migrations::migrate_ecrypted_db(ctx.repos.pool.as_ref()).await?;
+    ctx.repos.token.convert_plaintext_tokens().await?;
+    ctx.repos.wrapping.convert_plaintext_tokens().await
+}
 
-    let te = TokenEntry {
-        id: Token::new(),
-        entity_name: entity.name,
-        expires_at: None,
-        issued_at: Utc::now(),
-        namespace_id: ns.id.clone(),
-    };
-    let token = te.id().clone();
-    repos.token.create(&te).await?;
-
-    let _res = repos.token.lookup_policies(&token).await;
-
-    Ok(token)
+pub(super) fn start_expiration_manager(ctx: &Context) {
+    let expiration_manager = Arc::clone(&ctx.expiration_manager);
+    tokio::spawn(async move {
+        if expiration_manager.start().await.is_err() {
+            // TODO: stop the server
+        }
+    });
 }
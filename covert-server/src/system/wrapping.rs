@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use covert_framework::extract::{Extension, Json};
+use covert_types::{
+    methods::system::{
+        LookupWrappingParams, LookupWrappingResponse, RewrapParams, UnwrapParams, WrapInfo,
+    },
+    response::Response,
+};
+use serde_json::Value;
+
+use crate::{
+    context::Context,
+    error::{Error, ErrorType},
+    repos::{
+        namespace::Namespace,
+        wrapping::{WrappedResponse, WrappingRepo},
+    },
+};
+
+/// TTL used by `sys/wrapping/wrap` if the request does not specify one.
+pub const DEFAULT_WRAP_TTL: Duration = Duration::from_mins(5);
+
+/// Store the response in a single use cubbyhole and return the information
+/// needed to retrieve it.
+pub async fn wrap_response(
+    repo: &WrappingRepo,
+    namespace_id: &str,
+    creation_path: &str,
+    response: Value,
+    ttl: Duration,
+) -> Result<WrapInfo, Error> {
+    let chrono_ttl = chrono::Duration::from_std(ttl)
+        .map_err(|_| ErrorType::BadRequest(format!("Wrap TTL `{ttl:?}` is too large")))?;
+
+    // Opportunistically cleanup responses that were never unwrapped
+    repo.remove_expired(Utc::now()).await?;
+
+    let entry = WrappedResponse::new(
+        namespace_id.to_string(),
+        creation_path.to_string(),
+        response,
+        chrono_ttl,
+    );
+    repo.create(&entry).await?;
+
+    Ok(WrapInfo {
+        token: entry.token,
+        ttl,
+        creation_time: entry.issued_at,
+        creation_path: entry.creation_path,
+    })
+}
+
+/// Returns the request data as-is. The response wrapping layer takes care of
+/// wrapping it.
+#[tracing::instrument(skip_all)]
+pub async fn handle_wrap(Json(body): Json<Value>) -> Result<Response, Error> {
+    Ok(Response::Raw(body))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_unwrap(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Json(body): Json<UnwrapParams>,
+) -> Result<Response, Error> {
+    let entry = ctx
+        .repos
+        .wrapping
+        .take(&body.token, &ns.id)
+        .await?
+        .ok_or_else(wrapping_token_not_found)?;
+    Ok(Response::Raw(entry.response))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_lookup_wrapping(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Json(body): Json<LookupWrappingParams>,
+) -> Result<Response, Error> {
+    let entry = ctx
+        .repos
+        .wrapping
+        .lookup(&body.token, &ns.id)
+        .await?
+        .ok_or_else(wrapping_token_not_found)?;
+
+    let resp = LookupWrappingResponse {
+        ttl: entry.ttl().to_std().map_err(|_| {
+            ErrorType::InternalError(anyhow::Error::msg("Invalid wrapped response TTL"))
+        })?,
+        creation_time: entry.issued_at,
+        creation_path: entry.creation_path,
+    };
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn handle_rewrap(
+    Extension(ctx): Extension<Context>,
+    Extension(ns): Extension<Namespace>,
+    Json(body): Json<RewrapParams>,
+) -> Result<Response, Error> {
+    let entry = ctx
+        .repos
+        .wrapping
+        .take(&body.token, &ns.id)
+        .await?
+        .ok_or_else(wrapping_token_not_found)?;
+    let ttl = entry.ttl().to_std().map_err(|_| {
+        ErrorType::InternalError(anyhow::Error::msg("Invalid wrapped response TTL"))
+    })?;
+
+    let resp = wrap_response(
+        &ctx.repos.wrapping,
+        &ns.id,
+        &entry.creation_path,
+        entry.response,
+        ttl,
+    )
+    .await?;
+    Response::raw(resp).map_err(|err| ErrorType::BadResponseData(err).into())
+}
+
+fn wrapping_token_not_found() -> Error {
+    ErrorType::NotFound("Wrapping token is invalid or has expired".to_string()).into()
+}
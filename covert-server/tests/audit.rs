@@ -0,0 +1,107 @@
+mod common;
+
+use common::setup_unseal;
+use covert_sdk::audit::{AuditDevice, AuditDeviceConfig, EnableAuditDeviceParams};
+use serde_json::Value;
+
+fn read_entries(path: &std::path::Path) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn audit_device() {
+    let sdk = setup_unseal().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("audit.log");
+
+    // No devices enabled
+    assert!(sdk.audit.list().await.unwrap().devices.is_empty());
+
+    let config = AuditDeviceConfig::File {
+        file_path: file_path.to_str().unwrap().to_string(),
+    };
+    let resp = sdk
+        .audit
+        .enable(
+            "file",
+            &EnableAuditDeviceParams {
+                config: config.clone(),
+            },
+        )
+        .await
+        .unwrap();
+    let device = AuditDevice {
+        path: "file".into(),
+        config: config.clone(),
+    };
+    assert_eq!(resp.device, device);
+    assert_eq!(
+        sdk.audit.list().await.unwrap().devices,
+        vec![device.clone()]
+    );
+
+    // Path is unique
+    assert!(sdk
+        .audit
+        .enable(
+            "file",
+            &EnableAuditDeviceParams {
+                config: config.clone(),
+            },
+        )
+        .await
+        .is_err());
+
+    // Devices that can not be written to are not enabled
+    assert!(sdk
+        .audit
+        .enable(
+            "dir",
+            &EnableAuditDeviceParams {
+                config: AuditDeviceConfig::File {
+                    file_path: dir.path().to_str().unwrap().to_string(),
+                },
+            },
+        )
+        .await
+        .is_err());
+
+    // Requests and responses are recorded
+    let entries = read_entries(&file_path);
+    assert!(!entries.is_empty());
+    let last = entries.last().unwrap();
+    assert_eq!(last["type"], "response");
+    assert_eq!(last["request"]["path"], "sys/audit/dir");
+    assert_eq!(last["response"]["error"]["status_code"], 400);
+
+    // Secrets are not written in plaintext
+    let policy = covert_sdk::policy::CreatePolicyParams {
+        name: "secret-name".into(),
+        policy: r#"path "sys/*" { capabilities = ["read"] }"#.into(),
+    };
+    sdk.policy.create(&policy).await.unwrap();
+    let log = std::fs::read_to_string(&file_path).unwrap();
+    assert!(!log.contains("secret-name"));
+    assert!(log.contains("hmac-sha256:"));
+    for entry in read_entries(&file_path) {
+        assert!(entry["request"]["client_token"]
+            .as_str()
+            .unwrap()
+            .starts_with("hmac-sha256:"));
+    }
+
+    // Disabled devices no longer record requests
+    let entries_before_disable = read_entries(&file_path).len();
+    let resp = sdk.audit.disable("file").await.unwrap();
+    assert_eq!(resp.device, device);
+    assert!(sdk.audit.list().await.unwrap().devices.is_empty());
+    assert!(sdk.audit.disable("file").await.is_err());
+    // The disable request itself is the last entry
+    assert_eq!(read_entries(&file_path).len(), entries_before_disable + 1);
+    sdk.audit.list().await.unwrap();
+    assert_eq!(read_entries(&file_path).len(), entries_before_disable + 1);
+}
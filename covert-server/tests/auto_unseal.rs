@@ -0,0 +1,197 @@
+mod common;
+
+use common::generate_root_token;
+use covert_sdk::{
+    operator::{InitializeParams, InitializeResponse, RotateParams, UnsealParams, UnsealResponse},
+    policy::CreatePolicyParams,
+    Client,
+};
+use covert_system::{AutoUnsealConfig, CommandKekConfig, FileKekConfig};
+use covert_types::state::StorageState;
+use tokio::sync::oneshot;
+
+async fn setup(storage_path: &str, seal: AutoUnsealConfig) -> (Client, oneshot::Sender<()>) {
+    let (port_tx, port_rx) = oneshot::channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    let config = covert_system::Config {
+        port: 0,
+        port_tx: Some(port_tx),
+        storage_path: storage_path.into(),
+        replication: None,
+        listener: covert_system::ListenerConfig::default(),
+        seal: Some(seal),
+        metrics: None,
+        standby: None,
+        ha: None,
+    };
+
+    tokio::spawn(async move {
+        let shutdown_signal = async {
+            let _ = shutdown_rx.await;
+        };
+        if let Err(err) = covert_system::start(config, shutdown_signal).await {
+            panic!("server error: {}", err);
+        }
+    });
+
+    let port = port_rx.await.unwrap();
+    (
+        Client::new(format!("http://localhost:{port}/v1")),
+        shutdown_tx,
+    )
+}
+
+async fn state(sdk: &Client) -> StorageState {
+    sdk.status.status().await.unwrap().state
+}
+
+#[tokio::test]
+async fn auto_unseal_with_recovery_fallback() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir.path().to_str().unwrap();
+    let key_file = tmpdir.path().join("kek");
+    std::fs::write(&key_file, hex::encode([42; 32])).unwrap();
+    let file_seal = AutoUnsealConfig::File(FileKekConfig {
+        key_file: key_file.to_str().unwrap().to_string(),
+    });
+
+    // Storage is unsealed right away when initialized with auto-unseal
+    let (sdk, shutdown_tx) = setup(storage_path, file_seal.clone()).await;
+    let InitializeResponse::NewKeyShares(recovery_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 2,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new recovery shares");
+    };
+    assert_eq!(recovery_shares.shares.len(), 3);
+    assert_eq!(state(&sdk).await, StorageState::Unsealed);
+    shutdown_tx.send(()).unwrap();
+
+    // And on every start
+    let (sdk, shutdown_tx) = setup(storage_path, file_seal).await;
+    assert_eq!(state(&sdk).await, StorageState::Unsealed);
+    shutdown_tx.send(()).unwrap();
+
+    // Falls back to the recovery key shares if the provider is unavailable
+    let failing_seal = AutoUnsealConfig::Command(CommandKekConfig {
+        encrypt_command: vec!["false".into()],
+        decrypt_command: vec!["false".into()],
+    });
+    let (sdk, _shutdown_tx) = setup(storage_path, failing_seal).await;
+    assert_eq!(state(&sdk).await, StorageState::Sealed);
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: recovery_shares.shares[1..].to_vec(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    assert_eq!(state(&sdk).await, StorageState::Unsealed);
+}
+
+#[tokio::test]
+async fn initialize_checks_the_key_encryption_key_provider() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir.path().to_str().unwrap();
+    let initialize_params = InitializeParams {
+        shares: 3,
+        threshold: 2,
+        pgp_keys: None,
+    };
+
+    // A provider that can't decrypt what it encrypts is rejected before the
+    // storage is initialized
+    let broken_seal = AutoUnsealConfig::Command(CommandKekConfig {
+        encrypt_command: vec!["cat".into()],
+        decrypt_command: vec!["false".into()],
+    });
+    let (sdk, shutdown_tx) = setup(storage_path, broken_seal).await;
+    assert!(sdk.operator.initialize(&initialize_params).await.is_err());
+    assert_eq!(state(&sdk).await, StorageState::Uninitialized);
+    shutdown_tx.send(()).unwrap();
+
+    let seal = AutoUnsealConfig::Command(CommandKekConfig {
+        encrypt_command: vec!["cat".into()],
+        decrypt_command: vec!["cat".into()],
+    });
+    let (sdk, _shutdown_tx) = setup(storage_path, seal).await;
+    let resp = sdk.operator.initialize(&initialize_params).await.unwrap();
+    assert!(matches!(resp, InitializeResponse::NewKeyShares(_)));
+    assert_eq!(state(&sdk).await, StorageState::Unsealed);
+}
+
+#[tokio::test]
+async fn failed_rotation_keeps_the_current_master_key() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir.path().to_str().unwrap();
+    let available = tmpdir.path().join("available");
+    std::fs::write(&available, "").unwrap();
+
+    // The provider is only able to encrypt while the file exists
+    let seal = AutoUnsealConfig::Command(CommandKekConfig {
+        encrypt_command: vec![
+            "sh".into(),
+            "-c".into(),
+            format!("test -f {} && cat", available.to_str().unwrap()),
+        ],
+        decrypt_command: vec!["cat".into()],
+    });
+    let (sdk, _shutdown_tx) = setup(storage_path, seal).await;
+    let InitializeResponse::NewKeyShares(recovery_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 2,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new recovery shares");
+    };
+    let shares = recovery_shares.shares;
+    let root_token = generate_root_token(&sdk, shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
+    sdk.policy
+        .create(&CreatePolicyParams {
+            name: "foo".into(),
+            policy: r#"path "sys/*" { capabilities = ["read"] }"#.into(),
+        })
+        .await
+        .unwrap();
+
+    std::fs::remove_file(&available).unwrap();
+    assert!(sdk
+        .operator
+        .rotate(&RotateParams {
+            shares: shares[1..].to_vec(),
+        })
+        .await
+        .is_err());
+    assert_eq!(sdk.operator.key_status().await.unwrap().generation, 1);
+
+    // The storage is still encrypted with the current master key
+    sdk.operator.seal().await.unwrap();
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: shares[..2].to_vec(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    let policies = sdk.policy.list().await.unwrap().policies;
+    assert!(policies.iter().any(|policy| policy.name == "foo"));
+}
@@ -1,5 +1,8 @@
 use covert_sdk::{
-    operator::{InitializeParams, InitializeResponse, UnsealParams, UnsealResponse},
+    operator::{
+        generate_otp, GenerateRootParams, GenerateRootUpdateParams, GenerateRootUpdateResponse,
+        InitializeParams, InitializeResponse, Token, UnsealParams, UnsealResponse,
+    },
     Client,
 };
 use covert_system::ReplicationConfig;
@@ -7,6 +10,7 @@ use tokio::sync::oneshot;
 
 use std::future::Future;
 
+#[allow(dead_code)]
 pub async fn setup(
     storage_path: &str,
     shutdown_signal: impl Future<Output = ()> + Send + Sync + 'static,
@@ -19,6 +23,11 @@ pub async fn setup(
         port_tx: Some(port_tx),
         storage_path: storage_path.into(),
         replication,
+        listener: covert_system::ListenerConfig::default(),
+        seal: None,
+        metrics: None,
+        standby: None,
+        ha: None,
     };
 
     tokio::spawn(async move {
@@ -35,12 +44,18 @@ pub async fn setup(
 
 #[allow(dead_code)]
 pub async fn setup_unseal() -> Client {
+    setup_unseal_with_root_token().await.0
+}
+
+#[allow(dead_code)]
+pub async fn setup_unseal_with_root_token() -> (Client, String) {
     let sdk = setup(":memory:", covert_system::shutdown_signal(), None).await;
     let shares = match sdk
         .operator
         .initialize(&InitializeParams {
             shares: 1,
             threshold: 1,
+            pgp_keys: None,
         })
         .await
         .unwrap()
@@ -48,10 +63,48 @@ pub async fn setup_unseal() -> Client {
         InitializeResponse::NewKeyShares(shares) => shares.shares,
         _ => panic!("should get new shares"),
     };
-    let resp = sdk.operator.unseal(&UnsealParams { shares }).await.unwrap();
-    if let UnsealResponse::Complete { root_token } = resp {
-        sdk.set_token(Some(root_token.to_string())).await;
-    }
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    let UnsealResponse::Complete = resp else {
+        panic!("should complete unseal");
+    };
+    let root_token = generate_root_token(&sdk, shares).await;
+    sdk.set_token(Some(root_token.clone())).await;
 
-    sdk
+    (sdk, root_token)
+}
+
+/// Generate a root token with a quorum of the key shares.
+#[allow(dead_code)]
+pub async fn generate_root_token(sdk: &Client, shares: Vec<String>) -> String {
+    let otp = generate_otp();
+    let attempt = sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: Some(otp.clone()),
+            pgp_key: None,
+        })
+        .await
+        .unwrap();
+    let resp = sdk
+        .operator
+        .generate_root_update(&GenerateRootUpdateParams {
+            nonce: attempt.nonce.unwrap(),
+            shares,
+        })
+        .await
+        .unwrap();
+    let GenerateRootUpdateResponse::Complete { encoded_token } = resp else {
+        panic!("should complete generate root");
+    };
+    Token::decode_with_otp(&encoded_token, &otp)
+        .unwrap()
+        .to_string()
 }
@@ -0,0 +1,205 @@
+mod common;
+
+use common::{generate_root_token, setup, setup_unseal, setup_unseal_with_root_token};
+use covert_sdk::{
+    cubbyhole::WriteSecretParams,
+    entity::{AttachEntityAliasParams, AttachEntityPolicyParams, CreateEntityParams, EntityAlias},
+    mounts::{BackendType, CreateMountParams},
+    namespace::CreateNamespaceParams,
+    operator::{InitializeParams, InitializeResponse, UnsealParams},
+    policy::CreatePolicyParams,
+    userpass::{CreateUserParams, LoginParams},
+    Client,
+};
+use serde_json::json;
+
+/// Create a user with a policy that grants nothing but `kv/`, and return a
+/// login response for it.
+async fn login(sdk: &Client) -> covert_sdk::userpass::AuthResponse {
+    sdk.mount
+        .create(
+            "auth/userpass/",
+            &CreateMountParams {
+                variant: BackendType::Userpass,
+                config: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+    sdk.userpass
+        .create(
+            "auth/userpass/",
+            &CreateUserParams {
+                username: "foo".into(),
+                password: "bar".into(),
+            },
+        )
+        .await
+        .unwrap();
+    sdk.policy
+        .create(&CreatePolicyParams {
+            name: "kv".into(),
+            policy: r#"path "kv/*" { capabilities = ["read"] }"#.into(),
+        })
+        .await
+        .unwrap();
+    sdk.entity
+        .create(&CreateEntityParams { name: "foo".into() })
+        .await
+        .unwrap();
+    sdk.entity
+        .attach_policies(&AttachEntityPolicyParams {
+            name: "foo".into(),
+            policy_names: vec!["kv".into()],
+        })
+        .await
+        .unwrap();
+    sdk.entity
+        .attach_alias(&AttachEntityAliasParams {
+            name: "foo".into(),
+            aliases: vec![EntityAlias {
+                name: "foo".into(),
+                mount_path: "auth/userpass/".into(),
+            }],
+        })
+        .await
+        .unwrap();
+    sdk.userpass
+        .login(
+            "auth/userpass/",
+            &LoginParams {
+                username: "foo".into(),
+                password: "bar".into(),
+            },
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn cubbyhole_is_mounted_and_cannot_be_removed() {
+    let sdk = setup_unseal().await;
+
+    let mounts = sdk.mount.list().await.unwrap();
+    assert!(mounts
+        .secret
+        .iter()
+        .any(|m| m.path == "cubbyhole/" && m.variant == BackendType::Cubbyhole));
+
+    assert!(sdk.mount.remove("cubbyhole/").await.is_err());
+    assert!(sdk
+        .mount
+        .create(
+            "other-cubbyhole/",
+            &CreateMountParams {
+                variant: BackendType::Cubbyhole,
+                config: Default::default(),
+            },
+        )
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn cubbyhole_is_scoped_to_token() {
+    let (sdk, root_token) = setup_unseal_with_root_token().await;
+    let auth = login(&sdk).await;
+
+    sdk.cubbyhole
+        .write(
+            "foo",
+            &WriteSecretParams {
+                data: json!({ "owner": "root" }),
+            },
+        )
+        .await
+        .unwrap();
+
+    // The user has access to its own cubbyhole without any policy for it
+    sdk.set_token(Some(auth.token.to_string())).await;
+    assert!(sdk.cubbyhole.read("foo").await.is_err());
+    sdk.cubbyhole
+        .write(
+            "foo",
+            &WriteSecretParams {
+                data: json!({ "owner": "user" }),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        sdk.cubbyhole.read("foo").await.unwrap().data,
+        json!({ "owner": "user" })
+    );
+
+    // Root can't read the cubbyhole of the user
+    sdk.set_token(Some(root_token.clone())).await;
+    assert_eq!(
+        sdk.cubbyhole.read("foo").await.unwrap().data,
+        json!({ "owner": "root" })
+    );
+    sdk.cubbyhole.delete("foo").await.unwrap();
+    assert!(sdk.cubbyhole.read("foo").await.is_err());
+    assert!(sdk.cubbyhole.delete("foo").await.is_err());
+
+    // Revoking the token removes access to the cubbyhole
+    sdk.lease.revoke(&auth.lease_id).await.unwrap();
+    sdk.set_token(Some(auth.token.to_string())).await;
+    assert!(sdk.cubbyhole.read("foo").await.is_err());
+}
+
+#[tokio::test]
+async fn cubbyhole_is_mounted_in_every_namespace_after_seal() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let sdk = setup(
+        tmpdir.path().to_str().unwrap(),
+        covert_system::shutdown_signal(),
+        None,
+    )
+    .await;
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 1,
+            threshold: 1,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    let unseal = UnsealParams {
+        shares: key_shares.shares.clone(),
+        nonce: None,
+        reset: false,
+    };
+    sdk.operator.unseal(&unseal).await.unwrap();
+    let root_token = generate_root_token(&sdk, key_shares.shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
+
+    sdk.namespace
+        .create(&CreateNamespaceParams { name: "foo".into() })
+        .await
+        .unwrap();
+    sdk.set_namespace(Some("root/foo".into())).await;
+    sdk.cubbyhole
+        .write(
+            "foo",
+            &WriteSecretParams {
+                data: json!({ "namespace": "foo" }),
+            },
+        )
+        .await
+        .unwrap();
+
+    sdk.set_namespace(None).await;
+    sdk.operator.seal().await.unwrap();
+    sdk.operator.unseal(&unseal).await.unwrap();
+
+    sdk.set_namespace(Some("root/foo".into())).await;
+    assert_eq!(
+        sdk.cubbyhole.read("foo").await.unwrap().data,
+        json!({ "namespace": "foo" })
+    );
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use common::setup;
+use covert_sdk::{
+    operator::{
+        generate_otp, GenerateRootParams, GenerateRootUpdateParams, GenerateRootUpdateResponse,
+        InitializeParams, InitializeResponse, Token, UnsealParams, UnsealResponse,
+    },
+    Client,
+};
+
+async fn setup_unsealed(shares: u8, threshold: u8) -> (Client, Vec<String>) {
+    let sdk = setup(":memory:", covert_system::shutdown_signal(), None).await;
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+
+    (sdk, key_shares.shares)
+}
+
+#[tokio::test]
+async fn generate_root_token_with_otp() {
+    let (sdk, shares) = setup_unsealed(5, 3).await;
+
+    // Unseal does not hand out a root token
+    assert!(sdk.mount.list().await.is_err());
+
+    let otp = generate_otp();
+    let attempt = sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: Some(otp.clone()),
+            pgp_key: None,
+        })
+        .await
+        .unwrap();
+    assert!(attempt.started);
+    assert_eq!(attempt.progress, 0);
+    assert_eq!(attempt.required, 3);
+    let nonce = attempt.nonce.unwrap();
+
+    // Only one attempt at a time
+    assert!(sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: Some(generate_otp()),
+            pgp_key: None,
+        })
+        .await
+        .is_err());
+
+    // The nonce must match the attempt
+    assert!(sdk
+        .operator
+        .generate_root_update(&GenerateRootUpdateParams {
+            nonce: "wrong".into(),
+            shares: vec![shares[0].clone()],
+        })
+        .await
+        .is_err());
+
+    let resp = sdk
+        .operator
+        .generate_root_update(&GenerateRootUpdateParams {
+            nonce: nonce.clone(),
+            shares: shares[..2].to_vec(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        resp,
+        GenerateRootUpdateResponse::InProgress {
+            progress: 2,
+            required: 3,
+            ..
+        }
+    ));
+    assert_eq!(
+        sdk.operator.generate_root_status().await.unwrap().progress,
+        2
+    );
+
+    let resp = sdk
+        .operator
+        .generate_root_update(&GenerateRootUpdateParams {
+            nonce,
+            shares: vec![shares[4].clone()],
+        })
+        .await
+        .unwrap();
+    let GenerateRootUpdateResponse::Complete { encoded_token } = resp else {
+        panic!("should complete generate root");
+    };
+    let root_token = Token::decode_with_otp(&encoded_token, &otp).unwrap();
+    assert!(!sdk.operator.generate_root_status().await.unwrap().started);
+
+    sdk.set_token(Some(root_token.to_string())).await;
+    assert!(sdk.mount.list().await.is_ok());
+
+    // Root tokens can be revoked when they are no longer needed
+    let revoked = sdk.token.revoke_root().await.unwrap();
+    assert_eq!(revoked.accessors.len(), 1);
+    assert!(sdk.mount.list().await.is_err());
+}
+
+#[tokio::test]
+async fn generate_root_attempt_is_validated() {
+    let (sdk, _shares) = setup_unsealed(1, 1).await;
+
+    // Exactly one of the one-time pad and the PGP key
+    assert!(sdk
+        .operator
+        .generate_root_init(&GenerateRootParams::default())
+        .await
+        .is_err());
+    assert!(sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: Some(generate_otp()),
+            pgp_key: Some("key".into()),
+        })
+        .await
+        .is_err());
+    assert!(sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: Some("too-short".into()),
+            pgp_key: None,
+        })
+        .await
+        .is_err());
+    assert!(sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: None,
+            pgp_key: Some("not a PGP key".into()),
+        })
+        .await
+        .is_err());
+    assert!(!sdk.operator.generate_root_status().await.unwrap().started);
+
+    // Key shares of another server are rejected and end the attempt
+    let (_other_sdk, other_shares) = setup_unsealed(1, 1).await;
+    let attempt = sdk
+        .operator
+        .generate_root_init(&GenerateRootParams {
+            otp: Some(generate_otp()),
+            pgp_key: None,
+        })
+        .await
+        .unwrap();
+    assert!(sdk
+        .operator
+        .generate_root_update(&GenerateRootUpdateParams {
+            nonce: attempt.nonce.unwrap(),
+            shares: other_shares,
+        })
+        .await
+        .is_err());
+    assert!(!sdk.operator.generate_root_status().await.unwrap().started);
+}
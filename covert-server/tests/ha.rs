@@ -0,0 +1,188 @@
+mod common;
+
+use std::{collections::HashMap, future::Future, path::Path, time::Duration};
+
+use covert_sdk::{
+    kv::CreateSecretParams,
+    mounts::{BackendType, CreateMountParams},
+    operator::{InitializeParams, InitializeResponse, UnsealParams},
+    Client,
+};
+use covert_system::{
+    DirectoryReplicaConfig, FileLockConfig, HaConfig, HaLockConfig, ReplicaTargetConfig,
+    ReplicationConfig,
+};
+use hyper::{header::LOCATION, Method, StatusCode};
+use tokio::sync::oneshot;
+
+use common::generate_root_token;
+
+fn directory_replication(path: &Path) -> ReplicationConfig {
+    ReplicationConfig {
+        target: ReplicaTargetConfig::Directory(DirectoryReplicaConfig {
+            path: path.to_str().unwrap().to_string(),
+        }),
+        sync_interval: Duration::from_millis(100),
+    }
+}
+
+async fn setup_ha(
+    storage_path: &str,
+    replication: ReplicationConfig,
+    lock_path: &str,
+    address: &str,
+    shutdown_signal: impl Future<Output = ()> + Send + Sync + 'static,
+) -> (Client, u16) {
+    let (port_tx, port_rx) = oneshot::channel();
+
+    let config = covert_system::Config {
+        port: 0,
+        port_tx: Some(port_tx),
+        storage_path: storage_path.into(),
+        replication: Some(replication),
+        listener: covert_system::ListenerConfig::default(),
+        seal: None,
+        metrics: None,
+        standby: None,
+        ha: Some(HaConfig {
+            address: address.into(),
+            lock: HaLockConfig::File(FileLockConfig {
+                path: lock_path.into(),
+            }),
+            lock_ttl: Duration::from_millis(300),
+            max_lag: Duration::from_secs(10),
+        }),
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = covert_system::start(config, shutdown_signal).await {
+            panic!("server error: {}", err);
+        }
+    });
+
+    let port = port_rx.await.unwrap();
+    (Client::new(format!("http://localhost:{port}/v1")), port)
+}
+
+fn secret(value: &str) -> CreateSecretParams {
+    CreateSecretParams {
+        data: HashMap::from([("value".to_string(), value.to_string())]),
+    }
+}
+
+#[tokio::test]
+async fn standby_takes_over_from_active_server() {
+    let replica = tempfile::tempdir().unwrap();
+    let replication = directory_replication(replica.path());
+    let lock_path = replica.path().join("lock");
+    let lock_path = lock_path.to_str().unwrap();
+
+    // The first server takes the lock
+    let tmpdir_a = tempfile::tempdir().unwrap();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let (sdk_a, _) = setup_ha(
+        tmpdir_a.path().to_str().unwrap(),
+        replication.clone(),
+        lock_path,
+        "http://covert-a:8080",
+        async { shutdown_rx.await.unwrap() },
+    )
+    .await;
+    let leader = sdk_a.status.leader().await.unwrap();
+    assert!(leader.ha_enabled);
+    assert!(leader.is_self);
+    assert_eq!(
+        leader.leader_address.as_deref(),
+        Some("http://covert-a:8080")
+    );
+
+    let InitializeResponse::NewKeyShares(key_shares) = sdk_a
+        .operator
+        .initialize(&InitializeParams {
+            shares: 1,
+            threshold: 1,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    let unseal = UnsealParams {
+        shares: key_shares.shares.clone(),
+        nonce: None,
+        reset: false,
+    };
+    sdk_a.operator.unseal(&unseal).await.unwrap();
+    let root_token = generate_root_token(&sdk_a, key_shares.shares.clone()).await;
+    sdk_a.set_token(Some(root_token.clone())).await;
+    sdk_a
+        .mount
+        .create(
+            "kv/",
+            &CreateMountParams {
+                variant: BackendType::Kv,
+                config: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+    sdk_a.kv.create("kv/", "foo", &secret("bar")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // The second server is a standby of the first one
+    let tmpdir_b = tempfile::tempdir().unwrap();
+    let (sdk_b, port_b) = setup_ha(
+        tmpdir_b.path().to_str().unwrap(),
+        replication,
+        lock_path,
+        "http://covert-b:8080",
+        covert_system::shutdown_signal(),
+    )
+    .await;
+    let leader = sdk_b.status.leader().await.unwrap();
+    assert!(!leader.is_self);
+    assert_eq!(
+        leader.leader_address.as_deref(),
+        Some("http://covert-a:8080")
+    );
+    assert!(sdk_b.status.health().await.unwrap().standby);
+
+    sdk_b.operator.unseal(&unseal).await.unwrap();
+    sdk_b.set_token(Some(root_token)).await;
+    let resp = sdk_b.kv.read("kv/", "foo", None).await.unwrap();
+    assert_eq!(resp.data.unwrap()["value"], "bar");
+
+    let req = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://localhost:{port_b}/v1/kv/data/foo"))
+        .body(hyper::Body::from(r#"{"data":{"value":"baz"}}"#))
+        .unwrap();
+    let resp = hyper::Client::new().request(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        resp.headers()[LOCATION],
+        "http://covert-a:8080/v1/kv/data/foo"
+    );
+
+    // The standby takes over once the active server releases the lock
+    sdk_a.kv.create("kv/", "foo", &secret("baz")).await.unwrap();
+    shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let leader = sdk_b.status.leader().await.unwrap();
+    assert!(leader.is_self);
+    assert_eq!(
+        leader.leader_address.as_deref(),
+        Some("http://covert-b:8080")
+    );
+    assert!(leader.active_since.is_some());
+    assert!(!sdk_b.status.health().await.unwrap().standby);
+
+    // With the last changes of the previous active server
+    let resp = sdk_b.kv.read("kv/", "foo", None).await.unwrap();
+    assert_eq!(resp.data.unwrap()["value"], "baz");
+    sdk_b.kv.create("kv/", "foo", &secret("qux")).await.unwrap();
+    let resp = sdk_b.kv.read("kv/", "foo", None).await.unwrap();
+    assert_eq!(resp.data.unwrap()["value"], "qux");
+}
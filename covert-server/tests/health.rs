@@ -0,0 +1,107 @@
+use covert_sdk::{
+    operator::{InitializeParams, InitializeResponse, UnsealParams},
+    Client,
+};
+use hyper::{Method, StatusCode};
+use tokio::sync::oneshot;
+
+async fn setup() -> (Client, u16) {
+    let (port_tx, port_rx) = oneshot::channel();
+
+    let config = covert_system::Config {
+        port: 0,
+        port_tx: Some(port_tx),
+        storage_path: ":memory:".into(),
+        replication: None,
+        listener: covert_system::ListenerConfig::default(),
+        seal: None,
+        metrics: None,
+        standby: None,
+        ha: None,
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = covert_system::start(config, covert_system::shutdown_signal()).await {
+            panic!("server error: {}", err);
+        }
+    });
+
+    let port = port_rx.await.unwrap();
+    (Client::new(format!("http://localhost:{port}/v1")), port)
+}
+
+async fn health_status(port: u16, method: Method, query: &str) -> StatusCode {
+    let req = hyper::Request::builder()
+        .method(method)
+        .uri(format!("http://localhost:{port}/v1/sys/health{query}"))
+        .body(hyper::Body::empty())
+        .unwrap();
+    hyper::Client::new().request(req).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn health() {
+    let (sdk, port) = setup().await;
+
+    let health = sdk.status.health().await.unwrap();
+    assert!(!health.initialized);
+    assert!(health.sealed);
+    assert!(!health.standby);
+    assert_eq!(health.version, env!("CARGO_PKG_VERSION"));
+    assert!(!health.cluster_id.is_empty());
+    assert_eq!(
+        health_status(port, Method::GET, "").await,
+        StatusCode::NOT_IMPLEMENTED
+    );
+    assert_eq!(
+        health_status(port, Method::HEAD, "?uninitcode=200").await,
+        StatusCode::OK
+    );
+
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 1,
+            threshold: 1,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    assert_eq!(
+        health_status(port, Method::GET, "").await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert_eq!(
+        health_status(port, Method::GET, "?sealedcode=299")
+            .await
+            .as_u16(),
+        299
+    );
+
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares,
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    let unsealed_health = sdk.status.health().await.unwrap();
+    assert!(unsealed_health.initialized);
+    assert!(!unsealed_health.sealed);
+    assert_eq!(unsealed_health.cluster_id, health.cluster_id);
+    assert_eq!(health_status(port, Method::GET, "").await, StatusCode::OK);
+    assert_eq!(
+        health_status(port, Method::GET, "?activecode=204").await,
+        StatusCode::NO_CONTENT
+    );
+
+    // Malformed status codes are rejected
+    assert_eq!(
+        health_status(port, Method::GET, "?activecode=42").await,
+        StatusCode::BAD_REQUEST
+    );
+}
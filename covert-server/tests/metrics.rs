@@ -0,0 +1,199 @@
+mod common;
+
+use common::generate_root_token;
+use covert_sdk::{
+    operator::{InitializeParams, InitializeResponse, UnsealParams},
+    Client,
+};
+use covert_system::MetricsConfig;
+use hyper::{header::CONTENT_TYPE, Body, Request, StatusCode};
+use tokio::sync::oneshot;
+
+async fn setup(metrics: MetricsConfig) -> (Client, u16) {
+    let (port_tx, port_rx) = oneshot::channel();
+
+    let config = covert_system::Config {
+        port: 0,
+        port_tx: Some(port_tx),
+        storage_path: ":memory:".into(),
+        replication: None,
+        listener: covert_system::ListenerConfig::default(),
+        seal: None,
+        metrics: Some(metrics),
+        standby: None,
+        ha: None,
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = covert_system::start(config, covert_system::shutdown_signal()).await {
+            panic!("server error: {}", err);
+        }
+    });
+
+    let port = port_rx.await.unwrap();
+    (Client::new(format!("http://localhost:{port}/v1")), port)
+}
+
+async fn scrape(port: u16, token: Option<&str>) -> (StatusCode, String) {
+    let mut req = Request::get(format!("http://localhost:{port}/v1/sys/metrics"));
+    if let Some(token) = token {
+        req = req.header("X-Covert-Token", token);
+    }
+    let resp = hyper::Client::new()
+        .request(req.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = resp.status();
+    if status == StatusCode::OK {
+        assert!(resp.headers()[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+    }
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// Value of the sample with the given name that has all the given labels.
+fn sample(metrics: &str, name: &str, labels: &[&str]) -> Option<f64> {
+    metrics
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter(|line| {
+            line.starts_with(&format!("{name}{{")) || line.starts_with(&format!("{name} "))
+        })
+        .find(|line| labels.iter().all(|label| line.contains(label)))
+        .and_then(|line| line.rsplit(' ').next())
+        .map(|value| value.parse().unwrap())
+}
+
+#[tokio::test]
+async fn metrics() {
+    let (sdk, port) = setup(MetricsConfig::default()).await;
+
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 1,
+            threshold: 1,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    let root_token = generate_root_token(&sdk, key_shares.shares).await;
+
+    // A token is required on the main listener
+    let (status, _) = scrape(port, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, metrics) = scrape(port, Some(&root_token)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Requests are labelled with the mount that handled them
+    let labels = [
+        r#"mount_path="sys/""#,
+        r#"backend_type="system""#,
+        r#"operation="read""#,
+        r#"status="200""#,
+    ];
+    let requests = sample(&metrics, "covert_requests_total", &labels).unwrap();
+    sdk.set_token(Some(root_token.clone())).await;
+    sdk.mount.list().await.unwrap();
+    let (_, metrics) = scrape(port, Some(&root_token)).await;
+    // The mount list and the previous scrape
+    assert_eq!(
+        sample(&metrics, "covert_requests_total", &labels),
+        Some(requests + 2.0)
+    );
+    assert_eq!(
+        sample(&metrics, "covert_request_duration_seconds_count", &labels),
+        Some(requests + 2.0)
+    );
+    assert_eq!(
+        sample(&metrics, "covert_storage_state", &[r#"state="unsealed""#]),
+        Some(1.0)
+    );
+    assert!(sample(&metrics, "covert_leases_pending", &[]).is_some());
+    assert_eq!(
+        sample(&metrics, "covert_lease_revocation_worker_concurrency", &[]),
+        Some(100.0)
+    );
+    let auth_failures = sample(&metrics, "covert_auth_failures_total", &[]).unwrap();
+
+    // Requests with an unknown token are counted as auth failures
+    sdk.set_token(Some(format!("{root_token}0"))).await;
+    assert!(sdk.mount.list().await.is_err());
+    let (_, metrics) = scrape(port, Some(&root_token)).await;
+    assert_eq!(
+        sample(&metrics, "covert_auth_failures_total", &[]),
+        Some(auth_failures + 1.0)
+    );
+}
+
+#[tokio::test]
+async fn unauthenticated_metrics_access() {
+    let (_sdk, port) = setup(MetricsConfig {
+        unauthenticated_metrics_access: true,
+        ..Default::default()
+    })
+    .await;
+
+    let (status, metrics) = scrape(port, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        sample(
+            &metrics,
+            "covert_storage_state",
+            &[r#"state="uninitialized""#]
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        sample(&metrics, "covert_storage_state", &[r#"state="unsealed""#]),
+        Some(0.0)
+    );
+}
+
+#[tokio::test]
+async fn metrics_on_dedicated_listener() {
+    // Find a free port for the metrics listener
+    let metrics_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let (_sdk, port) = setup(MetricsConfig {
+        port: Some(metrics_port),
+        ..Default::default()
+    })
+    .await;
+
+    let (status, metrics) = scrape(metrics_port, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(metrics.contains("covert_storage_state"));
+
+    // Not served on the main listener
+    let (status, _) = scrape(port, None).await;
+    assert_ne!(status, StatusCode::OK);
+
+    // And nothing else is served on the metrics listener
+    let resp = hyper::Client::new()
+        .get(
+            format!("http://localhost:{metrics_port}/v1/sys/health")
+                .parse()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
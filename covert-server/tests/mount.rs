@@ -2,7 +2,7 @@ mod common;
 
 use std::time::Duration;
 
-use common::{setup, setup_unseal};
+use common::{generate_root_token, setup, setup_unseal};
 use covert_sdk::{
     mounts::{BackendType, CreateMountParams, MountConfig, UpdateMountParams},
     operator::{InitializeParams, InitializeResponse, UnsealParams, UnsealResponse},
@@ -13,10 +13,10 @@ use covert_types::state::StorageState;
 async fn mount() {
     let sdk = setup_unseal().await;
 
-    // Initial mounts
+    // Initial mounts, only the cubbyhole is mounted by default
     let mounts = sdk.mount.list().await.unwrap();
     assert_eq!(mounts.auth.len(), 0);
-    assert_eq!(mounts.secret.len(), 0);
+    assert_eq!(mounts.secret.len(), 1);
 
     // Mount kv secret engine
     let mut mount_config = MountConfig {
@@ -36,9 +36,9 @@ async fn mount() {
 
     let mounts = sdk.mount.list().await.unwrap();
     assert_eq!(mounts.auth.len(), 0);
-    assert_eq!(mounts.secret.len(), 1);
-    assert_eq!(mounts.secret[0].variant, BackendType::Kv);
-    assert_eq!(mounts.secret[0].config, mount_config);
+    assert_eq!(mounts.secret.len(), 2);
+    assert_eq!(mounts.secret[1].variant, BackendType::Kv);
+    assert_eq!(mounts.secret[1].config, mount_config);
 
     // Mount again under conflicting path returns error
     assert!(sdk
@@ -66,15 +66,15 @@ async fn mount() {
         .unwrap();
     let mounts = sdk.mount.list().await.unwrap();
     assert_eq!(mounts.auth.len(), 0);
-    assert_eq!(mounts.secret.len(), 1);
-    assert_eq!(mounts.secret[0].variant, BackendType::Kv);
-    assert_eq!(mounts.secret[0].config, mount_config);
+    assert_eq!(mounts.secret.len(), 2);
+    assert_eq!(mounts.secret[1].variant, BackendType::Kv);
+    assert_eq!(mounts.secret[1].config, mount_config);
 
     // Disable mount
     sdk.mount.remove("kv/").await.unwrap();
     let mounts = sdk.mount.list().await.unwrap();
     assert_eq!(mounts.auth.len(), 0);
-    assert_eq!(mounts.secret.len(), 0);
+    assert_eq!(mounts.secret.len(), 1);
 }
 
 #[tokio::test]
@@ -87,6 +87,7 @@ async fn recover_mounts_after_seal() {
         .initialize(&InitializeParams {
             shares: 1,
             threshold: 1,
+            pgp_keys: None,
         })
         .await
         .unwrap();
@@ -99,12 +100,14 @@ async fn recover_mounts_after_seal() {
         .operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .unwrap();
-    if let UnsealResponse::Complete { root_token } = resp {
-        sdk.set_token(Some(root_token.to_string())).await;
-    }
+    assert!(matches!(resp, UnsealResponse::Complete));
+    let root_token = generate_root_token(&sdk, key_shares.shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
 
     // Mount kv secret engine
     sdk.mount
@@ -140,7 +143,7 @@ async fn recover_mounts_after_seal() {
 
     let mounts = sdk.mount.list().await.unwrap();
     assert_eq!(mounts.auth.len(), 0);
-    assert_eq!(mounts.secret.len(), 3);
+    assert_eq!(mounts.secret.len(), 4);
 
     // Seal
     sdk.operator.seal().await.unwrap();
@@ -154,6 +157,8 @@ async fn recover_mounts_after_seal() {
     sdk.operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .unwrap();
@@ -161,5 +166,5 @@ async fn recover_mounts_after_seal() {
     assert_eq!(resp, Ok(StorageState::Unsealed));
     let mounts = sdk.mount.list().await.unwrap();
     assert_eq!(mounts.auth.len(), 0);
-    assert_eq!(mounts.secret.len(), 3);
+    assert_eq!(mounts.secret.len(), 4);
 }
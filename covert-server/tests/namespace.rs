@@ -101,9 +101,10 @@ async fn namespace_isolation() {
             .await
             .unwrap();
 
+        // The cubbyhole is mounted in every namespace
         let mounts = sdk.mount.list().await.unwrap();
         assert_eq!(mounts.auth.len(), 0);
-        assert_eq!(mounts.secret.len(), 1);
+        assert_eq!(mounts.secret.len(), 2);
 
         // Create auth method
         sdk.mount
@@ -119,7 +120,7 @@ async fn namespace_isolation() {
 
         let mounts = sdk.mount.list().await.unwrap();
         assert_eq!(mounts.auth.len(), 1);
-        assert_eq!(mounts.secret.len(), 1);
+        assert_eq!(mounts.secret.len(), 2);
     }
 }
 
@@ -202,7 +203,7 @@ async fn access_to_parent_ns_is_denied() {
         .create(&CreatePolicyParams {
             name: policy_name.clone(),
             policy: r#"path "*" { 
-                capabilities = ["read","update","create","delete] 
+                capabilities = ["read","update","create","delete","sudo"] 
             }"#
             .to_string(),
         })
@@ -290,7 +291,7 @@ async fn access_to_parent_ns_is_denied() {
         .await
         .unwrap();
     let mounts = sdk.mount.list().await.unwrap();
-    assert_eq!(mounts.secret[0].path, "psql/");
+    assert_eq!(mounts.secret[1].path, "psql/");
     assert_eq!(mounts.auth[0].path, "auth/userpass/");
 
     // Try to mount a secret engine in the root namespace should not work
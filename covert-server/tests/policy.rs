@@ -1,7 +1,10 @@
 mod common;
 
 use covert_sdk::policy::CreatePolicyParams;
-use covert_types::policy::{PathPolicy, Policy};
+use covert_types::{
+    policy::{PathPolicy, Policy},
+    request::Operation,
+};
 
 use common::setup_unseal;
 
@@ -35,3 +38,35 @@ async fn policy() {
     assert_eq!(created_policy.name, policy.name);
     assert_eq!(created_policy.paths, policy.paths);
 }
+
+#[tokio::test]
+async fn json_and_malformed_policies() {
+    let sdk = setup_unseal().await;
+
+    let created_policy = sdk
+        .policy
+        .create(&CreatePolicyParams {
+            name: "json".to_string(),
+            policy: r#"{ "path": { "kv/+/config": { "capabilities": ["read", "deny"] } } }"#
+                .to_string(),
+        })
+        .await
+        .unwrap()
+        .policy;
+    let mut expected = PathPolicy::new("kv/+/config".to_string(), vec![Operation::Read]);
+    expected.deny = true;
+    assert_eq!(created_policy.paths, vec![expected]);
+
+    let err = sdk
+        .policy
+        .create(&CreatePolicyParams {
+            name: "broken".to_string(),
+            policy: "path \"kv/*\" {\n  capabilities = [\"read\" \"update\"]\n}".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert!(
+        err.contains("line 2, column 26"),
+        "error should point at the missing comma, got: {err}"
+    );
+}
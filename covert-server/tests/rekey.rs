@@ -0,0 +1,271 @@
+mod common;
+
+use common::{generate_root_token, setup};
+use covert_sdk::{
+    operator::{
+        InitializeParams, InitializeResponse, RekeyParams, RekeyUpdateParams, RekeyUpdateResponse,
+        RekeyVerifyResponse, UnsealParams, UnsealResponse,
+    },
+    Client,
+};
+use covert_types::state::StorageState;
+
+async fn setup_unsealed(shares: u8, threshold: u8) -> (Client, Vec<String>) {
+    let sdk = setup(":memory:", covert_system::shutdown_signal(), None).await;
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+
+    (sdk, key_shares.shares)
+}
+
+async fn seal_and_unseal(sdk: &Client, shares: Vec<String>) -> Result<UnsealResponse, String> {
+    sdk.operator.seal().await.unwrap();
+    assert_eq!(
+        sdk.status.status().await.map(|resp| resp.state),
+        Ok(StorageState::Sealed)
+    );
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares,
+            nonce: None,
+            reset: false,
+        })
+        .await
+}
+
+#[tokio::test]
+async fn rekey() {
+    let (sdk, old_shares) = setup_unsealed(3, 2).await;
+
+    // Rekey requires a root token
+    let params = RekeyParams {
+        shares: 5,
+        threshold: 3,
+        require_verification: false,
+    };
+    assert!(sdk.operator.rekey_init(&params).await.is_err());
+    let root_token = generate_root_token(&sdk, old_shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
+
+    let attempt = sdk.operator.rekey_init(&params).await.unwrap();
+    assert!(attempt.started);
+    assert_eq!(attempt.required, 2);
+    assert_eq!(attempt.shares, Some(5));
+    assert_eq!(attempt.threshold, Some(3));
+    let nonce = attempt.nonce.unwrap();
+
+    // Only one attempt at a time
+    assert!(sdk.operator.rekey_init(&params).await.is_err());
+
+    // Nonce has to match
+    assert!(sdk
+        .operator
+        .rekey_update(&RekeyUpdateParams {
+            nonce: "wrong".into(),
+            shares: vec![old_shares[0].clone()],
+        })
+        .await
+        .is_err());
+
+    let resp = sdk
+        .operator
+        .rekey_update(&RekeyUpdateParams {
+            nonce: nonce.clone(),
+            shares: vec![old_shares[0].clone()],
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        resp,
+        RekeyUpdateResponse::InProgress {
+            progress: 1,
+            required: 2,
+            ..
+        }
+    ));
+    let resp = sdk
+        .operator
+        .rekey_update(&RekeyUpdateParams {
+            nonce,
+            shares: vec![old_shares[1].clone()],
+        })
+        .await
+        .unwrap();
+    let RekeyUpdateResponse::Complete {
+        shares: new_shares,
+        verification_nonce: None,
+    } = resp
+    else {
+        panic!("should complete rekey");
+    };
+    assert_eq!(new_shares.len(), 5);
+    assert!(!sdk.operator.rekey_status().await.unwrap().started);
+
+    // The old key shares are no longer able to unseal
+    assert!(seal_and_unseal(&sdk, old_shares).await.is_err());
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: new_shares[..2].to_vec(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        resp,
+        UnsealResponse::InProgress {
+            threshold: 3,
+            key_shares_total: 5,
+            key_shares_provided: 2,
+            ..
+        }
+    ));
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: vec![new_shares[4].clone()],
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+}
+
+#[tokio::test]
+async fn rekey_with_verification() {
+    let (sdk, old_shares) = setup_unsealed(1, 1).await;
+    let root_token = generate_root_token(&sdk, old_shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
+
+    let attempt = sdk
+        .operator
+        .rekey_init(&RekeyParams {
+            shares: 3,
+            threshold: 2,
+            require_verification: true,
+        })
+        .await
+        .unwrap();
+    let resp = sdk
+        .operator
+        .rekey_update(&RekeyUpdateParams {
+            nonce: attempt.nonce.unwrap(),
+            shares: old_shares.clone(),
+        })
+        .await
+        .unwrap();
+    let RekeyUpdateResponse::Complete {
+        shares: new_shares,
+        verification_nonce: Some(verification_nonce),
+    } = resp
+    else {
+        panic!("should deal new shares awaiting verification");
+    };
+    let status = sdk.operator.rekey_status().await.unwrap();
+    assert_eq!(
+        status.verification_nonce.as_ref(),
+        Some(&verification_nonce)
+    );
+    assert_eq!(status.required, 2);
+
+    // Verification nonce has to match
+    assert!(sdk
+        .operator
+        .rekey_verify(&RekeyUpdateParams {
+            nonce: "wrong".into(),
+            shares: vec![new_shares[0].clone()],
+        })
+        .await
+        .is_err());
+
+    let resp = sdk
+        .operator
+        .rekey_verify(&RekeyUpdateParams {
+            nonce: verification_nonce.clone(),
+            shares: vec![new_shares[0].clone()],
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        resp,
+        RekeyVerifyResponse::InProgress {
+            progress: 1,
+            required: 2,
+            ..
+        }
+    ));
+    let resp = sdk
+        .operator
+        .rekey_verify(&RekeyUpdateParams {
+            nonce: verification_nonce,
+            shares: vec![new_shares[2].clone()],
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, RekeyVerifyResponse::Complete));
+    assert!(!sdk.operator.rekey_status().await.unwrap().started);
+
+    let resp = seal_and_unseal(&sdk, new_shares[1..].to_vec())
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+}
+
+#[tokio::test]
+async fn cancel_rekey_awaiting_verification() {
+    let (sdk, old_shares) = setup_unsealed(1, 1).await;
+    let root_token = generate_root_token(&sdk, old_shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
+
+    let attempt = sdk
+        .operator
+        .rekey_init(&RekeyParams {
+            shares: 1,
+            threshold: 1,
+            require_verification: true,
+        })
+        .await
+        .unwrap();
+    let resp = sdk
+        .operator
+        .rekey_update(&RekeyUpdateParams {
+            nonce: attempt.nonce.unwrap(),
+            shares: old_shares.clone(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        resp,
+        RekeyUpdateResponse::Complete {
+            verification_nonce: Some(_),
+            ..
+        }
+    ));
+    assert!(!sdk.operator.rekey_cancel().await.unwrap().started);
+
+    // The old key shares are kept until the new key shares are verified
+    let resp = seal_and_unseal(&sdk, old_shares).await.unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+}
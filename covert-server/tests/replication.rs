@@ -1,36 +1,41 @@
 mod common;
 
-use std::time::Duration;
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
 use covert_sdk::{
     entity::CreateEntityParams,
     operator::{InitializeParams, InitializeResponse, UnsealParams, UnsealResponse},
 };
-use covert_system::ReplicationConfig;
+use covert_system::{
+    DirectoryReplicaConfig, ReplicaTargetConfig, ReplicationConfig, S3ReplicaConfig,
+};
 use covert_types::{methods::system::EntityWithPolicyAndAlias, state::StorageState};
 use rand::{distributions::Alphanumeric, Rng};
 use tokio::sync::oneshot;
 
-use common::setup;
+use common::{generate_root_token, setup};
+
+fn directory_replication(path: &Path) -> ReplicationConfig {
+    ReplicationConfig {
+        target: ReplicaTargetConfig::Directory(DirectoryReplicaConfig {
+            path: path.to_str().unwrap().to_string(),
+        }),
+        sync_interval: Duration::from_millis(100),
+    }
+}
 
 #[tokio::test]
-#[cfg_attr(not(feature = "replication-integration-test"), ignore)]
 async fn unseal_with_recovered_seal_config_from_local_storage() {
     let tmpdir_storage_path = tempfile::tempdir().unwrap();
     let storage_path = tmpdir_storage_path.path().to_str().unwrap().to_string();
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
     let shutdown_rx_fut = async { shutdown_rx.await.unwrap() };
 
-    let random_bucket_key: String = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(10)
-        .map(char::from)
-        .collect();
-    let replication = ReplicationConfig {
-        access_key_id: "minioadmin".to_string(),
-        secret_access_key: "minioadmin".to_string(),
-        bucket_url: format!("s3://mybkt.localhost:9000/{random_bucket_key}/"),
-    };
+    let replica = tempfile::tempdir().unwrap();
+    let replication = directory_replication(replica.path());
 
     let sdk = setup(&storage_path, shutdown_rx_fut, Some(replication.clone())).await;
 
@@ -43,7 +48,11 @@ async fn unseal_with_recovered_seal_config_from_local_storage() {
     let threshold = 3;
     let resp = sdk
         .operator
-        .initialize(&InitializeParams { shares, threshold })
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
         .await
         .unwrap();
     let InitializeResponse::NewKeyShares(key_shares) = resp else {
@@ -52,25 +61,26 @@ async fn unseal_with_recovered_seal_config_from_local_storage() {
     assert_eq!(key_shares.shares.len(), usize::from(shares));
 
     // unseal
-    let root_token = match sdk
+    let resp = sdk
         .operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
-        .unwrap()
-    {
-        UnsealResponse::Complete { root_token } => root_token,
-        _ => panic!("unexpected unseal response"),
-    };
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    let root_token = generate_root_token(&sdk, key_shares.shares.clone()).await;
     sdk.set_token(Some(root_token.to_string())).await;
 
     // And state should now be unseal
     let resp = sdk.status.status().await.map(|resp| resp.state);
     assert_eq!(resp, Ok(StorageState::Unsealed));
 
-    // Shutdown
+    // Shutdown, the last changes are shipped before the server stops
     shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
 
     // It is down
     assert!(sdk.status.status().await.is_err());
@@ -98,6 +108,8 @@ async fn unseal_with_recovered_seal_config_from_local_storage() {
                     "bad key 2".to_string(),
                     "bad key 3".to_string()
                 ],
+                nonce: None,
+                reset: false
             })
             .await
             .is_err());
@@ -106,6 +118,8 @@ async fn unseal_with_recovered_seal_config_from_local_storage() {
         sdk.operator
             .unseal(&UnsealParams {
                 shares: key_shares.shares.clone(),
+                nonce: None,
+                reset: false,
             })
             .await
             .unwrap();
@@ -114,30 +128,21 @@ async fn unseal_with_recovered_seal_config_from_local_storage() {
         let resp = sdk.status.status().await.map(|resp| resp.state);
         assert_eq!(resp, Ok(StorageState::Unsealed));
 
-        // Just to clean up all replication subprocesses
+        // Ship the last changes and stop replication
         shutdown_tx.send(()).expect("shutdown cleanly");
         tokio::time::sleep(Duration::from_secs(1)).await;
     }
 }
 
 #[tokio::test]
-#[cfg_attr(not(feature = "replication-integration-test"), ignore)]
 async fn unseal_with_recovered_seal_config_from_remote_backup() {
     let tmpdir_storage_path = tempfile::tempdir().unwrap();
     let storage_path = tmpdir_storage_path.path().to_str().unwrap().to_string();
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
     let shutdown_rx_fut = async { shutdown_rx.await.unwrap() };
 
-    let random_bucket_key: String = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(10)
-        .map(char::from)
-        .collect();
-    let replication = ReplicationConfig {
-        access_key_id: "minioadmin".to_string(),
-        secret_access_key: "minioadmin".to_string(),
-        bucket_url: format!("s3://mybkt.localhost:9000/{random_bucket_key}/"),
-    };
+    let replica = tempfile::tempdir().unwrap();
+    let replication = directory_replication(replica.path());
 
     let sdk = setup(&storage_path, shutdown_rx_fut, Some(replication.clone())).await;
 
@@ -150,7 +155,11 @@ async fn unseal_with_recovered_seal_config_from_remote_backup() {
     let threshold = 3;
     let resp = sdk
         .operator
-        .initialize(&InitializeParams { shares, threshold })
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
         .await
         .unwrap();
     let InitializeResponse::NewKeyShares(key_shares) = resp else {
@@ -159,17 +168,17 @@ async fn unseal_with_recovered_seal_config_from_remote_backup() {
     assert_eq!(key_shares.shares.len(), usize::from(shares));
 
     // unseal
-    let root_token = match sdk
+    let resp = sdk
         .operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
-        .unwrap()
-    {
-        UnsealResponse::Complete { root_token } => root_token,
-        _ => panic!("unexpected unseal response"),
-    };
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    let root_token = generate_root_token(&sdk, key_shares.shares.clone()).await;
     sdk.set_token(Some(root_token.to_string())).await;
 
     // Wait for replication
@@ -179,8 +188,9 @@ async fn unseal_with_recovered_seal_config_from_remote_backup() {
     let resp = sdk.status.status().await.map(|resp| resp.state);
     assert_eq!(resp, Ok(StorageState::Unsealed));
 
-    // Shutdown
+    // Shutdown, the last changes are shipped before the server stops
     shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
 
     // Delete local storage
     std::fs::remove_dir_all(tmpdir_storage_path.path()).unwrap();
@@ -211,6 +221,8 @@ async fn unseal_with_recovered_seal_config_from_remote_backup() {
                     "bad key 2".to_string(),
                     "bad key 3".to_string()
                 ],
+                nonce: None,
+                reset: false
             })
             .await
             .is_err());
@@ -219,6 +231,8 @@ async fn unseal_with_recovered_seal_config_from_remote_backup() {
         sdk.operator
             .unseal(&UnsealParams {
                 shares: key_shares.shares.clone(),
+                nonce: None,
+                reset: false,
             })
             .await
             .unwrap();
@@ -227,8 +241,9 @@ async fn unseal_with_recovered_seal_config_from_remote_backup() {
         let resp = sdk.status.status().await.map(|resp| resp.state);
         assert_eq!(resp, Ok(StorageState::Unsealed));
 
-        // Just to clean up all replication subprocesses
+        // Ship the last changes and stop replication
         shutdown_tx.send(()).expect("shutdown cleanly");
+        tokio::time::sleep(Duration::from_millis(500)).await;
 
         // Delete local storage
         std::fs::remove_dir_all(tmpdir_storage_path.path()).unwrap();
@@ -239,23 +254,14 @@ async fn unseal_with_recovered_seal_config_from_remote_backup() {
 }
 
 #[tokio::test]
-#[cfg_attr(not(feature = "replication-integration-test"), ignore)]
 async fn recover_encrypted_db_from_backup() {
     let tmpdir_storage_path = tempfile::tempdir().unwrap();
     let storage_path = tmpdir_storage_path.path().to_str().unwrap().to_string();
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
     let shutdown_rx_fut = async { shutdown_rx.await.unwrap() };
 
-    let random_bucket_key: String = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(10)
-        .map(char::from)
-        .collect();
-    let replication = ReplicationConfig {
-        access_key_id: "minioadmin".to_string(),
-        secret_access_key: "minioadmin".to_string(),
-        bucket_url: format!("s3://mybkt.localhost:9000/{random_bucket_key}/"),
-    };
+    let replica = tempfile::tempdir().unwrap();
+    let replication = directory_replication(replica.path());
 
     let sdk = setup(&storage_path, shutdown_rx_fut, Some(replication.clone())).await;
 
@@ -268,7 +274,11 @@ async fn recover_encrypted_db_from_backup() {
     let threshold = 3;
     let resp = sdk
         .operator
-        .initialize(&InitializeParams { shares, threshold })
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
         .await
         .unwrap();
     let InitializeResponse::NewKeyShares(key_shares) = resp else {
@@ -277,17 +287,17 @@ async fn recover_encrypted_db_from_backup() {
     assert_eq!(key_shares.shares.len(), usize::from(shares));
 
     // unseal
-    let root_token = match sdk
+    let resp = sdk
         .operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
-        .unwrap()
-    {
-        UnsealResponse::Complete { root_token } => root_token,
-        _ => panic!("unexpected unseal response"),
-    };
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    let root_token = generate_root_token(&sdk, key_shares.shares.clone()).await;
     sdk.set_token(Some(root_token.to_string())).await;
 
     // And state should now be unseal
@@ -311,8 +321,9 @@ async fn recover_encrypted_db_from_backup() {
     // Wait 2 sec until replication should have picked up
     tokio::time::sleep(Duration::from_secs(2)).await;
 
-    // Shutdown
+    // Shutdown, the last changes are shipped before the server stops
     shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
 
     // Delete local storage
     std::fs::remove_dir_all(tmpdir_storage_path.path()).unwrap();
@@ -337,6 +348,8 @@ async fn recover_encrypted_db_from_backup() {
     sdk.operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .unwrap();
@@ -380,8 +393,9 @@ async fn recover_encrypted_db_from_backup() {
     // Wait 2 sec until replication should have picked up
     tokio::time::sleep(Duration::from_secs(2)).await;
 
-    // Shutdown
+    // Shutdown, the last changes are shipped before the server stops
     shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
 
     // Delete local storage
     std::fs::remove_dir_all(tmpdir_storage_path.path()).unwrap();
@@ -402,6 +416,8 @@ async fn recover_encrypted_db_from_backup() {
     sdk.operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .unwrap();
@@ -445,6 +461,7 @@ async fn recover_encrypted_db_from_backup() {
 
     // Shutdown and don't wait for replication and don't delete local storage
     shutdown_tx.send(()).expect("shutdown cleanly");
+    tokio::time::sleep(Duration::from_millis(500)).await;
 
     // It is down
     assert!(sdk.status.status().await.is_err());
@@ -461,6 +478,8 @@ async fn recover_encrypted_db_from_backup() {
     sdk.operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .unwrap();
@@ -498,7 +517,183 @@ async fn recover_encrypted_db_from_backup() {
         ]
     );
 
-    // Just to clean up all replication subprocesses
+    // Ship the last changes and stop replication
     shutdown_tx.send(()).expect("shutdown cleanly");
     tokio::time::sleep(Duration::from_secs(2)).await;
 }
+
+/// Initialize and unseal, returning the key shares and a root token.
+async fn initialize_and_unseal(sdk: &covert_sdk::Client) -> (Vec<String>, String) {
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 1,
+            threshold: 1,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("Unexpected init response");
+    };
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    let root_token = generate_root_token(sdk, key_shares.shares.clone()).await;
+    sdk.set_token(Some(root_token.clone())).await;
+    (key_shares.shares, root_token)
+}
+
+async fn entity_names(sdk: &covert_sdk::Client) -> Vec<String> {
+    sdk.entity
+        .list()
+        .await
+        .unwrap()
+        .entities
+        .into_iter()
+        .map(|entity| entity.name)
+        .collect()
+}
+
+#[tokio::test]
+async fn restore_to_point_in_time() {
+    let tmpdir_storage_path = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir_storage_path.path().to_str().unwrap().to_string();
+    let replica = tempfile::tempdir().unwrap();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let shutdown_rx_fut = async { shutdown_rx.await.unwrap() };
+
+    let sdk = setup(
+        &storage_path,
+        shutdown_rx_fut,
+        Some(directory_replication(replica.path())),
+    )
+    .await;
+    let (shares, root_token) = initialize_and_unseal(&sdk).await;
+
+    sdk.entity
+        .create(&CreateEntityParams {
+            name: "john".to_string(),
+        })
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let restore_point = SystemTime::now();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    sdk.entity
+        .create(&CreateEntityParams {
+            name: "james".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(entity_names(&sdk).await, vec!["james", "john", "root"]);
+
+    // Shutdown and delete local storage
+    shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    std::fs::remove_dir_all(tmpdir_storage_path.path()).unwrap();
+
+    let config = covert_system::Config {
+        port: 0,
+        port_tx: None,
+        storage_path: storage_path.clone(),
+        replication: Some(directory_replication(replica.path())),
+        listener: covert_system::ListenerConfig::default(),
+        seal: None,
+        metrics: None,
+        standby: None,
+        ha: None,
+    };
+    covert_system::restore_storage(&config, Some(restore_point))
+        .await
+        .unwrap();
+    // Existing storage is never overwritten
+    assert!(covert_system::restore_storage(&config, Some(restore_point))
+        .await
+        .is_err());
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let shutdown_rx_fut = async { shutdown_rx.await.unwrap() };
+    let sdk = setup(
+        &storage_path,
+        shutdown_rx_fut,
+        Some(directory_replication(replica.path())),
+    )
+    .await;
+    sdk.set_token(Some(root_token)).await;
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares,
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+
+    // Changes after the restore point are not restored
+    assert_eq!(entity_names(&sdk).await, vec!["john", "root"]);
+
+    shutdown_tx.send(()).expect("shutdown cleanly");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "replication-integration-test"), ignore)]
+async fn recover_from_s3_replica() {
+    let tmpdir_storage_path = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir_storage_path.path().to_str().unwrap().to_string();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let shutdown_rx_fut = async { shutdown_rx.await.unwrap() };
+
+    let random_bucket_key: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(char::from)
+        .collect();
+    let replication = ReplicationConfig {
+        target: ReplicaTargetConfig::S3(S3ReplicaConfig {
+            access_key_id: "minioadmin".to_string(),
+            secret_access_key: "minioadmin".to_string(),
+            bucket_url: format!("s3://mybkt/{random_bucket_key}/"),
+            region: "us-east-1".to_string(),
+            endpoint: Some("http://localhost:9000".to_string()),
+        }),
+        sync_interval: Duration::from_millis(100),
+    };
+
+    let sdk = setup(&storage_path, shutdown_rx_fut, Some(replication.clone())).await;
+    let (shares, root_token) = initialize_and_unseal(&sdk).await;
+    sdk.entity
+        .create(&CreateEntityParams {
+            name: "john".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // Shutdown and delete local storage
+    shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    std::fs::remove_dir_all(tmpdir_storage_path.path()).unwrap();
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let shutdown_rx_fut = async { shutdown_rx.await.unwrap() };
+    let sdk = setup(&storage_path, shutdown_rx_fut, Some(replication)).await;
+    sdk.set_token(Some(root_token)).await;
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares,
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert_eq!(entity_names(&sdk).await, vec!["john", "root"]);
+
+    shutdown_tx.send(()).expect("shutdown cleanly");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use common::{generate_root_token, setup};
+use covert_sdk::{
+    operator::{InitializeParams, InitializeResponse, RotateParams, UnsealParams, UnsealResponse},
+    policy::CreatePolicyParams,
+};
+
+#[tokio::test]
+async fn rotate_master_key() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir.path().to_str().unwrap();
+    let sdk = setup(storage_path, covert_system::shutdown_signal(), None).await;
+
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 2,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    let old_shares = key_shares.shares;
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares: old_shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+
+    // Key status requires a token
+    assert!(sdk.operator.key_status().await.is_err());
+    let root_token = generate_root_token(&sdk, old_shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
+
+    let status = sdk.operator.key_status().await.unwrap();
+    assert_eq!(status.generation, 1);
+    assert!(status.created_at.is_some());
+
+    sdk.policy
+        .create(&CreatePolicyParams {
+            name: "foo".into(),
+            policy: r#"path "sys/*" { capabilities = ["read"] }"#.into(),
+        })
+        .await
+        .unwrap();
+
+    // A threshold of valid key shares is required
+    assert!(sdk
+        .operator
+        .rotate(&RotateParams {
+            shares: vec![old_shares[0].clone()],
+        })
+        .await
+        .is_err());
+
+    let resp = sdk
+        .operator
+        .rotate(&RotateParams {
+            shares: old_shares[1..].to_vec(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(resp.generation, 2);
+    assert_eq!(resp.shares.len(), 3);
+    let new_shares = resp.shares;
+    assert_eq!(sdk.operator.key_status().await.unwrap().generation, 2);
+
+    // Storage is still available after the rotation
+    let policies = sdk.policy.list().await.unwrap().policies;
+    assert!(policies.iter().any(|policy| policy.name == "foo"));
+
+    // Only the new key shares are able to unseal
+    sdk.operator.seal().await.unwrap();
+    assert!(sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: old_shares[..2].to_vec(),
+            nonce: None,
+            reset: false
+        })
+        .await
+        .is_err());
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: new_shares[..2].to_vec(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+
+    let policies = sdk.policy.list().await.unwrap().policies;
+    assert!(policies.iter().any(|policy| policy.name == "foo"));
+}
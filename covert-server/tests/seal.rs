@@ -1,11 +1,61 @@
 mod common;
 
-use covert_sdk::operator::{InitializeParams, InitializeResponse, UnsealParams, UnsealResponse};
+use covert_sdk::{
+    operator::{InitializeParams, InitializeResponse, UnsealParams, UnsealResponse},
+    Client,
+};
 use covert_types::state::StorageState;
 
-use common::setup;
+use common::{generate_root_token, setup};
+use pgp::{
+    composed::{
+        Deserializable, KeyType, Message, SecretKeyParamsBuilder, SignedSecretKey,
+        SubkeyParamsBuilder,
+    },
+    types::SecretKeyTrait,
+};
 use tokio::sync::oneshot;
 
+/// Generate a PGP key pair. Returns the secret key and the ASCII armored
+/// public key.
+fn generate_pgp_key() -> (SignedSecretKey, String) {
+    let params = SecretKeyParamsBuilder::default()
+        .key_type(KeyType::EdDSA)
+        .can_sign(true)
+        .can_create_certificates(true)
+        .primary_user_id("covert <covert@example.com>".into())
+        .subkey(
+            SubkeyParamsBuilder::default()
+                .key_type(KeyType::ECDH)
+                .can_encrypt(true)
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+    let secret_key = params.generate().unwrap().sign(String::new).unwrap();
+    let public_key = secret_key
+        .public_key()
+        .sign(&secret_key, String::new)
+        .unwrap()
+        .to_armored_string(None)
+        .unwrap();
+    (secret_key, public_key)
+}
+
+fn pgp_decrypt(secret_key: &SignedSecretKey, armored: &str) -> String {
+    let (message, _) = Message::from_string(armored).unwrap();
+    let (mut decrypter, _) = message.decrypt(String::new, &[secret_key]).unwrap();
+    let content = decrypter
+        .next()
+        .unwrap()
+        .unwrap()
+        .get_content()
+        .unwrap()
+        .unwrap();
+    String::from_utf8(content).unwrap()
+}
+
 #[tokio::test]
 async fn seal() {
     let sdk = setup(":memory:", covert_system::shutdown_signal(), None).await;
@@ -19,7 +69,11 @@ async fn seal() {
     let threshold = 3;
     let resp = sdk
         .operator
-        .initialize(&InitializeParams { shares, threshold })
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
         .await
         .unwrap();
     let InitializeResponse::NewKeyShares(key_shares) = resp else {
@@ -34,7 +88,11 @@ async fn seal() {
     // Init again fails
     assert!(sdk
         .operator
-        .initialize(&InitializeParams { shares, threshold })
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
         .await
         .is_err());
 
@@ -44,19 +102,22 @@ async fn seal() {
             .operator
             .unseal(&UnsealParams {
                 shares: vec![key_shares.shares[i].clone()],
+                nonce: None,
+                reset: false,
             })
             .await
             .unwrap();
 
         if u8::try_from(i).unwrap() == threshold - 1 {
-            assert!(matches!(resp, UnsealResponse::Complete { .. }));
+            assert!(matches!(resp, UnsealResponse::Complete));
         } else {
             assert!(matches!(
                 resp,
                 UnsealResponse::InProgress {
                     threshold: returned_threshold,
                     key_shares_provided,
-                    key_shares_total
+                    key_shares_total,
+                    ..
                 } if threshold == returned_threshold && key_shares_provided == i+1 && key_shares_total == shares
             ));
 
@@ -75,11 +136,21 @@ async fn seal() {
         .operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .is_err());
 
+    // Seal requires a token with sudo
+    assert!(sdk.operator.seal().await.is_err());
+    let resp = sdk.status.status().await.map(|resp| resp.state);
+    assert_eq!(resp, Ok(StorageState::Unsealed));
+
     // Seal again
+    let root_token =
+        generate_root_token(&sdk, key_shares.shares[..usize::from(threshold)].to_vec()).await;
+    sdk.set_token(Some(root_token)).await;
     assert!(sdk.operator.seal().await.is_ok());
     let resp = sdk.status.status().await.map(|resp| resp.state);
     assert_eq!(resp, Ok(StorageState::Sealed));
@@ -90,6 +161,8 @@ async fn seal() {
             .operator
             .unseal(&UnsealParams {
                 shares: vec![key_shares.shares[0].clone()],
+                nonce: None,
+                reset: false,
             })
             .await
             .unwrap();
@@ -99,7 +172,8 @@ async fn seal() {
             UnsealResponse::InProgress {
                 threshold: returned_threshold,
                 key_shares_provided,
-                key_shares_total
+                key_shares_total,
+                ..
             } if threshold == returned_threshold && key_shares_provided == 1 && key_shares_total == shares
         ));
 
@@ -108,11 +182,13 @@ async fn seal() {
         assert_eq!(resp, Ok(StorageState::Sealed));
     }
 
-    // Key shares are cleared when threshold is reached and it was unable to unseal
+    // Malformed key shares are rejected
     assert!(sdk
         .operator
         .unseal(&UnsealParams {
             shares: vec!["Bad key 1".to_string(), "Bad key 2".to_string()],
+            nonce: None,
+            reset: false,
         })
         .await
         .is_err());
@@ -127,19 +203,22 @@ async fn seal() {
             .operator
             .unseal(&UnsealParams {
                 shares: vec![key_shares.shares[i].clone()],
+                nonce: None,
+                reset: false,
             })
             .await
             .unwrap();
 
         if u8::try_from(i).unwrap() == threshold - 1 {
-            assert!(matches!(resp, UnsealResponse::Complete { .. }));
+            assert!(matches!(resp, UnsealResponse::Complete));
         } else {
             assert!(matches!(
                 resp,
                 UnsealResponse::InProgress {
                     threshold: returned_threshold,
                     key_shares_provided,
-                    key_shares_total
+                    key_shares_total,
+                    ..
                 } if threshold == returned_threshold && key_shares_provided == i+1 && key_shares_total == shares
             ));
 
@@ -154,6 +233,194 @@ async fn seal() {
     assert_eq!(resp, Ok(StorageState::Unsealed));
 }
 
+async fn initialize(sdk: &Client) -> Vec<String> {
+    let resp = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 3,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap();
+    let InitializeResponse::NewKeyShares(key_shares) = resp else {
+        panic!("Unexpected init response");
+    };
+    key_shares.shares
+}
+
+async fn unseal(
+    sdk: &Client,
+    shares: Vec<String>,
+    nonce: Option<String>,
+    reset: bool,
+) -> Result<UnsealResponse, String> {
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares,
+            nonce,
+            reset,
+        })
+        .await
+}
+
+#[tokio::test]
+async fn unseal_attempt() {
+    let sdk = setup(":memory:", covert_system::shutdown_signal(), None).await;
+    let status = sdk.status.seal_status().await.unwrap();
+    assert_eq!(status.state, StorageState::Uninitialized);
+    assert!(status.threshold.is_none());
+
+    let shares = initialize(&sdk).await;
+    let other_sdk = setup(":memory:", covert_system::shutdown_signal(), None).await;
+    let other_shares = initialize(&other_sdk).await;
+
+    let UnsealResponse::InProgress {
+        key_shares_provided: 1,
+        nonce: Some(nonce),
+        ..
+    } = unseal(&sdk, vec![shares[0].clone()], None, false)
+        .await
+        .unwrap()
+    else {
+        panic!("should start unseal attempt");
+    };
+    let status = sdk.status.seal_status().await.unwrap();
+    assert_eq!(status.state, StorageState::Sealed);
+    assert_eq!(status.threshold, Some(3));
+    assert_eq!(status.shares, Some(3));
+    assert_eq!(status.progress, 1);
+    assert_eq!(status.nonce.as_ref(), Some(&nonce));
+
+    // Nonce has to match the attempt in progress
+    assert!(
+        unseal(&sdk, vec![shares[1].clone()], Some("wrong".into()), false)
+            .await
+            .is_err()
+    );
+
+    // The invalid key share is pointed out and the progress is kept
+    let err = unseal(&sdk, vec![other_shares[0].clone()], None, false)
+        .await
+        .unwrap_err();
+    assert!(err.contains("Key share number 1 is not one of the current key shares"));
+    let err = unseal(&sdk, vec![shares[1].clone(), "zz".into()], None, false)
+        .await
+        .unwrap_err();
+    assert!(err.contains("Key share number 2 is not hex encoded"));
+    let status = sdk.status.seal_status().await.unwrap();
+    assert_eq!(status.progress, 1);
+    assert_eq!(status.nonce.as_ref(), Some(&nonce));
+
+    // Reset discards the provided key shares
+    let resp = unseal(&sdk, vec![], None, true).await.unwrap();
+    assert!(matches!(
+        resp,
+        UnsealResponse::InProgress {
+            key_shares_provided: 0,
+            nonce: None,
+            ..
+        }
+    ));
+    let status = sdk.status.seal_status().await.unwrap();
+    assert_eq!(status.progress, 0);
+    assert!(status.nonce.is_none());
+
+    let UnsealResponse::InProgress {
+        key_shares_provided: 2,
+        nonce: Some(new_nonce),
+        ..
+    } = unseal(&sdk, shares[..2].to_vec(), None, false)
+        .await
+        .unwrap()
+    else {
+        panic!("should start new unseal attempt");
+    };
+    assert_ne!(nonce, new_nonce);
+    let resp = unseal(&sdk, vec![shares[2].clone()], Some(new_nonce), false)
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+
+    let status = sdk.status.seal_status().await.unwrap();
+    assert_eq!(status.state, StorageState::Unsealed);
+    assert_eq!(status.progress, 0);
+    assert!(status.nonce.is_none());
+}
+
+#[tokio::test]
+async fn initialize_with_pgp_keys() {
+    let sdk = setup(":memory:", covert_system::shutdown_signal(), None).await;
+    let keys = (0..3).map(|_| generate_pgp_key()).collect::<Vec<_>>();
+    let public_keys = keys
+        .iter()
+        .map(|(_, public_key)| public_key.clone())
+        .collect::<Vec<_>>();
+
+    // One PGP key is required for each share
+    assert!(sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 2,
+            pgp_keys: Some(public_keys[..2].to_vec()),
+        })
+        .await
+        .is_err());
+    assert!(sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 2,
+            pgp_keys: Some(vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                "not a PGP key".into(),
+            ]),
+        })
+        .await
+        .is_err());
+    let resp = sdk.status.status().await.map(|resp| resp.state);
+    assert_eq!(resp, Ok(StorageState::Uninitialized));
+
+    let resp = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 2,
+            pgp_keys: Some(public_keys),
+        })
+        .await
+        .unwrap();
+    let InitializeResponse::NewKeyShares(key_shares) = resp else {
+        panic!("Unexpected init response");
+    };
+    let fingerprints = key_shares.pgp_fingerprints.unwrap();
+    assert_eq!(fingerprints.len(), 3);
+    assert!(key_shares
+        .shares
+        .iter()
+        .all(|share| share.starts_with("-----BEGIN PGP MESSAGE-----")));
+
+    // Each holder decrypts their own key share
+    let shares = key_shares
+        .shares
+        .iter()
+        .zip(&keys)
+        .map(|(share, (secret_key, _))| pgp_decrypt(secret_key, share))
+        .collect::<Vec<_>>();
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: shares[1..].to_vec(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+}
+
 #[tokio::test]
 async fn recover_seal_config_after_shutdown() {
     let tmpdir_storage_path = tempfile::tempdir().unwrap();
@@ -172,7 +439,11 @@ async fn recover_seal_config_after_shutdown() {
     let threshold = 3;
     let resp = sdk
         .operator
-        .initialize(&InitializeParams { shares, threshold })
+        .initialize(&InitializeParams {
+            shares,
+            threshold,
+            pgp_keys: None,
+        })
         .await
         .unwrap();
     let InitializeResponse::NewKeyShares(key_shares) = resp else {
@@ -184,6 +455,8 @@ async fn recover_seal_config_after_shutdown() {
     sdk.operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .unwrap();
@@ -214,6 +487,8 @@ async fn recover_seal_config_after_shutdown() {
                 "bad key 2".to_string(),
                 "bad key 3".to_string()
             ],
+            nonce: None,
+            reset: false,
         })
         .await
         .is_err());
@@ -222,6 +497,8 @@ async fn recover_seal_config_after_shutdown() {
     sdk.operator
         .unseal(&UnsealParams {
             shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
         })
         .await
         .unwrap();
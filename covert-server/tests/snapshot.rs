@@ -0,0 +1,220 @@
+mod common;
+
+use common::{generate_root_token, setup};
+use covert_sdk::{
+    operator::{
+        InitializeParams, InitializeResponse, RotateParams, SnapshotRestoreParams, UnsealParams,
+        UnsealResponse,
+    },
+    policy::CreatePolicyParams,
+};
+use covert_types::state::StorageState;
+
+fn policy(name: &str) -> CreatePolicyParams {
+    CreatePolicyParams {
+        name: name.into(),
+        policy: r#"path "sys/*" { capabilities = ["read"] }"#.into(),
+    }
+}
+
+#[tokio::test]
+async fn snapshot_and_restore() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir.path().to_str().unwrap();
+    let sdk = setup(storage_path, covert_system::shutdown_signal(), None).await;
+
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 3,
+            threshold: 2,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    let old_shares = key_shares.shares;
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares: old_shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+
+    // Snapshot requires a token
+    let snapshot_file = tmpdir.path().join("snapshot");
+    assert!(sdk.operator.snapshot(&snapshot_file).await.is_err());
+    let root_token = generate_root_token(&sdk, old_shares.clone()).await;
+    sdk.set_token(Some(root_token)).await;
+
+    sdk.policy.create(&policy("foo")).await.unwrap();
+    let seal = sdk.operator.snapshot(&snapshot_file).await.unwrap();
+    assert_eq!(seal.shares, 3);
+    assert_eq!(seal.threshold, 2);
+    assert_eq!(seal.generation, 1);
+    // The storage is sent as is, encrypted with the master key
+    let storage = std::fs::read(&snapshot_file).unwrap();
+    assert!(!storage.starts_with(b"SQLite format 3"));
+
+    // Changes after the snapshot, including a new master key
+    sdk.policy.create(&policy("bar")).await.unwrap();
+    let new_shares = sdk
+        .operator
+        .rotate(&RotateParams {
+            shares: old_shares.clone(),
+        })
+        .await
+        .unwrap()
+        .shares;
+
+    // Restoring requires a sealed server
+    assert!(sdk
+        .operator
+        .snapshot_restore(
+            &SnapshotRestoreParams {
+                shares: new_shares.clone(),
+                seal: seal.clone(),
+            },
+            &snapshot_file,
+        )
+        .await
+        .is_err());
+    sdk.operator.seal().await.unwrap();
+
+    // A threshold of the current key shares is required
+    assert!(sdk
+        .operator
+        .snapshot_restore(
+            &SnapshotRestoreParams {
+                shares: new_shares[..1].to_vec(),
+                seal: seal.clone(),
+            },
+            &snapshot_file,
+        )
+        .await
+        .is_err());
+    assert!(sdk
+        .operator
+        .snapshot_restore(
+            &SnapshotRestoreParams {
+                shares: old_shares[..2].to_vec(),
+                seal: seal.clone(),
+            },
+            &snapshot_file,
+        )
+        .await
+        .is_err());
+
+    let resp = sdk
+        .operator
+        .snapshot_restore(
+            &SnapshotRestoreParams {
+                shares: new_shares[..2].to_vec(),
+                seal: seal.clone(),
+            },
+            &snapshot_file,
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.generation, 1);
+    let resp = sdk.status.status().await.map(|resp| resp.state);
+    assert_eq!(resp, Ok(StorageState::Sealed));
+
+    // The restored storage is unsealed with the key shares of the snapshot
+    assert!(sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: new_shares,
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .is_err());
+    let resp = sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: old_shares.clone(),
+            nonce: None,
+            reset: true,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    assert_eq!(sdk.operator.key_status().await.unwrap().generation, 1);
+
+    let policies = sdk.policy.list().await.unwrap().policies;
+    assert!(policies.iter().any(|policy| policy.name == "foo"));
+    assert!(!policies.iter().any(|policy| policy.name == "bar"));
+}
+
+#[tokio::test]
+async fn restore_into_uninitialized_server() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let storage_path = tmpdir.path().to_str().unwrap();
+    let sdk = setup(storage_path, covert_system::shutdown_signal(), None).await;
+
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 1,
+            threshold: 1,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    let root_token = generate_root_token(&sdk, key_shares.shares.clone()).await;
+    sdk.set_token(Some(root_token.clone())).await;
+    sdk.policy.create(&policy("foo")).await.unwrap();
+    let snapshot_file = tmpdir.path().join("snapshot");
+    let seal = sdk.operator.snapshot(&snapshot_file).await.unwrap();
+
+    let other_tmpdir = tempfile::tempdir().unwrap();
+    let other_storage_path = other_tmpdir.path().to_str().unwrap();
+    let other_sdk = setup(other_storage_path, covert_system::shutdown_signal(), None).await;
+    let resp = other_sdk.status.status().await.map(|resp| resp.state);
+    assert_eq!(resp, Ok(StorageState::Uninitialized));
+
+    // No key shares exist to require before the server is initialized
+    other_sdk
+        .operator
+        .snapshot_restore(
+            &SnapshotRestoreParams {
+                shares: vec![],
+                seal,
+            },
+            &snapshot_file,
+        )
+        .await
+        .unwrap();
+    let resp = other_sdk.status.status().await.map(|resp| resp.state);
+    assert_eq!(resp, Ok(StorageState::Sealed));
+
+    other_sdk
+        .operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares,
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    // Tokens are part of the storage
+    other_sdk.set_token(Some(root_token)).await;
+    let policies = other_sdk.policy.list().await.unwrap().policies;
+    assert!(policies.iter().any(|policy| policy.name == "foo"));
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use covert_sdk::{
+    kv::CreateSecretParams,
+    mounts::{BackendType, CreateMountParams},
+    operator::{InitializeParams, InitializeResponse, UnsealParams, UnsealResponse},
+    Client,
+};
+use covert_system::{
+    DirectoryReplicaConfig, ReplicaTargetConfig, ReplicationConfig, StandbyConfig,
+};
+use hyper::{header::LOCATION, Method, StatusCode};
+use tokio::sync::oneshot;
+
+use common::{generate_root_token, setup};
+
+fn directory_replication(path: &Path) -> ReplicationConfig {
+    ReplicationConfig {
+        target: ReplicaTargetConfig::Directory(DirectoryReplicaConfig {
+            path: path.to_str().unwrap().to_string(),
+        }),
+        sync_interval: Duration::from_millis(100),
+    }
+}
+
+async fn setup_standby(
+    storage_path: &str,
+    replication: ReplicationConfig,
+    active_address: &str,
+) -> (Client, u16) {
+    let (port_tx, port_rx) = oneshot::channel();
+
+    let config = covert_system::Config {
+        port: 0,
+        port_tx: Some(port_tx),
+        storage_path: storage_path.into(),
+        replication: Some(replication),
+        listener: covert_system::ListenerConfig::default(),
+        seal: None,
+        metrics: None,
+        standby: Some(StandbyConfig {
+            active_address: active_address.into(),
+            max_lag: Duration::from_secs(10),
+        }),
+        ha: None,
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = covert_system::start(config, covert_system::shutdown_signal()).await {
+            panic!("server error: {}", err);
+        }
+    });
+
+    let port = port_rx.await.unwrap();
+    (Client::new(format!("http://localhost:{port}/v1")), port)
+}
+
+fn secret(value: &str) -> CreateSecretParams {
+    CreateSecretParams {
+        data: HashMap::from([("value".to_string(), value.to_string())]),
+    }
+}
+
+#[tokio::test]
+async fn standby_follows_active_server() {
+    let active_tmpdir = tempfile::tempdir().unwrap();
+    let active_storage_path = active_tmpdir.path().to_str().unwrap();
+    let replica = tempfile::tempdir().unwrap();
+    let replication = directory_replication(replica.path());
+
+    let sdk = setup(
+        active_storage_path,
+        covert_system::shutdown_signal(),
+        Some(replication.clone()),
+    )
+    .await;
+    let InitializeResponse::NewKeyShares(key_shares) = sdk
+        .operator
+        .initialize(&InitializeParams {
+            shares: 1,
+            threshold: 1,
+            pgp_keys: None,
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("should get new shares");
+    };
+    sdk.operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    let root_token = generate_root_token(&sdk, key_shares.shares.clone()).await;
+    sdk.set_token(Some(root_token.clone())).await;
+    sdk.mount
+        .create(
+            "kv/",
+            &CreateMountParams {
+                variant: BackendType::Kv,
+                config: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+    sdk.kv.create("kv/", "foo", &secret("bar")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let standby_tmpdir = tempfile::tempdir().unwrap();
+    let standby_storage_path = standby_tmpdir.path().to_str().unwrap();
+    let (standby, port) =
+        setup_standby(standby_storage_path, replication, "http://active:8080").await;
+
+    // The standby is unsealed with the key shares of the active server
+    let resp = standby
+        .operator
+        .unseal(&UnsealParams {
+            shares: key_shares.shares.clone(),
+            nonce: None,
+            reset: false,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(resp, UnsealResponse::Complete));
+    standby.set_token(Some(root_token)).await;
+
+    let resp = standby.kv.read("kv/", "foo", None).await.unwrap();
+    assert_eq!(resp.data.unwrap()["value"], "bar");
+    let health = standby.status.health().await.unwrap();
+    assert!(health.standby);
+    assert!(!health.replication_lagging);
+
+    // Writes are redirected to the active server
+    let req = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://localhost:{port}/v1/kv/data/foo"))
+        .body(hyper::Body::from(r#"{"data":{"value":"baz"}}"#))
+        .unwrap();
+    let resp = hyper::Client::new().request(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        resp.headers()[LOCATION],
+        "http://active:8080/v1/kv/data/foo"
+    );
+
+    // Changes on the active server show up on the standby
+    sdk.kv.create("kv/", "foo", &secret("baz")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    let resp = standby.kv.read("kv/", "foo", None).await.unwrap();
+    assert_eq!(resp.data.unwrap()["value"], "baz");
+
+    let status = standby.status.status().await.unwrap();
+    let standby_status = status.standby.unwrap();
+    assert_eq!(standby_status.active_address, "http://active:8080");
+    assert!(!standby_status.lagging);
+    assert!(sdk.status.status().await.unwrap().standby.is_none());
+}
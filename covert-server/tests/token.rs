@@ -0,0 +1,345 @@
+mod common;
+
+use std::time::Duration;
+
+use common::setup_unseal_with_root_token;
+use covert_sdk::{
+    cubbyhole::WriteSecretParams,
+    entity::{AttachEntityAliasParams, AttachEntityPolicyParams, CreateEntityParams, EntityAlias},
+    mounts::{BackendType, CreateMountParams},
+    policy::CreatePolicyParams,
+    token::{CreateTokenParams, RenewTokenSelfParams},
+    userpass::{CreateUserParams, LoginParams},
+    Client,
+};
+use serde_json::json;
+
+async fn login(sdk: &Client) -> covert_sdk::userpass::AuthResponse {
+    sdk.mount
+        .create(
+            "auth/userpass/",
+            &CreateMountParams {
+                variant: BackendType::Userpass,
+                config: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+    sdk.userpass
+        .create(
+            "auth/userpass/",
+            &CreateUserParams {
+                username: "foo".into(),
+                password: "bar".into(),
+            },
+        )
+        .await
+        .unwrap();
+    sdk.policy
+        .create(&CreatePolicyParams {
+            name: "kv".into(),
+            policy: r#"path "kv/*" { capabilities = ["read"] }"#.into(),
+        })
+        .await
+        .unwrap();
+    sdk.entity
+        .create(&CreateEntityParams { name: "foo".into() })
+        .await
+        .unwrap();
+    sdk.entity
+        .attach_policies(&AttachEntityPolicyParams {
+            name: "foo".into(),
+            policy_names: vec!["kv".into()],
+        })
+        .await
+        .unwrap();
+    sdk.entity
+        .attach_alias(&AttachEntityAliasParams {
+            name: "foo".into(),
+            aliases: vec![EntityAlias {
+                name: "foo".into(),
+                mount_path: "auth/userpass/".into(),
+            }],
+        })
+        .await
+        .unwrap();
+    sdk.userpass
+        .login(
+            "auth/userpass/",
+            &LoginParams {
+                username: "foo".into(),
+                password: "bar".into(),
+            },
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn lookup_and_revoke_token_by_accessor() {
+    let (sdk, root_token) = setup_unseal_with_root_token().await;
+    let auth = login(&sdk).await;
+
+    // The user can use its token but is not allowed to lookup accessors
+    sdk.set_token(Some(auth.token.to_string())).await;
+    sdk.cubbyhole
+        .write(
+            "foo",
+            &WriteSecretParams {
+                data: json!({ "foo": "bar" }),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(sdk
+        .token
+        .lookup_accessor(auth.accessor.clone())
+        .await
+        .is_err());
+
+    sdk.set_token(Some(root_token.clone())).await;
+    let lookup = sdk
+        .token
+        .lookup_accessor(auth.accessor.clone())
+        .await
+        .unwrap();
+    assert_eq!(lookup.accessor, auth.accessor);
+    assert_eq!(lookup.entity_name, "foo");
+
+    let revoked = sdk
+        .token
+        .revoke_accessor(auth.accessor.clone())
+        .await
+        .unwrap();
+    assert_eq!(revoked.accessor, auth.accessor);
+
+    // The token no longer works
+    sdk.set_token(Some(auth.token.to_string())).await;
+    assert!(sdk.cubbyhole.read("foo").await.is_err());
+
+    // And the accessor is gone
+    sdk.set_token(Some(root_token)).await;
+    assert!(sdk
+        .token
+        .lookup_accessor(auth.accessor.clone())
+        .await
+        .is_err());
+    assert!(sdk.token.revoke_accessor(auth.accessor).await.is_err());
+}
+
+#[tokio::test]
+async fn child_tokens_are_revoked_with_their_parent() {
+    let (sdk, root_token) = setup_unseal_with_root_token().await;
+    sdk.policy
+        .create(&CreatePolicyParams {
+            name: "token".into(),
+            policy: r#"
+                path "kv/*" { capabilities = ["read"] }
+                path "sys/token/create" { capabilities = ["create", "update"] }
+            "#
+            .into(),
+        })
+        .await
+        .unwrap();
+
+    let parent = sdk
+        .token
+        .create(&CreateTokenParams {
+            policies: Some(vec!["token".into()]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(parent.policies, vec!["token".to_string()]);
+
+    // Children get the policies of the parent by default, and can not get
+    // more than that
+    sdk.set_token(Some(parent.token.to_string())).await;
+    let child = sdk
+        .token
+        .create(&CreateTokenParams::default())
+        .await
+        .unwrap();
+    assert_eq!(child.policies, vec!["token".to_string()]);
+    assert!(sdk
+        .token
+        .create(&CreateTokenParams {
+            policies: Some(vec!["root".into()]),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+    assert!(sdk
+        .token
+        .create_orphan(&CreateTokenParams::default())
+        .await
+        .is_err());
+
+    sdk.set_token(Some(root_token.clone())).await;
+    let orphan = sdk
+        .token
+        .create_orphan(&CreateTokenParams {
+            policies: Some(vec!["token".into()]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sdk.token
+        .revoke_accessor(parent.accessor.clone())
+        .await
+        .unwrap();
+
+    for token in [&parent.token, &child.token] {
+        sdk.set_token(Some(token.to_string())).await;
+        assert!(sdk
+            .cubbyhole
+            .write("foo", &WriteSecretParams { data: json!({}) })
+            .await
+            .is_err());
+    }
+    sdk.set_token(Some(orphan.token.to_string())).await;
+    sdk.cubbyhole
+        .write("foo", &WriteSecretParams { data: json!({}) })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn token_with_limited_number_of_uses() {
+    let (sdk, _root_token) = setup_unseal_with_root_token().await;
+
+    let token = sdk
+        .token
+        .create(&CreateTokenParams {
+            num_uses: 2,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(token.num_uses, 2);
+
+    sdk.set_token(Some(token.token.to_string())).await;
+    sdk.cubbyhole
+        .write("foo", &WriteSecretParams { data: json!({}) })
+        .await
+        .unwrap();
+    sdk.cubbyhole.read("foo").await.unwrap();
+    assert!(sdk.cubbyhole.read("foo").await.is_err());
+}
+
+#[tokio::test]
+async fn token_with_a_single_use_is_revoked_after_use() {
+    let (sdk, root_token) = setup_unseal_with_root_token().await;
+
+    let token = sdk
+        .token
+        .create(&CreateTokenParams {
+            num_uses: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    sdk.set_token(Some(token.token.to_string())).await;
+    let lookup = sdk.token.lookup_self().await.unwrap();
+    assert_eq!(lookup.accessor, token.accessor);
+    assert!(sdk.token.lookup_self().await.is_err());
+
+    sdk.set_token(Some(root_token)).await;
+    assert!(sdk.token.lookup_accessor(token.accessor).await.is_err());
+}
+
+#[tokio::test]
+async fn child_token_does_not_outlive_its_parent() {
+    let (sdk, _root_token) = setup_unseal_with_root_token().await;
+
+    let parent = sdk
+        .token
+        .create(&CreateTokenParams {
+            ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    sdk.set_token(Some(parent.token.to_string())).await;
+    let child = sdk
+        .token
+        .create(&CreateTokenParams {
+            ttl: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(child.ttl <= Duration::from_secs(60));
+}
+
+#[tokio::test]
+async fn token_self_service() {
+    let (sdk, _root_token) = setup_unseal_with_root_token().await;
+    let auth = login(&sdk).await;
+    sdk.set_token(Some(auth.token.to_string())).await;
+
+    let lookup = sdk.token.lookup_self().await.unwrap();
+    assert_eq!(lookup.accessor, auth.accessor);
+    assert_eq!(lookup.entity_name, "foo");
+    assert_eq!(lookup.policies, vec!["kv".to_string()]);
+    assert!(lookup.ttl.is_some());
+
+    let capabilities = sdk
+        .token
+        .capabilities_self(vec![
+            "kv/foo".into(),
+            "sys/mounts".into(),
+            "cubbyhole/foo".into(),
+            "auth/token/lookup-self".into(),
+        ])
+        .await
+        .unwrap()
+        .capabilities;
+    assert_eq!(capabilities["kv/foo"], vec!["read".to_string()]);
+    assert_eq!(capabilities["sys/mounts"], vec!["deny".to_string()]);
+    // Every token has access to its own cubbyhole and can manage itself
+    let all = ["create", "read", "update", "delete", "list"].map(ToString::to_string);
+    assert_eq!(capabilities["cubbyhole/foo"], all);
+    assert_eq!(capabilities["auth/token/lookup-self"], all);
+
+    let renewed = sdk
+        .token
+        .renew_self(&RenewTokenSelfParams {
+            ttl: Some(Duration::from_secs(60)),
+        })
+        .await
+        .unwrap();
+    assert!(renewed.ttl.unwrap() <= Duration::from_secs(60));
+
+    let revoked = sdk.token.revoke_self().await.unwrap();
+    assert_eq!(revoked.accessor, auth.accessor);
+    assert!(sdk.token.lookup_self().await.is_err());
+}
+
+#[tokio::test]
+async fn renew_self_is_limited_by_the_max_ttl() {
+    let (sdk, _root_token) = setup_unseal_with_root_token().await;
+
+    let token = sdk
+        .token
+        .create(&CreateTokenParams {
+            ttl: Some(Duration::from_secs(60)),
+            explicit_max_ttl: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    sdk.set_token(Some(token.token.to_string())).await;
+    let renewed = sdk
+        .token
+        .renew_self(&RenewTokenSelfParams {
+            ttl: Some(Duration::from_secs(2 * 3600)),
+        })
+        .await
+        .unwrap();
+    let ttl = renewed.ttl.unwrap();
+    assert!(ttl > Duration::from_secs(60) && ttl <= Duration::from_secs(3600));
+}
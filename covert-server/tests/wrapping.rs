@@ -0,0 +1,57 @@
+mod common;
+
+use std::time::Duration;
+
+use common::setup_unseal;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn wrap_and_unwrap() {
+    let sdk = setup_unseal().await;
+
+    let data = json!({ "password": "hunter2" });
+    let wrap_info = sdk.wrapping.wrap(&data).await.unwrap();
+    assert_eq!(wrap_info.ttl, Duration::from_mins(5));
+    assert_eq!(wrap_info.creation_path, "sys/wrapping/wrap");
+
+    let lookup = sdk.wrapping.lookup(wrap_info.token.clone()).await.unwrap();
+    assert_eq!(lookup.ttl, wrap_info.ttl);
+    assert_eq!(lookup.creation_path, wrap_info.creation_path);
+
+    // Unwrapping does not require a token
+    sdk.set_token(None).await;
+    let unwrapped: Value = sdk.wrapping.unwrap(wrap_info.token.clone()).await.unwrap();
+    assert_eq!(unwrapped, data);
+
+    // Wrapping tokens are single use
+    assert!(sdk
+        .wrapping
+        .unwrap::<Value>(wrap_info.token.clone())
+        .await
+        .is_err());
+    assert!(sdk.wrapping.lookup(wrap_info.token).await.is_err());
+}
+
+#[tokio::test]
+async fn wrap_ttl_header_and_rewrap() {
+    let sdk = setup_unseal().await;
+
+    let data = json!({ "password": "hunter2" });
+    sdk.set_wrap_ttl(Some(Duration::from_mins(1))).await;
+    let wrap_info = sdk.wrapping.wrap(&data).await.unwrap();
+    sdk.set_wrap_ttl(None).await;
+    assert_eq!(wrap_info.ttl, Duration::from_mins(1));
+
+    // Rewrapping keeps the original TTL and invalidates the old token
+    let rewrapped = sdk.wrapping.rewrap(wrap_info.token.clone()).await.unwrap();
+    assert_eq!(rewrapped.ttl, wrap_info.ttl);
+    assert_eq!(rewrapped.creation_path, wrap_info.creation_path);
+    assert!(sdk.wrapping.lookup(wrap_info.token).await.is_err());
+
+    let unwrapped: Value = sdk.wrapping.unwrap(rewrapped.token).await.unwrap();
+    assert_eq!(unwrapped, data);
+
+    // Invalid TTLs are rejected
+    sdk.set_wrap_ttl(Some(Duration::ZERO)).await;
+    assert!(sdk.wrapping.wrap(&data).await.is_err());
+}
@@ -10,7 +10,9 @@ use sqlx::{
 
 use crate::{
     states::{Sealed, Uninitialized, Unsealed},
-    storage::{create_ecrypted_pool, create_master_key, Storage},
+    storage::{
+        create_ecrypted_pool, create_master_key, master_key_digest, write_snapshot, Storage,
+    },
     utils::owned_rw_lock::{OwnedRwLock, TransitionResult},
 };
 
@@ -164,6 +166,12 @@ pub enum EncryptedPoolError {
         from: StorageState,
         to: StorageState,
     },
+    #[error("Failed to rotate the master key. Error: {0}")]
+    Rotation(#[source] sqlx::Error),
+    #[error("Failed to take a snapshot of the storage. Error: {0}")]
+    Snapshot(#[source] sqlx::Error),
+    #[error("Failed to restore the storage from a snapshot. Error: {0}")]
+    Restore(#[source] std::io::Error),
 }
 
 impl EncryptedPool {
@@ -188,11 +196,15 @@ impl EncryptedPool {
     pub fn new_tmp() -> Self {
         let storage_path = ":memory:".to_string();
         let master_key = create_master_key();
+        let master_key_digest = master_key_digest(&master_key);
         let pool = create_ecrypted_pool(true, &storage_path, master_key)
             .expect("to create encrypted pool and this should only be used for testing");
 
         Self(OwnedRwLock::new(PoolState::Unsealed(Storage {
-            state: Unsealed { pool },
+            state: Unsealed {
+                pool,
+                master_key_digest,
+            },
             storage_path,
         })))
     }
@@ -315,6 +327,121 @@ impl EncryptedPool {
         })
     }
 
+    /// Re-encrypt the pool with a new master key, created with
+    /// [`create_master_key`].
+    ///
+    /// The only connection of the pool is held during the rotation, so
+    /// queries wait for the rotation to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the pool is not unsealed or the re-encryption fails.
+    pub async fn rotate(&self, master_key: String) -> Result<(), EncryptedPoolError> {
+        let pool = self
+            .0
+            .read()
+            .get_unsealed()
+            .map(|storage| storage.state.pool.clone())?;
+        let mut conn = pool.acquire().await.map_err(EncryptedPoolError::Rotation)?;
+
+        self.0.write(|barrier| {
+            let barrier = match barrier {
+                PoolState::Unsealed(barrier) => barrier,
+                barrier => {
+                    let state = StorageState::from(&barrier);
+                    return TransitionResult {
+                        state: barrier,
+                        result: Err(EncryptedPoolError::InvalidState(state)),
+                    };
+                }
+            };
+
+            match barrier.rotate(master_key, &mut conn) {
+                Ok(barrier) => TransitionResult {
+                    state: PoolState::Unsealed(barrier),
+                    result: Ok(()),
+                },
+                Err((barrier, err)) => TransitionResult {
+                    state: PoolState::Unsealed(barrier),
+                    result: Err(EncryptedPoolError::Rotation(err)),
+                },
+            }
+        })
+    }
+
+    /// Write a consistent copy of the storage to `path`, encrypted with the
+    /// same master key.
+    ///
+    /// The copy is made with `VACUUM INTO` on the only connection of the
+    /// pool, as sqlx does not expose the online backup API, so writes wait
+    /// for the copy to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the pool is not unsealed or the copy fails.
+    pub async fn snapshot(&self, path: &str) -> Result<(), EncryptedPoolError> {
+        let pool = self
+            .0
+            .read()
+            .get_unsealed()
+            .map(|storage| storage.state.pool.clone())?;
+        let mut conn = pool.acquire().await.map_err(EncryptedPoolError::Snapshot)?;
+        sqlx::query("VACUUM INTO $1")
+            .bind(path)
+            .execute(&mut conn)
+            .await
+            .map_err(EncryptedPoolError::Snapshot)?;
+        Ok(())
+    }
+
+    /// Replace the storage with the file of a snapshot taken by
+    /// [`EncryptedPool::snapshot`]. The pool is sealed afterwards and is
+    /// unsealed with the master key of the snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the pool is unsealed or the snapshot can't be written.
+    pub fn restore(&self, snapshot_path: &str) -> Result<(), EncryptedPoolError> {
+        self.0.write(|barrier| {
+            let storage_path = match &barrier {
+                PoolState::Uninitialized(storage) => storage.storage_path.clone(),
+                PoolState::Sealed(storage) => storage.storage_path.clone(),
+                PoolState::Unsealed(_) => {
+                    return TransitionResult {
+                        state: barrier,
+                        result: Err(EncryptedPoolError::InvalidState(StorageState::Unsealed)),
+                    }
+                }
+            };
+
+            match write_snapshot(&storage_path, snapshot_path) {
+                Ok(()) => TransitionResult {
+                    state: PoolState::Sealed(Storage {
+                        state: Sealed,
+                        storage_path,
+                    }),
+                    result: Ok(()),
+                },
+                Err(err) => TransitionResult {
+                    state: barrier,
+                    result: Err(EncryptedPoolError::Restore(err)),
+                },
+            }
+        })
+    }
+
+    /// Check that a master key is the key the pool was unsealed with.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the pool is not unsealed.
+    pub fn verify_master_key(&self, master_key: &str) -> Result<bool, EncryptedPoolError> {
+        self.0
+            .read()
+            .get_unsealed()
+            .map(|storage| storage.verify_master_key(master_key))
+    }
+
     fn pool(&self) -> Result<Pool<Sqlite>, sqlx::Error> {
         self.0
             .read()
@@ -362,10 +489,14 @@ mod tests {
         let res = sqlx::query(query).execute(&pool).await;
         assert!(matches!(res.unwrap_err(), sqlx::Error::PoolClosed));
 
+        assert!(pool.verify_master_key(&master_key).is_err());
+
         // Unseal and we should get a success response
         pool.unseal(master_key.clone()).unwrap();
         let res = sqlx::query(query).execute(&pool).await;
         assert!(res.is_ok());
+        assert!(pool.verify_master_key(&master_key).unwrap());
+        assert!(!pool.verify_master_key("wrong-key").unwrap());
 
         // Seal and we should not be able to query
         pool.seal().unwrap();
@@ -377,4 +508,84 @@ mod tests {
         let res = sqlx::query(query).execute(&pool).await;
         assert!(res.is_ok());
     }
+
+    #[sqlx::test]
+    async fn rotate() {
+        let dir = std::env::temp_dir().join(format!("covert-rotate-{}", create_master_key()));
+        std::fs::create_dir(&dir).unwrap();
+        let storage_path = dir.join("db").to_str().unwrap().to_string();
+
+        let pool = EncryptedPool::new(&storage_path);
+        assert!(pool.rotate(create_master_key()).await.is_err());
+        let master_key = pool.initialize().unwrap().unwrap();
+        pool.unseal(master_key.clone()).unwrap();
+        sqlx::query("CREATE TABLE FOO (bar TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let new_master_key = create_master_key();
+        pool.rotate(new_master_key.clone()).await.unwrap();
+        assert!(pool.verify_master_key(&new_master_key).unwrap());
+        assert!(!pool.verify_master_key(&master_key).unwrap());
+        sqlx::query("INSERT INTO FOO (bar) VALUES ('baz')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // Only the new master key is able to unseal
+        pool.seal().unwrap();
+        assert!(pool.unseal(master_key).is_err());
+        pool.unseal(new_master_key).unwrap();
+        let count: (i64,) = sqlx::query_as("SELECT count(*) FROM FOO")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count.0, 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[sqlx::test]
+    async fn snapshot_and_restore() {
+        let dir = std::env::temp_dir().join(format!("covert-snapshot-{}", create_master_key()));
+        std::fs::create_dir(&dir).unwrap();
+        let storage_path = dir.join("db").to_str().unwrap().to_string();
+        let snapshot_path = dir.join("snapshot").to_str().unwrap().to_string();
+
+        let pool = EncryptedPool::new(&storage_path);
+        let master_key = pool.initialize().unwrap().unwrap();
+        pool.unseal(master_key.clone()).unwrap();
+        sqlx::query("CREATE TABLE FOO (bar TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO FOO (bar) VALUES ('baz')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        pool.snapshot(&snapshot_path).await.unwrap();
+        sqlx::query("INSERT INTO FOO (bar) VALUES ('qux')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // Only sealed storage can be restored
+        assert!(pool.restore(&snapshot_path).is_err());
+        pool.seal().unwrap();
+        assert!(pool.snapshot(&snapshot_path).await.is_err());
+        pool.restore(&snapshot_path).unwrap();
+        assert_eq!(pool.state(), StorageState::Sealed);
+
+        // The snapshot is encrypted with the same master key
+        pool.unseal(master_key).unwrap();
+        let count: (i64,) = sqlx::query_as("SELECT count(*) FROM FOO")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count.0, 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
@@ -14,3 +14,4 @@ mod utils;
 
 pub use backend_pool::BackendStoragePool;
 pub use encrypted_pool::{EncryptedPool, EncryptedPoolError, PoolState};
+pub use storage::create_master_key;
@@ -9,4 +9,7 @@ pub struct Sealed;
 #[derive(Debug)]
 pub struct Unsealed {
     pub pool: Pool<Sqlite>,
+    /// Digest of the master key, used to verify a master key reconstructed
+    /// from key shares while unsealed.
+    pub master_key_digest: Vec<u8>,
 }
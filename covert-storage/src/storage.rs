@@ -1,9 +1,13 @@
 use std::path::Path;
 
 use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
-    Pool, Sqlite,
+    sqlite::{
+        SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions,
+        SqliteSynchronous,
+    },
+    Executor, Pool, Sqlite,
 };
 
 use crate::states::{Sealed, Uninitialized, Unsealed};
@@ -56,9 +60,13 @@ impl Storage<Uninitialized> {
 
 impl Storage<Sealed> {
     pub fn unseal(self, key: String) -> Result<Storage<Unsealed>, Self> {
+        let master_key_digest = master_key_digest(&key);
         create_ecrypted_pool(false, &self.storage_path, key)
             .map(|pool| Storage {
-                state: Unsealed { pool },
+                state: Unsealed {
+                    pool,
+                    master_key_digest,
+                },
                 storage_path: self.storage_path.clone(),
             })
             .map_err(|_| self)
@@ -66,6 +74,44 @@ impl Storage<Sealed> {
 }
 
 impl Storage<Unsealed> {
+    pub fn verify_master_key(&self, key: &str) -> bool {
+        master_key_digest(key) == self.state.master_key_digest
+    }
+
+    /// Re-encrypt the storage with a new master key, using a connection from
+    /// the pool of the storage.
+    ///
+    /// A new pool is connected with the new key as the connection options of
+    /// the current pool hold the old key.
+    pub fn rotate(
+        self,
+        key: String,
+        conn: &mut SqliteConnection,
+    ) -> Result<Self, (Self, sqlx::Error)> {
+        let res =
+            futures::executor::block_on(conn.execute(format!("PRAGMA rekey = '{key}'").as_str()));
+        if let Err(err) = res {
+            return Err((self, err));
+        }
+
+        let master_key_digest = master_key_digest(&key);
+        let pool = if self.storage_path == ":memory:" {
+            self.state.pool.clone()
+        } else {
+            match create_ecrypted_pool(false, &self.storage_path, key) {
+                Ok(pool) => pool,
+                Err(err) => return Err((self, err)),
+            }
+        };
+        Ok(Storage {
+            state: Unsealed {
+                pool,
+                master_key_digest,
+            },
+            storage_path: self.storage_path,
+        })
+    }
+
     pub fn seal(self) -> Storage<Sealed> {
         Storage {
             state: Sealed,
@@ -74,10 +120,12 @@ impl Storage<Unsealed> {
     }
 }
 
-pub(crate) fn create_master_key() -> String {
+/// Generate a new random master key for the encrypted storage.
+#[must_use]
+pub fn create_master_key() -> String {
     let mut key = "1".to_string();
     // sqlcipher doesn't accept keys starting with a digit
-    while key.chars().next().expect("key not empty").is_numeric() {
+    while key.starts_with(|c: char| c.is_numeric()) {
         key = rand::thread_rng()
             .sample_iter(&Alphanumeric)
             .take(50)
@@ -88,6 +136,32 @@ pub(crate) fn create_master_key() -> String {
     key
 }
 
+pub(crate) fn master_key_digest(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+/// Replace the storage file with a snapshot. The snapshot is written to a
+/// temporary file first, so a failed write leaves the storage untouched.
+pub(crate) fn write_snapshot(storage_path: &str, snapshot_path: &str) -> std::io::Result<()> {
+    if storage_path.contains(":memory:") {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "in-memory storage can not be restored from a snapshot",
+        ));
+    }
+    let tmp_path = format!("{storage_path}.tmp");
+    std::fs::copy(snapshot_path, &tmp_path)?;
+
+    // The WAL of the replaced storage must not be applied to the snapshot
+    for stale_file in [format!("{storage_path}-wal"), format!("{storage_path}-shm")] {
+        match std::fs::remove_file(stale_file) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+    }
+    std::fs::rename(tmp_path, storage_path)
+}
+
 pub(crate) fn create_ecrypted_pool(
     create_if_missing: bool,
     storage_path: &str,
@@ -111,6 +185,10 @@ pub(crate) fn create_ecrypted_pool(
                 // TODO: allow configuration of these values
                 .min_connections(1)
                 .max_connections(1)
+                // Keep the connection open, closing the last connection
+                // checkpoints the WAL behind the back of the replication
+                .idle_timeout(None)
+                .max_lifetime(None)
                 .connect_with(opts)
                 .await?;
 
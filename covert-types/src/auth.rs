@@ -5,6 +5,10 @@ pub enum AuthPolicy {
     Authenticated,
     /// Anyone without a token.
     Unauthenticated,
+    /// Authorized to access the requested path with operation, including
+    /// routes that are root protected, as the policy for the path has the
+    /// `sudo` capability.
+    Sudo,
 }
 
 impl Default for AuthPolicy {
@@ -5,6 +5,8 @@ use strum::{Display, EnumString};
     Debug, Copy, Clone, PartialEq, EnumString, Display, SerializeDisplay, DeserializeFromStr, Eq,
 )]
 pub enum BackendType {
+    #[strum(ascii_case_insensitive, serialize = "cubbyhole")]
+    Cubbyhole,
     #[strum(ascii_case_insensitive, serialize = "kv")]
     Kv,
     #[strum(ascii_case_insensitive, serialize = "psql")]
@@ -28,9 +30,10 @@ pub enum BackendCategory {
 impl From<BackendType> for BackendCategory {
     fn from(value: BackendType) -> Self {
         match value {
-            BackendType::Kv | BackendType::Postgres | BackendType::System => {
-                BackendCategory::Logical
-            }
+            BackendType::Cubbyhole
+            | BackendType::Kv
+            | BackendType::Postgres
+            | BackendType::System => BackendCategory::Logical,
             BackendType::Userpass => BackendCategory::Credential,
         }
     }
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WriteSecretParams {
+    pub data: Value,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WriteSecretResponse {
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReadSecretResponse {
+    pub data: Value,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteSecretResponse {
+    pub path: String,
+}
@@ -45,6 +45,12 @@ pub struct ReadSecretResponse {
     pub metadata: CreateSecretResponse,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListSecretsResponse {
+    /// Keys and folders directly under the prefix. Folders end with a `/`.
+    pub keys: Vec<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct HardDeleteSecretParams {
     pub versions: Vec<u32>,
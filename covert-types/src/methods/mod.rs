@@ -1,3 +1,4 @@
+pub mod cubbyhole;
 pub mod kv;
 pub mod psql;
 pub mod system;
@@ -7,7 +8,7 @@ use std::time::Duration;
 
 use serde::{self, Deserialize, Serialize};
 
-use crate::token::Token;
+use crate::token::{Token, TokenAccessor};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SecretLeaseResponse<T> {
@@ -20,6 +21,7 @@ pub struct SecretLeaseResponse<T> {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub token: Token,
+    pub accessor: TokenAccessor,
     pub lease_id: String,
     #[serde(with = "humantime_serde")]
     pub ttl: std::time::Duration,
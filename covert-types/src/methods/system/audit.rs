@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+/// Where an audit device writes its log entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum AuditDeviceConfig {
+    /// Append to a file on the local filesystem.
+    File { file_path: String },
+    /// Write to a unix domain socket.
+    Socket { socket_path: String },
+    /// Write to the standard output of the server process.
+    Stdout,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnableAuditDeviceParams {
+    #[serde(flatten)]
+    pub config: AuditDeviceConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditDevice {
+    pub path: String,
+    #[serde(flatten)]
+    pub config: AuditDeviceConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnableAuditDeviceResponse {
+    pub device: AuditDevice,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListAuditDevicesResponse {
+    pub devices: Vec<AuditDevice>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisableAuditDeviceResponse {
+    pub device: AuditDevice,
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Start an attempt to generate a new root token. The root token is returned
+/// encoded with exactly one of the one-time pad or the PGP public key.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GenerateRootParams {
+    /// One-time pad, see [`crate::token::generate_otp`].
+    #[serde(default)]
+    pub otp: Option<String>,
+    /// ASCII armored PGP public key.
+    #[serde(default)]
+    pub pgp_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenerateRootStatusResponse {
+    pub started: bool,
+    pub nonce: Option<String>,
+    pub progress: usize,
+    pub required: u8,
+    pub pgp_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenerateRootUpdateParams {
+    pub nonce: String,
+    pub shares: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "generate_root_status", content = "data")]
+pub enum GenerateRootUpdateResponse {
+    #[serde(rename = "complete")]
+    Complete {
+        /// The root token, either encoded with the one-time pad or as an ASCII
+        /// armored PGP message.
+        encoded_token: String,
+    },
+    #[serde(rename = "in progress")]
+    InProgress {
+        nonce: String,
+        progress: usize,
+        required: u8,
+    },
+}
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Query parameters of `sys/health` to override the status codes returned for
+/// each state, e.g. to make a load balancer treat a standby as healthy.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthParams {
+    /// Return the active code for a standby.
+    pub standbyok: bool,
+    pub activecode: u16,
+    pub standbycode: u16,
+    pub laggingcode: u16,
+    pub sealedcode: u16,
+    pub uninitcode: u16,
+}
+
+impl Default for HealthParams {
+    fn default() -> Self {
+        Self {
+            standbyok: false,
+            activecode: 200,
+            standbycode: 429,
+            laggingcode: 472,
+            sealedcode: 503,
+            uninitcode: 501,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct HealthResponse {
+    pub initialized: bool,
+    pub sealed: bool,
+    pub standby: bool,
+    /// The replica followed by a standby is behind the active node.
+    pub replication_lagging: bool,
+    pub version: String,
+    pub server_time: DateTime<Utc>,
+    pub cluster_id: String,
+}
@@ -1,9 +1,18 @@
+mod audit;
 mod entity;
+mod generate_root;
+mod health;
 mod namespace;
 mod policy;
+mod rekey;
+mod rotate;
+mod snapshot;
+mod token;
+mod wrapping;
 
 use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -11,16 +20,27 @@ use crate::{
     backend::{BackendCategory, BackendType},
     mount::MountConfig,
     state::StorageState,
-    token::Token,
 };
+pub use audit::*;
 pub use entity::*;
+pub use generate_root::*;
+pub use health::*;
 pub use namespace::*;
 pub use policy::*;
+pub use rekey::*;
+pub use rotate::*;
+pub use snapshot::*;
+pub use token::*;
+pub use wrapping::*;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitializeParams {
     pub shares: u8,
     pub threshold: u8,
+    /// ASCII armored PGP public keys, one for each key share. The key shares
+    /// are returned encrypted for the keys in the same order.
+    #[serde(default)]
+    pub pgp_keys: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,6 +53,9 @@ pub enum InitializeResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitializedKeyShares {
     pub shares: Vec<String>,
+    /// Fingerprints of the PGP keys the key shares are encrypted for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pgp_fingerprints: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,18 +66,26 @@ pub struct InitializedWithExistingKey {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UnsealParams {
     pub shares: Vec<String>,
+    /// Nonce of the unseal attempt the key shares are provided for. The key
+    /// shares are rejected if another attempt is in progress.
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// Discard the key shares provided so far and start a new unseal attempt.
+    #[serde(default)]
+    pub reset: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "unseal_status", content = "data")]
 pub enum UnsealResponse {
     #[serde(rename = "complete")]
-    Complete { root_token: Token },
+    Complete,
     #[serde(rename = "in progress")]
     InProgress {
         threshold: u8,
         key_shares_total: u8,
         key_shares_provided: usize,
+        nonce: Option<String>,
     },
 }
 
@@ -66,6 +97,48 @@ pub struct SealResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StatusResponse {
     pub state: StorageState,
+    /// Only set when the server is a standby.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub standby: Option<StandbyStatus>,
+}
+
+/// Replication status of a standby server.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StandbyStatus {
+    /// Address of the active server that writes are redirected to.
+    pub active_address: String,
+    /// When the standby last caught up with the replica.
+    pub synced_at: DateTime<Utc>,
+    /// Time since the standby last caught up with the replica.
+    #[serde(with = "humantime_serde")]
+    pub lag: Duration,
+    /// Whether the lag is above the configured maximum lag.
+    pub lagging: bool,
+}
+
+/// The active server of a high availability cluster.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeaderResponse {
+    /// Whether the active server is elected among the servers of a cluster.
+    pub ha_enabled: bool,
+    /// Whether the server that answered is the active server.
+    pub is_self: bool,
+    /// Address of the active server, if known.
+    pub leader_address: Option<String>,
+    /// When the active server took over.
+    pub active_since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealStatusResponse {
+    pub state: StorageState,
+    /// Not set before the storage is initialized.
+    pub threshold: Option<u8>,
+    pub shares: Option<u8>,
+    /// Number of key shares provided for the unseal attempt.
+    pub progress: usize,
+    /// Nonce of the unseal attempt in progress.
+    pub nonce: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -129,6 +202,7 @@ pub struct LeaseEntry {
     pub issue_time: String,
     pub expire_time: String,
     pub last_renewal_time: String,
+    pub token_accessor: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
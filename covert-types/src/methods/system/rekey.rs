@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+/// Start an attempt to replace the key shares with a new set of shares.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RekeyParams {
+    pub shares: u8,
+    pub threshold: u8,
+    /// Require a threshold of the new key shares to be provided before the
+    /// old key shares stop working.
+    #[serde(default)]
+    pub require_verification: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RekeyStatusResponse {
+    pub started: bool,
+    pub nonce: Option<String>,
+    pub progress: usize,
+    pub required: u8,
+    pub shares: Option<u8>,
+    pub threshold: Option<u8>,
+    pub require_verification: bool,
+    pub verification_nonce: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RekeyUpdateParams {
+    pub nonce: String,
+    pub shares: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "rekey_status", content = "data")]
+pub enum RekeyUpdateResponse {
+    #[serde(rename = "complete")]
+    Complete {
+        /// The new key shares.
+        shares: Vec<String>,
+        /// Nonce to verify the new key shares with, if verification is
+        /// required.
+        verification_nonce: Option<String>,
+    },
+    #[serde(rename = "in progress")]
+    InProgress {
+        nonce: String,
+        progress: usize,
+        required: u8,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "rekey_verification_status", content = "data")]
+pub enum RekeyVerifyResponse {
+    #[serde(rename = "complete")]
+    Complete,
+    #[serde(rename = "in progress")]
+    InProgress {
+        nonce: String,
+        progress: usize,
+        required: u8,
+    },
+}
@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Rotate the master key. A threshold of the current key shares is required
+/// as the new master key is split into a new set of key shares.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateParams {
+    pub shares: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateResponse {
+    /// The new key shares, the old key shares are no longer able to unseal.
+    pub shares: Vec<String>,
+    pub generation: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyStatusResponse {
+    pub generation: u32,
+    /// Unknown for the first generation of storage initialized before key
+    /// generations were recorded.
+    pub created_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Header with the JSON encoded [`SealSnapshot`] of a snapshot. The body of
+/// the snapshot is a copy of the storage, still encrypted with the master key.
+pub const SNAPSHOT_SEAL_HEADER: &str = "X-Covert-Snapshot-Seal";
+
+/// Header with the comma separated key shares a snapshot is restored with.
+pub const SNAPSHOT_SHARES_HEADER: &str = "X-Covert-Snapshot-Shares";
+
+/// What is needed to unseal a snapshot of the storage with the key shares
+/// that were in use when it was taken.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SealSnapshot {
+    pub shares: u8,
+    pub threshold: u8,
+    /// Master key encrypted with the unseal key. Not set for storage
+    /// initialized before the master key was encrypted.
+    pub master_key: Option<SnapshotMasterKey>,
+    /// Hex encoded master key encrypted with the key encryption key of
+    /// auto-unseal.
+    pub kek_master_key: Option<String>,
+    pub generation: u32,
+    pub generation_created_at: Option<DateTime<Utc>>,
+}
+
+/// Hex encoded master key encrypted with the unseal key.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotMasterKey {
+    pub key: String,
+    pub nonce: String,
+    pub share_digests: Option<String>,
+}
+
+/// Replace the storage of a sealed server with a snapshot. A threshold of the
+/// current key shares is required unless the server is uninitialized.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotRestoreParams {
+    #[serde(default)]
+    pub shares: Vec<String>,
+    pub seal: SealSnapshot,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotRestoreResponse {
+    /// Generation of the master key of the restored storage. The server is
+    /// left sealed and is unsealed with the key shares of this generation.
+    pub generation: u32,
+}
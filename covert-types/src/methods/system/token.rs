@@ -0,0 +1,79 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::token::{Token, TokenAccessor};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CreateTokenParams {
+    /// Names of the policies of the new token. Must be a subset of the
+    /// policies of the calling token, which are used if not set.
+    #[serde(default)]
+    pub policies: Option<Vec<String>>,
+    #[serde(default, with = "humantime_serde")]
+    pub ttl: Option<Duration>,
+    /// The token can not be renewed beyond this TTL.
+    #[serde(default, with = "humantime_serde")]
+    pub explicit_max_ttl: Option<Duration>,
+    /// Number of requests the token can be used for, unlimited if zero.
+    #[serde(default)]
+    pub num_uses: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTokenResponse {
+    pub token: Token,
+    pub accessor: TokenAccessor,
+    pub policies: Vec<String>,
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+    pub num_uses: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenAccessorParams {
+    pub accessor: TokenAccessor,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LookupTokenResponse {
+    pub accessor: TokenAccessor,
+    pub entity_name: String,
+    pub policies: Vec<String>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Time left until the token expires, not set for tokens that never
+    /// expire.
+    #[serde(with = "humantime_serde")]
+    pub ttl: Option<Duration>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RenewTokenSelfParams {
+    /// Requested TTL from now, the default TTL is used if not set.
+    #[serde(default, with = "humantime_serde")]
+    pub ttl: Option<Duration>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapabilitiesParams {
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapabilitiesResponse {
+    /// The capabilities of the token on each of the paths. A path the token
+    /// has no access to only has the `deny` capability.
+    pub capabilities: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokedTokenResponse {
+    pub accessor: TokenAccessor,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokedRootTokensResponse {
+    pub accessors: Vec<TokenAccessor>,
+}
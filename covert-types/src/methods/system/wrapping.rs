@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::token::Token;
+
+/// Header used to request that the response is wrapped. The value is a
+/// duration like `5m` or `300s`.
+pub const WRAP_TTL_HEADER: &str = "X-Covert-Wrap-TTL";
+
+/// Returned in place of the real response when it has been wrapped. The real
+/// response can be retrieved once with `sys/wrapping/unwrap`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WrapInfo {
+    pub token: Token,
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+    pub creation_time: DateTime<Utc>,
+    pub creation_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnwrapParams {
+    pub token: Token,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LookupWrappingParams {
+    pub token: Token,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LookupWrappingResponse {
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+    pub creation_time: DateTime<Utc>,
+    pub creation_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RewrapParams {
+    pub token: Token,
+}
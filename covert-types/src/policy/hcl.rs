@@ -0,0 +1,526 @@
+//! A small parser for the subset of HCL used by policy documents.
+//!
+//! The parser understands blocks with string labels, attributes, strings,
+//! numbers, booleans, lists and objects, as well as `#`, `//` and `/* */`
+//! comments. JSON documents are accepted as well, since every JSON value is
+//! a valid expression in this grammar.
+
+use std::fmt;
+
+use thiserror::Error;
+
+/// Line and column (both 1-based) of a character in the policy document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Error returned when a policy document cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message} at {position}")]
+pub struct PolicyParseError {
+    pub message: String,
+    pub position: Position,
+}
+
+impl PolicyParseError {
+    pub(crate) fn new(message: impl Into<String>, position: Position) -> Self {
+        Self {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Spanned<T> {
+    pub value: T,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    String(String),
+    Number(String),
+    Bool(bool),
+    List(Vec<Spanned<Value>>),
+    Object(Vec<(Spanned<String>, Spanned<Value>)>),
+}
+
+impl Value {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Bool(_) => "boolean",
+            Value::List(_) => "list",
+            Value::Object(_) => "object",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Item {
+    Attribute {
+        key: Spanned<String>,
+        value: Spanned<Value>,
+    },
+    Block {
+        kind: Spanned<String>,
+        labels: Vec<Spanned<String>>,
+        body: Vec<Item>,
+    },
+}
+
+/// Parse a policy document written in either HCL or JSON.
+///
+/// JSON documents are translated into the equivalent HCL items, so
+/// `{"path": {"kv/*": {"capabilities": ["read"]}}}` yields the same items as
+/// `path "kv/*" { capabilities = ["read"] }`.
+pub(crate) fn parse(input: &str) -> Result<Vec<Item>, PolicyParseError> {
+    let mut parser = Parser::new(input);
+    parser.skip_trivia()?;
+    if parser.peek() == Some('{') {
+        let document = parser.parse_value()?;
+        parser.skip_trivia()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("unexpected trailing characters"));
+        }
+        json_to_items(document)
+    } else {
+        parser.parse_body(None)
+    }
+}
+
+fn json_to_items(document: Spanned<Value>) -> Result<Vec<Item>, PolicyParseError> {
+    let Value::Object(entries) = document.value else {
+        return Err(PolicyParseError::new(
+            "expected an object",
+            document.position,
+        ));
+    };
+
+    let mut items = vec![];
+    for (key, value) in entries {
+        let position = value.position;
+        match value.value {
+            // Blocks: `"path": { "<label>": { ... } }`
+            Value::Object(blocks) => {
+                for (label, body) in blocks {
+                    let Value::Object(attributes) = body.value else {
+                        return Err(PolicyParseError::new(
+                            format!("expected an object, found {}", body.value.kind()),
+                            body.position,
+                        ));
+                    };
+                    items.push(Item::Block {
+                        kind: key.clone(),
+                        labels: vec![label],
+                        body: attributes
+                            .into_iter()
+                            .map(|(key, value)| Item::Attribute { key, value })
+                            .collect(),
+                    });
+                }
+            }
+            value => items.push(Item::Attribute {
+                key,
+                value: Spanned { value, position },
+            }),
+        }
+    }
+
+    Ok(items)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> PolicyParseError {
+        PolicyParseError::new(message, self.position())
+    }
+
+    fn unexpected(&self, expected: &str) -> PolicyParseError {
+        match self.peek() {
+            Some(c) => self.error(format!("unexpected `{c}`, expected {expected}")),
+            None => self.error(format!("unexpected end of input, expected {expected}")),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.offset).copied()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.chars.get(self.offset + 1).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), PolicyParseError> {
+        self.skip_trivia()?;
+        if self.peek() == Some(expected) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{expected}`")))
+        }
+    }
+
+    /// Skip whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), PolicyParseError> {
+        loop {
+            match (self.peek(), self.peek_next()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('#'), _) | (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.position();
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match (self.peek(), self.peek_next()) {
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                self.bump();
+                                break;
+                            }
+                            (Some(_), _) => {
+                                self.bump();
+                            }
+                            (None, _) => {
+                                return Err(PolicyParseError::new("unterminated comment", start))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Parse attributes and blocks until `terminator`, or the end of input if
+    /// there is no terminator.
+    fn parse_body(&mut self, terminator: Option<char>) -> Result<Vec<Item>, PolicyParseError> {
+        let mut items = vec![];
+        loop {
+            self.skip_trivia()?;
+            match (self.peek(), terminator) {
+                (None, None) => return Ok(items),
+                (None, Some(terminator)) => return Err(self.unexpected(&format!("`{terminator}`"))),
+                (Some(c), Some(terminator)) if c == terminator => {
+                    self.bump();
+                    return Ok(items);
+                }
+                _ => (),
+            }
+
+            let key = self.parse_identifier()?;
+            self.skip_trivia()?;
+            match self.peek() {
+                Some('=') => {
+                    self.bump();
+                    let value = self.parse_value()?;
+                    items.push(Item::Attribute { key, value });
+                }
+                Some('"' | '{') => {
+                    let mut labels = vec![];
+                    while self.peek() == Some('"') {
+                        labels.push(self.parse_string()?);
+                        self.skip_trivia()?;
+                    }
+                    self.expect('{')?;
+                    let body = self.parse_body(Some('}'))?;
+                    items.push(Item::Block {
+                        kind: key,
+                        labels,
+                        body,
+                    });
+                }
+                _ => return Err(self.unexpected("`=` or a block")),
+            }
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<Spanned<String>, PolicyParseError> {
+        let position = self.position();
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+            _ => return Err(self.unexpected("an identifier")),
+        }
+
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                value.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+
+        Ok(Spanned { value, position })
+    }
+
+    fn parse_string(&mut self) -> Result<Spanned<String>, PolicyParseError> {
+        let position = self.position();
+        self.expect('"')?;
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(Spanned { value, position }),
+                Some('\\') => {
+                    let escape_position = self.position();
+                    let escaped = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        _ => {
+                            return Err(PolicyParseError::new(
+                                "invalid escape sequence",
+                                escape_position,
+                            ))
+                        }
+                    };
+                    value.push(escaped);
+                }
+                Some('\n') | None => {
+                    return Err(PolicyParseError::new("unterminated string", position))
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Spanned<Value>, PolicyParseError> {
+        self.skip_trivia()?;
+        let position = self.position();
+        let value = match self.peek() {
+            Some('"') => Value::String(self.parse_string()?.value),
+            Some('[') => self.parse_list()?,
+            Some('{') => self.parse_object()?,
+            Some(c) if c.is_ascii_digit() || c == '-' => {
+                let mut number = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                        number.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                if number.parse::<f64>().is_err() {
+                    return Err(PolicyParseError::new(
+                        format!("invalid number `{number}`"),
+                        position,
+                    ));
+                }
+                Value::Number(number)
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let ident = self.parse_identifier()?;
+                match ident.value.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    other => {
+                        return Err(PolicyParseError::new(
+                            format!("unexpected `{other}`, expected a value"),
+                            position,
+                        ))
+                    }
+                }
+            }
+            _ => return Err(self.unexpected("a value")),
+        };
+
+        Ok(Spanned { value, position })
+    }
+
+    fn parse_list(&mut self) -> Result<Value, PolicyParseError> {
+        self.expect('[')?;
+        let mut values = vec![];
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::List(values));
+            }
+
+            values.push(self.parse_value()?);
+
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(']') => (),
+                _ => return Err(self.unexpected("`,` or `]`")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, PolicyParseError> {
+        self.expect('{')?;
+        let mut entries = vec![];
+        loop {
+            self.skip_trivia()?;
+            let key = match self.peek() {
+                Some('}') => {
+                    self.bump();
+                    return Ok(Value::Object(entries));
+                }
+                Some('"') => self.parse_string()?,
+                _ => self.parse_identifier()?,
+            };
+
+            self.skip_trivia()?;
+            match self.peek() {
+                Some('=' | ':') => {
+                    self.bump();
+                }
+                _ => return Err(self.unexpected("`=` or `:`")),
+            }
+
+            let value = self.parse_value()?;
+            entries.push((key, value));
+
+            self.skip_trivia()?;
+            if self.peek() == Some(',') {
+                self.bump();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err(input: &str) -> (String, usize, usize) {
+        let err = parse(input).unwrap_err();
+        (err.message, err.position.line, err.position.column)
+    }
+
+    #[test]
+    fn parses_blocks_with_braces_in_labels_and_comments() {
+        let items = parse(
+            r#"
+            /* leading
+               comment */
+            path "kv/{foo}/*" { // trailing comment
+                capabilities = ["read", "list",]
+            }
+            "#,
+        )
+        .unwrap();
+
+        let [Item::Block { kind, labels, body }] = items.as_slice() else {
+            panic!("expected a single block, got {items:?}");
+        };
+        assert_eq!(kind.value, "path");
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].value, "kv/{foo}/*");
+        assert_eq!(
+            labels[0].position,
+            Position {
+                line: 4,
+                column: 18
+            }
+        );
+        let [Item::Attribute { key, value }] = body.as_slice() else {
+            panic!("expected a single attribute, got {body:?}");
+        };
+        assert_eq!(key.value, "capabilities");
+        let Value::List(values) = &value.value else {
+            panic!("expected a list");
+        };
+        assert_eq!(values.len(), 2);
+    }
+
+    #[test]
+    fn parses_json_documents() {
+        let hcl = parse(r#"path "kv/*" { capabilities = ["read"] }"#).unwrap();
+        let json = parse(r#"{"path": {"kv/*": {"capabilities": ["read"]}}}"#).unwrap();
+
+        let strip = |items: Vec<Item>| -> Vec<(String, Vec<String>)> {
+            items
+                .into_iter()
+                .map(|item| match item {
+                    Item::Block { kind, labels, .. } => {
+                        (kind.value, labels.into_iter().map(|l| l.value).collect())
+                    }
+                    Item::Attribute { key, .. } => (key.value, vec![]),
+                })
+                .collect()
+        };
+        assert_eq!(strip(hcl), strip(json));
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        assert_eq!(
+            err("path \"kv/*\" {\n  capabilities = [\"read\"\n}"),
+            ("unexpected `}`, expected `,` or `]`".into(), 3, 1)
+        );
+        assert_eq!(err("path \"kv/*\n"), ("unterminated string".into(), 1, 6));
+        assert_eq!(
+            err("path \"kv/*\" {\n  capabilities = [\"read\"]\n"),
+            ("unexpected end of input, expected `}`".into(), 3, 1)
+        );
+        assert_eq!(
+            err("/* never closed"),
+            ("unterminated comment".into(), 1, 1)
+        );
+        assert_eq!(
+            err("{\"path\": {\n  \"kv/*\": [] }}"),
+            ("expected an object, found list".into(), 2, 11)
+        );
+    }
+}
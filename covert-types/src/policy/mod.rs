@@ -0,0 +1,764 @@
+mod hcl;
+mod parameters;
+mod template;
+
+use std::{cmp::Reverse, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::request::Operation;
+
+pub use hcl::{PolicyParseError, Position};
+pub use parameters::ParameterConstraints;
+
+use hcl::{Item, Spanned, Value};
+
+// Policy is used to represent the policy specified by an ACL configuration.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Policy {
+    pub name: String,
+    pub paths: Vec<PathPolicy>,
+    pub namespace_id: String,
+}
+
+impl Policy {
+    #[must_use]
+    pub fn new(name: String, paths: Vec<PathPolicy>, namespace_id: String) -> Self {
+        Self {
+            name,
+            paths,
+            namespace_id,
+        }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn is_authorized(&self, path: &str, operations: &[Operation]) -> bool {
+        Self::batch_authorize(std::slice::from_ref(self), path, operations)
+    }
+
+    /// Check if a set of policies allows all of the operations on a path.
+    ///
+    /// Only the most specific rule matching the path is considered, see
+    /// [`Policy::resolve`].
+    #[must_use]
+    pub fn batch_authorize(policies: &[Policy], path: &str, operations: &[Operation]) -> bool {
+        Self::resolve(policies, path).is_some_and(|rule| rule.allows(operations))
+    }
+
+    /// Find the rule that applies to a path for a set of policies.
+    ///
+    /// Rules for the exact same path pattern are merged across the policies,
+    /// a `deny` in any of them wins. When several patterns match the path the
+    /// most specific one is used, in order of precedence:
+    ///
+    /// 1. the pattern with the latest `+` or `*` wildcard
+    /// 2. a pattern not ending with `*`
+    /// 3. the pattern with the fewest `+` segments
+    /// 4. the longest pattern
+    /// 5. the lexicographically greatest pattern
+    #[must_use]
+    pub fn resolve(policies: &[Policy], path: &str) -> Option<PathPolicy> {
+        let rules = || policies.iter().flat_map(|policy| &policy.paths);
+
+        let pattern = rules()
+            .filter(|rule| rule.matches(path))
+            .map(PathPolicy::path)
+            .max_by_key(|pattern| specificity(pattern))?;
+
+        let mut resolved = PathPolicy::new(pattern.to_string(), vec![]);
+        for rule in rules().filter(|rule| rule.path == pattern) {
+            for op in &rule.operations {
+                if !resolved.operations.contains(op) {
+                    resolved.operations.push(*op);
+                }
+            }
+            resolved.deny |= rule.deny;
+            resolved.sudo |= rule.sudo;
+            resolved.merge_parameters(rule);
+        }
+
+        Some(resolved)
+    }
+
+    #[must_use]
+    pub fn batch_is_authorized(policies: &[Policy], derived_policies: &[Policy]) -> bool {
+        let mut derived_policies = derived_policies
+            .iter()
+            // No need to check policies that have the same name
+            .filter(|p| !policies.iter().any(|p2| p2.name == p.name));
+
+        derived_policies.all(|derived_policy| {
+            derived_policy.paths.iter().all(|derived_policy_path| {
+                // A deny rule can only take away access
+                if derived_policy_path.deny {
+                    return true;
+                }
+
+                // Verify that path for policy is allowed by the existing policies
+                let Some(rule) = Self::resolve(policies, &derived_policy_path.path) else {
+                    return false;
+                };
+                rule.allows(&derived_policy_path.operations)
+                    && (!derived_policy_path.sudo || rule.sudo)
+                    // Constraints cannot be loosened
+                    && (!rule.has_parameter_constraints()
+                        || (rule.allowed_parameters == derived_policy_path.allowed_parameters
+                            && rule.denied_parameters == derived_policy_path.denied_parameters
+                            && rule.required_parameters
+                                == derived_policy_path.required_parameters))
+            })
+        })
+    }
+}
+
+/// Sort key where a greater key means a more specific path pattern.
+fn specificity(pattern: &str) -> (usize, bool, Reverse<usize>, usize, &str) {
+    let mut first_wildcard = pattern.find('*').unwrap_or(usize::MAX);
+    let mut wildcard_segments = 0;
+    let mut offset = 0;
+    for segment in pattern.split('/') {
+        if segment == "+" {
+            first_wildcard = first_wildcard.min(offset);
+            wildcard_segments += 1;
+        }
+        offset += segment.len() + 1;
+    }
+
+    (
+        first_wildcard,
+        !pattern.ends_with('*'),
+        Reverse(wildcard_segments),
+        pattern.len(),
+        pattern,
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, sqlx::FromRow)]
+pub struct PathPolicy {
+    pub path: String,
+    // TODO: rename to capabilities
+    pub operations: Vec<Operation>,
+    /// Deny all access to the path, regardless of the other capabilities.
+    #[serde(default)]
+    pub deny: bool,
+    /// Allow access to root protected paths.
+    #[serde(default)]
+    pub sudo: bool,
+    #[serde(default, skip_serializing_if = "ParameterConstraints::is_empty")]
+    #[sqlx(default)]
+    pub allowed_parameters: ParameterConstraints,
+    #[serde(default, skip_serializing_if = "ParameterConstraints::is_empty")]
+    #[sqlx(default)]
+    pub denied_parameters: ParameterConstraints,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[sqlx(default)]
+    pub required_parameters: Vec<String>,
+}
+
+impl PathPolicy {
+    #[must_use]
+    pub fn new(path: String, operations: Vec<Operation>) -> Self {
+        Self {
+            path,
+            operations,
+            deny: false,
+            sudo: false,
+            allowed_parameters: ParameterConstraints::new(),
+            denied_parameters: ParameterConstraints::new(),
+            required_parameters: vec![],
+        }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[must_use]
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Parse a policy document into a list of path rules.
+    ///
+    /// The document is either written in HCL:
+    ///
+    /// ```hcl
+    /// path "kv/+/config" {
+    ///     capabilities = ["read", "update"]
+    /// }
+    /// ```
+    ///
+    /// or in the equivalent JSON format:
+    ///
+    /// ```json
+    /// { "path": { "kv/+/config": { "capabilities": ["read", "update"] } } }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with the line and column of the offending input if
+    /// the string is not in a valid policy format.
+    pub fn parse(s: &str) -> Result<Vec<Self>, PolicyParseError> {
+        hcl::parse(s)?
+            .into_iter()
+            .map(|item| match item {
+                Item::Block { kind, labels, body } if kind.value == "path" => {
+                    let [label] = <[_; 1]>::try_from(labels).map_err(|_| {
+                        PolicyParseError::new(
+                            "a `path` block must have exactly one label",
+                            kind.position,
+                        )
+                    })?;
+                    Self::from_block(label, body)
+                }
+                Item::Block { kind, .. } => Err(PolicyParseError::new(
+                    format!("unknown block `{}`", kind.value),
+                    kind.position,
+                )),
+                Item::Attribute { key, .. } => Err(PolicyParseError::new(
+                    format!("unknown attribute `{}`", key.value),
+                    key.position,
+                )),
+            })
+            .collect()
+    }
+
+    fn from_block(path: Spanned<String>, body: Vec<Item>) -> Result<Self, PolicyParseError> {
+        template::validate(&path.value)
+            .map_err(|message| PolicyParseError::new(message, path.position))?;
+        let mut rule = Self::new(path.value, vec![]);
+        let mut has_capabilities = false;
+
+        for item in body {
+            match item {
+                Item::Attribute { key, value } if key.value == "capabilities" => {
+                    has_capabilities = true;
+                    for capability in expect_string_list(value)? {
+                        rule.add_capability(&capability)?;
+                    }
+                }
+                Item::Attribute { key, value } if key.value == "allowed_parameters" => {
+                    rule.allowed_parameters = expect_constraints(value)?;
+                }
+                Item::Attribute { key, value } if key.value == "denied_parameters" => {
+                    rule.denied_parameters = expect_constraints(value)?;
+                }
+                Item::Attribute { key, value } if key.value == "required_parameters" => {
+                    rule.required_parameters = expect_string_list(value)?
+                        .into_iter()
+                        .map(|key| key.value)
+                        .collect();
+                }
+                Item::Attribute { key, .. } | Item::Block { kind: key, .. } => {
+                    return Err(PolicyParseError::new(
+                        format!("unknown field `{}`", key.value),
+                        key.position,
+                    ))
+                }
+            }
+        }
+
+        if !has_capabilities {
+            return Err(PolicyParseError::new(
+                "missing `capabilities`",
+                path.position,
+            ));
+        }
+
+        Ok(rule)
+    }
+
+    fn add_capability(&mut self, capability: &Spanned<String>) -> Result<(), PolicyParseError> {
+        match capability.value.as_str() {
+            "deny" => self.deny = true,
+            "sudo" => self.sudo = true,
+            other => {
+                let op = Operation::from_str(other).map_err(|_| {
+                    PolicyParseError::new(
+                        format!("unknown capability `{other}`"),
+                        capability.position,
+                    )
+                })?;
+                self.operations.push(op);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if the path pattern of this rule matches the path.
+    ///
+    /// A `+` segment matches any single path segment and a trailing `*`
+    /// matches any suffix.
+    fn matches(&self, path: &str) -> bool {
+        let (pattern, is_glob) = match self.path.strip_suffix('*') {
+            Some(prefix) => (prefix, true),
+            None => (self.path.as_str(), false),
+        };
+        let pattern = pattern.split('/').collect::<Vec<_>>();
+        let path = path.split('/').collect::<Vec<_>>();
+
+        if path.len() < pattern.len() || (!is_glob && path.len() != pattern.len()) {
+            return false;
+        }
+
+        let last = pattern.len() - 1;
+        pattern
+            .iter()
+            .zip(&path)
+            .enumerate()
+            .all(|(i, (expected, segment))| {
+                *expected == "+"
+                    || if is_glob && i == last {
+                        segment.starts_with(expected)
+                    } else {
+                        expected == segment
+                    }
+            })
+    }
+
+    /// Check if the rule grants all of the operations.
+    #[must_use]
+    pub fn allows(&self, operations: &[Operation]) -> bool {
+        !self.deny && operations.iter().all(|op| self.operations.contains(op))
+    }
+
+    #[cfg(test)]
+    fn is_authorized(&self, path: &str, operations: &[Operation]) -> bool {
+        self.matches(path) && self.allows(operations)
+    }
+}
+
+fn expect_string_list(value: Spanned<Value>) -> Result<Vec<Spanned<String>>, PolicyParseError> {
+    let Value::List(values) = value.value else {
+        return Err(PolicyParseError::new(
+            format!("expected a list, found {}", value.value.kind()),
+            value.position,
+        ));
+    };
+    values
+        .into_iter()
+        .map(|value| match value.value {
+            Value::String(s) => Ok(Spanned {
+                value: s,
+                position: value.position,
+            }),
+            other => Err(PolicyParseError::new(
+                format!("expected a string, found {}", other.kind()),
+                value.position,
+            )),
+        })
+        .collect()
+}
+
+fn expect_constraints(value: Spanned<Value>) -> Result<ParameterConstraints, PolicyParseError> {
+    let Value::Object(entries) = value.value else {
+        return Err(PolicyParseError::new(
+            format!("expected an object, found {}", value.value.kind()),
+            value.position,
+        ));
+    };
+    entries
+        .into_iter()
+        .map(|(key, values)| {
+            let Value::List(values) = values.value else {
+                return Err(PolicyParseError::new(
+                    format!("expected a list, found {}", values.value.kind()),
+                    values.position,
+                ));
+            };
+            let values = values
+                .into_iter()
+                .map(|value| match value.value {
+                    Value::String(s) => Ok(serde_json::Value::String(s)),
+                    Value::Bool(b) => Ok(serde_json::Value::Bool(b)),
+                    Value::Number(n) => serde_json::from_str(&n).map_err(|_| {
+                        PolicyParseError::new(format!("invalid number `{n}`"), value.position)
+                    }),
+                    other => Err(PolicyParseError::new(
+                        format!(
+                            "expected a string, number or boolean, found {}",
+                            other.kind()
+                        ),
+                        value.position,
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((key.value, values))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Operation::{Create, Delete, Read, Update};
+
+    #[test]
+    fn parses_policy() {
+        let default_policy = r#"
+        # Allow tokens to look up their own properties
+path "auth/token/lookup-self" {
+    capabilities = ["read"]
+}
+
+# Allow tokens to renew themselves
+path "auth/token/renew-self" {
+    capabilities = ["update"]
+}
+
+# Allow tokens to revoke themselves
+path "auth/token/revoke-self" {
+    capabilities = ["update"]
+}
+
+# Allow a token to look up its own capabilities on a path
+path "sys/capabilities-self" {
+    capabilities = ["update"]
+}
+
+
+
+# Allow a token to look up its resultant ACL from all policies. This is useful
+# for UIs. It is an internal path because the format may change at any time
+# based on how the internal ACL features and capabilities change.
+path "sys/internal/ui/resultant-acl" {
+    capabilities = ["read"]
+}
+
+# Allow a token to renew a lease via lease_id in the request body; old path for
+# old clients, new path for newer
+path "sys/renew" {
+    capabilities = ["update"]
+}
+path "sys/leases/renew" {
+    capabilities = ["update"]
+}
+
+# Allow looking up lease properties. This requires knowing the lease ID ahead
+# of time and does not divulge any sensitive information.
+path "sys/leases/lookup" {
+    capabilities = ["update"]
+}
+
+# Allow a token to manage its own cubbyhole
+path "cubbyhole/*" {
+    capabilities = ["create", "read", "update", "delete"]
+}
+
+# Allow a token to wrap arbitrary values in a response-wrapping token
+path "sys/wrapping/wrap" {
+    capabilities = ["update"]
+}
+
+# Allow a token to look up the creation time and TTL of a given
+# response-wrapping token
+path "sys/wrapping/lookup" {
+    capabilities = ["update"]
+}
+
+# Allow a token to unwrap a response-wrapping token. This is a convenience to
+# avoid client token swapping since this is also part of the response wrapping
+# policy.
+path "sys/wrapping/unwrap" {
+    capabilities = ["update"]
+}
+
+# Allow general purpose tools
+path "sys/tools/hash" {
+    capabilities = ["update"]
+}
+path "sys/tools/hash/*" {
+    capabilities = ["update"]
+}
+
+# Allow checking the status of a Control Group request if the user has the
+# accessor
+path "sys/control-group/request" {
+    capabilities = ["update"]
+}
+"#;
+
+        let parse_result = PathPolicy::parse(default_policy);
+        assert!(parse_result.is_ok());
+        let policies = parse_result.unwrap();
+        assert_eq!(
+            policies,
+            vec![
+                PathPolicy::new("auth/token/lookup-self".into(), vec![Read]),
+                PathPolicy::new("auth/token/renew-self".into(), vec![Update]),
+                PathPolicy::new("auth/token/revoke-self".into(), vec![Update]),
+                PathPolicy::new("sys/capabilities-self".into(), vec![Update]),
+                PathPolicy::new("sys/internal/ui/resultant-acl".into(), vec![Read]),
+                PathPolicy::new("sys/renew".into(), vec![Update]),
+                PathPolicy::new("sys/leases/renew".into(), vec![Update]),
+                PathPolicy::new("sys/leases/lookup".into(), vec![Update]),
+                PathPolicy::new("cubbyhole/*".into(), vec![Create, Read, Update, Delete]),
+                PathPolicy::new("sys/wrapping/wrap".into(), vec![Update]),
+                PathPolicy::new("sys/wrapping/lookup".into(), vec![Update]),
+                PathPolicy::new("sys/wrapping/unwrap".into(), vec![Update]),
+                PathPolicy::new("sys/tools/hash".into(), vec![Update]),
+                PathPolicy::new("sys/tools/hash/*".into(), vec![Update]),
+                PathPolicy::new("sys/control-group/request".into(), vec![Update]),
+            ]
+        );
+    }
+
+    #[test]
+    fn authorize_request_against_policy() {
+        use Operation::{Read, Update};
+        let policy = PathPolicy::new("sys/mounts".into(), vec![Read]);
+        assert!(policy.is_authorized("sys/mounts", &[Read]));
+        assert!(!policy.is_authorized("sys/mounts", &[Update]));
+        assert!(!policy.is_authorized("sys/mounts/", &[Read]));
+        assert!(!policy.is_authorized("sys/", &[Read]));
+        assert!(!policy.is_authorized("secret/", &[Read]));
+        assert!(!policy.is_authorized("/", &[Read]));
+
+        let policy = PathPolicy::new("sys/*".into(), vec![Read, Update]);
+        assert!(policy.is_authorized("sys/mounts", &[Read]));
+        assert!(policy.is_authorized("sys/mounts", &[Update]));
+        assert!(policy.is_authorized("sys/mounts/", &[Read]));
+        assert!(policy.is_authorized("sys/", &[Read]));
+        assert!(!policy.is_authorized("secret/", &[Read]));
+        assert!(!policy.is_authorized("/", &[Read]));
+    }
+
+    #[test]
+    fn parses_deny_sudo_and_json_policies() {
+        let hcl = PathPolicy::parse(
+            r#"
+            path "sys/mounts/{weird}" { capabilities = ["read", "sudo"] }
+            path "kv/+/secret" { capabilities = ["deny"] }
+            "#,
+        )
+        .unwrap();
+        let json = PathPolicy::parse(
+            r#"{
+                "path": {
+                    "sys/mounts/{weird}": { "capabilities": ["read", "sudo"] },
+                    "kv/+/secret": { "capabilities": ["deny"] }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let mut mounts = PathPolicy::new("sys/mounts/{weird}".into(), vec![Read]);
+        mounts.sudo = true;
+        let mut secret = PathPolicy::new("kv/+/secret".into(), vec![]);
+        secret.deny = true;
+        assert_eq!(hcl, vec![mounts.clone(), secret.clone()]);
+        assert_eq!(json, vec![mounts, secret]);
+    }
+
+    #[test]
+    fn reports_invalid_policies() {
+        let err = PathPolicy::parse("path \"kv/*\" {\n    capabilities = [\"read\", \"fly\"]\n}")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown capability `fly` at line 2, column 29"
+        );
+
+        let err = PathPolicy::parse("path \"kv/*\" {\n    policy = \"read\"\n}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown field `policy` at line 2, column 5"
+        );
+
+        let err = PathPolicy::parse("path \"kv/*\" {}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "missing `capabilities` at line 1, column 6"
+        );
+
+        let err = PathPolicy::parse("path \"a\" \"b\" { capabilities = [] }").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a `path` block must have exactly one label at line 1, column 1"
+        );
+    }
+
+    #[test]
+    fn matches_single_segment_wildcards() {
+        let policy = PathPolicy::new("kv/+/config".into(), vec![Read]);
+        assert!(policy.is_authorized("kv/foo/config", &[Read]));
+        assert!(policy.is_authorized("kv//config", &[Read]));
+        assert!(!policy.is_authorized("kv/foo/bar/config", &[Read]));
+        assert!(!policy.is_authorized("kv/config", &[Read]));
+
+        let policy = PathPolicy::new("kv/+/data/*".into(), vec![Read]);
+        assert!(policy.is_authorized("kv/foo/data/", &[Read]));
+        assert!(policy.is_authorized("kv/foo/data/a/b", &[Read]));
+        assert!(!policy.is_authorized("kv/foo/metadata/a", &[Read]));
+
+        let policy = PathPolicy::new("kv/fo*".into(), vec![Read]);
+        assert!(policy.is_authorized("kv/foo", &[Read]));
+        assert!(policy.is_authorized("kv/foo/bar", &[Read]));
+        assert!(!policy.is_authorized("kv/bar", &[Read]));
+    }
+
+    #[test]
+    fn most_specific_rule_takes_precedence() {
+        let policy = Policy::new(
+            "foo".into(),
+            vec![
+                PathPolicy::new("*".into(), vec![Read]),
+                PathPolicy::new("kv/*".into(), vec![Create, Read, Update, Delete]),
+                PathPolicy::new("kv/+/config".into(), vec![Read, Update]),
+                PathPolicy::new("kv/team/*".into(), vec![Read]),
+                PathPolicy::new("kv/team/config".into(), vec![Delete]),
+            ],
+            "ns".into(),
+        );
+
+        // Exact match wins over everything
+        assert!(policy.is_authorized("kv/team/config", &[Delete]));
+        assert!(!policy.is_authorized("kv/team/config", &[Read]));
+        // `kv/team/*` has the wildcard later than `kv/+/config`
+        assert!(policy.is_authorized("kv/team/foo", &[Read]));
+        assert!(!policy.is_authorized("kv/team/foo", &[Update]));
+        // Same wildcard position, but `kv/+/config` does not end in a glob
+        assert!(policy.is_authorized("kv/other/config", &[Update]));
+        assert!(!policy.is_authorized("kv/other/config", &[Delete]));
+        // Longer prefix wins
+        assert!(policy.is_authorized("kv/other/foo", &[Delete]));
+        assert!(!policy.is_authorized("sys/mounts", &[Delete]));
+        assert!(policy.is_authorized("sys/mounts", &[Read]));
+    }
+
+    #[test]
+    fn deny_overrides_grants_on_the_same_path() {
+        let mut deny = PathPolicy::new("kv/secret".into(), vec![]);
+        deny.deny = true;
+        let policies = [
+            Policy::new(
+                "admin".into(),
+                vec![
+                    PathPolicy::new("kv/*".into(), vec![Read]),
+                    PathPolicy::new("kv/secret".into(), vec![Read]),
+                ],
+                "ns".into(),
+            ),
+            Policy::new("deny-secret".into(), vec![deny], "ns".into()),
+        ];
+
+        assert!(Policy::batch_authorize(&policies, "kv/other", &[Read]));
+        assert!(!Policy::batch_authorize(&policies, "kv/secret", &[Read]));
+
+        let rule = Policy::resolve(&policies, "kv/secret").unwrap();
+        assert!(rule.deny);
+        assert_eq!(rule.operations, vec![Read]);
+    }
+
+    #[test]
+    fn derived_policies_must_be_subset() {
+        let mut sudo = PathPolicy::new("sys/*".into(), vec![Read]);
+        sudo.sudo = true;
+        let mut deny = PathPolicy::new("kv/secret".into(), vec![]);
+        deny.deny = true;
+        let policies = [Policy::new(
+            "parent".into(),
+            vec![PathPolicy::new("kv/*".into(), vec![Read, Update]), sudo],
+            "ns".into(),
+        )];
+
+        let derived = |paths| [Policy::new("child".into(), paths, "ns".into())];
+        assert!(Policy::batch_is_authorized(
+            &policies,
+            &derived(vec![PathPolicy::new("kv/foo/*".into(), vec![Read]), deny])
+        ));
+        assert!(!Policy::batch_is_authorized(
+            &policies,
+            &derived(vec![PathPolicy::new("kv/foo".into(), vec![Delete])])
+        ));
+
+        let mut child_sudo = PathPolicy::new("kv/foo".into(), vec![Read]);
+        child_sudo.sudo = true;
+        assert!(!Policy::batch_is_authorized(
+            &policies,
+            &derived(vec![child_sudo.clone()])
+        ));
+        child_sudo.path = "sys/mounts".into();
+        assert!(Policy::batch_is_authorized(
+            &policies,
+            &derived(vec![child_sudo])
+        ));
+    }
+
+    #[test]
+    fn parses_parameter_constraints() {
+        let policies = PathPolicy::parse(
+            r#"
+            path "kv/data/*" {
+                capabilities = ["create", "update"]
+                allowed_parameters = {
+                    "data" = []
+                    "version" = [1, 2]
+                }
+                denied_parameters = { "*" = [] }
+                required_parameters = ["data"]
+            }
+            "#,
+        )
+        .unwrap();
+
+        let mut expected = PathPolicy::new("kv/data/*".into(), vec![Create, Update]);
+        expected.allowed_parameters = ParameterConstraints::from([
+            ("data".to_string(), vec![]),
+            ("version".to_string(), vec![1.into(), 2.into()]),
+        ]);
+        expected.denied_parameters = ParameterConstraints::from([("*".to_string(), vec![])]);
+        expected.required_parameters = vec!["data".to_string()];
+        assert_eq!(policies, vec![expected]);
+
+        let err = PathPolicy::parse(
+            "path \"kv/*\" {\n  capabilities = []\n  allowed_parameters = { a = [[]] }\n}",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected a string, number or boolean, found list at line 3, column 31"
+        );
+    }
+
+    #[test]
+    fn checks_parameter_constraints() {
+        let params = |value: serde_json::Value| value.as_object().unwrap().clone();
+
+        let mut rule = PathPolicy::new("psql/creds/ci".into(), vec![Create]);
+        rule.allowed_parameters = ParameterConstraints::from([
+            ("ttl".to_string(), vec!["*m".into(), "1h".into()]),
+            ("tags".to_string(), vec!["ci-*".into()]),
+            ("count".to_string(), vec![1.into()]),
+        ]);
+        rule.denied_parameters =
+            ParameterConstraints::from([("tags".to_string(), vec!["ci-admin".into()])]);
+        rule.required_parameters = vec!["ttl".to_string()];
+
+        assert!(rule.allows_parameters(&params(serde_json::json!({ "ttl": "30m" }))));
+        assert!(rule.allows_parameters(&params(serde_json::json!({ "ttl": "1h", "count": "1" }))));
+        assert!(rule.allows_parameters(&params(
+            serde_json::json!({ "ttl": "1h", "tags": ["ci-a", "ci-b"] })
+        )));
+        assert!(!rule.allows_parameters(&params(serde_json::json!({ "ttl": "2h" }))));
+        assert!(!rule.allows_parameters(&params(serde_json::json!({ "ttl": "1h", "foo": 1 }))));
+        assert!(!rule.allows_parameters(&params(serde_json::json!({ "count": 1 }))));
+        assert!(!rule.allows_parameters(&params(
+            serde_json::json!({ "ttl": "1h", "tags": ["ci-a", "ci-admin"] })
+        )));
+        assert!(!rule.allows_parameters(&params(
+            serde_json::json!({ "ttl": "1h", "tags": ["ci-a", "prod"] })
+        )));
+
+        // No constraints allows anything
+        let rule = PathPolicy::new("kv/*".into(), vec![Create]);
+        assert!(rule.allows_parameters(&params(serde_json::json!({ "foo": "bar" }))));
+    }
+}
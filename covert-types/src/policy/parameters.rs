@@ -0,0 +1,140 @@
+//! Constraints on the parameters in the body of a request.
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use serde_json::{Map, Value};
+
+use super::PathPolicy;
+
+/// Parameter names mapped to the values they are constrained to.
+///
+/// An empty list of values matches any value. The `*` key applies to every
+/// parameter without an entry of its own. String values ending with `*` match
+/// by prefix and values starting with `*` match by suffix.
+pub type ParameterConstraints = BTreeMap<String, Vec<Value>>;
+
+impl PathPolicy {
+    /// Check the parameters of a request body against the parameter
+    /// constraints of the rule.
+    ///
+    /// - every parameter must be listed in `allowed_parameters`, if any are
+    ///   listed, with one of the allowed values
+    /// - no parameter may be listed in `denied_parameters` with a matching
+    ///   value
+    /// - all of the `required_parameters` must be present
+    ///
+    /// Array values are checked element by element.
+    #[must_use]
+    pub fn allows_parameters(&self, parameters: &Map<String, Value>) -> bool {
+        let allowed = parameters.iter().all(|(key, value)| {
+            if self.allowed_parameters.is_empty() {
+                return true;
+            }
+            match lookup(&self.allowed_parameters, key) {
+                None => false,
+                Some([]) => true,
+                Some(patterns) => elements(value)
+                    .all(|value| patterns.iter().any(|pattern| matches(pattern, value))),
+            }
+        });
+
+        let denied =
+            parameters
+                .iter()
+                .any(|(key, value)| match lookup(&self.denied_parameters, key) {
+                    None => false,
+                    Some([]) => true,
+                    Some(patterns) => elements(value)
+                        .any(|value| patterns.iter().any(|pattern| matches(pattern, value))),
+                });
+
+        let has_required = self
+            .required_parameters
+            .iter()
+            .all(|key| parameters.contains_key(key));
+
+        allowed && !denied && has_required
+    }
+
+    /// Returns true if the rule puts any constraints on the request
+    /// parameters.
+    #[must_use]
+    pub fn has_parameter_constraints(&self) -> bool {
+        !self.allowed_parameters.is_empty()
+            || !self.denied_parameters.is_empty()
+            || !self.required_parameters.is_empty()
+    }
+
+    /// Merge the parameter constraints of another rule for the same path.
+    pub(crate) fn merge_parameters(&mut self, other: &PathPolicy) {
+        merge(&mut self.allowed_parameters, &other.allowed_parameters);
+        merge(&mut self.denied_parameters, &other.denied_parameters);
+        for key in &other.required_parameters {
+            if !self.required_parameters.contains(key) {
+                self.required_parameters.push(key.clone());
+            }
+        }
+    }
+}
+
+fn merge(into: &mut ParameterConstraints, other: &ParameterConstraints) {
+    for (key, values) in other {
+        match into.get_mut(key) {
+            // Any value is already matched
+            Some(existing) if existing.is_empty() => (),
+            Some(existing) if values.is_empty() => existing.clear(),
+            Some(existing) => {
+                for value in values {
+                    if !existing.contains(value) {
+                        existing.push(value.clone());
+                    }
+                }
+            }
+            None => {
+                into.insert(key.clone(), values.clone());
+            }
+        }
+    }
+}
+
+fn lookup<'a>(constraints: &'a ParameterConstraints, key: &str) -> Option<&'a [Value]> {
+    constraints
+        .get(key)
+        .or_else(|| constraints.get("*"))
+        .map(Vec::as_slice)
+}
+
+fn elements(value: &Value) -> Box<dyn Iterator<Item = &Value> + '_> {
+    match value {
+        Value::Array(values) => Box::new(values.iter()),
+        value => Box::new(std::iter::once(value)),
+    }
+}
+
+fn as_text(value: &Value) -> Option<Cow<'_, str>> {
+    match value {
+        Value::String(s) => Some(Cow::Borrowed(s)),
+        Value::Number(n) => Some(Cow::Owned(n.to_string())),
+        Value::Bool(b) => Some(Cow::Owned(b.to_string())),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+fn matches(pattern: &Value, value: &Value) -> bool {
+    if pattern == value {
+        return true;
+    }
+    let (Some(pattern), Some(value)) = (as_text(pattern), as_text(value)) else {
+        return false;
+    };
+
+    if pattern == "*" {
+        true
+    } else if let Some(prefix) = pattern.strip_suffix('*') {
+        value.starts_with(prefix)
+    } else if let Some(suffix) = pattern.strip_prefix('*') {
+        value.ends_with(suffix)
+    } else {
+        pattern == value
+    }
+}
@@ -0,0 +1,191 @@
+//! Identity templates in policy paths.
+//!
+//! A path like `kv/users/{{identity.entity.name}}/*` is rendered for the
+//! entity of the token making the request, so a single policy can be attached
+//! to every entity.
+
+use crate::entity::EntityAlias;
+
+use super::Policy;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Variable<'a> {
+    /// `identity.entity.name`
+    EntityName,
+    /// `identity.entity.aliases.<mount>.name`
+    AliasName { mount_path: &'a str },
+}
+
+impl<'a> Variable<'a> {
+    fn parse(expr: &'a str) -> Option<Self> {
+        let expr = expr.trim();
+        if expr == "identity.entity.name" {
+            return Some(Self::EntityName);
+        }
+        let mount_path = expr
+            .strip_prefix("identity.entity.aliases.")?
+            .strip_suffix(".name")?;
+        if mount_path.is_empty() {
+            None
+        } else {
+            Some(Self::AliasName { mount_path })
+        }
+    }
+
+    fn resolve<'b>(&self, entity_name: &'b str, aliases: &'b [EntityAlias]) -> Option<&'b str> {
+        match self {
+            Self::EntityName => Some(entity_name),
+            Self::AliasName { mount_path } => aliases
+                .iter()
+                .find(|alias| {
+                    alias.mount_path.trim_end_matches('/') == mount_path.trim_end_matches('/')
+                })
+                .map(|alias| alias.name.as_str()),
+        }
+    }
+}
+
+/// Split a path into the literal text and the template variables.
+fn split(path: &str) -> Result<Vec<Result<&str, Variable<'_>>>, String> {
+    let mut parts = vec![];
+    let mut rest = path;
+    while let Some(start) = rest.find("{{") {
+        parts.push(Ok(&rest[..start]));
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "unterminated template, expected `}}`".to_string())?;
+        let expr = &after[..end];
+        let variable = Variable::parse(expr)
+            .ok_or_else(|| format!("unknown template variable `{}`", expr.trim()))?;
+        parts.push(Err(variable));
+        rest = &after[end + 2..];
+    }
+    parts.push(Ok(rest));
+    Ok(parts)
+}
+
+/// Check that all templates in a path refer to known identity variables.
+pub(crate) fn validate(path: &str) -> Result<(), String> {
+    split(path).map(|_| ())
+}
+
+/// Render the templates in a path. Returns `None` if any of the variables
+/// cannot be resolved for the identity, or resolves to a value that would
+/// widen the path pattern.
+fn render(path: &str, entity_name: &str, aliases: &[EntityAlias]) -> Option<String> {
+    let mut rendered = String::with_capacity(path.len());
+    for part in split(path).ok()? {
+        match part {
+            Ok(literal) => rendered.push_str(literal),
+            Err(variable) => {
+                let value = variable.resolve(entity_name, aliases)?;
+                if value.is_empty() || value.contains(['/', '*', '+']) {
+                    return None;
+                }
+                rendered.push_str(value);
+            }
+        }
+    }
+    Some(rendered)
+}
+
+impl Policy {
+    /// Returns true if any of the rule paths contains an identity template.
+    #[must_use]
+    pub fn is_templated(&self) -> bool {
+        self.paths.iter().any(|rule| rule.path.contains("{{"))
+    }
+
+    /// Render the identity templates in the rule paths for an entity and its
+    /// aliases.
+    ///
+    /// Rules with templates that cannot be rendered for the entity, e.g. a
+    /// reference to an alias the entity does not have, are removed.
+    pub fn render_templates(&mut self, entity_name: &str, aliases: &[EntityAlias]) {
+        self.paths.retain_mut(|rule| {
+            if !rule.path.contains("{{") {
+                return true;
+            }
+            match render(&rule.path, entity_name, aliases) {
+                Some(path) => {
+                    rule.path = path;
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{policy::PathPolicy, request::Operation};
+
+    #[test]
+    fn renders_identity_templates() {
+        let aliases = vec![EntityAlias {
+            name: "john".into(),
+            mount_path: "auth/userpass/".into(),
+        }];
+        let mut policy = Policy::new(
+            "users".into(),
+            vec![
+                PathPolicy::new(
+                    "kv/users/{{identity.entity.name}}/*".into(),
+                    vec![Operation::Read],
+                ),
+                PathPolicy::new(
+                    "kv/aliases/{{ identity.entity.aliases.auth/userpass.name }}".into(),
+                    vec![Operation::Read],
+                ),
+                PathPolicy::new(
+                    "kv/github/{{identity.entity.aliases.auth/github/.name}}".into(),
+                    vec![Operation::Read],
+                ),
+                PathPolicy::new("kv/shared/*".into(), vec![Operation::Read]),
+            ],
+            "ns".into(),
+        );
+        assert!(policy.is_templated());
+
+        policy.render_templates("alice", &aliases);
+        assert_eq!(
+            policy
+                .paths
+                .iter()
+                .map(PathPolicy::path)
+                .collect::<Vec<_>>(),
+            vec!["kv/users/alice/*", "kv/aliases/john", "kv/shared/*"]
+        );
+        assert!(!policy.is_templated());
+    }
+
+    #[test]
+    fn drops_rules_that_would_widen_the_path() {
+        let mut policy = Policy::new(
+            "users".into(),
+            vec![PathPolicy::new(
+                "kv/users/{{identity.entity.name}}".into(),
+                vec![Operation::Read],
+            )],
+            "ns".into(),
+        );
+        policy.render_templates("*", &[]);
+        assert!(policy.paths.is_empty());
+    }
+
+    #[test]
+    fn validates_templates() {
+        assert!(validate("kv/{{identity.entity.name}}/{foo}").is_ok());
+        assert_eq!(
+            validate("kv/{{identity.entity.id}}"),
+            Err("unknown template variable `identity.entity.id`".to_string())
+        );
+        assert_eq!(
+            validate("kv/{{identity.entity.name"),
+            Err("unterminated template, expected `}}`".to_string())
+        );
+    }
+}
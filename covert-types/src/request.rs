@@ -1,7 +1,7 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, fmt, str::FromStr};
 
 use bytes::Bytes;
-use http::{Extensions, Method};
+use http::Method;
 use http_body::Limited;
 use hyper::Body;
 use serde::{Deserialize, Serialize};
@@ -38,6 +38,7 @@ pub enum Operation {
     Read,
     Update,
     Delete,
+    List,
     // The operations below are called globally, the path is less relevant.
     Revoke,
     Renew,
@@ -52,6 +53,7 @@ impl FromStr for Operation {
             "read" => Ok(Self::Read),
             "update" => Ok(Self::Update),
             "delete" => Ok(Self::Delete),
+            "list" => Ok(Self::List),
             "revoke" => Ok(Self::Revoke),
             "renew" => Ok(Self::Renew),
             _ => Err(ApiError::bad_request()),
@@ -59,6 +61,31 @@ impl FromStr for Operation {
     }
 }
 
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operation = match self {
+            Self::Create => "create",
+            Self::Read => "read",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::List => "list",
+            Self::Revoke => "revoke",
+            Self::Renew => "renew",
+        };
+        f.write_str(operation)
+    }
+}
+
+/// Returns true if the query string contains `list=true`, which turns a `GET`
+/// request into a list request for clients that cannot send `LIST`.
+fn is_list_query(query: Option<&str>) -> bool {
+    query.is_some_and(|query| {
+        query
+            .split('&')
+            .any(|pair| pair.eq_ignore_ascii_case("list=true"))
+    })
+}
+
 impl Request {
     /// Create a internal logical request from a http request.
     ///
@@ -66,7 +93,7 @@ impl Request {
     ///
     /// Returns an error if the http request contains unsupported elements that
     /// cannot be converted to the logical request format.
-    pub async fn new(raw: hyper::Request<Limited<Body>>) -> Result<Self, ApiError> {
+    pub async fn new(mut raw: hyper::Request<Limited<Body>>) -> Result<Self, ApiError> {
         let uri = raw.uri().clone();
         let token = raw
             .headers()
@@ -107,13 +134,17 @@ impl Request {
             .collect();
 
         let operation = match *raw.method() {
+            Method::GET if is_list_query(uri.query()) => Operation::List,
             Method::GET => Operation::Read,
             Method::POST => Operation::Create,
             Method::PUT => Operation::Update,
             Method::DELETE => Operation::Delete,
+            ref method if method.as_str() == "LIST" => Operation::List,
             _ => return Err(ApiError::bad_request()),
         };
 
+        // Extensions added by the http layers are passed on to the handlers
+        let extensions = std::mem::take(raw.extensions_mut());
         let bytes = hyper::body::to_bytes(raw.into_body())
             .await
             .map_err(|_| ApiError::bad_request())?;
@@ -129,7 +160,7 @@ impl Request {
             namespace,
             query_string: uri.query().unwrap_or_default().to_string(),
             path: path.to_string(),
-            extensions: Extensions::new(),
+            extensions,
             token,
             params: vec![],
             data: bytes,
@@ -151,3 +182,17 @@ impl Request {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_query() {
+        assert!(is_list_query(Some("list=true")));
+        assert!(is_list_query(Some("version=1&list=TRUE")));
+        assert!(!is_list_query(Some("list=false")));
+        assert!(!is_list_query(Some("blacklist=true")));
+        assert!(!is_list_query(None));
+    }
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::{Deserialize, Serialize};
@@ -7,6 +7,12 @@ use crate::error::ApiError;
 
 const TOKEN_LENGTH: usize = 24;
 
+const ACCESSOR_LENGTH: usize = 24;
+
+/// Length of the one-time pad used to encode a token, which is the length of
+/// the token including its prefix.
+pub const OTP_LENGTH: usize = TOKEN_LENGTH + 2;
+
 enum TokenType {
     Service,
 }
@@ -19,7 +25,7 @@ impl TokenType {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Token(String);
 
 impl FromStr for Token {
@@ -46,6 +52,21 @@ impl Token {
         Self(token)
     }
 
+    /// Encode the token with a one-time pad, which must have the same length
+    /// as the token. Returns `None` if the length of the pad does not match.
+    #[must_use]
+    pub fn encode_with_otp(&self, otp: &str) -> Option<String> {
+        xor(self.0.as_bytes(), otp.as_bytes()).map(hex::encode)
+    }
+
+    /// Decode a token that was encoded with [`Token::encode_with_otp`].
+    #[must_use]
+    pub fn decode_with_otp(encoded: &str, otp: &str) -> Option<Self> {
+        let encoded = hex::decode(encoded).ok()?;
+        let token = String::from_utf8(xor(&encoded, otp.as_bytes())?).ok()?;
+        token.parse().ok()
+    }
+
     // Not using the ToString/Display trait to prevent accidental leaks
     #[allow(clippy::inherent_to_string)]
     #[must_use]
@@ -54,8 +75,86 @@ impl Token {
     }
 }
 
+/// Generate a one-time pad that can be used to encode a token.
+#[must_use]
+pub fn generate_otp() -> String {
+    let mut rng = thread_rng();
+    (0..OTP_LENGTH)
+        .map(|_| rng.sample(Alphanumeric) as char)
+        .collect()
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Option<Vec<u8>> {
+    if a.len() == b.len() {
+        Some(a.iter().zip(b).map(|(a, b)| a ^ b).collect())
+    } else {
+        None
+    }
+}
+
 impl Default for Token {
     fn default() -> Self {
         Self::new()
     }
 }
+
+/// A reference to a token that is not itself a secret. It can be used to
+/// lookup and revoke a token without knowing the token.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenAccessor(String);
+
+impl TokenAccessor {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut rng = thread_rng();
+        let chars: String = (0..ACCESSOR_LENGTH)
+            .map(|_| rng.sample(Alphanumeric) as char)
+            .collect();
+        Self(chars)
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for TokenAccessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<String> for TokenAccessor {
+    fn from(accessor: String) -> Self {
+        Self(accessor)
+    }
+}
+
+impl fmt::Display for TokenAccessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_with_otp() {
+        let token = Token::new();
+        let otp = generate_otp();
+        assert_eq!(token.to_string().len(), OTP_LENGTH);
+
+        let encoded = token.encode_with_otp(&otp).unwrap();
+        assert_ne!(encoded, token.to_string());
+        assert_eq!(Token::decode_with_otp(&encoded, &otp), Some(token.clone()));
+
+        assert!(token.encode_with_otp("too-short").is_none());
+        assert_ne!(
+            Token::decode_with_otp(&encoded, &generate_otp()),
+            Some(token)
+        );
+    }
+}